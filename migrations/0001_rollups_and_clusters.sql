@@ -0,0 +1,45 @@
+-- Rollups and their status snapshots
+CREATE TABLE IF NOT EXISTS rollups (
+    name        TEXT PRIMARY KEY,
+    chain_id    BIGINT NOT NULL,
+    state       TEXT NOT NULL,
+    config      JSONB NOT NULL,
+    status      JSONB NOT NULL,
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at  TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- History is kept without a foreign key so it outlives deleted rollups
+CREATE TABLE IF NOT EXISTS rollup_history (
+    id          BIGSERIAL PRIMARY KEY,
+    rollup_name TEXT NOT NULL,
+    state       TEXT NOT NULL,
+    config      JSONB NOT NULL,
+    status      JSONB NOT NULL,
+    recorded_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS rollup_history_name_idx
+    ON rollup_history (rollup_name, recorded_at DESC);
+
+-- Clusters managed through the infrastructure controller
+CREATE TABLE IF NOT EXISTS clusters (
+    name        TEXT PRIMARY KEY,
+    state       TEXT NOT NULL,
+    config      JSONB NOT NULL,
+    status      JSONB NOT NULL,
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at  TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE TABLE IF NOT EXISTS cluster_history (
+    id           BIGSERIAL PRIMARY KEY,
+    cluster_name TEXT NOT NULL,
+    state        TEXT NOT NULL,
+    config       JSONB NOT NULL,
+    status       JSONB NOT NULL,
+    recorded_at  TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS cluster_history_name_idx
+    ON cluster_history (cluster_name, recorded_at DESC);
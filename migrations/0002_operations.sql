@@ -0,0 +1,16 @@
+-- Long-running API operations and their step logs
+CREATE TABLE IF NOT EXISTS operations (
+    id          UUID PRIMARY KEY,
+    kind        TEXT NOT NULL,
+    target      TEXT NOT NULL,
+    state       TEXT NOT NULL,
+    progress    SMALLINT NOT NULL DEFAULT 0,
+    steps       JSONB NOT NULL DEFAULT '[]',
+    result      JSONB,
+    error       TEXT,
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at  TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS operations_target_idx ON operations (target, created_at DESC);
+CREATE INDEX IF NOT EXISTS operations_state_idx ON operations (state);
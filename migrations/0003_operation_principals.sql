@@ -0,0 +1,4 @@
+-- Principal that requested each operation through the API
+ALTER TABLE operations ADD COLUMN IF NOT EXISTS requested_by TEXT;
+
+CREATE INDEX IF NOT EXISTS operations_requested_by_idx ON operations (requested_by, created_at DESC);
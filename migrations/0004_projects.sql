@@ -0,0 +1,17 @@
+-- Projects own rollups and clusters and map to a Kubernetes namespace
+CREATE TABLE IF NOT EXISTS projects (
+    name        TEXT PRIMARY KEY,
+    namespace   TEXT NOT NULL UNIQUE,
+    quotas      JSONB NOT NULL DEFAULT '{}',
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT now(),
+    updated_at  TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- Everything created before projects existed belongs to the default project
+ALTER TABLE rollups ADD COLUMN IF NOT EXISTS project TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE clusters ADD COLUMN IF NOT EXISTS project TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE operations ADD COLUMN IF NOT EXISTS project TEXT;
+
+CREATE INDEX IF NOT EXISTS rollups_project_idx ON rollups (project);
+CREATE INDEX IF NOT EXISTS clusters_project_idx ON clusters (project);
+CREATE INDEX IF NOT EXISTS operations_project_idx ON operations (project, created_at DESC);
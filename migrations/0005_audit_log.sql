@@ -0,0 +1,31 @@
+-- Append-only record of every infrastructure mutation
+CREATE TABLE IF NOT EXISTS audit_log (
+    id            BIGSERIAL PRIMARY KEY,
+    recorded_at   TIMESTAMPTZ NOT NULL DEFAULT now(),
+    actor         TEXT NOT NULL,
+    action        TEXT NOT NULL,
+    target        TEXT NOT NULL,
+    project       TEXT,
+    before        JSONB,
+    after         JSONB,
+    outcome       TEXT NOT NULL,
+    error         TEXT,
+    operation_id  UUID
+);
+
+CREATE INDEX IF NOT EXISTS audit_log_recorded_at_idx ON audit_log (recorded_at DESC);
+CREATE INDEX IF NOT EXISTS audit_log_actor_idx ON audit_log (actor, recorded_at DESC);
+CREATE INDEX IF NOT EXISTS audit_log_target_idx ON audit_log (target, recorded_at DESC);
+CREATE INDEX IF NOT EXISTS audit_log_project_idx ON audit_log (project, recorded_at DESC);
+
+-- Entries can only be added, never changed or removed
+CREATE OR REPLACE FUNCTION audit_log_append_only() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'audit_log is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS audit_log_append_only ON audit_log;
+CREATE TRIGGER audit_log_append_only
+    BEFORE UPDATE OR DELETE OR TRUNCATE ON audit_log
+    FOR EACH STATEMENT EXECUTE FUNCTION audit_log_append_only();
@@ -0,0 +1,9 @@
+-- galato instances and when each last reported itself alive, so an instance
+-- only fails the operations of instances that stopped
+CREATE TABLE IF NOT EXISTS instances (
+    id            UUID PRIMARY KEY,
+    heartbeat_at  TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- Instance running each operation
+ALTER TABLE operations ADD COLUMN IF NOT EXISTS instance_id UUID;
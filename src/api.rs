@@ -1,48 +1,377 @@
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    response::IntoResponse,
-    routing::{get, post},
+    extract::{Path, Query, Request, State},
+    http::{header, StatusCode},
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{get, patch, post},
     Json, Router,
 };
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc, time::Duration};
 use tower_http::trace::TraceLayer;
+use uuid::Uuid;
 
 use crate::{
-    config::Config,
-    infra::Controller,
-    monitoring::System,
-    rollup::{Manager, RollupConfig, RollupStatus},
+    audit::{AuditFilter, AuditLog, MAX_LIMIT},
+    auth::{authenticate, Authenticator, Principal, Role, ALL_PROJECTS},
+    error::{assign_request_id, ApiError, ApiJson},
+    infra::{
+        self, default_account, ClusterConfig, Controller, NodePool, NodePoolSize, TeardownOptions,
+    },
+    monitoring::{track_requests, System},
+    operations::{Operation, Operations},
+    project::{
+        self, default_project, Project, ProjectQuotas, ProjectUsage, Projects, QuotaResource,
+        DEFAULT_PROJECT,
+    },
+    rollup::{self, Manager, RollupConfig, RollupStatus},
 };
 
+const OPERATION_LIST_LIMIT: i64 = 100;
+
 pub struct AppState {
-    config: Arc<Config>,
-    infra_controller: Arc<Controller>,
-    rollup_manager: Arc<Manager>,
-    monitoring: Arc<System>,
+    pub infra_controller: Arc<Controller>,
+    pub rollup_manager: Arc<Manager>,
+    pub projects: Arc<Projects>,
+    pub monitoring: Arc<System>,
+    pub operations: Arc<Operations>,
+    pub auth: Arc<Authenticator>,
+    pub audit: AuditLog,
 }
 
-pub fn create_router(state: AppState) -> Router {
+pub fn create_router(state: Arc<AppState>) -> Router {
+    // Everything under /api and the metrics require credentials; handlers
+    // check the role
+    let api = Router::new()
+        .route("/api/v1/projects", get(list_projects).post(create_project))
+        .route(
+            "/api/v1/projects/:id",
+            get(get_project).put(update_project).delete(delete_project),
+        )
+        .route("/api/v1/rollups", get(list_rollups).post(create_rollup))
+        .route(
+            "/api/v1/rollups/:id",
+            get(get_rollup)
+                .put(replace_rollup)
+                .patch(patch_rollup)
+                .delete(delete_rollup),
+        )
+        .route("/api/v1/rollups/:id/history", get(get_rollup_history))
+        .route("/api/v1/clusters", get(list_clusters).post(create_cluster))
+        .route(
+            "/api/v1/clusters/:id",
+            get(get_cluster)
+                .put(replace_cluster)
+                .patch(patch_cluster)
+                .delete(delete_cluster),
+        )
+        .route("/api/v1/clusters/:id/history", get(get_cluster_history))
+        .route(
+            "/api/v1/clusters/:id/kubeconfig",
+            get(get_cluster_kubeconfig),
+        )
+        .route("/api/v1/clusters/:id/scale", post(scale_cluster))
+        .route(
+            "/api/v1/clusters/:id/node-pools",
+            get(list_node_pools).post(add_node_pool),
+        )
+        .route(
+            "/api/v1/clusters/:id/node-pools/:pool",
+            patch(resize_node_pool).delete(remove_node_pool),
+        )
+        .route("/api/v1/operations", get(list_operations))
+        .route("/api/v1/operations/:id", get(get_operation))
+        .route("/api/v1/audit", get(query_audit))
+        .route("/api/v1/audit/export", get(export_audit))
+        .route("/metrics", get(metrics))
+        .route_layer(middleware::from_fn_with_state(
+            state.auth.clone(),
+            authenticate,
+        ));
+
     Router::new()
         .route("/health", get(health_check))
-        .route("/metrics", get(metrics))
-        .route("/api/v1/rollups", post(create_rollup))
-        .route("/api/v1/rollups/:id", get(get_rollup))
-        .route("/api/v1/rollups/:id", post(delete_rollup))
-        .route("/api/v1/clusters", post(create_cluster))
-        .route("/api/v1/clusters/:id", get(get_cluster))
-        .route("/api/v1/clusters/:id", post(delete_cluster))
-        .layer(TraceLayer::new_for_http())
+        .merge(api)
+        .layer(middleware::from_fn_with_state(
+            state.monitoring.clone(),
+            track_requests,
+        ))
+        .layer(middleware::from_fn(assign_request_id))
+        .layer(
+            TraceLayer::new_for_http().make_span_with(|request: &Request| {
+                tracing::info_span!(
+                    "request",
+                    method = %request.method(),
+                    uri = %request.uri(),
+                    principal = tracing::field::Empty,
+                    role = tracing::field::Empty,
+                )
+            }),
+        )
         .with_state(state)
 }
 
+/// Replies 202 with the operation and a `Location` to poll it at.
+fn accepted(operation: Operation) -> Response {
+    let location = format!("/api/v1/operations/{}", operation.id);
+    (
+        StatusCode::ACCEPTED,
+        [(header::LOCATION, location)],
+        Json(operation),
+    )
+        .into_response()
+}
+
 async fn health_check() -> impl IntoResponse {
-    StatusCode::OK
+    Json(serde_json::json!({ "status": "ok" }))
+}
+
+/// Prometheus text exposition of every metric galato records. The series
+/// name rollups of every project, so only viewers of all projects may scrape.
+async fn metrics(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+) -> Result<Response, ApiError> {
+    principal.require(Role::Viewer)?;
+    principal.require_project(ALL_PROJECTS)?;
+    Ok((
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.monitoring.render(),
+    )
+        .into_response())
+}
+
+/// Narrows list results to a single project.
+#[derive(Debug, Deserialize)]
+struct ProjectFilter {
+    project: Option<String>,
+}
+
+impl ProjectFilter {
+    fn matches(&self, principal: &Principal, project: &str) -> bool {
+        principal.can_access(project) && self.project.as_deref().is_none_or(|p| p == project)
+    }
+}
+
+/// Looks up a rollup the principal can see. Rollups in other projects are
+/// reported as missing so their names do not leak.
+async fn visible_rollup(
+    state: &AppState,
+    principal: &Principal,
+    name: &str,
+) -> Result<RollupStatus, ApiError> {
+    let status = state
+        .rollup_manager
+        .get_rollup_status(name)
+        .await?
+        .filter(|status| principal.can_access(&status.project))
+        .ok_or_else(|| rollup::Error::NotFound(name.to_string()))?;
+    Ok(status)
 }
 
-async fn metrics(State(state): State<Arc<AppState>>) -> impl IntoResponse {
-    state.monitoring.get_metrics().await
+async fn visible_cluster(
+    state: &AppState,
+    principal: &Principal,
+    name: &str,
+) -> Result<ClusterConfig, ApiError> {
+    let config = state
+        .infra_controller
+        .get_cluster_config(name)
+        .await?
+        .filter(|config| principal.can_access(&config.project))
+        .ok_or_else(|| infra::Error::ClusterNotFound(name.to_string()))?;
+    Ok(config)
+}
+
+async fn visible_project(
+    state: &AppState,
+    principal: &Principal,
+    name: &str,
+) -> Result<Project, ApiError> {
+    let project = state
+        .projects
+        .get(name)
+        .await
+        .filter(|project| principal.can_access(&project.name))
+        .ok_or_else(|| project::Error::NotFound(name.to_string()))?;
+    Ok(project)
+}
+
+#[derive(Debug, Serialize)]
+struct ProjectView {
+    #[serde(flatten)]
+    project: Project,
+    usage: ProjectUsage,
+}
+
+async fn list_projects(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+) -> Result<Response, ApiError> {
+    principal.require(Role::Viewer)?;
+    let projects: Vec<Project> = state
+        .projects
+        .list()
+        .await
+        .into_iter()
+        .filter(|project| principal.can_access(&project.name))
+        .collect();
+    Ok(Json(projects).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateProjectRequest {
+    name: String,
+    /// Defaults to the project name
+    namespace: Option<String>,
+    #[serde(default)]
+    quotas: ProjectQuotas,
+}
+
+async fn create_project(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    ApiJson(req): ApiJson<CreateProjectRequest>,
+) -> Result<Response, ApiError> {
+    principal.require(Role::Admin)?;
+    principal.require_project(&req.name)?;
+
+    let project = Project {
+        namespace: req.namespace.unwrap_or_else(|| req.name.clone()),
+        name: req.name,
+        quotas: req.quotas,
+        created_at: chrono::Utc::now(),
+    };
+    state.projects.validate_new_project(&project).await?;
+
+    let projects = state.projects.clone();
+    let name = project.name.clone();
+
+    let operation = state
+        .operations
+        .start(
+            "create_project",
+            &name,
+            &name,
+            &principal.subject,
+            move |operation| async move {
+                operation.record_change(None, audit_value(&project)).await;
+                operation
+                    .step(10, "Creating project namespace and quota")
+                    .await?;
+                let name = project.name.clone();
+                projects.create_project(project).await?;
+                Ok(serde_json::to_value(projects.get(&name).await)?)
+            },
+        )
+        .await?;
+
+    Ok(accepted(operation))
+}
+
+async fn get_project(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path(id): Path<String>,
+) -> Result<Response, ApiError> {
+    principal.require(Role::Viewer)?;
+    let project = visible_project(&state, &principal, &id).await?;
+    let usage = state.projects.usage(&id).await?;
+    Ok(Json(ProjectView { project, usage }).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+struct UpdateProjectRequest {
+    quotas: ProjectQuotas,
+}
+
+async fn update_project(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path(id): Path<String>,
+    ApiJson(req): ApiJson<UpdateProjectRequest>,
+) -> Result<Response, ApiError> {
+    principal.require(Role::Admin)?;
+    let before = visible_project(&state, &principal, &id).await?;
+
+    let projects = state.projects.clone();
+    let name = id.clone();
+
+    let operation = state
+        .operations
+        .start(
+            "update_project",
+            &id,
+            &id,
+            &principal.subject,
+            move |operation| async move {
+                let mut after = before.clone();
+                after.quotas = req.quotas.clone();
+                operation
+                    .record_change(audit_value(&before), audit_value(&after))
+                    .await;
+                operation.step(10, "Updating project quota").await?;
+                Ok(serde_json::to_value(
+                    projects.update_quotas(&name, req.quotas).await?,
+                )?)
+            },
+        )
+        .await?;
+
+    Ok(accepted(operation))
+}
+
+async fn delete_project(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path(id): Path<String>,
+) -> Result<Response, ApiError> {
+    principal.require(Role::Admin)?;
+    let before = visible_project(&state, &principal, &id).await?;
+    if id == DEFAULT_PROJECT {
+        return Err(project::Error::InvalidConfig(
+            "the default project cannot be deleted".to_string(),
+        )
+        .into());
+    }
+    state.projects.ensure_empty(&id).await?;
+
+    let projects = state.projects.clone();
+    let name = id.clone();
+
+    let operation = state
+        .operations
+        .start(
+            "delete_project",
+            &id,
+            &id,
+            &principal.subject,
+            move |operation| async move {
+                operation.record_change(audit_value(&before), None).await;
+                operation.step(10, "Deleting project namespace").await?;
+                projects.delete_project(&name).await?;
+                Ok(serde_json::json!({ "deleted": name }))
+            },
+        )
+        .await?;
+
+    Ok(accepted(operation))
+}
+
+async fn list_rollups(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Query(filter): Query<ProjectFilter>,
+) -> Result<Response, ApiError> {
+    principal.require(Role::Viewer)?;
+    let rollups: Vec<RollupStatus> = state
+        .rollup_manager
+        .list_rollups()
+        .await?
+        .into_iter()
+        .filter(|status| filter.matches(&principal, &status.project))
+        .collect();
+    Ok(Json(rollups).into_response())
 }
 
 #[derive(Debug, Deserialize)]
@@ -52,79 +381,1195 @@ struct CreateRollupRequest {
 
 async fn create_rollup(
     State(state): State<Arc<AppState>>,
-    Json(req): Json<CreateRollupRequest>,
-) -> impl IntoResponse {
-    match state.rollup_manager.create_rollup(req.config).await {
-        Ok(_) => StatusCode::CREATED.into_response(),
-        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
-    }
+    principal: Principal,
+    ApiJson(mut req): ApiJson<CreateRollupRequest>,
+) -> Result<Response, ApiError> {
+    principal.require(Role::Operator)?;
+    principal.require_project(&req.config.project)?;
+    req.config.resource = None;
+
+    // Reject duplicates, bad configurations and exhausted quotas before
+    // starting any work
+    state
+        .rollup_manager
+        .validate_new_rollup(&req.config)
+        .await?;
+
+    let manager = state.rollup_manager.clone();
+    let monitoring = state.monitoring.clone();
+    let config = req.config;
+    let target = config.name.clone();
+    let project = config.project.clone();
+    let name = config.name.clone();
+
+    let operation = state
+        .operations
+        .start(
+            "create_rollup",
+            &target,
+            &project,
+            &principal.subject,
+            move |operation| async move {
+                operation.record_change(None, audit_value(&config)).await;
+                operation.step(10, "Applying rollup manifest").await?;
+                manager.create_rollup(config).await?;
+                monitoring.record_rollup_created();
+                operation
+                    .step(
+                        90,
+                        "Rollup resources applied; readiness is reported on the rollup status",
+                    )
+                    .await?;
+                Ok(serde_json::to_value(
+                    manager.get_rollup_status(&name).await?,
+                )?)
+            },
+        )
+        .await?;
+
+    Ok(accepted(operation))
 }
 
 async fn get_rollup(
     State(state): State<Arc<AppState>>,
+    principal: Principal,
     Path(id): Path<String>,
-) -> impl IntoResponse {
-    match state.rollup_manager.get_rollup_status(&id).await {
-        Ok(Some(status)) => Json(status).into_response(),
-        Ok(None) => StatusCode::NOT_FOUND.into_response(),
-        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+) -> Result<Response, ApiError> {
+    principal.require(Role::Viewer)?;
+    let status = visible_rollup(&state, &principal, &id).await?;
+    Ok(Json(status).into_response())
+}
+
+async fn get_rollup_history(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path(id): Path<String>,
+) -> Result<Response, ApiError> {
+    principal.require(Role::Viewer)?;
+    visible_rollup(&state, &principal, &id).await?;
+    let history = state.rollup_manager.get_rollup_history(&id).await?;
+    Ok(Json(history).into_response())
+}
+
+async fn replace_rollup(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path(id): Path<String>,
+    ApiJson(req): ApiJson<CreateRollupRequest>,
+) -> Result<Response, ApiError> {
+    principal.require(Role::Operator)?;
+    let mut config = req.config;
+    config.name = id;
+    update_rollup(state, principal, config).await
+}
+
+/// Applies a JSON merge patch (RFC 7396) to the stored rollup configuration.
+async fn patch_rollup(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path(id): Path<String>,
+    ApiJson(patch): ApiJson<serde_json::Value>,
+) -> Result<Response, ApiError> {
+    principal.require(Role::Operator)?;
+    visible_rollup(&state, &principal, &id).await?;
+    let existing = state
+        .rollup_manager
+        .get_rollup_config(&id)
+        .await?
+        .ok_or_else(|| rollup::Error::NotFound(id.clone()))?;
+
+    let mut config = merge_patch(&existing, &patch).map_err(invalid_patch)?;
+    config.name = id;
+    update_rollup(state, principal, config).await
+}
+
+/// Applies `config` as a change made through the API, which the manager
+/// refuses for rollups managed by a `Rollup` resource.
+async fn update_rollup(
+    state: Arc<AppState>,
+    principal: Principal,
+    mut config: RollupConfig,
+) -> Result<Response, ApiError> {
+    let current = visible_rollup(&state, &principal, &config.name).await?;
+    config.resource = None;
+    if let Some(existing) = state.rollup_manager.get_rollup_config(&config.name).await? {
+        existing.check_manager(&config)?;
+        existing.check_deployment_type(&config)?;
     }
+
+    let manager = state.rollup_manager.clone();
+    let target = config.name.clone();
+    let name = config.name.clone();
+
+    let operation = state
+        .operations
+        .start(
+            "update_rollup",
+            &target,
+            &current.project,
+            &principal.subject,
+            move |operation| async move {
+                let before = manager.get_rollup_config(&name).await?;
+                operation
+                    .record_change(before.as_ref().and_then(audit_value), audit_value(&config))
+                    .await;
+                operation
+                    .step(10, "Applying updated rollup manifest")
+                    .await?;
+                manager.apply_rollup(config).await?;
+                Ok(serde_json::to_value(
+                    manager.get_rollup_status(&name).await?,
+                )?)
+            },
+        )
+        .await?;
+
+    Ok(accepted(operation))
+}
+
+#[derive(Debug, Deserialize)]
+struct DeleteRollupParams {
+    #[serde(default)]
+    purge_volumes: bool,
+    wait_secs: Option<u64>,
 }
 
 async fn delete_rollup(
     State(state): State<Arc<AppState>>,
+    principal: Principal,
     Path(id): Path<String>,
-) -> impl IntoResponse {
-    match state.rollup_manager.delete_rollup(&id).await {
-        Ok(_) => StatusCode::OK.into_response(),
-        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
-    }
+    Query(params): Query<DeleteRollupParams>,
+) -> Result<Response, ApiError> {
+    principal.require(Role::Operator)?;
+    let current = visible_rollup(&state, &principal, &id).await?;
+
+    let options = TeardownOptions {
+        include_volumes: params.purge_volumes,
+        wait_timeout: params.wait_secs.map(Duration::from_secs),
+    };
+    let manager = state.rollup_manager.clone();
+    let monitoring = state.monitoring.clone();
+    let name = id.clone();
+
+    let operation = state
+        .operations
+        .start(
+            "delete_rollup",
+            &id,
+            &current.project,
+            &principal.subject,
+            move |operation| async move {
+                let before = manager.get_rollup_config(&name).await?;
+                operation
+                    .record_change(before.as_ref().and_then(audit_value), None)
+                    .await;
+                operation.step(10, "Deleting rollup resources").await?;
+                manager.delete_rollup(&name, &options).await?;
+                monitoring.record_rollup_deleted();
+                monitoring.forget_rollup(&name);
+                Ok(serde_json::json!({ "deleted": name }))
+            },
+        )
+        .await?;
+
+    Ok(accepted(operation))
+}
+
+async fn list_clusters(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Query(filter): Query<ProjectFilter>,
+) -> Result<Response, ApiError> {
+    principal.require(Role::Viewer)?;
+    let clusters: Vec<_> = state
+        .infra_controller
+        .list_clusters()
+        .await?
+        .into_iter()
+        .filter(|status| filter.matches(&principal, &status.project))
+        .collect();
+    Ok(Json(clusters).into_response())
 }
 
 #[derive(Debug, Deserialize)]
 struct CreateClusterRequest {
     name: String,
+    #[serde(default = "default_project")]
+    project: String,
+    #[serde(default = "default_account")]
+    account: String,
     region: String,
     node_count: i32,
     node_type: String,
     kubernetes_version: String,
+    #[serde(default)]
+    tags: HashMap<String, String>,
+    #[serde(default)]
+    node_pools: Vec<NodePool>,
 }
 
 async fn create_cluster(
     State(state): State<Arc<AppState>>,
-    Json(req): Json<CreateClusterRequest>,
-) -> impl IntoResponse {
-    let config = crate::infra::ClusterConfig {
+    principal: Principal,
+    ApiJson(req): ApiJson<CreateClusterRequest>,
+) -> Result<Response, ApiError> {
+    principal.require(Role::Admin)?;
+    principal.require_project(&req.project)?;
+
+    let config = ClusterConfig {
         name: req.name,
+        project: req.project,
+        account: req.account,
         region: req.region,
         node_count: req.node_count,
         node_type: req.node_type,
         kubernetes_version: req.kubernetes_version,
-        tags: std::collections::HashMap::new(),
+        tags: req.tags,
+        node_pools: req.node_pools,
     };
+    state.infra_controller.validate_new_cluster(&config).await?;
+    state
+        .projects
+        .check_quota(&config.project, QuotaResource::Clusters)
+        .await?;
 
-    match state.infra_controller.create_cluster(config).await {
-        Ok(_) => StatusCode::CREATED.into_response(),
-        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
-    }
+    let controller = state.infra_controller.clone();
+    let monitoring = state.monitoring.clone();
+    let target = config.name.clone();
+    let project = config.project.clone();
+    let name = config.name.clone();
+
+    let operation = state
+        .operations
+        .start(
+            "create_cluster",
+            &target,
+            &project,
+            &principal.subject,
+            move |operation| async move {
+                operation.record_change(None, audit_value(&config)).await;
+                operation
+                    .step(10, "Requesting cluster from the cloud provider")
+                    .await?;
+                controller.create_cluster(config).await?;
+                monitoring.record_cluster_created();
+                operation
+                    .step(80, "Cluster requested; fetching provider status")
+                    .await?;
+                Ok(serde_json::to_value(
+                    controller.get_cluster_status(&name).await?,
+                )?)
+            },
+        )
+        .await?;
+
+    Ok(accepted(operation))
 }
 
 async fn get_cluster(
     State(state): State<Arc<AppState>>,
+    principal: Principal,
     Path(id): Path<String>,
-) -> impl IntoResponse {
-    match state.infra_controller.get_cluster_status(&id).await {
-        Ok(status) => Json(status).into_response(),
-        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
-    }
+) -> Result<Response, ApiError> {
+    principal.require(Role::Viewer)?;
+    visible_cluster(&state, &principal, &id).await?;
+    let status = state.infra_controller.get_cluster_status(&id).await?;
+    Ok(Json(status).into_response())
+}
+
+async fn get_cluster_history(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path(id): Path<String>,
+) -> Result<Response, ApiError> {
+    principal.require(Role::Viewer)?;
+    visible_cluster(&state, &principal, &id).await?;
+    let history = state.infra_controller.get_cluster_history(&id).await?;
+    Ok(Json(history).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+struct ReplaceClusterRequest {
+    region: String,
+    node_count: i32,
+    node_type: String,
+    kubernetes_version: String,
+    #[serde(default)]
+    tags: HashMap<String, String>,
+}
+
+async fn replace_cluster(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path(id): Path<String>,
+    ApiJson(req): ApiJson<ReplaceClusterRequest>,
+) -> Result<Response, ApiError> {
+    principal.require(Role::Admin)?;
+    let existing = visible_cluster(&state, &principal, &id).await?;
+
+    let config = ClusterConfig {
+        name: id,
+        project: existing.project,
+        account: existing.account,
+        region: req.region,
+        node_count: req.node_count,
+        node_type: req.node_type,
+        kubernetes_version: req.kubernetes_version,
+        tags: req.tags,
+        node_pools: existing.node_pools,
+    };
+    update_cluster(state, principal, config).await
+}
+
+async fn patch_cluster(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path(id): Path<String>,
+    ApiJson(patch): ApiJson<serde_json::Value>,
+) -> Result<Response, ApiError> {
+    principal.require(Role::Admin)?;
+    let existing = visible_cluster(&state, &principal, &id).await?;
+
+    let mut config = merge_patch(&existing, &patch).map_err(invalid_patch)?;
+    config.name = id;
+    update_cluster(state, principal, config).await
+}
+
+async fn update_cluster(
+    state: Arc<AppState>,
+    principal: Principal,
+    config: ClusterConfig,
+) -> Result<Response, ApiError> {
+    let current = visible_cluster(&state, &principal, &config.name).await?;
+
+    let controller = state.infra_controller.clone();
+    let target = config.name.clone();
+    let name = config.name.clone();
+
+    let operation = state
+        .operations
+        .start(
+            "update_cluster",
+            &target,
+            &current.project,
+            &principal.subject,
+            move |operation| async move {
+                let before = controller.get_cluster_config(&name).await?;
+                operation
+                    .record_change(before.as_ref().and_then(audit_value), audit_value(&config))
+                    .await;
+                operation.step(10, "Updating cluster configuration").await?;
+                controller.update_cluster(config).await?;
+                Ok(serde_json::to_value(
+                    controller.get_cluster_status(&name).await?,
+                )?)
+            },
+        )
+        .await?;
+
+    Ok(accepted(operation))
 }
 
 async fn delete_cluster(
     State(state): State<Arc<AppState>>,
+    principal: Principal,
     Path(id): Path<String>,
-) -> impl IntoResponse {
-    match state.infra_controller.delete_cluster(&id).await {
-        Ok(_) => StatusCode::OK.into_response(),
-        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+) -> Result<Response, ApiError> {
+    principal.require(Role::Admin)?;
+    let current = visible_cluster(&state, &principal, &id).await?;
+    state.infra_controller.ensure_unused(&id).await?;
+
+    let controller = state.infra_controller.clone();
+    let monitoring = state.monitoring.clone();
+    let name = id.clone();
+
+    let operation = state
+        .operations
+        .start(
+            "delete_cluster",
+            &id,
+            &current.project,
+            &principal.subject,
+            move |operation| async move {
+                let before = controller.get_cluster_config(&name).await?;
+                operation
+                    .record_change(before.as_ref().and_then(audit_value), None)
+                    .await;
+                operation
+                    .step(10, "Deleting cluster through the cloud provider")
+                    .await?;
+                controller.delete_cluster(&name).await?;
+                monitoring.record_cluster_deleted();
+                Ok(serde_json::json!({ "deleted": name }))
+            },
+        )
+        .await?;
+
+    Ok(accepted(operation))
+}
+
+/// Returns an admin kubeconfig for the cluster.
+async fn get_cluster_kubeconfig(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path(id): Path<String>,
+) -> Result<Response, ApiError> {
+    principal.require(Role::Admin)?;
+    visible_cluster(&state, &principal, &id).await?;
+    let kubeconfig = state.infra_controller.get_cluster_kubeconfig(&id).await?;
+    Ok(([(header::CONTENT_TYPE, "application/yaml")], kubeconfig).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+struct ScaleClusterRequest {
+    node_count: i32,
+}
+
+async fn scale_cluster(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path(id): Path<String>,
+    ApiJson(req): ApiJson<ScaleClusterRequest>,
+) -> Result<Response, ApiError> {
+    principal.require(Role::Admin)?;
+    let existing = visible_cluster(&state, &principal, &id).await?;
+
+    let config = ClusterConfig {
+        node_count: req.node_count,
+        ..existing
+    };
+    update_cluster(state, principal, config).await
+}
+
+async fn list_node_pools(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path(id): Path<String>,
+) -> Result<Response, ApiError> {
+    principal.require(Role::Viewer)?;
+    let config = visible_cluster(&state, &principal, &id).await?;
+    Ok(Json(config.node_pools).into_response())
+}
+
+async fn add_node_pool(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path(id): Path<String>,
+    ApiJson(pool): ApiJson<NodePool>,
+) -> Result<Response, ApiError> {
+    principal.require(Role::Admin)?;
+    let current = visible_cluster(&state, &principal, &id).await?;
+    if current.node_pools.iter().any(|p| p.name == pool.name) {
+        return Err(infra::Error::NodePoolAlreadyExists {
+            cluster: id,
+            pool: pool.name,
+        }
+        .into());
     }
-} 
\ No newline at end of file
+    pool.validate()?;
+
+    let controller = state.infra_controller.clone();
+    let target = format!("{}/{}", id, pool.name);
+
+    let operation = state
+        .operations
+        .start(
+            "add_node_pool",
+            &target,
+            &current.project,
+            &principal.subject,
+            move |operation| async move {
+                operation.record_change(None, audit_value(&pool)).await;
+                operation
+                    .step(10, "Creating node pool through the cloud provider")
+                    .await?;
+                controller.add_node_pool(&id, pool.clone()).await?;
+                Ok(serde_json::to_value(pool)?)
+            },
+        )
+        .await?;
+
+    Ok(accepted(operation))
+}
+
+async fn resize_node_pool(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path((id, pool)): Path<(String, String)>,
+    ApiJson(size): ApiJson<NodePoolSize>,
+) -> Result<Response, ApiError> {
+    principal.require(Role::Admin)?;
+    let current = visible_cluster(&state, &principal, &id).await?;
+    let existing = current
+        .node_pools
+        .iter()
+        .find(|p| p.name == pool)
+        .cloned()
+        .ok_or_else(|| infra::Error::NodePoolNotFound {
+            cluster: id.clone(),
+            pool: pool.clone(),
+        })?;
+    existing.resized(&size).validate()?;
+
+    let controller = state.infra_controller.clone();
+    let target = format!("{}/{}", id, pool);
+
+    let operation = state
+        .operations
+        .start(
+            "resize_node_pool",
+            &target,
+            &current.project,
+            &principal.subject,
+            move |operation| async move {
+                operation
+                    .record_change(
+                        audit_value(&existing),
+                        audit_value(&existing.resized(&size)),
+                    )
+                    .await;
+                operation
+                    .step(10, "Resizing node pool through the cloud provider")
+                    .await?;
+                let resized = controller.resize_node_pool(&id, &pool, &size).await?;
+                Ok(serde_json::to_value(resized)?)
+            },
+        )
+        .await?;
+
+    Ok(accepted(operation))
+}
+
+async fn remove_node_pool(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path((id, pool)): Path<(String, String)>,
+) -> Result<Response, ApiError> {
+    principal.require(Role::Admin)?;
+    let current = visible_cluster(&state, &principal, &id).await?;
+    let existing = current
+        .node_pools
+        .iter()
+        .find(|p| p.name == pool)
+        .cloned()
+        .ok_or_else(|| infra::Error::NodePoolNotFound {
+            cluster: id.clone(),
+            pool: pool.clone(),
+        })?;
+
+    let controller = state.infra_controller.clone();
+    let target = format!("{}/{}", id, pool);
+
+    let operation = state
+        .operations
+        .start(
+            "remove_node_pool",
+            &target,
+            &current.project,
+            &principal.subject,
+            move |operation| async move {
+                operation.record_change(audit_value(&existing), None).await;
+                operation
+                    .step(10, "Deleting node pool through the cloud provider")
+                    .await?;
+                controller.remove_node_pool(&id, &pool).await?;
+                Ok(serde_json::json!({ "deleted": pool }))
+            },
+        )
+        .await?;
+
+    Ok(accepted(operation))
+}
+
+/// Operations recorded before projects existed belong to the default project.
+fn operation_project(operation: &Operation) -> &str {
+    operation.project.as_deref().unwrap_or(DEFAULT_PROJECT)
+}
+
+async fn list_operations(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Query(filter): Query<ProjectFilter>,
+) -> Result<Response, ApiError> {
+    principal.require(Role::Viewer)?;
+    // Projects are filtered by the store, so the limit counts only operations
+    // the principal can see
+    let projects = match filter.project {
+        Some(project) if principal.can_access(&project) => Some(vec![project]),
+        Some(_) => return Ok(Json(Vec::<Operation>::new()).into_response()),
+        None => principal.project_scope(),
+    };
+    let operations = state
+        .operations
+        .list(projects.as_deref(), OPERATION_LIST_LIMIT)
+        .await?;
+    Ok(Json(operations).into_response())
+}
+
+async fn get_operation(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Path(id): Path<Uuid>,
+) -> Result<Response, ApiError> {
+    principal.require(Role::Viewer)?;
+    let operation = state
+        .operations
+        .get(id)
+        .await?
+        .filter(|operation| principal.can_access(operation_project(operation)))
+        .ok_or_else(|| ApiError::not_found("operation", &id.to_string()))?;
+    Ok(Json(operation).into_response())
+}
+
+async fn query_audit(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Query(mut filter): Query<AuditFilter>,
+) -> Result<Response, ApiError> {
+    principal.require(Role::Admin)?;
+    filter.projects = principal.project_scope();
+
+    let entries = state.audit.query(&filter).await?;
+    Ok(Json(entries).into_response())
+}
+
+/// Exports matching audit entries as JSON lines, newest first.
+async fn export_audit(
+    State(state): State<Arc<AppState>>,
+    principal: Principal,
+    Query(mut filter): Query<AuditFilter>,
+) -> Result<Response, ApiError> {
+    principal.require(Role::Admin)?;
+    filter.projects = principal.project_scope();
+    filter.limit.get_or_insert(MAX_LIMIT);
+
+    let mut body = String::new();
+    for entry in state.audit.query(&filter).await? {
+        body.push_str(&serde_json::to_string(&entry).map_err(anyhow::Error::from)?);
+        body.push('\n');
+    }
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/x-ndjson"),
+            (
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"audit.jsonl\"",
+            ),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+/// Configuration snapshot recorded in the audit log.
+fn audit_value<T: Serialize>(value: &T) -> Option<serde_json::Value> {
+    serde_json::to_value(value).ok()
+}
+
+fn invalid_patch(e: serde_json::Error) -> ApiError {
+    ApiError::new(
+        StatusCode::UNPROCESSABLE_ENTITY,
+        "invalid_patch",
+        format!("patched configuration is invalid: {}", e),
+    )
+}
+
+/// Applies a JSON merge patch to a serializable value.
+fn merge_patch<T>(target: &T, patch: &serde_json::Value) -> serde_json::Result<T>
+where
+    T: Serialize + serde::de::DeserializeOwned,
+{
+    let mut value = serde_json::to_value(target)?;
+    merge(&mut value, patch);
+    serde_json::from_value(value)
+}
+
+fn merge(target: &mut serde_json::Value, patch: &serde_json::Value) {
+    let serde_json::Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+
+    if !target.is_object() {
+        *target = serde_json::Value::Object(serde_json::Map::new());
+    }
+    if let serde_json::Value::Object(target) = target {
+        for (key, value) in patch {
+            if value.is_null() {
+                target.remove(key);
+            } else {
+                merge(
+                    target.entry(key.clone()).or_insert(serde_json::Value::Null),
+                    value,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{Method, StatusCode};
+    use serde_json::json;
+    use sha2::{Digest, Sha256};
+
+    use crate::{
+        audit::AuditFilter,
+        auth::Role,
+        config::ApiToken,
+        infra::TeardownOptions,
+        operations::OperationState,
+        rollup::ChainMetrics,
+        testing::{cluster_config, rollup_config, test_config, Fault, Harness},
+    };
+
+    #[tokio::test]
+    async fn rollups_are_created_through_operations() {
+        let harness = Harness::new().await;
+        let body = json!({ "config": rollup_config("r1") });
+
+        let (status, accepted) = harness
+            .request(Method::POST, "/api/v1/rollups", Some(body), None)
+            .await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        let operation = harness.finish(&accepted).await;
+        assert_eq!(operation.state, OperationState::Succeeded);
+        assert_eq!(operation.result.unwrap()["state"], "Creating");
+
+        let (status, rollup) = harness
+            .request(Method::GET, "/api/v1/rollups/r1", None, None)
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(rollup["project"], "default");
+    }
+
+    #[tokio::test]
+    async fn duplicate_rollups_are_rejected_before_any_work_starts() {
+        let harness = Harness::new().await;
+        harness
+            .manager
+            .create_rollup(rollup_config("r1"))
+            .await
+            .unwrap();
+
+        let body = json!({ "config": rollup_config("r1") });
+        let (status, problem) = harness
+            .request(Method::POST, "/api/v1/rollups", Some(body), None)
+            .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(problem["code"], "rollup_already_exists");
+        assert!(problem["request_id"].is_string());
+        assert!(harness.state.operations.list(None, 10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn internal_errors_do_not_leak_their_cause() {
+        let harness = Harness::new().await;
+        harness.store.faults.inject(
+            "list_rollups",
+            Fault::Error("cannot reach db.internal:5432".to_string()),
+        );
+
+        let (status, problem) = harness
+            .request(Method::GET, "/api/v1/rollups", None, None)
+            .await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(problem["code"], "internal");
+        assert_eq!(problem["message"], "Internal Server Error");
+        assert!(problem["request_id"].is_string());
+        assert!(!problem.to_string().contains("db.internal"));
+    }
+
+    #[tokio::test]
+    async fn malformed_bodies_are_unprocessable() {
+        let harness = Harness::new().await;
+        let (status, problem) = harness
+            .request(
+                Method::POST,
+                "/api/v1/clusters",
+                Some(json!({ "name": "c1" })),
+                None,
+            )
+            .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(problem["code"], "invalid_body");
+    }
+
+    #[tokio::test]
+    async fn provider_failures_fail_the_operation_and_are_audited() {
+        let harness = Harness::new().await;
+        harness.provider.faults.inject(
+            "create_cluster",
+            Fault::Error("quota exhausted".to_string()),
+        );
+
+        let body = serde_json::to_value(cluster_config("c1")).unwrap();
+        let (status, accepted) = harness
+            .request(Method::POST, "/api/v1/clusters", Some(body), None)
+            .await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        let operation = harness.finish(&accepted).await;
+        assert_eq!(operation.state, OperationState::Failed);
+        assert!(operation.error.unwrap().contains("quota exhausted"));
+
+        // The audit entry is written just after the operation is marked failed
+        let mut entries = Vec::new();
+        for _ in 0..100 {
+            entries = harness
+                .state
+                .audit
+                .query(&AuditFilter::default())
+                .await
+                .unwrap();
+            if !entries.is_empty() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].event.action, "create_cluster");
+        assert_eq!(entries[0].event.operation_id, Some(operation.id));
+        assert!(entries[0].event.error.is_some());
+    }
+
+    #[tokio::test]
+    async fn clusters_running_rollups_cannot_be_deleted() {
+        let harness = Harness::new().await;
+        harness
+            .controller
+            .create_cluster(cluster_config("c1"))
+            .await
+            .unwrap();
+        let mut config = rollup_config("r1");
+        config.placement.cluster = Some("c1".to_string());
+        harness.manager.create_rollup(config).await.unwrap();
+
+        let (status, problem) = harness
+            .request(Method::DELETE, "/api/v1/clusters/c1", None, None)
+            .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(problem["code"], "cluster_in_use");
+        assert_eq!(problem["details"]["rollups"], json!(["r1"]));
+        assert!(harness.provider.cluster("c1").is_some());
+
+        harness
+            .manager
+            .delete_rollup("r1", &TeardownOptions::default())
+            .await
+            .unwrap();
+        let (status, accepted) = harness
+            .request(Method::DELETE, "/api/v1/clusters/c1", None, None)
+            .await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        let operation = harness.finish(&accepted).await;
+        assert_eq!(operation.state, OperationState::Succeeded);
+        assert!(harness.provider.cluster("c1").is_none());
+    }
+
+    #[tokio::test]
+    async fn rollups_managed_by_resources_cannot_be_changed_through_the_api() {
+        let harness = Harness::new().await;
+        let mut config = rollup_config("r1");
+        config.resource = Some("default/r1".to_string());
+        harness.manager.create_rollup(config).await.unwrap();
+
+        let patch = json!({ "l2_rpc_url": "http://127.0.0.1:2", "resource": "default/r1" });
+        let (status, problem) = harness
+            .request(Method::PATCH, "/api/v1/rollups/r1", Some(patch), None)
+            .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(problem["code"], "rollup_managed_elsewhere");
+        assert_eq!(problem["details"]["manager"], "Rollup resource default/r1");
+
+        let stored = harness
+            .manager
+            .get_rollup_config("r1")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.l2_rpc_url, "http://127.0.0.1:1");
+    }
+
+    #[tokio::test]
+    async fn rollup_deployment_types_cannot_be_patched() {
+        let harness = Harness::new().await;
+        harness
+            .manager
+            .create_rollup(rollup_config("r1"))
+            .await
+            .unwrap();
+
+        let patch = json!({ "deployment_type": "ZkRollup" });
+        let (status, problem) = harness
+            .request(Method::PATCH, "/api/v1/rollups/r1", Some(patch), None)
+            .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(problem["code"], "invalid_rollup_config");
+    }
+
+    #[tokio::test]
+    async fn node_pools_are_managed_through_cluster_routes() {
+        let harness = Harness::new().await;
+        harness
+            .controller
+            .create_cluster(cluster_config("c1"))
+            .await
+            .unwrap();
+
+        let pool = json!({
+            "name": "sequencers",
+            "machine_type": "highcpu-16",
+            "node_count": 2,
+            "min_nodes": 1,
+            "max_nodes": 3,
+            "taints": [{ "key": "dedicated", "value": "sequencer", "effect": "NoSchedule" }],
+        });
+        let (status, accepted) = harness
+            .request(
+                Method::POST,
+                "/api/v1/clusters/c1/node-pools",
+                Some(pool),
+                None,
+            )
+            .await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        assert_eq!(
+            harness.finish(&accepted).await.state,
+            OperationState::Succeeded
+        );
+
+        let size = json!({ "min_nodes": 1, "max_nodes": 1 });
+        let (status, problem) = harness
+            .request(
+                Method::PATCH,
+                "/api/v1/clusters/c1/node-pools/missing",
+                Some(size.clone()),
+                None,
+            )
+            .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(problem["code"], "node_pool_not_found");
+
+        let (_, accepted) = harness
+            .request(
+                Method::PATCH,
+                "/api/v1/clusters/c1/node-pools/sequencers",
+                Some(size),
+                None,
+            )
+            .await;
+        assert_eq!(
+            harness.finish(&accepted).await.state,
+            OperationState::Succeeded
+        );
+
+        let (status, pools) = harness
+            .request(Method::GET, "/api/v1/clusters/c1/node-pools", None, None)
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(pools[0]["node_count"], 1);
+        assert_eq!(
+            harness.provider.cluster("c1").unwrap().node_pools[0].node_count,
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn roles_and_credentials_are_enforced() {
+        let mut config = test_config();
+        config.auth.enabled = true;
+        config.auth.tokens.push(ApiToken {
+            name: "ci".to_string(),
+            role: Role::Viewer,
+            token_sha256: hex::encode(Sha256::digest(b"viewer-token")),
+            projects: vec!["*".to_string()],
+        });
+        let harness = Harness::with_config(config).await;
+
+        let (status, _) = harness
+            .request(Method::GET, "/api/v1/rollups", None, None)
+            .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let (status, _) = harness
+            .request(Method::GET, "/api/v1/rollups", None, Some("viewer-token"))
+            .await;
+        assert_eq!(status, StatusCode::OK);
+
+        let body = json!({ "config": rollup_config("r1") });
+        let (status, problem) = harness
+            .request(
+                Method::POST,
+                "/api/v1/rollups",
+                Some(body),
+                Some("viewer-token"),
+            )
+            .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(problem["code"], "forbidden");
+
+        // Health checks stay reachable without credentials
+        let (status, _) = harness.request(Method::GET, "/health", None, None).await;
+        assert_eq!(status, StatusCode::OK);
+
+        // Metrics name rollups, so scraping them needs credentials
+        let (status, _) = harness.request(Method::GET, "/metrics", None, None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = harness
+            .request_text(Method::GET, "/metrics", None, Some("viewer-token"))
+            .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn tokens_without_projects_can_access_none() {
+        let mut config = test_config();
+        config.auth.enabled = true;
+        let token: ApiToken = serde_json::from_value(json!({
+            "name": "ci",
+            "role": "operator",
+            "token_sha256": hex::encode(Sha256::digest(b"operator-token")),
+        }))
+        .unwrap();
+        assert!(token.projects.is_empty());
+        config.auth.tokens.push(token);
+        let harness = Harness::with_config(config).await;
+
+        // Metrics span every project
+        let (status, _) = harness
+            .request_text(Method::GET, "/metrics", None, Some("operator-token"))
+            .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let body = json!({ "config": rollup_config("r1") });
+        let (status, _) = harness
+            .request(
+                Method::POST,
+                "/api/v1/rollups",
+                Some(body),
+                Some("operator-token"),
+            )
+            .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let (status, rollups) = harness
+            .request(Method::GET, "/api/v1/rollups", None, Some("operator-token"))
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(rollups, json!([]));
+    }
+
+    #[tokio::test]
+    async fn metrics_are_exposed_in_the_prometheus_format() {
+        let harness = Harness::new().await;
+        let body = json!({ "config": rollup_config("r1") });
+        let (_, accepted) = harness
+            .request(Method::POST, "/api/v1/rollups", Some(body), None)
+            .await;
+        harness.finish(&accepted).await;
+        harness.reconciler().reconcile_all().await.unwrap();
+
+        let (status, text) = harness
+            .request_text(Method::GET, "/metrics", None, None)
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(text.contains("# HELP galato_rollups Rollups managed by galato, by state"));
+        assert!(text.contains("# TYPE galato_rollups gauge"));
+        assert!(text.contains("# TYPE galato_rollups_created_total counter"));
+        assert!(text
+            .lines()
+            .any(|line| line.starts_with("galato_rollups{state=\"Creating\"}")));
+    }
+
+    #[tokio::test]
+    async fn requests_are_measured_by_route_template() {
+        let harness = Harness::new().await;
+        let (status, _) = harness
+            .request(Method::GET, "/api/v1/rollups/missing-rollup", None, None)
+            .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        harness
+            .request(Method::GET, "/no/such/path", None, None)
+            .await;
+
+        let (_, text) = harness
+            .request_text(Method::GET, "/metrics", None, None)
+            .await;
+        let series = |name: &str, labels: &str| {
+            text.lines()
+                .any(|line| line.starts_with(name) && line.contains(labels))
+        };
+        assert!(series(
+            "galato_request_latency_seconds_count",
+            "route=\"/api/v1/rollups/:id\",method=\"GET\",status=\"404\""
+        ));
+        assert!(series(
+            "galato_errors_total",
+            "route=\"/api/v1/rollups/:id\""
+        ));
+        assert!(series("galato_errors_total", "route=\"unmatched\""));
+        assert!(!text.contains("missing-rollup"));
+        assert!(!text.contains("/no/such/path"));
+    }
+
+    #[tokio::test]
+    async fn rollup_chain_metrics_are_labelled_by_rollup_and_chain() {
+        let harness = Harness::new().await;
+        let chain = ChainMetrics {
+            head: 120,
+            safe: Some(100),
+            finalized: None,
+            tps: 2.5,
+            ..Default::default()
+        };
+        harness
+            .state
+            .monitoring
+            .observe_rollup("chain-metrics", 4242, &chain);
+
+        let (_, text) = harness
+            .request_text(Method::GET, "/metrics", None, None)
+            .await;
+        let labels = "rollup=\"chain-metrics\",chain_id=\"4242\"";
+        assert!(text.contains(&format!(
+            "galato_rollup_block_height{{{},block=\"safe\"}} 100",
+            labels
+        )));
+        // A node without finalized blocks publishes no finalized height
+        assert!(!text.contains(&format!("{},block=\"finalized\"", labels)));
+        assert!(text.contains(&format!("galato_rollup_tps{{{}}} 2.5", labels)));
+        assert!(text.contains("# TYPE galato_rollup_l1_submission_lag_seconds gauge"));
+    }
+
+    #[tokio::test]
+    async fn deleted_rollups_stop_exporting_chain_metrics() {
+        let harness = Harness::new().await;
+        let body = json!({ "config": rollup_config("r1") });
+        let (_, accepted) = harness
+            .request(Method::POST, "/api/v1/rollups", Some(body), None)
+            .await;
+        harness.finish(&accepted).await;
+        let monitoring = &harness.state.monitoring;
+        monitoring.observe_rollup("r1", 4242, &ChainMetrics::default());
+        // Deleted without going through this instance
+        monitoring.observe_rollup("elsewhere", 4243, &ChainMetrics::default());
+
+        harness.reconciler().reconcile_all().await.unwrap();
+        let (_, text) = harness
+            .request_text(Method::GET, "/metrics", None, None)
+            .await;
+        assert!(text.contains("rollup=\"r1\""));
+        assert!(!text.contains("rollup=\"elsewhere\""));
+
+        let (status, accepted) = harness
+            .request(Method::DELETE, "/api/v1/rollups/r1", None, None)
+            .await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        harness.finish(&accepted).await;
+        let (_, text) = harness
+            .request_text(Method::GET, "/metrics", None, None)
+            .await;
+        assert!(!text.contains("galato_rollup_tps"));
+    }
+}
@@ -0,0 +1,125 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::store::Store;
+
+/// Entries returned by a query when no limit is given.
+const DEFAULT_LIMIT: i64 = 100;
+/// Upper bound on entries returned by a single query or export.
+pub const MAX_LIMIT: i64 = 100_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AuditOutcome {
+    Succeeded,
+    Failed,
+}
+
+impl AuditOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditOutcome::Succeeded => "Succeeded",
+            AuditOutcome::Failed => "Failed",
+        }
+    }
+
+    pub fn parse(outcome: &str) -> Result<Self> {
+        match outcome {
+            "Succeeded" => Ok(AuditOutcome::Succeeded),
+            "Failed" => Ok(AuditOutcome::Failed),
+            other => anyhow::bail!("unknown audit outcome {}", other),
+        }
+    }
+}
+
+/// A mutation to be recorded in the audit log.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEvent {
+    /// Principal that requested the change
+    pub actor: String,
+    /// What was done, e.g. `delete_rollup`
+    pub action: String,
+    /// Name of the rollup, cluster or project changed
+    pub target: String,
+    pub project: Option<String>,
+    /// Configuration before the change; absent for creations
+    pub before: Option<serde_json::Value>,
+    /// Requested configuration; absent for deletions
+    pub after: Option<serde_json::Value>,
+    pub outcome: AuditOutcome,
+    pub error: Option<String>,
+    /// Operation that carried out the change, when run through the API
+    pub operation_id: Option<Uuid>,
+}
+
+/// A recorded audit event.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub recorded_at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub event: AuditEvent,
+}
+
+/// Narrows an audit query. Every field is optional.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AuditFilter {
+    pub actor: Option<String>,
+    pub action: Option<String>,
+    pub target: Option<String>,
+    pub project: Option<String>,
+    pub outcome: Option<AuditOutcome>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub limit: Option<i64>,
+    /// Restricts results to these projects; set from the caller, not the query
+    #[serde(skip)]
+    pub projects: Option<Vec<String>>,
+}
+
+impl AuditFilter {
+    pub fn limit(&self) -> i64 {
+        self.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+    }
+}
+
+/// Append-only trail of who changed what.
+#[derive(Clone)]
+pub struct AuditLog {
+    store: Arc<dyn Store>,
+}
+
+impl AuditLog {
+    pub fn new(store: Arc<dyn Store>) -> Self {
+        Self { store }
+    }
+
+    /// Records an event. Callers decide how the audited work reacts when
+    /// the entry cannot be stored.
+    pub async fn record(&self, event: AuditEvent) -> Result<()> {
+        tracing::info!(
+            actor = %event.actor,
+            action = %event.action,
+            target = %event.target,
+            outcome = event.outcome.as_str(),
+            "Audit"
+        );
+        self.store.append_audit(&event).await.map_err(|e| {
+            tracing::error!(
+                "Failed to record audit event {} of {} by {}: {:#}",
+                event.action,
+                event.target,
+                event.actor,
+                e
+            );
+            e
+        })
+    }
+
+    /// Returns matching entries, newest first.
+    pub async fn query(&self, filter: &AuditFilter) -> Result<Vec<AuditEntry>> {
+        self.store.query_audit(filter).await
+    }
+}
@@ -0,0 +1,211 @@
+use anyhow::{Context, Result};
+use jsonwebtoken::{
+    decode, decode_header,
+    jwk::{Jwk, JwkSet},
+    Algorithm, DecodingKey, Validation,
+};
+use serde::Deserialize;
+use std::{collections::HashMap, str::FromStr};
+
+use super::{Principal, Role};
+use crate::config::JwtConfig;
+
+#[derive(Debug, Deserialize)]
+struct Claims {
+    sub: String,
+    #[serde(flatten)]
+    extra: HashMap<String, serde_json::Value>,
+}
+
+/// Verifies JWTs against a JSON Web Key Set read at startup.
+pub struct JwtVerifier {
+    keys: JwkSet,
+    algorithm: Option<Algorithm>,
+    issuer: Option<String>,
+    audience: Option<String>,
+    role_claim: String,
+    projects_claim: String,
+}
+
+impl JwtVerifier {
+    pub fn new(config: &JwtConfig) -> Result<Self> {
+        let jwks = std::fs::read_to_string(&config.jwks_path)
+            .with_context(|| format!("failed to read JWT key set {}", config.jwks_path))?;
+        let keys: JwkSet = serde_json::from_str(&jwks)
+            .with_context(|| format!("failed to parse JWT key set {}", config.jwks_path))?;
+        tracing::info!("Loaded {} JWT verification keys", keys.keys.len());
+        Self::with_keys(keys, config)
+    }
+
+    fn with_keys(keys: JwkSet, config: &JwtConfig) -> Result<Self> {
+        let algorithm = config
+            .algorithm
+            .as_deref()
+            .map(Algorithm::from_str)
+            .transpose()
+            .context("invalid JWT algorithm")?;
+
+        Ok(Self {
+            keys,
+            algorithm,
+            issuer: config.issuer.clone(),
+            audience: config.audience.clone(),
+            role_claim: config.role_claim.clone(),
+            projects_claim: config.projects_claim.clone(),
+        })
+    }
+
+    pub fn verify(&self, token: &str) -> Result<Principal> {
+        let header = decode_header(token)?;
+        let kid = header.kid.as_deref().context("token has no key id")?;
+        let jwk = self
+            .keys
+            .find(kid)
+            .with_context(|| format!("unknown key id {}", kid))?;
+        let key = DecodingKey::from_jwk(jwk)?;
+
+        // The token header is chosen by the caller, so the algorithm comes
+        // from the key or the configuration and the header must match it
+        let algorithm = self.algorithm_of(kid, jwk)?;
+        if header.alg != algorithm {
+            anyhow::bail!(
+                "token algorithm {:?} does not match {:?} of key {}",
+                header.alg,
+                algorithm,
+                kid
+            );
+        }
+        let mut validation = Validation::new(algorithm);
+        if let Some(issuer) = &self.issuer {
+            validation.set_issuer(&[issuer]);
+        }
+        match &self.audience {
+            Some(audience) => validation.set_audience(&[audience]),
+            None => validation.validate_aud = false,
+        }
+
+        let claims = decode::<Claims>(token, &key, &validation)?.claims;
+        let role = claims
+            .extra
+            .get(&self.role_claim)
+            .and_then(serde_json::Value::as_str)
+            .and_then(Role::parse)
+            .with_context(|| format!("token has no valid {} claim", self.role_claim))?;
+
+        let projects = match claims.extra.get(&self.projects_claim) {
+            Some(projects) => serde_json::from_value(projects.clone()).with_context(|| {
+                format!("{} claim must be a list of strings", self.projects_claim)
+            })?,
+            None => Vec::new(),
+        };
+
+        Ok(Principal {
+            subject: claims.sub,
+            role,
+            projects,
+        })
+    }
+
+    fn algorithm_of(&self, kid: &str, jwk: &Jwk) -> Result<Algorithm> {
+        match jwk.common.key_algorithm {
+            Some(algorithm) => Algorithm::from_str(&algorithm.to_string())
+                .with_context(|| format!("key {} is not a signing key", kid)),
+            None => self.algorithm.with_context(|| {
+                format!("key {} has no alg and auth.jwt.algorithm is not set", kid)
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use serde_json::json;
+
+    const SECRET: &[u8] = b"a test secret of enough length for hs256";
+
+    fn verifier(alg: Option<&str>, configured: Option<&str>) -> JwtVerifier {
+        let mut key = json!({ "kty": "oct", "kid": "k1", "k": URL_SAFE_NO_PAD.encode(SECRET) });
+        if let Some(alg) = alg {
+            key["alg"] = json!(alg);
+        }
+        let keys = serde_json::from_value(json!({ "keys": [key] })).unwrap();
+        let config = JwtConfig {
+            jwks_path: String::new(),
+            issuer: None,
+            audience: None,
+            algorithm: configured.map(str::to_string),
+            role_claim: "role".to_string(),
+            projects_claim: "projects".to_string(),
+        };
+        JwtVerifier::with_keys(keys, &config).unwrap()
+    }
+
+    fn token(alg: Algorithm) -> String {
+        token_with(
+            alg,
+            json!({ "sub": "alice", "role": "viewer", "exp": 4102444800u64 }),
+        )
+    }
+
+    fn token_with(alg: Algorithm, claims: serde_json::Value) -> String {
+        let mut header = Header::new(alg);
+        header.kid = Some("k1".to_string());
+        encode(&header, &claims, &EncodingKey::from_secret(SECRET)).unwrap()
+    }
+
+    #[test]
+    fn tokens_are_verified_with_the_key_algorithm() {
+        let principal = verifier(Some("HS256"), None)
+            .verify(&token(Algorithm::HS256))
+            .unwrap();
+        assert_eq!(principal.subject, "alice");
+        assert_eq!(principal.role, Role::Viewer);
+    }
+
+    #[test]
+    fn tokens_cannot_choose_another_algorithm() {
+        let err = verifier(Some("HS256"), None)
+            .verify(&token(Algorithm::HS512))
+            .unwrap_err();
+        assert!(err.to_string().contains("does not match"));
+
+        let err = verifier(None, Some("HS256"))
+            .verify(&token(Algorithm::HS384))
+            .unwrap_err();
+        assert!(err.to_string().contains("does not match"));
+    }
+
+    #[test]
+    fn keys_without_an_algorithm_need_one_configured() {
+        let err = verifier(None, None)
+            .verify(&token(Algorithm::HS256))
+            .unwrap_err();
+        assert!(err.to_string().contains("auth.jwt.algorithm"));
+
+        verifier(None, Some("HS256"))
+            .verify(&token(Algorithm::HS256))
+            .unwrap();
+    }
+
+    #[test]
+    fn tokens_without_a_projects_claim_get_no_projects() {
+        let verifier = verifier(Some("HS256"), None);
+        let principal = verifier.verify(&token(Algorithm::HS256)).unwrap();
+        assert!(principal.projects.is_empty());
+        assert!(!principal.can_access("default"));
+
+        let claims = json!({
+            "sub": "alice",
+            "role": "viewer",
+            "projects": ["*"],
+            "exp": 4102444800u64,
+        });
+        let principal = verifier
+            .verify(&token_with(Algorithm::HS256, claims))
+            .unwrap();
+        assert!(principal.can_access("default"));
+    }
+}
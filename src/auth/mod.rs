@@ -0,0 +1,229 @@
+mod jwt;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use axum::{
+    extract::{FromRequestParts, Request, State},
+    http::{header, request::Parts, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, fmt, sync::Arc};
+
+use crate::{config::AuthConfig, error::ApiError};
+
+use jwt::JwtVerifier;
+
+/// Project entry granting access to every project.
+pub const ALL_PROJECTS: &str = "*";
+
+/// Roles in increasing order of privilege; each role can do everything the
+/// roles before it can.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Read rollups, clusters and operations
+    Viewer,
+    /// Create, update and delete rollups
+    Operator,
+    /// Manage clusters
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Operator => "operator",
+            Role::Admin => "admin",
+        }
+    }
+
+    pub fn parse(role: &str) -> Option<Self> {
+        match role {
+            "viewer" => Some(Role::Viewer),
+            "operator" => Some(Role::Operator),
+            "admin" => Some(Role::Admin),
+            _ => None,
+        }
+    }
+}
+
+/// The caller of an API request.
+#[derive(Debug, Clone)]
+pub struct Principal {
+    /// Token name or JWT subject
+    pub subject: String,
+    pub role: Role,
+    /// Projects the principal can see and act on
+    pub projects: Vec<String>,
+}
+
+impl Principal {
+    fn anonymous() -> Self {
+        Self {
+            subject: "anonymous".to_string(),
+            role: Role::Admin,
+            projects: vec![ALL_PROJECTS.to_string()],
+        }
+    }
+
+    pub fn can_access(&self, project: &str) -> bool {
+        self.projects
+            .iter()
+            .any(|p| p == ALL_PROJECTS || p == project)
+    }
+
+    /// Projects the principal is limited to, or `None` for every project.
+    pub fn project_scope(&self) -> Option<Vec<String>> {
+        if self.projects.iter().any(|p| p == ALL_PROJECTS) {
+            None
+        } else {
+            Some(self.projects.clone())
+        }
+    }
+
+    /// Fails with 403 unless the principal belongs to `project`.
+    pub fn require_project(&self, project: &str) -> Result<(), ApiError> {
+        if self.can_access(project) {
+            Ok(())
+        } else {
+            Err(ApiError::new(
+                StatusCode::FORBIDDEN,
+                "forbidden",
+                format!("{} has no access to project {}", self.subject, project),
+            ))
+        }
+    }
+
+    /// Fails with 403 unless the principal has at least `role`.
+    pub fn require(&self, role: Role) -> Result<(), ApiError> {
+        if self.role >= role {
+            Ok(())
+        } else {
+            Err(ApiError::new(
+                StatusCode::FORBIDDEN,
+                "forbidden",
+                format!("{} requires the {} role", self.subject, role.as_str()),
+            ))
+        }
+    }
+}
+
+impl fmt::Display for Principal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.subject, self.role.as_str())
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Principal {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<Principal>()
+            .cloned()
+            .ok_or_else(|| unauthorized("missing credentials"))
+    }
+}
+
+/// Resolves bearer credentials to principals using static API tokens and,
+/// when configured, JWTs signed by a known key set.
+pub struct Authenticator {
+    enabled: bool,
+    /// Static tokens by the hex SHA-256 of the token
+    tokens: HashMap<String, Principal>,
+    jwt: Option<JwtVerifier>,
+}
+
+impl Authenticator {
+    pub fn new(config: &AuthConfig) -> Result<Self> {
+        if !config.enabled {
+            tracing::warn!("API authentication is disabled; every request is treated as admin");
+        }
+
+        let tokens = config
+            .tokens
+            .iter()
+            .map(|token| {
+                let principal = Principal {
+                    subject: token.name.clone(),
+                    role: token.role,
+                    projects: token.projects.clone(),
+                };
+                (token.token_sha256.to_lowercase(), principal)
+            })
+            .collect();
+
+        let jwt = config.jwt.as_ref().map(JwtVerifier::new).transpose()?;
+
+        Ok(Self {
+            enabled: config.enabled,
+            tokens,
+            jwt,
+        })
+    }
+
+    fn authenticate(&self, bearer: &str) -> Result<Principal, ApiError> {
+        let digest = hex::encode(Sha256::digest(bearer.as_bytes()));
+        if let Some(principal) = self.tokens.get(&digest) {
+            return Ok(principal.clone());
+        }
+
+        // Anything shaped like a JWT is checked against the key set
+        match &self.jwt {
+            Some(jwt) if bearer.matches('.').count() == 2 => jwt
+                .verify(bearer)
+                .map_err(|e| unauthorized(format!("invalid token: {:#}", e))),
+            _ => Err(unauthorized("invalid token")),
+        }
+    }
+}
+
+fn unauthorized(message: impl Into<String>) -> ApiError {
+    ApiError::new(StatusCode::UNAUTHORIZED, "unauthorized", message)
+}
+
+/// Authenticates the request, attaches the principal to the request span and
+/// makes it available to handlers.
+pub async fn authenticate(
+    State(authenticator): State<Arc<Authenticator>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let principal = if authenticator.enabled {
+        let bearer = request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+
+        let result = match bearer {
+            Some(bearer) => authenticator.authenticate(bearer.trim()),
+            None => Err(unauthorized("missing bearer token")),
+        };
+        match result {
+            Ok(principal) => principal,
+            Err(e) => {
+                let mut response = e.into_response();
+                response
+                    .headers_mut()
+                    .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+                return response;
+            }
+        }
+    } else {
+        Principal::anonymous()
+    };
+
+    let span = tracing::Span::current();
+    span.record("principal", principal.subject.as_str());
+    span.record("role", principal.role.as_str());
+
+    request.extensions_mut().insert(principal);
+    next.run(request).await
+}
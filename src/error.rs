@@ -0,0 +1,295 @@
+use axum::{
+    extract::{rejection::JsonRejection, FromRequest, Request},
+    http::{HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{config, infra, project, rollup};
+
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+tokio::task_local! {
+    /// Id of the request being handled, echoed in error bodies.
+    static REQUEST_ID: String;
+}
+
+/// An error returned to API clients as a JSON problem body.
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub code: &'static str,
+    pub message: String,
+    pub details: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize)]
+struct ProblemBody {
+    code: &'static str,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    details: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            code,
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    pub fn not_found(kind: &str, name: &str) -> Self {
+        Self::new(
+            StatusCode::NOT_FOUND,
+            "not_found",
+            format!("{} {} not found", kind, name),
+        )
+    }
+}
+
+impl From<anyhow::Error> for ApiError {
+    fn from(error: anyhow::Error) -> Self {
+        // Use the first typed error in the chain; context layers stay in the message
+        let mut message = format!("{:#}", error);
+        let (status, code, details) = error.chain().find_map(classify).unwrap_or((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "internal",
+            None,
+        ));
+
+        // Server-side causes can name hosts, queries or credentials, so they
+        // are only logged; clients find them through the request id
+        if status.is_server_error() {
+            let request_id = REQUEST_ID.try_with(Clone::clone).unwrap_or_default();
+            tracing::error!(request_id = %request_id, "Request failed: {}", message);
+            message = status.canonical_reason().unwrap_or("Internal Server Error").to_string();
+        }
+
+        Self {
+            status,
+            code,
+            message,
+            details,
+        }
+    }
+}
+
+impl From<rollup::Error> for ApiError {
+    fn from(error: rollup::Error) -> Self {
+        anyhow::Error::from(error).into()
+    }
+}
+
+impl From<infra::Error> for ApiError {
+    fn from(error: infra::Error) -> Self {
+        anyhow::Error::from(error).into()
+    }
+}
+
+impl From<project::Error> for ApiError {
+    fn from(error: project::Error) -> Self {
+        anyhow::Error::from(error).into()
+    }
+}
+
+type Classification = (StatusCode, &'static str, Option<serde_json::Value>);
+
+fn classify(cause: &(dyn std::error::Error + 'static)) -> Option<Classification> {
+    if let Some(e) = cause.downcast_ref::<rollup::Error>() {
+        return Some(match e {
+            rollup::Error::NotFound(_) => (StatusCode::NOT_FOUND, "rollup_not_found", None),
+            rollup::Error::AlreadyExists(_) => {
+                (StatusCode::CONFLICT, "rollup_already_exists", None)
+            }
+            rollup::Error::InvalidConfig(_) => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "invalid_rollup_config",
+                None,
+            ),
+            rollup::Error::ManagedElsewhere { manager, .. } => (
+                StatusCode::CONFLICT,
+                "rollup_managed_elsewhere",
+                Some(serde_json::json!({ "manager": manager })),
+            ),
+        });
+    }
+
+    if let Some(e) = cause.downcast_ref::<infra::Error>() {
+        return Some(match e {
+            infra::Error::ClusterNotFound(_) => (StatusCode::NOT_FOUND, "cluster_not_found", None),
+            infra::Error::ClusterAlreadyExists(_) => {
+                (StatusCode::CONFLICT, "cluster_already_exists", None)
+            }
+            infra::Error::ClusterInUse { rollups, .. } => (
+                StatusCode::CONFLICT,
+                "cluster_in_use",
+                Some(serde_json::json!({ "rollups": rollups })),
+            ),
+            infra::Error::NodePoolNotFound { .. } => {
+                (StatusCode::NOT_FOUND, "node_pool_not_found", None)
+            }
+            infra::Error::NodePoolAlreadyExists { .. } => {
+                (StatusCode::CONFLICT, "node_pool_already_exists", None)
+            }
+            infra::Error::ImmutableField { field, .. } => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "immutable_field",
+                Some(serde_json::json!({ "field": field })),
+            ),
+            infra::Error::UnknownAccount(_) => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "unknown_cloud_account",
+                None,
+            ),
+            infra::Error::InvalidConfig(_) => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "invalid_cluster_config",
+                None,
+            ),
+            infra::Error::ProviderUnavailable(_) => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "provider_unavailable",
+                None,
+            ),
+        });
+    }
+
+    if let Some(e) = cause.downcast_ref::<project::Error>() {
+        return Some(match e {
+            project::Error::NotFound(_) => (StatusCode::NOT_FOUND, "project_not_found", None),
+            project::Error::AlreadyExists(_) => {
+                (StatusCode::CONFLICT, "project_already_exists", None)
+            }
+            project::Error::NotEmpty {
+                rollups, clusters, ..
+            } => (
+                StatusCode::CONFLICT,
+                "project_not_empty",
+                Some(serde_json::json!({ "rollups": rollups, "clusters": clusters })),
+            ),
+            project::Error::QuotaExceeded {
+                resource, limit, ..
+            } => (
+                StatusCode::FORBIDDEN,
+                "quota_exceeded",
+                Some(serde_json::json!({ "resource": resource, "limit": limit })),
+            ),
+            project::Error::InvalidConfig(_) => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "invalid_project_config",
+                None,
+            ),
+        });
+    }
+
+    if let Some(e) = cause.downcast_ref::<config::Error>() {
+        return Some(match e {
+            config::Error::Invalid(_) => (StatusCode::UNPROCESSABLE_ENTITY, "invalid_config", None),
+            _ => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "config_unavailable",
+                None,
+            ),
+        });
+    }
+
+    if let Some(e) = cause.downcast_ref::<kube::Error>() {
+        return Some(match e {
+            kube::Error::Api(response) => {
+                let details = Some(serde_json::json!({ "reason": response.reason }));
+                match response.code {
+                    404 => (StatusCode::NOT_FOUND, "kubernetes_not_found", details),
+                    409 => (StatusCode::CONFLICT, "kubernetes_conflict", details),
+                    422 => (
+                        StatusCode::UNPROCESSABLE_ENTITY,
+                        "kubernetes_invalid",
+                        details,
+                    ),
+                    _ => (StatusCode::BAD_GATEWAY, "kubernetes_error", details),
+                }
+            }
+            kube::Error::HyperError(_) | kube::Error::Service(_) => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "kubernetes_unavailable",
+                None,
+            ),
+            _ => (StatusCode::INTERNAL_SERVER_ERROR, "kubernetes_error", None),
+        });
+    }
+
+    if let Some(e) = cause.downcast_ref::<sqlx::Error>() {
+        return Some(match e {
+            sqlx::Error::RowNotFound => (StatusCode::NOT_FOUND, "not_found", None),
+            sqlx::Error::Database(db) if db.code().as_deref() == Some("23505") => {
+                (StatusCode::CONFLICT, "already_exists", None)
+            }
+            sqlx::Error::PoolTimedOut | sqlx::Error::PoolClosed | sqlx::Error::Io(_) => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "database_unavailable",
+                None,
+            ),
+            _ => (StatusCode::INTERNAL_SERVER_ERROR, "database_error", None),
+        });
+    }
+
+    None
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        match rejection {
+            // Well-formed JSON that does not match the expected shape
+            JsonRejection::JsonDataError(e) => ApiError::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "invalid_body",
+                e.body_text(),
+            ),
+            other => ApiError::new(StatusCode::BAD_REQUEST, "bad_request", other.body_text()),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = ProblemBody {
+            code: self.code,
+            message: self.message,
+            details: self.details,
+            request_id: REQUEST_ID.try_with(Clone::clone).ok(),
+        };
+        (self.status, Json(body)).into_response()
+    }
+}
+
+/// `axum::Json` with rejections reported as `ApiError`.
+#[derive(FromRequest)]
+#[from_request(via(axum::Json), rejection(ApiError))]
+pub struct ApiJson<T>(pub T);
+
+/// Takes the request id from the `x-request-id` header, or generates one,
+/// and makes it available to error responses.
+pub async fn assign_request_id(request: Request, next: Next) -> Response {
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+    let mut response = REQUEST_ID
+        .scope(request_id.clone(), next.run(request))
+        .await;
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
@@ -0,0 +1,1071 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use aws_config::{BehaviorVersion, Region};
+use aws_runtime::env_config::file::{EnvConfigFileKind, EnvConfigFiles};
+use aws_sdk_eks::{
+    config::{Credentials, ProvideCredentials, SharedCredentialsProvider},
+    error::{DisplayErrorContext, SdkError},
+    types as eks, Client,
+};
+use aws_sigv4::{
+    http_request::{sign, SignableBody, SignableRequest, SignatureLocation, SigningSettings},
+    sign::v4,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::{Duration, SystemTime},
+};
+
+use super::{
+    ClusterConfig, ClusterState, ClusterStatus, Error, InfrastructureProvider, NodePool,
+    TaintEffect,
+};
+use crate::config::CloudConfig;
+
+/// Managed node group created with every cluster. It holds the cluster's
+/// `node_count` nodes of `node_type`; each node pool is a node group of its own.
+const DEFAULT_NODE_GROUP: &str = "galato-default";
+
+/// Tags added to every cluster and node group next to `ClusterConfig::tags`.
+const CLUSTER_TAG: &str = "galato.io/cluster";
+const PROJECT_TAG: &str = "galato.io/project";
+
+/// How long to wait for the control plane to become active, or for node
+/// groups to be deleted, before giving up.
+const CONTROL_PLANE_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+const POLL_INTERVAL: Duration = Duration::from_secs(15);
+
+/// EKS accepts a presigned STS `GetCallerIdentity` request as a bearer token,
+/// bound to one cluster by the `x-k8s-aws-id` header. EKS honours the token
+/// for 15 minutes whatever the presigned expiry.
+const TOKEN_PREFIX: &str = "k8s-aws-v1.";
+const CLUSTER_ID_HEADER: &str = "x-k8s-aws-id";
+const TOKEN_EXPIRY: Duration = Duration::from_secs(60);
+
+/// Runs clusters on EKS with managed node groups in the subnets from
+/// the account's `aws` settings. One provider serves the account's `region`.
+pub struct AwsProvider {
+    eks: Client,
+    /// Signs the tokens galato uses to reach cluster API servers
+    credentials: Option<SharedCredentialsProvider>,
+    region: String,
+    cluster_role_arn: String,
+    node_role_arn: String,
+    subnet_ids: Vec<String>,
+    security_group_ids: Vec<String>,
+}
+
+impl AwsProvider {
+    pub async fn new(cloud: &CloudConfig) -> Result<Self> {
+        let aws = &cloud.aws;
+        let mut loader = aws_config::defaults(BehaviorVersion::latest())
+            .region(Region::new(cloud.region.clone()));
+        if !cloud.credentials_path.is_empty() {
+            loader = loader.profile_files(
+                EnvConfigFiles::builder()
+                    .include_default_config_file(true)
+                    .with_file(EnvConfigFileKind::Credentials, &cloud.credentials_path)
+                    .build(),
+            );
+        }
+        if let Some(endpoint) = &aws.endpoint_url {
+            loader = loader.endpoint_url(endpoint);
+        }
+        let sdk_config = loader.load().await;
+
+        Ok(Self {
+            eks: Client::new(&sdk_config),
+            credentials: sdk_config.credentials_provider(),
+            region: cloud.region.clone(),
+            cluster_role_arn: aws.cluster_role_arn.clone(),
+            node_role_arn: aws.node_role_arn.clone(),
+            subnet_ids: aws.subnet_ids.clone(),
+            security_group_ids: aws.security_group_ids.clone(),
+        })
+    }
+
+    async fn describe_cluster(&self, name: &str) -> Result<Option<eks::Cluster>> {
+        match self.eks.describe_cluster().name(name).send().await {
+            Ok(output) => Ok(output.cluster().cloned()),
+            Err(e) if is_not_found(&e, |e| e.is_resource_not_found_exception()) => Ok(None),
+            Err(e) => Err(eks_error("DescribeCluster", e)),
+        }
+    }
+
+    async fn require_cluster(&self, name: &str) -> Result<eks::Cluster> {
+        self.describe_cluster(name)
+            .await?
+            .ok_or_else(|| Error::ClusterNotFound(name.to_string()).into())
+    }
+
+    /// API server endpoint and certificate authority data of a cluster.
+    async fn api_server(&self, name: &str) -> Result<(String, String)> {
+        let cluster = self.require_cluster(name).await?;
+        let endpoint = cluster
+            .endpoint()
+            .with_context(|| format!("cluster {} has no API endpoint yet", name))?;
+        let certificate_authority = cluster
+            .certificate_authority()
+            .and_then(|c| c.data())
+            .with_context(|| format!("cluster {} has no certificate authority yet", name))?;
+        Ok((endpoint.to_string(), certificate_authority.to_string()))
+    }
+
+    async fn describe_node_group(
+        &self,
+        cluster: &str,
+        group: &str,
+    ) -> Result<Option<eks::Nodegroup>> {
+        let result = self
+            .eks
+            .describe_nodegroup()
+            .cluster_name(cluster)
+            .nodegroup_name(group)
+            .send()
+            .await;
+        match result {
+            Ok(output) => Ok(output.nodegroup().cloned()),
+            Err(e) if is_not_found(&e, |e| e.is_resource_not_found_exception()) => Ok(None),
+            Err(e) => Err(eks_error("DescribeNodegroup", e)),
+        }
+    }
+
+    async fn list_node_groups(&self, cluster: &str) -> Result<Vec<String>> {
+        let mut groups = Vec::new();
+        let mut next_token = None;
+        loop {
+            let output = self
+                .eks
+                .list_nodegroups()
+                .cluster_name(cluster)
+                .set_next_token(next_token)
+                .send()
+                .await
+                .map_err(|e| eks_error("ListNodegroups", e))?;
+            groups.extend(output.nodegroups().iter().cloned());
+            next_token = output.next_token().map(str::to_string);
+            if next_token.is_none() {
+                return Ok(groups);
+            }
+        }
+    }
+
+    /// Creates a managed node group for `pool` in the cluster's subnets.
+    async fn create_node_group(
+        &self,
+        cluster: &str,
+        pool: &NodePool,
+        tags: &HashMap<String, String>,
+    ) -> Result<()> {
+        let taints = pool
+            .taints
+            .iter()
+            .map(|taint| {
+                eks::Taint::builder()
+                    .key(&taint.key)
+                    .set_value(taint.value.clone())
+                    .effect(taint_effect(&taint.effect))
+                    .build()
+            })
+            .collect();
+
+        self.eks
+            .create_nodegroup()
+            .cluster_name(cluster)
+            .nodegroup_name(&pool.name)
+            .node_role(&self.node_role_arn)
+            .set_subnets(Some(self.subnet_ids.clone()))
+            .instance_types(&pool.machine_type)
+            .scaling_config(scaling(pool.min_nodes, pool.max_nodes, pool.node_count))
+            .set_labels(Some(pool.labels.clone().into_iter().collect()))
+            .set_taints(Some(taints))
+            .set_tags(Some(tags.clone()))
+            .send()
+            .await
+            .map_err(|e| {
+                if e.as_service_error()
+                    .is_some_and(|e| e.is_resource_in_use_exception())
+                {
+                    Error::NodePoolAlreadyExists {
+                        cluster: cluster.to_string(),
+                        pool: pool.name.clone(),
+                    }
+                    .into()
+                } else {
+                    eks_error("CreateNodegroup", e)
+                }
+            })?;
+        Ok(())
+    }
+
+    /// Changes the size of a node group; returns false if it does not exist.
+    async fn update_node_group(
+        &self,
+        cluster: &str,
+        group: &str,
+        scaling_config: eks::NodegroupScalingConfig,
+    ) -> Result<bool> {
+        let result = self
+            .eks
+            .update_nodegroup_config()
+            .cluster_name(cluster)
+            .nodegroup_name(group)
+            .scaling_config(scaling_config)
+            .send()
+            .await;
+        match result {
+            Ok(_) => Ok(true),
+            Err(e) if is_not_found(&e, |e| e.is_resource_not_found_exception()) => Ok(false),
+            Err(e) => Err(eks_error("UpdateNodegroupConfig", e)),
+        }
+    }
+
+    /// Starts deleting a node group; returns false if it does not exist.
+    async fn delete_node_group(&self, cluster: &str, group: &str) -> Result<bool> {
+        let result = self
+            .eks
+            .delete_nodegroup()
+            .cluster_name(cluster)
+            .nodegroup_name(group)
+            .send()
+            .await;
+        match result {
+            Ok(_) => Ok(true),
+            Err(e) if is_not_found(&e, |e| e.is_resource_not_found_exception()) => Ok(false),
+            Err(e) => Err(eks_error("DeleteNodegroup", e)),
+        }
+    }
+
+    /// Sets `tags` on a cluster or node group and removes the `removed` keys.
+    async fn retag(
+        &self,
+        arn: &str,
+        tags: &HashMap<String, String>,
+        removed: &[String],
+    ) -> Result<()> {
+        self.eks
+            .tag_resource()
+            .resource_arn(arn)
+            .set_tags(Some(tags.clone()))
+            .send()
+            .await
+            .map_err(|e| eks_error("TagResource", e))?;
+        if !removed.is_empty() {
+            self.eks
+                .untag_resource()
+                .resource_arn(arn)
+                .set_tag_keys(Some(removed.to_vec()))
+                .send()
+                .await
+                .map_err(|e| eks_error("UntagResource", e))?;
+        }
+        Ok(())
+    }
+
+    /// EKS accepts node groups only once the control plane is active.
+    async fn wait_for_control_plane(&self, name: &str) -> Result<()> {
+        let deadline = tokio::time::Instant::now() + CONTROL_PLANE_TIMEOUT;
+        loop {
+            let cluster = self.require_cluster(name).await?;
+            match cluster.status() {
+                Some(eks::ClusterStatus::Active) => return Ok(()),
+                Some(eks::ClusterStatus::Failed) => {
+                    anyhow::bail!("EKS control plane of cluster {} failed to start", name)
+                }
+                _ => {}
+            }
+            if tokio::time::Instant::now() >= deadline {
+                anyhow::bail!(
+                    "EKS control plane of cluster {} was not active after {} minutes",
+                    name,
+                    CONTROL_PLANE_TIMEOUT.as_secs() / 60
+                );
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    /// EKS refuses to delete a cluster while it still has node groups.
+    async fn wait_for_node_groups_deleted(&self, cluster: &str) -> Result<()> {
+        let deadline = tokio::time::Instant::now() + CONTROL_PLANE_TIMEOUT;
+        loop {
+            let remaining = self.list_node_groups(cluster).await?;
+            if remaining.is_empty() {
+                return Ok(());
+            }
+            for group in &remaining {
+                let failed = self
+                    .describe_node_group(cluster, group)
+                    .await?
+                    .is_some_and(|g| g.status() == Some(&eks::NodegroupStatus::DeleteFailed));
+                if failed {
+                    anyhow::bail!(
+                        "node group {} of cluster {} failed to delete",
+                        group,
+                        cluster
+                    );
+                }
+            }
+            if tokio::time::Instant::now() >= deadline {
+                anyhow::bail!(
+                    "node groups {} of cluster {} were not deleted after {} minutes",
+                    remaining.join(", "),
+                    cluster,
+                    CONTROL_PLANE_TIMEOUT.as_secs() / 60
+                );
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+}
+
+/// Whether a failed call reported a missing cluster or node group.
+fn is_not_found<E>(err: &SdkError<E>, not_found: impl Fn(&E) -> bool) -> bool {
+    err.as_service_error().is_some_and(not_found)
+}
+
+/// Transport failures and timeouts mean EKS could not be reached; anything
+/// else is reported with the service's own message.
+fn eks_error<E>(action: &str, err: SdkError<E>) -> anyhow::Error
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    let message = format!("{} failed: {}", action, DisplayErrorContext(&err));
+    if matches!(
+        err,
+        SdkError::DispatchFailure(_) | SdkError::TimeoutError(_)
+    ) {
+        Error::ProviderUnavailable(message).into()
+    } else {
+        anyhow::anyhow!(message)
+    }
+}
+
+fn scaling(min_nodes: i32, max_nodes: i32, node_count: i32) -> eks::NodegroupScalingConfig {
+    eks::NodegroupScalingConfig::builder()
+        .min_size(min_nodes)
+        .max_size(max_nodes)
+        .desired_size(node_count)
+        .build()
+}
+
+fn taint_effect(effect: &TaintEffect) -> eks::TaintEffect {
+    match effect {
+        TaintEffect::NoSchedule => eks::TaintEffect::NoSchedule,
+        TaintEffect::PreferNoSchedule => eks::TaintEffect::PreferNoSchedule,
+        TaintEffect::NoExecute => eks::TaintEffect::NoExecute,
+    }
+}
+
+fn cluster_tags(config: &ClusterConfig) -> HashMap<String, String> {
+    let mut tags = config.tags.clone();
+    tags.insert(CLUSTER_TAG.to_string(), config.name.clone());
+    tags.insert(PROJECT_TAG.to_string(), config.project.clone());
+    tags
+}
+
+/// EKS takes `major.minor` versions, so patch levels are dropped.
+fn eks_version(version: &str) -> String {
+    version
+        .trim_start_matches('v')
+        .split('.')
+        .take(2)
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+/// Combines the control plane and default node group states: a cluster only
+/// runs once both are active.
+fn cluster_state(
+    control_plane: Option<&eks::ClusterStatus>,
+    nodes: Option<&eks::NodegroupStatus>,
+) -> ClusterState {
+    match control_plane {
+        Some(eks::ClusterStatus::Failed) => ClusterState::Failed,
+        Some(eks::ClusterStatus::Deleting) => ClusterState::Deleting,
+        Some(eks::ClusterStatus::Creating | eks::ClusterStatus::Pending) | None => {
+            ClusterState::Creating
+        }
+        Some(eks::ClusterStatus::Updating) => ClusterState::Scaling,
+        // Active, or a state newer than this SDK
+        Some(_) => match nodes {
+            None | Some(eks::NodegroupStatus::Creating) => ClusterState::Creating,
+            Some(eks::NodegroupStatus::Updating) => ClusterState::Scaling,
+            Some(eks::NodegroupStatus::Deleting) => ClusterState::Deleting,
+            Some(
+                eks::NodegroupStatus::CreateFailed
+                | eks::NodegroupStatus::DeleteFailed
+                | eks::NodegroupStatus::Degraded,
+            ) => ClusterState::Failed,
+            Some(_) => ClusterState::Running,
+        },
+    }
+}
+
+/// Kubeconfig in the form `aws eks update-kubeconfig` writes. Tokens come
+/// from the AWS CLI, so its user needs AWS credentials mapped to a cluster
+/// role.
+fn kubeconfig(
+    name: &str,
+    region: &str,
+    endpoint: &str,
+    certificate_authority: &str,
+) -> Result<String> {
+    let user = serde_json::json!({
+        "exec": {
+            "apiVersion": "client.authentication.k8s.io/v1beta1",
+            "command": "aws",
+            "args": ["eks", "get-token", "--cluster-name", name, "--region", region],
+        },
+    });
+    kubeconfig_with(name, endpoint, certificate_authority, user)
+}
+
+fn kubeconfig_with(
+    name: &str,
+    endpoint: &str,
+    certificate_authority: &str,
+    user: serde_json::Value,
+) -> Result<String> {
+    let config = serde_json::json!({
+        "apiVersion": "v1",
+        "kind": "Config",
+        "clusters": [{
+            "name": name,
+            "cluster": {
+                "server": endpoint,
+                "certificate-authority-data": certificate_authority,
+            },
+        }],
+        "users": [{ "name": name, "user": user }],
+        "contexts": [{
+            "name": name,
+            "context": { "cluster": name, "user": name },
+        }],
+        "current-context": name,
+    });
+    Ok(serde_yaml::to_string(&config)?)
+}
+
+/// Bearer token for cluster `name`, as `aws eks get-token` mints it: a
+/// `GetCallerIdentity` request presigned with `credentials`, which EKS
+/// resolves to their IAM identity.
+fn cluster_token(
+    name: &str,
+    region: &str,
+    credentials: Credentials,
+    time: SystemTime,
+) -> Result<String> {
+    let url = format!(
+        "https://sts.{}.amazonaws.com/?Action=GetCallerIdentity&Version=2011-06-15",
+        region
+    );
+    let mut settings = SigningSettings::default();
+    settings.signature_location = SignatureLocation::QueryParams;
+    settings.expires_in = Some(TOKEN_EXPIRY);
+    let identity = credentials.into();
+    let params = v4::SigningParams::builder()
+        .identity(&identity)
+        .region(region)
+        .name("sts")
+        .time(time)
+        .settings(settings)
+        .build()?
+        .into();
+
+    let request = SignableRequest::new(
+        "GET",
+        url.as_str(),
+        [(CLUSTER_ID_HEADER, name)].into_iter(),
+        SignableBody::Bytes(&[]),
+    )?;
+    let (instructions, _) = sign(request, &params)?.into_parts();
+    let mut presigned = reqwest::Url::parse(&url)?;
+    presigned
+        .query_pairs_mut()
+        .extend_pairs(instructions.params());
+
+    Ok(format!(
+        "{}{}",
+        TOKEN_PREFIX,
+        URL_SAFE_NO_PAD.encode(presigned.as_str())
+    ))
+}
+
+#[async_trait]
+impl InfrastructureProvider for AwsProvider {
+    async fn create_cluster(&self, name: &str, config: &ClusterConfig) -> Result<()> {
+        if config.region != self.region {
+            return Err(Error::InvalidConfig(format!(
+                "cluster {} is in region {}, but the AWS provider manages {}",
+                name, config.region, self.region
+            ))
+            .into());
+        }
+        tracing::info!("Creating AWS EKS cluster: {}", name);
+
+        let tags = cluster_tags(config);
+        let vpc = eks::VpcConfigRequest::builder()
+            .set_subnet_ids(Some(self.subnet_ids.clone()))
+            .set_security_group_ids(Some(self.security_group_ids.clone()))
+            .build();
+        self.eks
+            .create_cluster()
+            .name(name)
+            .version(eks_version(&config.kubernetes_version))
+            .role_arn(&self.cluster_role_arn)
+            .resources_vpc_config(vpc)
+            .set_tags(Some(tags.clone()))
+            .send()
+            .await
+            .map_err(|e| {
+                if e.as_service_error()
+                    .is_some_and(|e| e.is_resource_in_use_exception())
+                {
+                    Error::ClusterAlreadyExists(name.to_string()).into()
+                } else {
+                    eks_error("CreateCluster", e)
+                }
+            })?;
+
+        self.wait_for_control_plane(name).await?;
+
+        let default_group = NodePool {
+            name: DEFAULT_NODE_GROUP.to_string(),
+            machine_type: config.node_type.clone(),
+            node_count: config.node_count,
+            min_nodes: config.node_count,
+            max_nodes: config.node_count,
+            labels: BTreeMap::new(),
+            taints: Vec::new(),
+        };
+        self.create_node_group(name, &default_group, &tags).await?;
+        for pool in &config.node_pools {
+            self.create_node_group(name, pool, &tags).await?;
+        }
+        Ok(())
+    }
+
+    async fn delete_cluster(&self, name: &str) -> Result<()> {
+        tracing::info!("Deleting AWS EKS cluster: {}", name);
+        self.require_cluster(name).await?;
+
+        for group in self.list_node_groups(name).await? {
+            self.delete_node_group(name, &group).await?;
+        }
+        self.wait_for_node_groups_deleted(name).await?;
+
+        match self.eks.delete_cluster().name(name).send().await {
+            Ok(_) => Ok(()),
+            Err(e) if is_not_found(&e, |e| e.is_resource_not_found_exception()) => {
+                Err(Error::ClusterNotFound(name.to_string()).into())
+            }
+            Err(e) => Err(eks_error("DeleteCluster", e)),
+        }
+    }
+
+    async fn get_cluster_status(&self, name: &str) -> Result<ClusterStatus> {
+        let Some(cluster) = self.describe_cluster(name).await? else {
+            // The cluster was deleted outside galato
+            return Ok(ClusterStatus {
+                name: name.to_string(),
+                project: String::new(),
+                state: ClusterState::Failed,
+                node_count: 0,
+                version: String::new(),
+                endpoint: String::new(),
+                message: None,
+            });
+        };
+
+        let nodes = self.describe_node_group(name, DEFAULT_NODE_GROUP).await?;
+        let node_count = nodes
+            .as_ref()
+            .and_then(|g| g.scaling_config())
+            .and_then(|s| s.desired_size())
+            .unwrap_or_default();
+
+        Ok(ClusterStatus {
+            name: name.to_string(),
+            project: String::new(),
+            state: cluster_state(cluster.status(), nodes.as_ref().and_then(|g| g.status())),
+            node_count,
+            version: cluster.version().unwrap_or_default().to_string(),
+            endpoint: cluster.endpoint().unwrap_or_default().to_string(),
+            message: None,
+        })
+    }
+
+    async fn scale_cluster(&self, name: &str, node_count: i32) -> Result<()> {
+        tracing::info!("Scaling AWS EKS cluster {} to {} nodes", name, node_count);
+        let scaling_config = scaling(node_count, node_count, node_count);
+        if !self
+            .update_node_group(name, DEFAULT_NODE_GROUP, scaling_config)
+            .await?
+        {
+            return Err(Error::ClusterNotFound(name.to_string()).into());
+        }
+        Ok(())
+    }
+
+    async fn create_node_pool(&self, cluster: &str, pool: &NodePool) -> Result<()> {
+        tracing::info!(
+            "Creating AWS EKS node pool {} in cluster {}",
+            pool.name,
+            cluster
+        );
+        if pool.name == DEFAULT_NODE_GROUP {
+            return Err(Error::InvalidConfig(format!(
+                "node pool name {} is reserved",
+                DEFAULT_NODE_GROUP
+            ))
+            .into());
+        }
+        // Node groups carry the same tags as their cluster
+        let tags = self
+            .require_cluster(cluster)
+            .await?
+            .tags()
+            .cloned()
+            .unwrap_or_default();
+        self.create_node_group(cluster, pool, &tags).await
+    }
+
+    async fn resize_node_pool(&self, cluster: &str, pool: &NodePool) -> Result<()> {
+        tracing::info!(
+            "Resizing AWS EKS node pool {} in cluster {} to {} nodes",
+            pool.name,
+            cluster,
+            pool.node_count
+        );
+        let scaling_config = scaling(pool.min_nodes, pool.max_nodes, pool.node_count);
+        if !self
+            .update_node_group(cluster, &pool.name, scaling_config)
+            .await?
+        {
+            return Err(Error::NodePoolNotFound {
+                cluster: cluster.to_string(),
+                pool: pool.name.clone(),
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    async fn delete_node_pool(&self, cluster: &str, pool: &str) -> Result<()> {
+        tracing::info!(
+            "Deleting AWS EKS node pool {} from cluster {}",
+            pool,
+            cluster
+        );
+        if !self.delete_node_group(cluster, pool).await? {
+            return Err(Error::NodePoolNotFound {
+                cluster: cluster.to_string(),
+                pool: pool.to_string(),
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    async fn get_kubeconfig(&self, name: &str) -> Result<String> {
+        let (endpoint, certificate_authority) = self.api_server(name).await?;
+        kubeconfig(name, &self.region, &endpoint, &certificate_authority)
+    }
+
+    /// Node groups carry the same tags as their cluster, so both are updated.
+    async fn update_tags(
+        &self,
+        name: &str,
+        config: &ClusterConfig,
+        removed: &[String],
+    ) -> Result<()> {
+        tracing::info!("Updating tags of AWS EKS cluster {}", name);
+        let tags = cluster_tags(config);
+        let cluster = self.require_cluster(name).await?;
+        let arn = cluster
+            .arn()
+            .with_context(|| format!("cluster {} has no ARN", name))?;
+        self.retag(arn, &tags, removed).await?;
+
+        for group in self.list_node_groups(name).await? {
+            let Some(node_group) = self.describe_node_group(name, &group).await? else {
+                continue;
+            };
+            if let Some(arn) = node_group.nodegroup_arn() {
+                self.retag(arn, &tags, removed).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn get_service_kubeconfig(&self, name: &str) -> Result<String> {
+        let (endpoint, certificate_authority) = self.api_server(name).await?;
+        let credentials = self
+            .credentials
+            .as_ref()
+            .context("no AWS credentials are configured")?
+            .provide_credentials()
+            .await
+            .context("failed to load AWS credentials")?;
+        let token = cluster_token(name, &self.region, credentials, SystemTime::now())?;
+        let user = serde_json::json!({ "token": token });
+        kubeconfig_with(name, &endpoint, &certificate_authority, user)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kube::config::Kubeconfig;
+    use std::sync::Arc;
+
+    use crate::{
+        infra::node_pool::Taint,
+        testing::{cluster_config, test_config, Replay},
+    };
+
+    const CLUSTER_CREATING: &str = include_str!("testdata/eks/cluster_creating.json");
+    const CLUSTER_ACTIVE: &str = include_str!("testdata/eks/cluster_active.json");
+    const NODE_GROUP_CREATING: &str = include_str!("testdata/eks/nodegroup_creating.json");
+    const NODE_GROUP_ACTIVE: &str = include_str!("testdata/eks/nodegroup_active.json");
+    const NODE_GROUPS: &str = include_str!("testdata/eks/nodegroups.json");
+    const NO_NODE_GROUPS: &str = include_str!("testdata/eks/nodegroups_empty.json");
+    const UPDATE_IN_PROGRESS: &str = include_str!("testdata/eks/update_in_progress.json");
+    const NOT_FOUND: &str = include_str!("testdata/eks/error_not_found.json");
+    const IN_USE: &str = include_str!("testdata/eks/error_in_use.json");
+    const EMPTY: &str = include_str!("testdata/eks/empty.json");
+
+    const CLUSTER: &str = "/clusters/c1";
+    const NODE_GROUPS_PATH: &str = "/clusters/c1/node-groups";
+    const DEFAULT_GROUP_PATH: &str = "/clusters/c1/node-groups/galato-default";
+    const CLUSTER_TAGS: &str = "/tags/arn%3Aaws%3Aeks%3Aus-west-2%3A123456789012%3Acluster%2Fc1";
+    const NODE_GROUP_TAGS: &str = "/tags/arn%3Aaws%3Aeks%3Aus-west-2%3A123456789012%3Anodegroup%2Fc1%2Fgalato-default%2Fa2c7d5e1-0b3f-4c9e-8d6a-1f2e3d4c5b6a";
+
+    /// Starts the replay server and a provider pointed at it through
+    /// `aws.endpoint_url`, signing with static test credentials.
+    async fn provider(replay: Replay) -> (AwsProvider, Arc<Replay>) {
+        let (url, replay) = replay.start().await;
+
+        let path = std::env::temp_dir().join(format!("galato-eks-{}.ini", uuid::Uuid::new_v4()));
+        std::fs::write(
+            &path,
+            "[default]\naws_access_key_id = AKIDEXAMPLE\naws_secret_access_key = secret\n",
+        )
+        .unwrap();
+
+        let mut config = test_config();
+        config.cloud.region = "us-west-2".to_string();
+        config.cloud.credentials_path = path.to_string_lossy().into_owned();
+        let aws = &mut config.cloud.aws;
+        aws.cluster_role_arn = "arn:aws:iam::123456789012:role/galato-eks-cluster".to_string();
+        aws.node_role_arn = "arn:aws:iam::123456789012:role/galato-eks-node".to_string();
+        aws.subnet_ids = vec!["subnet-0a1b2c3d".to_string(), "subnet-4e5f6a7b".to_string()];
+        aws.endpoint_url = Some(url);
+        let provider = AwsProvider::new(&config.cloud).await.unwrap();
+
+        // Credentials are loaded lazily, on the first signed request
+        let credentials = provider.credentials.as_ref().unwrap();
+        credentials.provide_credentials().await.unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        (provider, replay)
+    }
+
+    fn eks_cluster_config() -> ClusterConfig {
+        let mut config = cluster_config("c1");
+        config.region = "us-west-2".to_string();
+        config.node_type = "m5.xlarge".to_string();
+        config
+            .tags
+            .insert("Team".to_string(), "platform".to_string());
+        config.node_pools.push(NodePool {
+            name: "sequencers".to_string(),
+            machine_type: "c5.4xlarge".to_string(),
+            node_count: 2,
+            min_nodes: 1,
+            max_nodes: 4,
+            labels: BTreeMap::from([("role".to_string(), "sequencer".to_string())]),
+            taints: vec![Taint {
+                key: "dedicated".to_string(),
+                value: Some("sequencer".to_string()),
+                effect: TaintEffect::NoSchedule,
+            }],
+        });
+        config
+    }
+
+    #[tokio::test]
+    async fn creates_clusters_then_tagged_node_groups_once_active() {
+        let (provider, replay) = provider(
+            Replay::default()
+                .respond("POST", "/clusters", 200, CLUSTER_CREATING)
+                .respond("GET", CLUSTER, 200, CLUSTER_ACTIVE)
+                .respond("POST", NODE_GROUPS_PATH, 200, NODE_GROUP_CREATING),
+        )
+        .await;
+
+        provider
+            .create_cluster("c1", &eks_cluster_config())
+            .await
+            .unwrap();
+
+        let create = &replay.received("POST", "/clusters")[0];
+        let authorization = create.authorization.as_deref().unwrap();
+        assert!(authorization.starts_with("AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/"));
+        let body = create.json();
+        assert_eq!(body["name"], "c1");
+        assert_eq!(body["version"], "1.28");
+        assert_eq!(
+            body["roleArn"],
+            "arn:aws:iam::123456789012:role/galato-eks-cluster"
+        );
+        assert_eq!(
+            body["resourcesVpcConfig"]["subnetIds"][1],
+            "subnet-4e5f6a7b"
+        );
+        assert_eq!(body["tags"]["galato.io/cluster"], "c1");
+        assert_eq!(body["tags"]["Team"], "platform");
+
+        let groups = replay.received("POST", NODE_GROUPS_PATH);
+        assert_eq!(groups.len(), 2);
+        let default_group = groups[0].json();
+        assert_eq!(default_group["nodegroupName"], "galato-default");
+        assert_eq!(default_group["instanceTypes"][0], "m5.xlarge");
+        assert_eq!(default_group["scalingConfig"]["desiredSize"], 3);
+        assert_eq!(default_group["tags"]["galato.io/project"], "default");
+
+        let pool = groups[1].json();
+        assert_eq!(pool["nodegroupName"], "sequencers");
+        assert_eq!(pool["scalingConfig"]["maxSize"], 4);
+        assert_eq!(pool["labels"]["role"], "sequencer");
+        assert_eq!(pool["taints"][0]["effect"], "NO_SCHEDULE");
+    }
+
+    #[tokio::test]
+    async fn existing_clusters_are_reported_as_conflicts() {
+        let (provider, _) = provider(Replay::default().respond_with_headers(
+            "POST",
+            "/clusters",
+            409,
+            IN_USE,
+            &[("x-amzn-errortype", "ResourceInUseException")],
+        ))
+        .await;
+
+        let err = provider
+            .create_cluster("c1", &eks_cluster_config())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::ClusterAlreadyExists(name)) if name == "c1"
+        ));
+    }
+
+    #[tokio::test]
+    async fn active_clusters_map_to_status() {
+        let (provider, _) = provider(
+            Replay::default()
+                .respond("GET", CLUSTER, 200, CLUSTER_ACTIVE)
+                .respond("GET", DEFAULT_GROUP_PATH, 200, NODE_GROUP_ACTIVE),
+        )
+        .await;
+
+        let status = provider.get_cluster_status("c1").await.unwrap();
+        assert_eq!(status.state.as_str(), "Running");
+        assert_eq!(status.node_count, 3);
+        assert_eq!(status.version, "1.28");
+        assert_eq!(
+            status.endpoint,
+            "https://0123456789ABCDEF.gr7.us-west-2.eks.amazonaws.com"
+        );
+    }
+
+    #[tokio::test]
+    async fn clusters_deleted_outside_galato_are_failed() {
+        let (provider, _) = provider(Replay::default().respond_with_headers(
+            "GET",
+            CLUSTER,
+            404,
+            NOT_FOUND,
+            &[("x-amzn-errortype", "ResourceNotFoundException")],
+        ))
+        .await;
+
+        let status = provider.get_cluster_status("c1").await.unwrap();
+        assert_eq!(status.state.as_str(), "Failed");
+        let err = provider.delete_cluster("c1").await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::ClusterNotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn clusters_are_deleted_after_their_node_groups() {
+        let (provider, replay) = provider(
+            Replay::default()
+                .respond("GET", CLUSTER, 200, CLUSTER_ACTIVE)
+                .respond("GET", NODE_GROUPS_PATH, 200, NODE_GROUPS)
+                .respond("GET", NODE_GROUPS_PATH, 200, NO_NODE_GROUPS)
+                .respond("DELETE", DEFAULT_GROUP_PATH, 200, NODE_GROUP_ACTIVE)
+                .respond("DELETE", CLUSTER, 200, CLUSTER_ACTIVE),
+        )
+        .await;
+
+        provider.delete_cluster("c1").await.unwrap();
+        assert_eq!(replay.received("DELETE", DEFAULT_GROUP_PATH).len(), 1);
+        assert_eq!(replay.received("GET", NODE_GROUPS_PATH).len(), 2);
+        assert_eq!(replay.received("DELETE", CLUSTER).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn scaling_updates_the_default_node_group() {
+        let path = format!("{}/update-config", DEFAULT_GROUP_PATH);
+        let (provider, replay) =
+            provider(Replay::default().respond("POST", &path, 200, UPDATE_IN_PROGRESS)).await;
+
+        provider.scale_cluster("c1", 5).await.unwrap();
+        let body = replay.received("POST", &path)[0].json();
+        assert_eq!(body["scalingConfig"]["minSize"], 5);
+        assert_eq!(body["scalingConfig"]["desiredSize"], 5);
+    }
+
+    #[tokio::test]
+    async fn tags_are_updated_on_the_cluster_and_its_node_groups() {
+        let (provider, replay) = provider(
+            Replay::default()
+                .respond("GET", CLUSTER, 200, CLUSTER_ACTIVE)
+                .respond("GET", NODE_GROUPS_PATH, 200, NODE_GROUPS)
+                .respond("GET", DEFAULT_GROUP_PATH, 200, NODE_GROUP_ACTIVE)
+                .respond("POST", CLUSTER_TAGS, 200, EMPTY)
+                .respond("DELETE", CLUSTER_TAGS, 200, EMPTY)
+                .respond("POST", NODE_GROUP_TAGS, 200, EMPTY)
+                .respond("DELETE", NODE_GROUP_TAGS, 200, EMPTY),
+        )
+        .await;
+
+        let mut config = eks_cluster_config();
+        config.tags = HashMap::from([("Owner".to_string(), "alice".to_string())]);
+        provider
+            .update_tags("c1", &config, &["Team".to_string()])
+            .await
+            .unwrap();
+
+        for path in [CLUSTER_TAGS, NODE_GROUP_TAGS] {
+            let tag = replay.received("POST", path);
+            assert_eq!(tag.len(), 1, "{}", path);
+            let tags = &tag[0].json()["tags"];
+            assert_eq!(tags["Owner"], "alice");
+            assert_eq!(tags["galato.io/cluster"], "c1");
+
+            let untag = replay.received("DELETE", path);
+            assert_eq!(untag[0].query.as_deref(), Some("tagKeys=Team"));
+        }
+    }
+
+    #[tokio::test]
+    async fn service_kubeconfigs_carry_a_token_for_the_configured_credentials() {
+        let (provider, _) =
+            provider(Replay::default().respond("GET", CLUSTER, 200, CLUSTER_ACTIVE)).await;
+
+        let yaml = provider.get_service_kubeconfig("c1").await.unwrap();
+        let config = Kubeconfig::from_yaml(&yaml).unwrap();
+        let cluster = config.clusters[0].cluster.as_ref().unwrap();
+        assert_eq!(
+            cluster.server.as_deref(),
+            Some("https://0123456789ABCDEF.gr7.us-west-2.eks.amazonaws.com")
+        );
+
+        let token = yaml
+            .lines()
+            .find_map(|line| line.trim().strip_prefix("token: "))
+            .unwrap();
+        let url = URL_SAFE_NO_PAD
+            .decode(token.strip_prefix(TOKEN_PREFIX).unwrap())
+            .unwrap();
+        let url = String::from_utf8(url).unwrap();
+        assert!(url.contains("X-Amz-Credential=AKIDEXAMPLE%2F"), "{}", url);
+    }
+
+    #[test]
+    fn clusters_run_once_control_plane_and_nodes_are_active() {
+        let active = Some(&eks::ClusterStatus::Active);
+        assert_eq!(
+            cluster_state(active, Some(&eks::NodegroupStatus::Active)).as_str(),
+            "Running"
+        );
+        assert_eq!(cluster_state(active, None).as_str(), "Creating");
+        assert_eq!(
+            cluster_state(active, Some(&eks::NodegroupStatus::Updating)).as_str(),
+            "Scaling"
+        );
+        assert_eq!(
+            cluster_state(active, Some(&eks::NodegroupStatus::Degraded)).as_str(),
+            "Failed"
+        );
+        assert_eq!(
+            cluster_state(
+                Some(&eks::ClusterStatus::Creating),
+                Some(&eks::NodegroupStatus::Active)
+            )
+            .as_str(),
+            "Creating"
+        );
+        assert_eq!(
+            cluster_state(Some(&eks::ClusterStatus::Failed), None).as_str(),
+            "Failed"
+        );
+    }
+
+    #[test]
+    fn versions_are_truncated_to_minor() {
+        assert_eq!(eks_version("1.28.5"), "1.28");
+        assert_eq!(eks_version("v1.29"), "1.29");
+    }
+
+    #[test]
+    fn kubeconfig_authenticates_through_the_aws_cli() {
+        let yaml = kubeconfig("c1", "us-west-2", "https://c1.eks.amazonaws.com", "Q0E=").unwrap();
+        let config = Kubeconfig::from_yaml(&yaml).unwrap();
+
+        assert_eq!(config.current_context.as_deref(), Some("c1"));
+        let exec = config.auth_infos[0]
+            .auth_info
+            .as_ref()
+            .and_then(|a| a.exec.as_ref())
+            .unwrap();
+        assert_eq!(exec.command.as_deref(), Some("aws"));
+        assert!(exec
+            .args
+            .as_ref()
+            .unwrap()
+            .windows(2)
+            .any(|w| w == ["--cluster-name", "c1"]));
+    }
+
+    #[test]
+    fn cluster_tokens_are_presigned_caller_identity_requests() {
+        let credentials = Credentials::new("AKIDEXAMPLE", "secret", None, None, "test");
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_718_000_000);
+        let token = cluster_token("c1", "us-west-2", credentials, time).unwrap();
+
+        let url = token.strip_prefix(TOKEN_PREFIX).unwrap();
+        let url = String::from_utf8(URL_SAFE_NO_PAD.decode(url).unwrap()).unwrap();
+        let url = reqwest::Url::parse(&url).unwrap();
+        assert_eq!(url.host_str(), Some("sts.us-west-2.amazonaws.com"));
+
+        let query: HashMap<_, _> = url.query_pairs().into_owned().collect();
+        assert_eq!(query["Action"], "GetCallerIdentity");
+        assert_eq!(query["X-Amz-Expires"], "60");
+        assert_eq!(query["X-Amz-Date"], "20240610T061320Z");
+        assert_eq!(
+            query["X-Amz-Credential"],
+            "AKIDEXAMPLE/20240610/us-west-2/sts/aws4_request"
+        );
+        assert_eq!(query["X-Amz-SignedHeaders"], "host;x-k8s-aws-id");
+        assert_eq!(query["X-Amz-Signature"].len(), 64);
+    }
+}
@@ -0,0 +1,990 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use reqwest::{header::HeaderMap, Method, Response, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value};
+use std::{collections::HashMap, time::Duration};
+use tokio::{sync::Mutex, time::Instant};
+
+use super::{ClusterConfig, ClusterState, ClusterStatus, Error, InfrastructureProvider, NodePool};
+use crate::config::CloudConfig;
+
+const DEFAULT_AUTHORITY: &str = "https://login.microsoftonline.com";
+const DEFAULT_RESOURCE_MANAGER: &str = "https://management.azure.com";
+
+const CLUSTER_API_VERSION: &str = "2024-02-01";
+const RESOURCE_GROUP_API_VERSION: &str = "2021-04-01";
+
+/// System agent pool created with every cluster. It holds the cluster's
+/// `node_count` nodes of `node_type`; each node pool is a user agent pool.
+/// AKS pool names are at most 12 lowercase letters and digits.
+const DEFAULT_AGENT_POOL: &str = "galato";
+
+/// Tags added to every cluster and agent pool next to `ClusterConfig::tags`.
+/// Azure tag names cannot contain `/`, unlike the tags used on AWS.
+const CLUSTER_TAG: &str = "galato-cluster";
+const PROJECT_TAG: &str = "galato-project";
+
+/// How long to wait for a long-running ARM operation before giving up.
+const OPERATION_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Runs clusters on AKS through the Azure Resource Manager REST API,
+/// authenticated as the service principal in `credentials_path`. Clusters
+/// live in the account's `azure.resource_group`, in the location given as
+/// its `region`.
+pub struct AzureProvider {
+    http: reqwest::Client,
+    endpoint: String,
+    subscription: String,
+    resource_group: String,
+    location: String,
+    principal: ServicePrincipal,
+    token: Mutex<Option<AccessToken>>,
+    poll_interval: Duration,
+}
+
+/// The fields of an `--sdk-auth` service principal file that galato uses.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ServicePrincipal {
+    client_id: String,
+    client_secret: String,
+    subscription_id: String,
+    tenant_id: String,
+    #[serde(default = "default_authority")]
+    active_directory_endpoint_url: String,
+    #[serde(default = "default_resource_manager")]
+    resource_manager_endpoint_url: String,
+}
+
+fn default_authority() -> String {
+    DEFAULT_AUTHORITY.to_string()
+}
+
+fn default_resource_manager() -> String {
+    DEFAULT_RESOURCE_MANAGER.to_string()
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: u64,
+}
+
+struct AccessToken {
+    value: String,
+    expires_at: Instant,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ManagedCluster {
+    #[serde(default)]
+    tags: HashMap<String, String>,
+    #[serde(default)]
+    properties: ManagedClusterProperties,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ManagedClusterProperties {
+    #[serde(default)]
+    provisioning_state: String,
+    power_state: Option<PowerState>,
+    #[serde(default)]
+    current_kubernetes_version: String,
+    #[serde(default)]
+    fqdn: String,
+    #[serde(default)]
+    agent_pool_profiles: Vec<AgentPoolProfile>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PowerState {
+    #[serde(default)]
+    code: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AgentPoolProfile {
+    name: String,
+    #[serde(default)]
+    count: i32,
+    #[serde(default)]
+    provisioning_state: String,
+}
+
+/// Body of an `Azure-AsyncOperation` status URL.
+#[derive(Debug, Deserialize)]
+struct AsyncOperation {
+    status: String,
+    error: Option<ErrorDetail>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CredentialResults {
+    #[serde(default)]
+    kubeconfigs: Vec<CredentialResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CredentialResult {
+    value: String,
+}
+
+#[derive(Deserialize)]
+struct ErrorBody {
+    error: ErrorDetail,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorDetail {
+    #[serde(default)]
+    code: String,
+    #[serde(default)]
+    message: String,
+}
+
+/// An ARM call that did not succeed.
+#[derive(Debug)]
+enum Failure {
+    /// ARM answered with an error status
+    Status(StatusCode, String),
+    /// ARM could not be reached or is throttling
+    Unavailable(String),
+    Other(anyhow::Error),
+}
+
+impl Failure {
+    fn into_error(self, action: &str) -> anyhow::Error {
+        match self {
+            Failure::Status(status, message) => {
+                anyhow::anyhow!("{} failed with {}: {}", action, status, message)
+            }
+            Failure::Unavailable(message) => {
+                Error::ProviderUnavailable(format!("{} failed: {}", action, message)).into()
+            }
+            Failure::Other(e) => e.context(format!("{} failed", action)),
+        }
+    }
+}
+
+/// Where ARM reports the progress of a long-running operation.
+enum Poll {
+    /// `Azure-AsyncOperation`: a status document
+    AsyncOperation(String),
+    /// `Location`: `202 Accepted` until the operation finishes
+    Location(String),
+}
+
+impl Poll {
+    fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string)
+        };
+        header("azure-asyncoperation")
+            .map(Poll::AsyncOperation)
+            .or_else(|| header("location").map(Poll::Location))
+    }
+}
+
+impl AzureProvider {
+    pub fn new(cloud: &CloudConfig) -> Result<Self> {
+        let credentials = cloud.credentials_file();
+        let path = credentials.display();
+        let file = std::fs::read_to_string(&credentials)
+            .with_context(|| format!("failed to read service principal {}", path))?;
+        let principal: ServicePrincipal = serde_json::from_str(&file)
+            .with_context(|| format!("failed to parse service principal {}", path))?;
+
+        let azure = &cloud.azure;
+        Ok(Self {
+            http: reqwest::Client::new(),
+            endpoint: azure
+                .endpoint_url
+                .clone()
+                .unwrap_or_else(|| principal.resource_manager_endpoint_url.clone())
+                .trim_end_matches('/')
+                .to_string(),
+            subscription: azure
+                .subscription_id
+                .clone()
+                .unwrap_or_else(|| principal.subscription_id.clone()),
+            resource_group: azure.resource_group.clone(),
+            location: cloud.region.clone(),
+            principal,
+            token: Mutex::new(None),
+            poll_interval: POLL_INTERVAL,
+        })
+    }
+
+    /// Requests an access token with the client credentials grant, reusing
+    /// it until shortly before it expires.
+    async fn access_token(&self) -> Result<String> {
+        let mut token = self.token.lock().await;
+        if let Some(token) = token.as_ref().filter(|t| t.expires_at > Instant::now()) {
+            return Ok(token.value.clone());
+        }
+
+        let url = format!(
+            "{}/{}/oauth2/v2.0/token",
+            self.principal
+                .active_directory_endpoint_url
+                .trim_end_matches('/'),
+            self.principal.tenant_id
+        );
+        let scope = format!(
+            "{}/.default",
+            self.principal
+                .resource_manager_endpoint_url
+                .trim_end_matches('/')
+        );
+        let response = self
+            .http
+            .post(&url)
+            .form(&[
+                ("grant_type", "client_credentials"),
+                ("client_id", self.principal.client_id.as_str()),
+                ("client_secret", self.principal.client_secret.as_str()),
+                ("scope", scope.as_str()),
+            ])
+            .send()
+            .await
+            .map_err(|e| Error::ProviderUnavailable(format!("cannot reach {}: {}", url, e)))?;
+        if !response.status().is_success() {
+            anyhow::bail!(
+                "service principal {} was refused an access token: {}",
+                self.principal.client_id,
+                response.text().await.unwrap_or_default()
+            );
+        }
+        let issued: TokenResponse = response.json().await?;
+
+        // Refresh a minute early so requests never carry an expired token
+        *token = Some(AccessToken {
+            value: issued.access_token.clone(),
+            expires_at: Instant::now() + Duration::from_secs(issued.expires_in.saturating_sub(60)),
+        });
+        Ok(issued.access_token)
+    }
+
+    /// Sends a request to an absolute URL and returns successful responses.
+    async fn send(
+        &self,
+        method: Method,
+        url: &str,
+        body: Option<&Value>,
+    ) -> Result<Response, Failure> {
+        let token = self.access_token().await.map_err(Failure::Other)?;
+        let mut request = self.http.request(method, url).bearer_auth(token);
+        if let Some(body) = body {
+            request = request.json(body);
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| Failure::Unavailable(e.to_string()))?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        let message = response
+            .json::<ErrorBody>()
+            .await
+            .map(|body| format!("{}: {}", body.error.code, body.error.message))
+            .unwrap_or_else(|_| status.to_string());
+        if status == StatusCode::SERVICE_UNAVAILABLE || status == StatusCode::TOO_MANY_REQUESTS {
+            Err(Failure::Unavailable(message))
+        } else {
+            Err(Failure::Status(status, message))
+        }
+    }
+
+    /// Sends a request for an ARM resource and waits for any long-running
+    /// operation it starts. Returns the status of the initial response.
+    async fn change(
+        &self,
+        method: Method,
+        url: &str,
+        body: Option<&Value>,
+    ) -> Result<StatusCode, Failure> {
+        let response = self.send(method, url, body).await?;
+        let status = response.status();
+        let poll = Poll::from_headers(response.headers());
+        self.wait_for_operation(poll)
+            .await
+            .map_err(Failure::Other)?;
+        Ok(status)
+    }
+
+    async fn get<T: DeserializeOwned>(&self, url: &str) -> Result<Option<T>, Failure> {
+        match self.send(Method::GET, url, None).await {
+            Ok(response) => response
+                .json()
+                .await
+                .map(Some)
+                .map_err(|e| Failure::Other(e.into())),
+            Err(Failure::Status(StatusCode::NOT_FOUND, _)) => Ok(None),
+            Err(f) => Err(f),
+        }
+    }
+
+    async fn wait_for_operation(&self, poll: Option<Poll>) -> Result<()> {
+        // Operations without a status URL completed synchronously
+        let Some(poll) = poll else {
+            return Ok(());
+        };
+        let deadline = Instant::now() + OPERATION_TIMEOUT;
+        loop {
+            match &poll {
+                Poll::AsyncOperation(url) => {
+                    let operation: AsyncOperation = self
+                        .send(Method::GET, url, None)
+                        .await
+                        .map_err(|f| f.into_error("GetOperationStatus"))?
+                        .json()
+                        .await?;
+                    match operation.status.as_str() {
+                        "Succeeded" => return Ok(()),
+                        "Failed" | "Canceled" => {
+                            let reason = operation
+                                .error
+                                .map(|e| format!("{}: {}", e.code, e.message))
+                                .unwrap_or_default();
+                            anyhow::bail!(
+                                "ARM operation {}: {}",
+                                operation.status.to_lowercase(),
+                                reason
+                            );
+                        }
+                        _ => {}
+                    }
+                }
+                Poll::Location(url) => {
+                    let response = self
+                        .send(Method::GET, url, None)
+                        .await
+                        .map_err(|f| f.into_error("GetOperationResult"))?;
+                    if response.status() != StatusCode::ACCEPTED {
+                        return Ok(());
+                    }
+                }
+            }
+            if Instant::now() >= deadline {
+                anyhow::bail!(
+                    "ARM operation did not finish within {} minutes",
+                    OPERATION_TIMEOUT.as_secs() / 60
+                );
+            }
+            tokio::time::sleep(self.poll_interval).await;
+        }
+    }
+
+    fn resource_group_url(&self) -> String {
+        format!(
+            "{}/subscriptions/{}/resourcegroups/{}?api-version={}",
+            self.endpoint, self.subscription, self.resource_group, RESOURCE_GROUP_API_VERSION
+        )
+    }
+
+    fn cluster_url(&self, name: &str, suffix: &str) -> String {
+        format!(
+            "{}/subscriptions/{}/resourceGroups/{}/providers/Microsoft.ContainerService/managedClusters/{}{}?api-version={}",
+            self.endpoint, self.subscription, self.resource_group, name, suffix, CLUSTER_API_VERSION
+        )
+    }
+
+    fn agent_pool_url(&self, cluster: &str, pool: &str) -> String {
+        self.cluster_url(cluster, &format!("/agentPools/{}", pool))
+    }
+
+    /// Creates the resource group, or updates it if it already exists.
+    async fn ensure_resource_group(&self) -> Result<()> {
+        let body = json!({
+            "location": self.location,
+            "tags": { "galato-managed": "true" },
+        });
+        self.send(Method::PUT, &self.resource_group_url(), Some(&body))
+            .await
+            .map_err(|f| f.into_error("CreateResourceGroup"))?;
+        Ok(())
+    }
+
+    async fn get_cluster(&self, name: &str) -> Result<Option<ManagedCluster>> {
+        self.get(&self.cluster_url(name, ""))
+            .await
+            .map_err(|f| f.into_error("GetManagedCluster"))
+    }
+
+    async fn require_cluster(&self, name: &str) -> Result<ManagedCluster> {
+        self.get_cluster(name)
+            .await?
+            .ok_or_else(|| Error::ClusterNotFound(name.to_string()).into())
+    }
+
+    /// Reads an agent pool, changes it and writes it back, as ARM replaces
+    /// agent pools as a whole. Returns false if the pool does not exist.
+    async fn update_agent_pool(
+        &self,
+        cluster: &str,
+        pool: &str,
+        update: impl FnOnce(&mut Value),
+    ) -> Result<bool> {
+        let url = self.agent_pool_url(cluster, pool);
+        let Some(mut agent_pool) = self
+            .get::<Value>(&url)
+            .await
+            .map_err(|f| f.into_error("GetAgentPool"))?
+        else {
+            return Ok(false);
+        };
+        update(&mut agent_pool["properties"]);
+
+        let body = json!({ "properties": agent_pool["properties"] });
+        self.change(Method::PUT, &url, Some(&body))
+            .await
+            .map_err(|f| f.into_error("UpdateAgentPool"))?;
+        Ok(true)
+    }
+}
+
+/// AKS pool names are 1-12 lowercase letters and digits, starting with a letter.
+fn validate_pool_name(name: &str) -> Result<(), Error> {
+    let valid = name.len() <= 12
+        && name.starts_with(|c: char| c.is_ascii_lowercase())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit());
+    if !valid {
+        return Err(Error::InvalidConfig(format!(
+            "AKS node pool names must be 1-12 lowercase letters and digits starting with a letter, got {}",
+            name
+        )));
+    }
+    Ok(())
+}
+
+fn cluster_tags(config: &ClusterConfig) -> HashMap<String, String> {
+    let mut tags = config.tags.clone();
+    tags.insert(CLUSTER_TAG.to_string(), config.name.clone());
+    tags.insert(PROJECT_TAG.to_string(), config.project.clone());
+    tags
+}
+
+/// Size and autoscaling settings; autoscaling is only enabled for pools that
+/// may change size on their own.
+fn set_scaling(properties: &mut Value, pool: &NodePool) {
+    properties["count"] = json!(pool.node_count);
+    if pool.min_nodes < pool.max_nodes {
+        properties["enableAutoScaling"] = json!(true);
+        properties["minCount"] = json!(pool.min_nodes);
+        properties["maxCount"] = json!(pool.max_nodes);
+    } else {
+        properties["enableAutoScaling"] = json!(false);
+        properties["minCount"] = Value::Null;
+        properties["maxCount"] = Value::Null;
+    }
+}
+
+fn agent_pool_properties(pool: &NodePool, mode: &str, tags: &HashMap<String, String>) -> Value {
+    let taints: Vec<String> = pool
+        .taints
+        .iter()
+        .map(|taint| match &taint.value {
+            Some(value) => format!("{}={}:{}", taint.key, value, taint.effect.as_str()),
+            None => format!("{}:{}", taint.key, taint.effect.as_str()),
+        })
+        .collect();
+    let mut properties = json!({
+        "mode": mode,
+        "vmSize": pool.machine_type,
+        "osType": "Linux",
+        "type": "VirtualMachineScaleSets",
+        "nodeLabels": pool.labels,
+        "nodeTaints": taints,
+        "tags": tags,
+    });
+    set_scaling(&mut properties, pool);
+    properties
+}
+
+/// Combines the cluster and system pool states: a cluster only runs once both
+/// have provisioned and the cluster is powered on.
+fn cluster_state(cluster: &ManagedClusterProperties, nodes: Option<&str>) -> ClusterState {
+    let stopped = cluster
+        .power_state
+        .as_ref()
+        .is_some_and(|p| p.code == "Stopped");
+    match cluster.provisioning_state.as_str() {
+        "Creating" => ClusterState::Creating,
+        "Updating" | "Scaling" | "Upgrading" => ClusterState::Scaling,
+        "Deleting" => ClusterState::Deleting,
+        "Failed" | "Canceled" => ClusterState::Failed,
+        "Succeeded" if stopped => ClusterState::Failed,
+        "Succeeded" => match nodes {
+            None | Some("Creating") => ClusterState::Creating,
+            Some("Updating" | "Scaling" | "Upgrading") => ClusterState::Scaling,
+            Some("Deleting") => ClusterState::Deleting,
+            Some("Failed" | "Canceled") => ClusterState::Failed,
+            Some(_) => ClusterState::Running,
+        },
+        _ => ClusterState::Creating,
+    }
+}
+
+#[async_trait]
+impl InfrastructureProvider for AzureProvider {
+    async fn create_cluster(&self, name: &str, config: &ClusterConfig) -> Result<()> {
+        if config.region != self.location {
+            return Err(Error::InvalidConfig(format!(
+                "cluster {} is in {}, but the Azure provider manages {}",
+                name, config.region, self.location
+            ))
+            .into());
+        }
+        for pool in &config.node_pools {
+            validate_pool_name(&pool.name)?;
+        }
+        // ARM creates or replaces on PUT, so existing clusters are checked first
+        if self.get_cluster(name).await?.is_some() {
+            return Err(Error::ClusterAlreadyExists(name.to_string()).into());
+        }
+        tracing::info!("Creating Azure AKS cluster: {}", name);
+
+        self.ensure_resource_group().await?;
+
+        let tags = cluster_tags(config);
+        let default_pool = NodePool {
+            name: DEFAULT_AGENT_POOL.to_string(),
+            machine_type: config.node_type.clone(),
+            node_count: config.node_count,
+            min_nodes: config.node_count,
+            max_nodes: config.node_count,
+            labels: Default::default(),
+            taints: Vec::new(),
+        };
+        let profile = |pool: &NodePool, mode: &str| {
+            let mut profile = agent_pool_properties(pool, mode, &tags);
+            profile["name"] = json!(pool.name);
+            profile
+        };
+        let mut profiles = vec![profile(&default_pool, "System")];
+        profiles.extend(config.node_pools.iter().map(|pool| profile(pool, "User")));
+
+        let body = json!({
+            "location": self.location,
+            "tags": tags,
+            "identity": { "type": "SystemAssigned" },
+            "properties": {
+                "kubernetesVersion": config.kubernetes_version,
+                "dnsPrefix": name,
+                "agentPoolProfiles": profiles,
+            },
+        });
+        self.change(Method::PUT, &self.cluster_url(name, ""), Some(&body))
+            .await
+            .map_err(|f| f.into_error("CreateManagedCluster"))?;
+        Ok(())
+    }
+
+    async fn delete_cluster(&self, name: &str) -> Result<()> {
+        tracing::info!("Deleting Azure AKS cluster: {}", name);
+        // Agent pools are deleted with the cluster
+        let status = self
+            .change(Method::DELETE, &self.cluster_url(name, ""), None)
+            .await
+            .map_err(|f| f.into_error("DeleteManagedCluster"))?;
+        // ARM answers 204 when there was nothing to delete
+        if status == StatusCode::NO_CONTENT {
+            return Err(Error::ClusterNotFound(name.to_string()).into());
+        }
+        Ok(())
+    }
+
+    async fn get_cluster_status(&self, name: &str) -> Result<ClusterStatus> {
+        let Some(cluster) = self.get_cluster(name).await? else {
+            // The cluster was deleted outside galato
+            return Ok(ClusterStatus {
+                name: name.to_string(),
+                project: String::new(),
+                state: ClusterState::Failed,
+                node_count: 0,
+                version: String::new(),
+                endpoint: String::new(),
+                message: None,
+            });
+        };
+
+        let properties = cluster.properties;
+        let nodes = properties
+            .agent_pool_profiles
+            .iter()
+            .find(|p| p.name == DEFAULT_AGENT_POOL);
+        let endpoint = if properties.fqdn.is_empty() {
+            String::new()
+        } else {
+            format!("https://{}", properties.fqdn)
+        };
+
+        Ok(ClusterStatus {
+            name: name.to_string(),
+            project: String::new(),
+            state: cluster_state(&properties, nodes.map(|p| p.provisioning_state.as_str())),
+            node_count: nodes.map(|p| p.count).unwrap_or_default(),
+            version: properties.current_kubernetes_version.clone(),
+            endpoint,
+            message: None,
+        })
+    }
+
+    async fn scale_cluster(&self, name: &str, node_count: i32) -> Result<()> {
+        tracing::info!("Scaling Azure AKS cluster {} to {} nodes", name, node_count);
+        let updated = self
+            .update_agent_pool(name, DEFAULT_AGENT_POOL, |properties| {
+                properties["count"] = json!(node_count);
+            })
+            .await?;
+        if !updated {
+            return Err(Error::ClusterNotFound(name.to_string()).into());
+        }
+        Ok(())
+    }
+
+    async fn create_node_pool(&self, cluster: &str, pool: &NodePool) -> Result<()> {
+        tracing::info!(
+            "Creating Azure AKS node pool {} in cluster {}",
+            pool.name,
+            cluster
+        );
+        validate_pool_name(&pool.name)?;
+        if pool.name == DEFAULT_AGENT_POOL {
+            return Err(Error::InvalidConfig(format!(
+                "node pool name {} is reserved",
+                DEFAULT_AGENT_POOL
+            ))
+            .into());
+        }
+        // Agent pools carry the same tags as their cluster
+        let tags = self.require_cluster(cluster).await?.tags;
+
+        let url = self.agent_pool_url(cluster, &pool.name);
+        let existing: Option<Value> = self
+            .get(&url)
+            .await
+            .map_err(|f| f.into_error("GetAgentPool"))?;
+        if existing.is_some() {
+            return Err(Error::NodePoolAlreadyExists {
+                cluster: cluster.to_string(),
+                pool: pool.name.clone(),
+            }
+            .into());
+        }
+
+        let body = json!({ "properties": agent_pool_properties(pool, "User", &tags) });
+        self.change(Method::PUT, &url, Some(&body))
+            .await
+            .map_err(|f| f.into_error("CreateAgentPool"))?;
+        Ok(())
+    }
+
+    async fn resize_node_pool(&self, cluster: &str, pool: &NodePool) -> Result<()> {
+        tracing::info!(
+            "Resizing Azure AKS node pool {} in cluster {} to {} nodes",
+            pool.name,
+            cluster,
+            pool.node_count
+        );
+        let updated = self
+            .update_agent_pool(cluster, &pool.name, |properties| {
+                set_scaling(properties, pool)
+            })
+            .await?;
+        if !updated {
+            return Err(Error::NodePoolNotFound {
+                cluster: cluster.to_string(),
+                pool: pool.name.clone(),
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    async fn delete_node_pool(&self, cluster: &str, pool: &str) -> Result<()> {
+        tracing::info!(
+            "Deleting Azure AKS node pool {} from cluster {}",
+            pool,
+            cluster
+        );
+        let status = self
+            .change(Method::DELETE, &self.agent_pool_url(cluster, pool), None)
+            .await
+            .map_err(|f| f.into_error("DeleteAgentPool"))?;
+        if status == StatusCode::NO_CONTENT {
+            return Err(Error::NodePoolNotFound {
+                cluster: cluster.to_string(),
+                pool: pool.to_string(),
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    async fn get_kubeconfig(&self, name: &str) -> Result<String> {
+        let url = self.cluster_url(name, "/listClusterUserCredential");
+        let response = match self.send(Method::POST, &url, None).await {
+            Ok(response) => response,
+            Err(Failure::Status(StatusCode::NOT_FOUND, _)) => {
+                return Err(Error::ClusterNotFound(name.to_string()).into())
+            }
+            Err(f) => return Err(f.into_error("ListClusterUserCredential")),
+        };
+        let credentials: CredentialResults = response.json().await?;
+        let kubeconfig = credentials
+            .kubeconfigs
+            .first()
+            .with_context(|| format!("AKS returned no kubeconfig for cluster {}", name))?;
+        let kubeconfig = BASE64
+            .decode(&kubeconfig.value)
+            .context("AKS returned a kubeconfig that is not base64")?;
+        String::from_utf8(kubeconfig).context("AKS returned a kubeconfig that is not UTF-8")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{collections::BTreeMap, sync::Arc};
+
+    use crate::{
+        infra::{node_pool::Taint, TaintEffect},
+        testing::{cluster_config, test_config, Replay},
+    };
+
+    const TOKEN: &str = include_str!("testdata/aks/token.json");
+    const RESOURCE_GROUP: &str = include_str!("testdata/aks/resource_group.json");
+    const CLUSTER_CREATING: &str = include_str!("testdata/aks/cluster_creating.json");
+    const CLUSTER_SUCCEEDED: &str = include_str!("testdata/aks/cluster_succeeded.json");
+    const AGENT_POOL: &str = include_str!("testdata/aks/agent_pool.json");
+    const OPERATION_IN_PROGRESS: &str = include_str!("testdata/aks/operation_in_progress.json");
+    const OPERATION_SUCCEEDED: &str = include_str!("testdata/aks/operation_succeeded.json");
+    const OPERATION_FAILED: &str = include_str!("testdata/aks/operation_failed.json");
+    const CREDENTIALS: &str = include_str!("testdata/aks/credentials.json");
+    const NOT_FOUND: &str = include_str!("testdata/aks/error_not_found.json");
+
+    const TENANT: &str = "11111111-1111-1111-1111-111111111111";
+    const RESOURCE_GROUP_PATH: &str =
+        "/subscriptions/00000000-0000-0000-0000-000000000001/resourcegroups/galato";
+    const CLUSTER: &str = "/subscriptions/00000000-0000-0000-0000-000000000001/resourceGroups/galato/providers/Microsoft.ContainerService/managedClusters/c1";
+    const OPERATION: &str = "/subscriptions/00000000-0000-0000-0000-000000000001/providers/Microsoft.ContainerService/locations/westeurope/operations/5e0f4d7c-8a1b-4c2d-9e3f-0a1b2c3d4e5f";
+    const ASYNC_OPERATION: (&str, &str) = (
+        "azure-asyncoperation",
+        "{base}/subscriptions/00000000-0000-0000-0000-000000000001/providers/Microsoft.ContainerService/locations/westeurope/operations/5e0f4d7c-8a1b-4c2d-9e3f-0a1b2c3d4e5f?api-version=2024-02-01",
+    );
+
+    /// Starts the replay server and a provider pointed at it.
+    async fn provider(replay: Replay) -> (AzureProvider, Arc<Replay>) {
+        let token_path = format!("/{}/oauth2/v2.0/token", TENANT);
+        let (url, replay) = replay
+            .respond("POST", &token_path, 200, TOKEN)
+            .start()
+            .await;
+
+        let principal = json!({
+            "clientId": "22222222-2222-2222-2222-222222222222",
+            "clientSecret": "recorded-test-secret",
+            "subscriptionId": "00000000-0000-0000-0000-000000000001",
+            "tenantId": TENANT,
+            "activeDirectoryEndpointUrl": url,
+            "resourceManagerEndpointUrl": "https://management.azure.com/",
+        });
+        let path = std::env::temp_dir().join(format!("galato-aks-{}.json", uuid::Uuid::new_v4()));
+        std::fs::write(&path, principal.to_string()).unwrap();
+
+        let mut config = test_config();
+        config.cloud.region = "westeurope".to_string();
+        config.cloud.credentials_path = path.to_string_lossy().into_owned();
+        config.cloud.azure.endpoint_url = Some(url);
+        let mut provider = AzureProvider::new(&config.cloud).unwrap();
+        provider.poll_interval = Duration::ZERO;
+        std::fs::remove_file(path).unwrap();
+
+        (provider, replay)
+    }
+
+    fn aks_cluster_config() -> ClusterConfig {
+        let mut config = cluster_config("c1");
+        config.region = "westeurope".to_string();
+        config.node_type = "Standard_D4s_v5".to_string();
+        config
+            .tags
+            .insert("team".to_string(), "platform".to_string());
+        config.node_pools.push(NodePool {
+            name: "sequencers".to_string(),
+            machine_type: "Standard_F16s_v2".to_string(),
+            node_count: 2,
+            min_nodes: 1,
+            max_nodes: 4,
+            labels: BTreeMap::from([("role".to_string(), "sequencer".to_string())]),
+            taints: vec![Taint {
+                key: "dedicated".to_string(),
+                value: Some("sequencer".to_string()),
+                effect: TaintEffect::NoSchedule,
+            }],
+        });
+        config
+    }
+
+    #[tokio::test]
+    async fn creates_clusters_in_the_resource_group_and_polls_the_operation() {
+        let (provider, replay) = provider(
+            Replay::default()
+                .respond("GET", CLUSTER, 404, NOT_FOUND)
+                .respond("PUT", RESOURCE_GROUP_PATH, 200, RESOURCE_GROUP)
+                .respond_with_headers("PUT", CLUSTER, 201, CLUSTER_CREATING, &[ASYNC_OPERATION])
+                .respond("GET", OPERATION, 200, OPERATION_IN_PROGRESS)
+                .respond("GET", OPERATION, 200, OPERATION_SUCCEEDED),
+        )
+        .await;
+
+        provider
+            .create_cluster("c1", &aks_cluster_config())
+            .await
+            .unwrap();
+
+        let token = &replay.received("POST", &format!("/{}/oauth2/v2.0/token", TENANT))[0];
+        assert!(token.body.contains("grant_type=client_credentials"));
+        assert!(token
+            .body
+            .contains("scope=https%3A%2F%2Fmanagement.azure.com%2F.default"));
+
+        let group = replay.received("PUT", RESOURCE_GROUP_PATH)[0].json();
+        assert_eq!(group["location"], "westeurope");
+
+        let create = &replay.received("PUT", CLUSTER)[0];
+        assert_eq!(create.query.as_deref(), Some("api-version=2024-02-01"));
+        let body = create.json();
+        assert_eq!(body["tags"]["galato-cluster"], "c1");
+        assert_eq!(body["properties"]["dnsPrefix"], "c1");
+
+        let profiles = &body["properties"]["agentPoolProfiles"];
+        assert_eq!(profiles[0]["name"], "galato");
+        assert_eq!(profiles[0]["mode"], "System");
+        assert_eq!(profiles[0]["count"], 3);
+        assert_eq!(profiles[0]["enableAutoScaling"], false);
+        assert_eq!(profiles[1]["name"], "sequencers");
+        assert_eq!(profiles[1]["mode"], "User");
+        assert_eq!(profiles[1]["minCount"], 1);
+        assert_eq!(
+            profiles[1]["nodeTaints"][0],
+            "dedicated=sequencer:NoSchedule"
+        );
+
+        assert_eq!(replay.received("GET", OPERATION).len(), 2);
+    }
+
+    #[tokio::test]
+    async fn existing_clusters_and_invalid_pool_names_are_rejected() {
+        let (provider, replay) =
+            provider(Replay::default().respond("GET", CLUSTER, 200, CLUSTER_SUCCEEDED)).await;
+
+        let err = provider
+            .create_cluster("c1", &aks_cluster_config())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::ClusterAlreadyExists(name)) if name == "c1"
+        ));
+        assert!(replay.received("PUT", CLUSTER).is_empty());
+
+        let mut config = aks_cluster_config();
+        config.node_pools[0].name = "sequencer-pool".to_string();
+        let err = provider.create_cluster("c2", &config).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::InvalidConfig(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn succeeded_clusters_map_to_status() {
+        let credentials = format!("{}/listClusterUserCredential", CLUSTER);
+        let (provider, _) = provider(
+            Replay::default()
+                .respond("GET", CLUSTER, 200, CLUSTER_SUCCEEDED)
+                .respond("POST", &credentials, 200, CREDENTIALS),
+        )
+        .await;
+
+        let status = provider.get_cluster_status("c1").await.unwrap();
+        assert_eq!(status.state.as_str(), "Running");
+        assert_eq!(status.node_count, 3);
+        assert_eq!(status.version, "1.28.9");
+        assert_eq!(
+            status.endpoint,
+            "https://c1-a1b2c3d4.hcp.westeurope.azmk8s.io"
+        );
+
+        let kubeconfig = provider.get_kubeconfig("c1").await.unwrap();
+        assert!(kubeconfig.contains("server: https://c1-a1b2c3d4.hcp.westeurope.azmk8s.io:443"));
+    }
+
+    #[tokio::test]
+    async fn scaling_rewrites_the_agent_pool_and_reports_failed_operations() {
+        let pool = format!("{}/agentPools/galato", CLUSTER);
+        let (provider, replay) = provider(
+            Replay::default()
+                .respond("GET", &pool, 200, AGENT_POOL)
+                .respond_with_headers("PUT", &pool, 200, AGENT_POOL, &[ASYNC_OPERATION])
+                .respond("GET", OPERATION, 200, OPERATION_FAILED),
+        )
+        .await;
+
+        let err = provider.scale_cluster("c1", 6).await.unwrap_err();
+        assert!(format!("{:#}", err).contains("exceeding approved standardDSv5Family Cores quota"));
+
+        let body = replay.received("PUT", &pool)[0].json();
+        assert_eq!(body["properties"]["count"], 6);
+        assert_eq!(body["properties"]["vmSize"], "Standard_D4s_v5");
+    }
+
+    #[tokio::test]
+    async fn deletions_follow_location_polling_and_report_missing_resources() {
+        let pool = format!("{}/agentPools/sequencers", CLUSTER);
+        let result = "/subscriptions/00000000-0000-0000-0000-000000000001/providers/Microsoft.ContainerService/locations/westeurope/operationresults/5e0f4d7c-8a1b-4c2d-9e3f-0a1b2c3d4e5f";
+        let (provider, replay) = provider(
+            Replay::default()
+                .respond_with_headers(
+                    "DELETE",
+                    &pool,
+                    202,
+                    "",
+                    &[(
+                        "location",
+                        "{base}/subscriptions/00000000-0000-0000-0000-000000000001/providers/Microsoft.ContainerService/locations/westeurope/operationresults/5e0f4d7c-8a1b-4c2d-9e3f-0a1b2c3d4e5f?api-version=2024-02-01",
+                    )],
+                )
+                .respond("GET", result, 202, "")
+                .respond("GET", result, 200, "")
+                .respond("DELETE", CLUSTER, 204, ""),
+        )
+        .await;
+
+        provider.delete_node_pool("c1", "sequencers").await.unwrap();
+        assert_eq!(replay.received("GET", result).len(), 2);
+
+        let err = provider.delete_cluster("c1").await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::ClusterNotFound(name)) if name == "c1"
+        ));
+    }
+}
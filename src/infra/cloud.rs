@@ -1,153 +0,0 @@
-use anyhow::Result;
-use async_trait::async_trait;
-use std::sync::Arc;
-
-use crate::config::{Config, CloudProvider};
-use super::{InfrastructureProvider, ClusterConfig, ClusterStatus, ClusterState};
-
-pub struct AwsProvider {
-    config: Arc<Config>,
-}
-
-impl AwsProvider {
-    pub fn new(config: &Config) -> Result<Self> {
-        Ok(Self {
-            config: Arc::new(config.clone()),
-        })
-    }
-}
-
-#[async_trait]
-impl InfrastructureProvider for AwsProvider {
-    async fn create_cluster(&self, name: &str, config: &ClusterConfig) -> Result<()> {
-        // Implement AWS EKS cluster creation
-        tracing::info!("Creating AWS EKS cluster: {}", name);
-        // TODO: Implement actual AWS EKS cluster creation
-        Ok(())
-    }
-
-    async fn delete_cluster(&self, name: &str) -> Result<()> {
-        // Implement AWS EKS cluster deletion
-        tracing::info!("Deleting AWS EKS cluster: {}", name);
-        // TODO: Implement actual AWS EKS cluster deletion
-        Ok(())
-    }
-
-    async fn get_cluster_status(&self, name: &str) -> Result<ClusterStatus> {
-        // Implement AWS EKS cluster status check
-        tracing::info!("Getting AWS EKS cluster status: {}", name);
-        // TODO: Implement actual AWS EKS cluster status check
-        Ok(ClusterStatus {
-            name: name.to_string(),
-            state: ClusterState::Running,
-            node_count: 3,
-            version: "1.28".to_string(),
-            endpoint: "https://eks.example.com".to_string(),
-        })
-    }
-
-    async fn scale_cluster(&self, name: &str, node_count: i32) -> Result<()> {
-        // Implement AWS EKS cluster scaling
-        tracing::info!("Scaling AWS EKS cluster {} to {} nodes", name, node_count);
-        // TODO: Implement actual AWS EKS cluster scaling
-        Ok(())
-    }
-}
-
-pub struct GcpProvider {
-    config: Arc<Config>,
-}
-
-impl GcpProvider {
-    pub fn new(config: &Config) -> Result<Self> {
-        Ok(Self {
-            config: Arc::new(config.clone()),
-        })
-    }
-}
-
-#[async_trait]
-impl InfrastructureProvider for GcpProvider {
-    async fn create_cluster(&self, name: &str, config: &ClusterConfig) -> Result<()> {
-        // Implement GCP GKE cluster creation
-        tracing::info!("Creating GCP GKE cluster: {}", name);
-        // TODO: Implement actual GCP GKE cluster creation
-        Ok(())
-    }
-
-    async fn delete_cluster(&self, name: &str) -> Result<()> {
-        // Implement GCP GKE cluster deletion
-        tracing::info!("Deleting GCP GKE cluster: {}", name);
-        // TODO: Implement actual GCP GKE cluster deletion
-        Ok(())
-    }
-
-    async fn get_cluster_status(&self, name: &str) -> Result<ClusterStatus> {
-        // Implement GCP GKE cluster status check
-        tracing::info!("Getting GCP GKE cluster status: {}", name);
-        // TODO: Implement actual GCP GKE cluster status check
-        Ok(ClusterStatus {
-            name: name.to_string(),
-            state: ClusterState::Running,
-            node_count: 3,
-            version: "1.28".to_string(),
-            endpoint: "https://gke.example.com".to_string(),
-        })
-    }
-
-    async fn scale_cluster(&self, name: &str, node_count: i32) -> Result<()> {
-        // Implement GCP GKE cluster scaling
-        tracing::info!("Scaling GCP GKE cluster {} to {} nodes", name, node_count);
-        // TODO: Implement actual GCP GKE cluster scaling
-        Ok(())
-    }
-}
-
-pub struct AzureProvider {
-    config: Arc<Config>,
-}
-
-impl AzureProvider {
-    pub fn new(config: &Config) -> Result<Self> {
-        Ok(Self {
-            config: Arc::new(config.clone()),
-        })
-    }
-}
-
-#[async_trait]
-impl InfrastructureProvider for AzureProvider {
-    async fn create_cluster(&self, name: &str, config: &ClusterConfig) -> Result<()> {
-        // Implement Azure AKS cluster creation
-        tracing::info!("Creating Azure AKS cluster: {}", name);
-        // TODO: Implement actual Azure AKS cluster creation
-        Ok(())
-    }
-
-    async fn delete_cluster(&self, name: &str) -> Result<()> {
-        // Implement Azure AKS cluster deletion
-        tracing::info!("Deleting Azure AKS cluster: {}", name);
-        // TODO: Implement actual Azure AKS cluster deletion
-        Ok(())
-    }
-
-    async fn get_cluster_status(&self, name: &str) -> Result<ClusterStatus> {
-        // Implement Azure AKS cluster status check
-        tracing::info!("Getting Azure AKS cluster status: {}", name);
-        // TODO: Implement actual Azure AKS cluster status check
-        Ok(ClusterStatus {
-            name: name.to_string(),
-            state: ClusterState::Running,
-            node_count: 3,
-            version: "1.28".to_string(),
-            endpoint: "https://aks.example.com".to_string(),
-        })
-    }
-
-    async fn scale_cluster(&self, name: &str, node_count: i32) -> Result<()> {
-        // Implement Azure AKS cluster scaling
-        tracing::info!("Scaling Azure AKS cluster {} to {} nodes", name, node_count);
-        // TODO: Implement actual Azure AKS cluster scaling
-        Ok(())
-    }
-} 
\ No newline at end of file
@@ -0,0 +1,24 @@
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("cluster {0} not found")]
+    ClusterNotFound(String),
+    #[error("cluster {0} already exists")]
+    ClusterAlreadyExists(String),
+    #[error("cluster {cluster} still runs rollups {}", rollups.join(", "))]
+    ClusterInUse {
+        cluster: String,
+        rollups: Vec<String>,
+    },
+    #[error("node pool {pool} not found in cluster {cluster}")]
+    NodePoolNotFound { cluster: String, pool: String },
+    #[error("node pool {pool} already exists in cluster {cluster}")]
+    NodePoolAlreadyExists { cluster: String, pool: String },
+    #[error("{field} of cluster {cluster} cannot be changed")]
+    ImmutableField { cluster: String, field: &'static str },
+    #[error("cloud account {0} is not configured")]
+    UnknownAccount(String),
+    #[error("invalid cluster configuration: {0}")]
+    InvalidConfig(String),
+    #[error("cloud provider unavailable: {0}")]
+    ProviderUnavailable(String),
+}
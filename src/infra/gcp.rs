@@ -0,0 +1,923 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use reqwest::{Method, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::Duration,
+};
+use tokio::{sync::Mutex, time::Instant};
+
+use super::{
+    ClusterConfig, ClusterState, ClusterStatus, Error, InfrastructureProvider, NodePool,
+    TaintEffect,
+};
+use crate::config::CloudConfig;
+
+const DEFAULT_ENDPOINT: &str = "https://container.googleapis.com";
+const DEFAULT_TOKEN_URI: &str = "https://oauth2.googleapis.com/token";
+const SCOPE: &str = "https://www.googleapis.com/auth/cloud-platform";
+const JWT_BEARER: &str = "urn:ietf:params:oauth:grant-type:jwt-bearer";
+
+/// How long before expiry access tokens are replaced.
+const TOKEN_MARGIN: Duration = Duration::from_secs(15 * 60);
+
+/// Node pool created with every cluster. It holds the cluster's `node_count`
+/// nodes of `node_type`; each node pool is a GKE node pool of its own.
+const DEFAULT_NODE_POOL: &str = "galato-default";
+
+/// Labels added to every cluster and node next to `ClusterConfig::tags`. GCP
+/// labels cannot contain `/` or `.`, unlike the tags used on other clouds.
+const CLUSTER_LABEL: &str = "galato-cluster";
+const PROJECT_LABEL: &str = "galato-project";
+
+/// How long to wait for a GKE operation before giving up.
+const OPERATION_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Runs clusters on GKE through its REST API, authenticated as the service
+/// account in `credentials_path`. One provider serves the account's
+/// `region`, which can be a region or a zone. In regional clusters GKE
+/// runs `node_count` nodes per zone.
+pub struct GcpProvider {
+    http: reqwest::Client,
+    endpoint: String,
+    project: String,
+    location: String,
+    network: Option<String>,
+    subnetwork: Option<String>,
+    account: ServiceAccountKey,
+    signing_key: EncodingKey,
+    token: Mutex<Option<AccessToken>>,
+    poll_interval: Duration,
+}
+
+/// The fields of a service account key file that galato uses.
+#[derive(Deserialize)]
+struct ServiceAccountKey {
+    client_email: String,
+    private_key: String,
+    project_id: String,
+    #[serde(default = "default_token_uri")]
+    token_uri: String,
+}
+
+fn default_token_uri() -> String {
+    DEFAULT_TOKEN_URI.to_string()
+}
+
+#[derive(Serialize)]
+struct TokenClaims<'a> {
+    iss: &'a str,
+    scope: &'a str,
+    aud: &'a str,
+    iat: i64,
+    exp: i64,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: u64,
+}
+
+struct AccessToken {
+    value: String,
+    expires_at: Instant,
+}
+
+#[derive(Debug, Deserialize)]
+struct Operation {
+    name: String,
+    #[serde(default)]
+    status: String,
+    error: Option<OperationError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OperationError {
+    #[serde(default)]
+    message: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Cluster {
+    #[serde(default)]
+    status: String,
+    #[serde(default)]
+    endpoint: String,
+    #[serde(default)]
+    current_master_version: String,
+    #[serde(default)]
+    current_node_count: i32,
+    master_auth: Option<MasterAuth>,
+    #[serde(default)]
+    node_pools: Vec<ClusterNodePool>,
+    #[serde(default)]
+    resource_labels: HashMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MasterAuth {
+    cluster_ca_certificate: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClusterNodePool {
+    name: String,
+    #[serde(default)]
+    status: String,
+}
+
+#[derive(Deserialize)]
+struct ErrorBody {
+    error: ErrorDetail,
+}
+
+#[derive(Deserialize)]
+struct ErrorDetail {
+    message: String,
+}
+
+/// A GKE call that did not succeed.
+#[derive(Debug)]
+enum Failure {
+    /// GKE answered with an error status
+    Status(StatusCode, String),
+    /// GKE could not be reached or is overloaded
+    Unavailable(String),
+    Other(anyhow::Error),
+}
+
+impl Failure {
+    fn into_error(self, action: &str) -> anyhow::Error {
+        match self {
+            Failure::Status(status, message) => {
+                anyhow::anyhow!("{} failed with {}: {}", action, status, message)
+            }
+            Failure::Unavailable(message) => {
+                Error::ProviderUnavailable(format!("{} failed: {}", action, message)).into()
+            }
+            Failure::Other(e) => e.context(format!("{} failed", action)),
+        }
+    }
+}
+
+impl GcpProvider {
+    pub fn new(cloud: &CloudConfig) -> Result<Self> {
+        let credentials = cloud.credentials_file();
+        let path = credentials.display();
+        let key = std::fs::read_to_string(&credentials)
+            .with_context(|| format!("failed to read service account key {}", path))?;
+        let account: ServiceAccountKey = serde_json::from_str(&key)
+            .with_context(|| format!("failed to parse service account key {}", path))?;
+        let signing_key = EncodingKey::from_rsa_pem(account.private_key.as_bytes())
+            .with_context(|| format!("invalid private key in {}", path))?;
+
+        let gcp = &cloud.gcp;
+        Ok(Self {
+            http: reqwest::Client::new(),
+            endpoint: gcp
+                .endpoint_url
+                .clone()
+                .unwrap_or_else(|| DEFAULT_ENDPOINT.to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            project: gcp
+                .project_id
+                .clone()
+                .unwrap_or_else(|| account.project_id.clone()),
+            location: cloud.region.clone(),
+            network: gcp.network.clone(),
+            subnetwork: gcp.subnetwork.clone(),
+            account,
+            signing_key,
+            token: Mutex::new(None),
+            poll_interval: POLL_INTERVAL,
+        })
+    }
+
+    /// Exchanges a signed JWT for an OAuth access token, reusing it until
+    /// shortly before it expires.
+    async fn access_token(&self) -> Result<String> {
+        let mut token = self.token.lock().await;
+        if let Some(token) = token.as_ref().filter(|t| t.expires_at > Instant::now()) {
+            return Ok(token.value.clone());
+        }
+
+        let now = Utc::now().timestamp();
+        let claims = TokenClaims {
+            iss: &self.account.client_email,
+            scope: SCOPE,
+            aud: &self.account.token_uri,
+            iat: now,
+            exp: now + 3600,
+        };
+        let assertion =
+            jsonwebtoken::encode(&Header::new(Algorithm::RS256), &claims, &self.signing_key)?;
+
+        let response = self
+            .http
+            .post(&self.account.token_uri)
+            .form(&[
+                ("grant_type", JWT_BEARER),
+                ("assertion", assertion.as_str()),
+            ])
+            .send()
+            .await
+            .map_err(|e| {
+                Error::ProviderUnavailable(format!(
+                    "cannot reach {}: {}",
+                    self.account.token_uri, e
+                ))
+            })?;
+        if !response.status().is_success() {
+            anyhow::bail!(
+                "service account {} was refused an access token: {}",
+                self.account.client_email,
+                response.text().await.unwrap_or_default()
+            );
+        }
+        let issued: TokenResponse = response.json().await?;
+
+        // Refresh well before expiry: besides requests, the token goes into
+        // the kubeconfigs of cached cluster connections
+        *token = Some(AccessToken {
+            value: issued.access_token.clone(),
+            expires_at: Instant::now()
+                + Duration::from_secs(issued.expires_in.saturating_sub(TOKEN_MARGIN.as_secs())),
+        });
+        Ok(issued.access_token)
+    }
+
+    async fn call<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Value>,
+    ) -> Result<T, Failure> {
+        let token = self.access_token().await.map_err(Failure::Other)?;
+        let mut request = self
+            .http
+            .request(method, format!("{}/v1/{}", self.endpoint, path))
+            .bearer_auth(token);
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+
+        let response = request
+            .send()
+            .await
+            .map_err(|e| Failure::Unavailable(e.to_string()))?;
+        let status = response.status();
+        if status.is_success() {
+            return response.json().await.map_err(|e| Failure::Other(e.into()));
+        }
+
+        let message = response
+            .json::<ErrorBody>()
+            .await
+            .map(|body| body.error.message)
+            .unwrap_or_else(|_| status.to_string());
+        if status == StatusCode::SERVICE_UNAVAILABLE || status == StatusCode::TOO_MANY_REQUESTS {
+            Err(Failure::Unavailable(message))
+        } else {
+            Err(Failure::Status(status, message))
+        }
+    }
+
+    fn location_path(&self) -> String {
+        format!("projects/{}/locations/{}", self.project, self.location)
+    }
+
+    fn cluster_path(&self, name: &str) -> String {
+        format!("{}/clusters/{}", self.location_path(), name)
+    }
+
+    fn node_pool_path(&self, cluster: &str, pool: &str) -> String {
+        format!("{}/nodePools/{}", self.cluster_path(cluster), pool)
+    }
+
+    async fn get_cluster(&self, name: &str) -> Result<Option<Cluster>> {
+        match self.call(Method::GET, &self.cluster_path(name), None).await {
+            Ok(cluster) => Ok(Some(cluster)),
+            Err(Failure::Status(StatusCode::NOT_FOUND, _)) => Ok(None),
+            Err(f) => Err(f.into_error("GetCluster")),
+        }
+    }
+
+    async fn require_cluster(&self, name: &str) -> Result<Cluster> {
+        self.get_cluster(name)
+            .await?
+            .ok_or_else(|| Error::ClusterNotFound(name.to_string()).into())
+    }
+
+    /// Polls an operation until GKE reports it done.
+    /// API server endpoint and certificate authority data of a cluster.
+    async fn api_server(&self, name: &str) -> Result<(String, String)> {
+        let cluster = self.require_cluster(name).await?;
+        if cluster.endpoint.is_empty() {
+            anyhow::bail!("cluster {} has no API endpoint yet", name);
+        }
+        let certificate_authority = cluster
+            .master_auth
+            .and_then(|auth| auth.cluster_ca_certificate)
+            .with_context(|| format!("cluster {} has no certificate authority yet", name))?;
+        Ok((cluster.endpoint, certificate_authority))
+    }
+
+    async fn wait_for_operation(&self, mut operation: Operation) -> Result<()> {
+        let deadline = Instant::now() + OPERATION_TIMEOUT;
+        loop {
+            if operation.status == "DONE" {
+                if let Some(error) = operation.error {
+                    anyhow::bail!("GKE operation {} failed: {}", operation.name, error.message);
+                }
+                return Ok(());
+            }
+            if Instant::now() >= deadline {
+                anyhow::bail!(
+                    "GKE operation {} did not finish within {} minutes",
+                    operation.name,
+                    OPERATION_TIMEOUT.as_secs() / 60
+                );
+            }
+            tokio::time::sleep(self.poll_interval).await;
+
+            let path = format!("{}/operations/{}", self.location_path(), operation.name);
+            operation = self
+                .call(Method::GET, &path, None)
+                .await
+                .map_err(|f| f.into_error("GetOperation"))?;
+        }
+    }
+
+    /// Runs a node pool call and waits for its operation, reporting a missing
+    /// pool as `NodePoolNotFound`.
+    async fn node_pool_operation(
+        &self,
+        action: &str,
+        method: Method,
+        cluster: &str,
+        pool: &str,
+        path: &str,
+        body: Option<Value>,
+    ) -> Result<()> {
+        match self.call(method, path, body).await {
+            Ok(operation) => self.wait_for_operation(operation).await,
+            Err(Failure::Status(StatusCode::NOT_FOUND, _)) => Err(Error::NodePoolNotFound {
+                cluster: cluster.to_string(),
+                pool: pool.to_string(),
+            }
+            .into()),
+            Err(f) => Err(f.into_error(action)),
+        }
+    }
+}
+
+/// GCP labels allow lowercase letters, digits, `_` and `-`, up to 63
+/// characters; anything else becomes `_`.
+fn label(value: &str) -> String {
+    value
+        .to_lowercase()
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | '0'..='9' | '_' | '-' => c,
+            _ => '_',
+        })
+        .take(63)
+        .collect()
+}
+
+fn cluster_labels(config: &ClusterConfig) -> HashMap<String, String> {
+    let mut labels: HashMap<String, String> = config
+        .tags
+        .iter()
+        .map(|(key, value)| (label(key), label(value)))
+        .collect();
+    labels.insert(CLUSTER_LABEL.to_string(), label(&config.name));
+    labels.insert(PROJECT_LABEL.to_string(), label(&config.project));
+    labels
+}
+
+fn taint_effect(effect: &TaintEffect) -> &'static str {
+    match effect {
+        TaintEffect::NoSchedule => "NO_SCHEDULE",
+        TaintEffect::PreferNoSchedule => "PREFER_NO_SCHEDULE",
+        TaintEffect::NoExecute => "NO_EXECUTE",
+    }
+}
+
+/// Autoscaling is only enabled for pools that may change size on their own.
+fn autoscaling(pool: &NodePool) -> Value {
+    json!({
+        "enabled": pool.min_nodes < pool.max_nodes,
+        "minNodeCount": pool.min_nodes,
+        "maxNodeCount": pool.max_nodes,
+    })
+}
+
+fn node_pool_body(pool: &NodePool, labels: &HashMap<String, String>) -> Value {
+    let taints: Vec<Value> = pool
+        .taints
+        .iter()
+        .map(|taint| {
+            json!({
+                "key": taint.key,
+                "value": taint.value.clone().unwrap_or_default(),
+                "effect": taint_effect(&taint.effect),
+            })
+        })
+        .collect();
+    json!({
+        "name": pool.name,
+        "initialNodeCount": pool.node_count,
+        "config": {
+            "machineType": pool.machine_type,
+            "labels": pool.labels,
+            "resourceLabels": labels,
+            "taints": taints,
+        },
+        "autoscaling": autoscaling(pool),
+    })
+}
+
+/// Combines the cluster and default node pool states: a cluster only runs
+/// once both are running.
+fn cluster_state(cluster: &str, nodes: Option<&str>) -> ClusterState {
+    match cluster {
+        "PROVISIONING" => ClusterState::Creating,
+        "RECONCILING" => ClusterState::Scaling,
+        "STOPPING" => ClusterState::Deleting,
+        "ERROR" | "DEGRADED" => ClusterState::Failed,
+        "RUNNING" => match nodes {
+            None | Some("PROVISIONING") => ClusterState::Creating,
+            Some("RECONCILING") => ClusterState::Scaling,
+            Some("STOPPING") => ClusterState::Deleting,
+            Some("ERROR" | "RUNNING_WITH_ERROR") => ClusterState::Failed,
+            Some(_) => ClusterState::Running,
+        },
+        _ => ClusterState::Creating,
+    }
+}
+
+/// Kubeconfig in the form `gcloud container clusters get-credentials` writes.
+/// Tokens come from `gke-gcloud-auth-plugin`, so its user needs Google
+/// credentials with access to the cluster.
+fn kubeconfig(name: &str, endpoint: &str, certificate_authority: &str) -> Result<String> {
+    let user = json!({
+        "exec": {
+            "apiVersion": "client.authentication.k8s.io/v1beta1",
+            "command": "gke-gcloud-auth-plugin",
+            "installHint": "Install gke-gcloud-auth-plugin with `gcloud components install gke-gcloud-auth-plugin`",
+            "provideClusterInfo": true,
+        },
+    });
+    kubeconfig_with(name, endpoint, certificate_authority, user)
+}
+
+fn kubeconfig_with(
+    name: &str,
+    endpoint: &str,
+    certificate_authority: &str,
+    user: Value,
+) -> Result<String> {
+    let config = json!({
+        "apiVersion": "v1",
+        "kind": "Config",
+        "clusters": [{
+            "name": name,
+            "cluster": {
+                "server": format!("https://{}", endpoint),
+                "certificate-authority-data": certificate_authority,
+            },
+        }],
+        "users": [{ "name": name, "user": user }],
+        "contexts": [{
+            "name": name,
+            "context": { "cluster": name, "user": name },
+        }],
+        "current-context": name,
+    });
+    Ok(serde_yaml::to_string(&config)?)
+}
+
+#[async_trait]
+impl InfrastructureProvider for GcpProvider {
+    async fn create_cluster(&self, name: &str, config: &ClusterConfig) -> Result<()> {
+        if config.region != self.location {
+            return Err(Error::InvalidConfig(format!(
+                "cluster {} is in {}, but the GCP provider manages {}",
+                name, config.region, self.location
+            ))
+            .into());
+        }
+        tracing::info!("Creating GCP GKE cluster: {}", name);
+
+        let labels = cluster_labels(config);
+        let default_pool = NodePool {
+            name: DEFAULT_NODE_POOL.to_string(),
+            machine_type: config.node_type.clone(),
+            node_count: config.node_count,
+            min_nodes: config.node_count,
+            max_nodes: config.node_count,
+            labels: BTreeMap::new(),
+            taints: Vec::new(),
+        };
+        // Every pool is created with the cluster, as GKE runs one operation
+        // per cluster at a time
+        let node_pools: Vec<Value> = std::iter::once(&default_pool)
+            .chain(&config.node_pools)
+            .map(|pool| node_pool_body(pool, &labels))
+            .collect();
+
+        let mut cluster = json!({
+            "name": name,
+            "initialClusterVersion": config.kubernetes_version,
+            "resourceLabels": labels,
+            "nodePools": node_pools,
+        });
+        if let Some(network) = &self.network {
+            cluster["network"] = json!(network);
+        }
+        if let Some(subnetwork) = &self.subnetwork {
+            cluster["subnetwork"] = json!(subnetwork);
+        }
+
+        let path = format!("{}/clusters", self.location_path());
+        let body = json!({ "cluster": cluster });
+        match self.call(Method::POST, &path, Some(body)).await {
+            Ok(operation) => self.wait_for_operation(operation).await,
+            Err(Failure::Status(StatusCode::CONFLICT, _)) => {
+                Err(Error::ClusterAlreadyExists(name.to_string()).into())
+            }
+            Err(f) => Err(f.into_error("CreateCluster")),
+        }
+    }
+
+    async fn delete_cluster(&self, name: &str) -> Result<()> {
+        tracing::info!("Deleting GCP GKE cluster: {}", name);
+        // Node pools are deleted with the cluster
+        match self
+            .call(Method::DELETE, &self.cluster_path(name), None)
+            .await
+        {
+            Ok(operation) => self.wait_for_operation(operation).await,
+            Err(Failure::Status(StatusCode::NOT_FOUND, _)) => {
+                Err(Error::ClusterNotFound(name.to_string()).into())
+            }
+            Err(f) => Err(f.into_error("DeleteCluster")),
+        }
+    }
+
+    async fn get_cluster_status(&self, name: &str) -> Result<ClusterStatus> {
+        let Some(cluster) = self.get_cluster(name).await? else {
+            // The cluster was deleted outside galato
+            return Ok(ClusterStatus {
+                name: name.to_string(),
+                project: String::new(),
+                state: ClusterState::Failed,
+                node_count: 0,
+                version: String::new(),
+                endpoint: String::new(),
+                message: None,
+            });
+        };
+
+        let nodes = cluster
+            .node_pools
+            .iter()
+            .find(|p| p.name == DEFAULT_NODE_POOL)
+            .map(|p| p.status.as_str());
+        let endpoint = if cluster.endpoint.is_empty() {
+            String::new()
+        } else {
+            format!("https://{}", cluster.endpoint)
+        };
+
+        Ok(ClusterStatus {
+            name: name.to_string(),
+            project: String::new(),
+            state: cluster_state(&cluster.status, nodes),
+            node_count: cluster.current_node_count,
+            version: cluster.current_master_version,
+            endpoint,
+            message: None,
+        })
+    }
+
+    async fn scale_cluster(&self, name: &str, node_count: i32) -> Result<()> {
+        tracing::info!("Scaling GCP GKE cluster {} to {} nodes", name, node_count);
+        let path = format!("{}:setSize", self.node_pool_path(name, DEFAULT_NODE_POOL));
+        let body = json!({ "nodeCount": node_count });
+        match self.call(Method::POST, &path, Some(body)).await {
+            Ok(operation) => self.wait_for_operation(operation).await,
+            Err(Failure::Status(StatusCode::NOT_FOUND, _)) => {
+                Err(Error::ClusterNotFound(name.to_string()).into())
+            }
+            Err(f) => Err(f.into_error("SetNodePoolSize")),
+        }
+    }
+
+    async fn create_node_pool(&self, cluster: &str, pool: &NodePool) -> Result<()> {
+        tracing::info!(
+            "Creating GCP GKE node pool {} in cluster {}",
+            pool.name,
+            cluster
+        );
+        if pool.name == DEFAULT_NODE_POOL {
+            return Err(Error::InvalidConfig(format!(
+                "node pool name {} is reserved",
+                DEFAULT_NODE_POOL
+            ))
+            .into());
+        }
+        // Nodes carry the same labels as their cluster
+        let labels = self.require_cluster(cluster).await?.resource_labels;
+
+        let path = format!("{}/nodePools", self.cluster_path(cluster));
+        let body = json!({ "nodePool": node_pool_body(pool, &labels) });
+        match self.call(Method::POST, &path, Some(body)).await {
+            Ok(operation) => self.wait_for_operation(operation).await,
+            Err(Failure::Status(StatusCode::CONFLICT, _)) => Err(Error::NodePoolAlreadyExists {
+                cluster: cluster.to_string(),
+                pool: pool.name.clone(),
+            }
+            .into()),
+            Err(f) => Err(f.into_error("CreateNodePool")),
+        }
+    }
+
+    async fn resize_node_pool(&self, cluster: &str, pool: &NodePool) -> Result<()> {
+        tracing::info!(
+            "Resizing GCP GKE node pool {} in cluster {} to {} nodes",
+            pool.name,
+            cluster,
+            pool.node_count
+        );
+        let path = self.node_pool_path(cluster, &pool.name);
+        self.node_pool_operation(
+            "SetNodePoolAutoscaling",
+            Method::POST,
+            cluster,
+            &pool.name,
+            &format!("{}:setAutoscaling", path),
+            Some(json!({ "autoscaling": autoscaling(pool) })),
+        )
+        .await?;
+        self.node_pool_operation(
+            "SetNodePoolSize",
+            Method::POST,
+            cluster,
+            &pool.name,
+            &format!("{}:setSize", path),
+            Some(json!({ "nodeCount": pool.node_count })),
+        )
+        .await
+    }
+
+    async fn delete_node_pool(&self, cluster: &str, pool: &str) -> Result<()> {
+        tracing::info!(
+            "Deleting GCP GKE node pool {} from cluster {}",
+            pool,
+            cluster
+        );
+        let path = self.node_pool_path(cluster, pool);
+        self.node_pool_operation("DeleteNodePool", Method::DELETE, cluster, pool, &path, None)
+            .await
+    }
+
+    async fn get_kubeconfig(&self, name: &str) -> Result<String> {
+        let (endpoint, certificate_authority) = self.api_server(name).await?;
+        kubeconfig(name, &endpoint, &certificate_authority)
+    }
+
+    /// Authenticates with the service account's own access token, which
+    /// GKE accepts for accounts granted access to the cluster.
+    async fn get_service_kubeconfig(&self, name: &str) -> Result<String> {
+        let (endpoint, certificate_authority) = self.api_server(name).await?;
+        let user = json!({ "token": self.access_token().await? });
+        kubeconfig_with(name, &endpoint, &certificate_authority, user)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kube::config::Kubeconfig;
+    use std::sync::Arc;
+
+    use crate::{
+        infra::node_pool::Taint,
+        testing::{cluster_config, test_config, Replay},
+    };
+
+    const TEST_KEY: &str = include_str!("testdata/gke/test_key.pem");
+    const TOKEN: &str = include_str!("testdata/gke/token.json");
+    const OPERATION_RUNNING: &str = include_str!("testdata/gke/operation_running.json");
+    const OPERATION_DONE: &str = include_str!("testdata/gke/operation_done.json");
+    const OPERATION_FAILED: &str = include_str!("testdata/gke/operation_failed.json");
+    const CLUSTER_RUNNING: &str = include_str!("testdata/gke/cluster_running.json");
+    const NOT_FOUND: &str = include_str!("testdata/gke/error_not_found.json");
+    const CONFLICT: &str = include_str!("testdata/gke/error_conflict.json");
+
+    const CLUSTERS: &str = "/v1/projects/galato-test/locations/us-central1/clusters";
+    const OPERATION: &str = "/v1/projects/galato-test/locations/us-central1/operations/operation-1718000000000-5f3c2a1b";
+
+    /// Starts the replay server and a provider pointed at it.
+    async fn provider(replay: Replay) -> (GcpProvider, Arc<Replay>) {
+        let (url, replay) = replay.respond("POST", "/token", 200, TOKEN).start().await;
+
+        let key = json!({
+            "type": "service_account",
+            "project_id": "galato-test",
+            "private_key": TEST_KEY,
+            "client_email": "galato@galato-test.iam.gserviceaccount.com",
+            "token_uri": format!("{}/token", url),
+        });
+        let path = std::env::temp_dir().join(format!("galato-gke-{}.json", uuid::Uuid::new_v4()));
+        std::fs::write(&path, key.to_string()).unwrap();
+
+        let mut config = test_config();
+        config.cloud.region = "us-central1".to_string();
+        config.cloud.credentials_path = path.to_string_lossy().into_owned();
+        config.cloud.gcp.endpoint_url = Some(url);
+        let mut provider = GcpProvider::new(&config.cloud).unwrap();
+        provider.poll_interval = Duration::ZERO;
+        std::fs::remove_file(path).unwrap();
+
+        (provider, replay)
+    }
+
+    fn gke_cluster_config() -> ClusterConfig {
+        let mut config = cluster_config("c1");
+        config.region = "us-central1".to_string();
+        config.node_type = "e2-standard-4".to_string();
+        config
+            .tags
+            .insert("Team".to_string(), "Platform".to_string());
+        config.node_pools.push(NodePool {
+            name: "sequencers".to_string(),
+            machine_type: "c2-standard-16".to_string(),
+            node_count: 2,
+            min_nodes: 1,
+            max_nodes: 4,
+            labels: BTreeMap::from([("role".to_string(), "sequencer".to_string())]),
+            taints: vec![Taint {
+                key: "dedicated".to_string(),
+                value: Some("sequencer".to_string()),
+                effect: TaintEffect::NoSchedule,
+            }],
+        });
+        config
+    }
+
+    #[tokio::test]
+    async fn creates_clusters_with_every_pool_and_waits_for_the_operation() {
+        let (provider, replay) = provider(
+            Replay::default()
+                .respond("POST", CLUSTERS, 200, OPERATION_RUNNING)
+                .respond("GET", OPERATION, 200, OPERATION_DONE),
+        )
+        .await;
+
+        provider
+            .create_cluster("c1", &gke_cluster_config())
+            .await
+            .unwrap();
+
+        let token = &replay.received("POST", "/token")[0];
+        assert!(token
+            .body
+            .contains("grant_type=urn%3Aietf%3Aparams%3Aoauth%3Agrant-type%3Ajwt-bearer"));
+
+        let create = &replay.received("POST", CLUSTERS)[0];
+        assert_eq!(
+            create.authorization.as_deref(),
+            Some("Bearer ya29.c.recorded-test-token")
+        );
+        let body = create.json();
+        let cluster = &body["cluster"];
+        assert_eq!(cluster["initialClusterVersion"], "1.28");
+        assert_eq!(cluster["resourceLabels"]["galato-cluster"], "c1");
+        assert_eq!(cluster["resourceLabels"]["team"], "platform");
+
+        let default_pool = &cluster["nodePools"][0];
+        assert_eq!(default_pool["name"], "galato-default");
+        assert_eq!(default_pool["initialNodeCount"], 3);
+        assert_eq!(default_pool["autoscaling"]["enabled"], false);
+
+        let pool = &cluster["nodePools"][1];
+        assert_eq!(pool["config"]["machineType"], "c2-standard-16");
+        assert_eq!(pool["config"]["taints"][0]["effect"], "NO_SCHEDULE");
+        assert_eq!(pool["autoscaling"]["maxNodeCount"], 4);
+
+        assert_eq!(replay.received("GET", OPERATION).len(), 1);
+        // The token is reused for the operation poll
+        assert_eq!(replay.received("POST", "/token").len(), 1);
+    }
+
+    #[tokio::test]
+    async fn existing_clusters_are_reported_as_conflicts() {
+        let (provider, _) =
+            provider(Replay::default().respond("POST", CLUSTERS, 409, CONFLICT)).await;
+
+        let err = provider
+            .create_cluster("c1", &gke_cluster_config())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::ClusterAlreadyExists(name)) if name == "c1"
+        ));
+    }
+
+    #[tokio::test]
+    async fn running_clusters_map_to_status() {
+        let path = format!("{}/c1", CLUSTERS);
+        let (provider, _) =
+            provider(Replay::default().respond("GET", &path, 200, CLUSTER_RUNNING)).await;
+
+        let status = provider.get_cluster_status("c1").await.unwrap();
+        assert_eq!(status.state.as_str(), "Running");
+        assert_eq!(status.endpoint, "https://34.123.45.67");
+        assert_eq!(status.version, "1.28.9-gke.1000000");
+        assert_eq!(status.node_count, 9);
+
+        let kubeconfig =
+            Kubeconfig::from_yaml(&provider.get_kubeconfig("c1").await.unwrap()).unwrap();
+        let exec = kubeconfig.auth_infos[0]
+            .auth_info
+            .as_ref()
+            .and_then(|a| a.exec.as_ref())
+            .unwrap();
+        assert_eq!(exec.command.as_deref(), Some("gke-gcloud-auth-plugin"));
+
+        // galato connects with its own access token rather than the plugin
+        let yaml = provider.get_service_kubeconfig("c1").await.unwrap();
+        let kubeconfig = Kubeconfig::from_yaml(&yaml).unwrap();
+        let auth = kubeconfig.auth_infos[0].auth_info.as_ref().unwrap();
+        assert!(auth.exec.is_none() && auth.token.is_some());
+        assert!(yaml.contains("token: ya29.c.recorded-test-token"));
+    }
+
+    #[tokio::test]
+    async fn missing_clusters_and_pools_are_not_found() {
+        let (provider, _) = provider(
+            Replay::default()
+                .respond("GET", &format!("{}/missing", CLUSTERS), 404, NOT_FOUND)
+                .respond(
+                    "DELETE",
+                    &format!("{}/c1/nodePools/gone", CLUSTERS),
+                    404,
+                    NOT_FOUND,
+                ),
+        )
+        .await;
+
+        let status = provider.get_cluster_status("missing").await.unwrap();
+        assert_eq!(status.state.as_str(), "Failed");
+
+        let err = provider.delete_node_pool("c1", "gone").await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::NodePoolNotFound { pool, .. }) if pool == "gone"
+        ));
+    }
+
+    #[tokio::test]
+    async fn failed_operations_surface_their_error() {
+        let path = format!("{}/c1/nodePools/galato-default:setSize", CLUSTERS);
+        let (provider, replay) =
+            provider(Replay::default().respond("POST", &path, 200, OPERATION_FAILED)).await;
+
+        let err = provider.scale_cluster("c1", 12).await.unwrap_err();
+        assert!(err.to_string().contains("Insufficient regional quota"));
+
+        let body = replay.received("POST", &path)[0].json();
+        assert_eq!(body["nodeCount"], 12);
+    }
+
+    #[test]
+    fn cluster_and_pool_states_combine() {
+        assert_eq!(
+            cluster_state("RUNNING", Some("RUNNING")).as_str(),
+            "Running"
+        );
+        assert_eq!(
+            cluster_state("RUNNING", Some("RECONCILING")).as_str(),
+            "Scaling"
+        );
+        assert_eq!(cluster_state("RUNNING", None).as_str(), "Creating");
+        assert_eq!(
+            cluster_state("PROVISIONING", Some("RUNNING")).as_str(),
+            "Creating"
+        );
+        assert_eq!(cluster_state("ERROR", None).as_str(), "Failed");
+    }
+}
@@ -1,24 +1,137 @@
-use anyhow::Result;
-use k8s_openapi::api::core::v1::Pod;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use k8s_openapi::{
+    api::{
+        apps::v1::Deployment,
+        core::v1::{ConfigMap, Namespace, PersistentVolumeClaim, Service},
+    },
+    NamespaceResourceScope,
+};
 use kube::{
-    api::{Api, DeleteParams, PostParams},
-    Client, Config,
+    api::{
+        Api, ApiResource, DeleteParams, DynamicObject, GroupVersionKind, ListParams, Patch,
+        PatchParams,
+    },
+    discovery::{self, ApiCapabilities, Scope},
+    Client, Resource, ResourceExt,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Debug,
+    sync::Arc,
+    time::{Duration, Instant},
 };
-use std::sync::Arc;
+use tokio::sync::RwLock;
 
 use crate::config::Config;
 
+/// Field manager recorded on every object galato applies.
+pub const FIELD_MANAGER: &str = "galato";
+
+/// Outcome of applying a manifest, listing objects as `Kind/name`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ApplyReport {
+    pub created: Vec<String>,
+    pub updated: Vec<String>,
+    pub unchanged: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DeploymentReadiness {
+    pub replicas: i32,
+    pub ready_replicas: i32,
+    pub available_replicas: i32,
+    /// Set when the deployment controller reports it stopped progressing
+    pub failure: Option<String>,
+}
+
+impl DeploymentReadiness {
+    pub fn is_ready(&self) -> bool {
+        self.replicas > 0 && self.available_replicas >= self.replicas
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TeardownOptions {
+    /// Also delete PersistentVolumeClaims, discarding component data
+    pub include_volumes: bool,
+    /// Block until every matching object is gone, failing after this long
+    pub wait_timeout: Option<Duration>,
+}
+
+/// The Kubernetes operations the controller relies on, so that tests can run
+/// against an in-memory cluster.
+#[async_trait]
+pub trait KubernetesApi: Send + Sync {
+    async fn apply_manifest(&self, manifest: &str) -> Result<ApplyReport>;
+    async fn delete_by_selector(
+        &self,
+        namespace: &str,
+        selector: &str,
+        options: &TeardownOptions,
+    ) -> Result<Vec<String>>;
+    async fn delete_namespace(&self, name: &str) -> Result<()>;
+    async fn get_namespace_labels(&self, name: &str) -> Result<Option<BTreeMap<String, String>>>;
+    async fn get_deployment_readiness(
+        &self,
+        namespace: &str,
+        name: &str,
+    ) -> Result<Option<DeploymentReadiness>>;
+}
+
+/// Opens Kubernetes APIs for managed clusters from their kubeconfigs.
+#[async_trait]
+pub trait KubernetesConnector: Send + Sync {
+    async fn connect(&self, cluster: &str, kubeconfig: &str) -> Result<Arc<dyn KubernetesApi>>;
+}
+
+/// Connects to managed clusters with a `KubernetesManager` each, defaulting
+/// to galato's namespace like the management cluster.
+pub struct KubeconfigConnector {
+    namespace: String,
+}
+
+impl KubeconfigConnector {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            namespace: config.kubernetes.namespace.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl KubernetesConnector for KubeconfigConnector {
+    async fn connect(&self, cluster: &str, kubeconfig: &str) -> Result<Arc<dyn KubernetesApi>> {
+        let manager = KubernetesManager::from_kubeconfig(kubeconfig, &self.namespace)
+            .await
+            .with_context(|| format!("failed to connect to cluster {}", cluster))?;
+        Ok(Arc::new(manager))
+    }
+}
+
+/// Selects ring as the process-wide rustls backend. kube and the AWS SDK
+/// enable different backends, so rustls cannot pick one by itself and
+/// building a client would panic.
+pub fn install_crypto_provider() {
+    // Fails only when a provider is already installed
+    let _ = rustls::crypto::ring::default_provider().install_default();
+}
+
+const TEARDOWN_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
 pub struct KubernetesManager {
     client: Client,
     namespace: String,
+    discovered: RwLock<HashMap<GroupVersionKind, (ApiResource, ApiCapabilities)>>,
 }
 
 impl KubernetesManager {
     pub async fn new(config: &Config) -> Result<Self> {
         let kube_config = if config.kubernetes.in_cluster {
-            Config::incluster()?
+            kube::Config::incluster()?
         } else {
-            Config::from_kubeconfig(&kube::config::KubeConfigOptions {
+            kube::Config::from_kubeconfig(&kube::config::KubeConfigOptions {
                 context: Some(config.kubernetes.context.clone()),
                 ..Default::default()
             })
@@ -30,88 +143,290 @@ impl KubernetesManager {
         Ok(Self {
             client,
             namespace: config.kubernetes.namespace.clone(),
+            discovered: RwLock::new(HashMap::new()),
         })
     }
 
-    pub async fn apply_manifest(&self, name: &str, manifest: &str) -> Result<()> {
-        // Parse the manifest
-        let resources = kube::api::DynamicObject::parse_yaml(manifest)?;
+    /// Manager for a managed cluster reached through `kubeconfig`.
+    pub async fn from_kubeconfig(kubeconfig: &str, namespace: &str) -> Result<Self> {
+        let kubeconfig = kube::config::Kubeconfig::from_yaml(kubeconfig)?;
+        let kube_config = kube::Config::from_custom_kubeconfig(
+            kubeconfig,
+            &kube::config::KubeConfigOptions::default(),
+        )
+        .await?;
 
-        // Apply each resource
-        for resource in resources {
-            let api: Api<kube::api::DynamicObject> = Api::namespaced(
-                self.client.clone(),
-                &self.namespace,
-            );
+        Ok(Self {
+            client: Client::try_from(kube_config)?,
+            namespace: namespace.to_string(),
+            discovered: RwLock::new(HashMap::new()),
+        })
+    }
 
-            api.create(&PostParams::default(), &resource).await?;
+    pub fn client(&self) -> Client {
+        self.client.clone()
+    }
+
+    /// Server-side applies every document of a multi-document YAML manifest.
+    pub async fn apply_manifest(&self, manifest: &str) -> Result<ApplyReport> {
+        let mut report = ApplyReport::default();
+
+        // Parse everything first: the YAML deserializer cannot be held across
+        // an await
+        let mut objects = Vec::new();
+        for document in serde_yaml::Deserializer::from_str(manifest) {
+            let value = serde_yaml::Value::deserialize(document)?;
+            if value.is_null() {
+                continue;
+            }
+            objects.push(serde_yaml::from_value::<DynamicObject>(value)?);
+        }
+        for object in objects {
+            self.apply_object(object, &mut report).await?;
+        }
+
+        Ok(report)
+    }
+
+    async fn apply_object(&self, mut object: DynamicObject, report: &mut ApplyReport) -> Result<()> {
+        let types = object
+            .types
+            .clone()
+            .context("manifest object is missing apiVersion or kind")?;
+        let gvk = GroupVersionKind::try_from(&types)?;
+        let name = object.name_any();
+        let (resource, capabilities) = self.discover(&gvk).await?;
+
+        let api: Api<DynamicObject> = match capabilities.scope {
+            Scope::Namespaced => {
+                let namespace = object
+                    .metadata
+                    .namespace
+                    .get_or_insert_with(|| self.namespace.clone())
+                    .clone();
+                Api::namespaced_with(self.client.clone(), &namespace, &resource)
+            }
+            Scope::Cluster => Api::all_with(self.client.clone(), &resource),
+        };
+
+        let existing = api.get_opt(&name).await?;
+        let params = PatchParams::apply(FIELD_MANAGER).force();
+        let applied = api.patch(&name, &params, &Patch::Apply(&object)).await?;
+
+        let entry = format!("{}/{}", types.kind, name);
+        match existing {
+            None => report.created.push(entry),
+            Some(existing)
+                if existing.metadata.resource_version == applied.metadata.resource_version =>
+            {
+                report.unchanged.push(entry)
+            }
+            Some(_) => report.updated.push(entry),
         }
 
         Ok(())
     }
 
-    pub async fn delete_manifest(&self, name: &str) -> Result<()> {
-        let api: Api<kube::api::DynamicObject> = Api::namespaced(
-            self.client.clone(),
-            &self.namespace,
-        );
+    /// Resolves a kind to its API resource, caching results per kind.
+    async fn discover(&self, gvk: &GroupVersionKind) -> Result<(ApiResource, ApiCapabilities)> {
+        if let Some(found) = self.discovered.read().await.get(gvk) {
+            return Ok(found.clone());
+        }
+
+        let found = discovery::pinned_kind(&self.client, gvk)
+            .await
+            .with_context(|| format!("failed to discover API for {}/{}", gvk.api_version(), gvk.kind))?;
+        self.discovered.write().await.insert(gvk.clone(), found.clone());
+
+        Ok(found)
+    }
+
+    /// Deletes every object in `namespace` matching `selector` and returns
+    /// them as `Kind/name`.
+    pub async fn delete_by_selector(
+        &self,
+        namespace: &str,
+        selector: &str,
+        options: &TeardownOptions,
+    ) -> Result<Vec<String>> {
+        let mut deleted = Vec::new();
+        self.delete_labelled::<Deployment>(namespace, selector, &mut deleted).await?;
+        self.delete_labelled::<Service>(namespace, selector, &mut deleted).await?;
+        self.delete_labelled::<ConfigMap>(namespace, selector, &mut deleted).await?;
+        if options.include_volumes {
+            self.delete_labelled::<PersistentVolumeClaim>(namespace, selector, &mut deleted).await?;
+        }
+
+        if let Some(timeout) = options.wait_timeout {
+            self.wait_until_gone(namespace, selector, options.include_volumes, timeout).await?;
+        }
+
+        Ok(deleted)
+    }
+
+    async fn delete_labelled<K>(&self, namespace: &str, selector: &str, deleted: &mut Vec<String>) -> Result<()>
+    where
+        K: Resource<Scope = NamespaceResourceScope, DynamicType = ()>
+            + Clone
+            + DeserializeOwned
+            + Debug,
+    {
+        let api: Api<K> = Api::namespaced(self.client.clone(), namespace);
+        let objects = api.list(&ListParams::default().labels(selector)).await?;
 
-        api.delete(name, &DeleteParams::default()).await?;
+        for object in objects {
+            let name = object.name_any();
+            match api.delete(&name, &DeleteParams::background()).await {
+                Ok(_) => deleted.push(format!("{}/{}", K::kind(&()), name)),
+                // Already removed by someone else
+                Err(kube::Error::Api(e)) if e.code == 404 => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
 
         Ok(())
     }
 
-    pub async fn get_manifest_status(&self, name: &str) -> Result<String> {
-        let api: Api<kube::api::DynamicObject> = Api::namespaced(
-            self.client.clone(),
-            &self.namespace,
-        );
+    async fn count_labelled<K>(&self, namespace: &str, selector: &str) -> Result<usize>
+    where
+        K: Resource<Scope = NamespaceResourceScope, DynamicType = ()>
+            + Clone
+            + DeserializeOwned
+            + Debug,
+    {
+        let api: Api<K> = Api::namespaced(self.client.clone(), namespace);
+        Ok(api.list_metadata(&ListParams::default().labels(selector)).await?.items.len())
+    }
 
-        let resource = api.get(name).await?;
-        let status = serde_json::to_string_pretty(&resource.status)?;
+    async fn wait_until_gone(
+        &self,
+        namespace: &str,
+        selector: &str,
+        include_volumes: bool,
+        timeout: Duration,
+    ) -> Result<()> {
+        let started = Instant::now();
+        loop {
+            let mut remaining = self.count_labelled::<Deployment>(namespace, selector).await?
+                + self.count_labelled::<Service>(namespace, selector).await?
+                + self.count_labelled::<ConfigMap>(namespace, selector).await?;
+            if include_volumes {
+                remaining += self
+                    .count_labelled::<PersistentVolumeClaim>(namespace, selector)
+                    .await?;
+            }
 
-        Ok(status)
+            if remaining == 0 {
+                return Ok(());
+            }
+            if started.elapsed() >= timeout {
+                anyhow::bail!(
+                    "{} objects matching {} still exist after {}s",
+                    remaining,
+                    selector,
+                    timeout.as_secs()
+                );
+            }
+            tokio::time::sleep(TEARDOWN_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Labels of a namespace, or `None` when it does not exist.
+    pub async fn get_namespace_labels(
+        &self,
+        name: &str,
+    ) -> Result<Option<BTreeMap<String, String>>> {
+        let api: Api<Namespace> = Api::all(self.client.clone());
+        Ok(api.get_opt(name).await?.map(|namespace| namespace.labels().clone()))
+    }
+
+    /// Deletes a namespace and everything left in it.
+    pub async fn delete_namespace(&self, name: &str) -> Result<()> {
+        let api: Api<Namespace> = Api::all(self.client.clone());
+        match api.delete(name, &DeleteParams::background()).await {
+            Ok(_) => Ok(()),
+            Err(kube::Error::Api(e)) if e.code == 404 => Ok(()),
+            Err(e) => Err(e.into()),
+        }
     }
 
-    pub async fn get_pod_logs(&self, pod_name: &str) -> Result<String> {
-        let api: Api<Pod> = Api::namespaced(
-            self.client.clone(),
-            &self.namespace,
-        );
+    pub async fn get_deployment_readiness(
+        &self,
+        namespace: &str,
+        name: &str,
+    ) -> Result<Option<DeploymentReadiness>> {
+        let api: Api<Deployment> = Api::namespaced(self.client.clone(), namespace);
 
-        let logs = api.logs(pod_name, &Default::default()).await?;
-        Ok(logs)
+        let Some(deployment) = api.get_opt(name).await? else {
+            return Ok(None);
+        };
+
+        let replicas = deployment
+            .spec
+            .as_ref()
+            .and_then(|spec| spec.replicas)
+            .unwrap_or(1);
+        let status = deployment.status.unwrap_or_default();
+        let failure = status
+            .conditions
+            .unwrap_or_default()
+            .into_iter()
+            .find(|c| c.type_ == "Progressing" && c.status == "False")
+            .map(|c| {
+                c.message
+                    .or(c.reason)
+                    .unwrap_or_else(|| "deployment stopped progressing".to_string())
+            });
+
+        Ok(Some(DeploymentReadiness {
+            replicas,
+            ready_replicas: status.ready_replicas.unwrap_or(0),
+            available_replicas: status.available_replicas.unwrap_or(0),
+            failure,
+        }))
     }
+} 
 
-    pub async fn get_pod_status(&self, pod_name: &str) -> Result<String> {
-        let api: Api<Pod> = Api::namespaced(
-            self.client.clone(),
-            &self.namespace,
-        );
+#[async_trait]
+impl KubernetesApi for KubernetesManager {
+    async fn apply_manifest(&self, manifest: &str) -> Result<ApplyReport> {
+        KubernetesManager::apply_manifest(self, manifest).await
+    }
 
-        let pod = api.get(pod_name).await?;
-        let status = serde_json::to_string_pretty(&pod.status)?;
+    async fn delete_by_selector(
+        &self,
+        namespace: &str,
+        selector: &str,
+        options: &TeardownOptions,
+    ) -> Result<Vec<String>> {
+        KubernetesManager::delete_by_selector(self, namespace, selector, options).await
+    }
 
-        Ok(status)
+    async fn delete_namespace(&self, name: &str) -> Result<()> {
+        KubernetesManager::delete_namespace(self, name).await
     }
 
-    pub async fn list_pods(&self) -> Result<Vec<Pod>> {
-        let api: Api<Pod> = Api::namespaced(
-            self.client.clone(),
-            &self.namespace,
-        );
+    async fn get_namespace_labels(&self, name: &str) -> Result<Option<BTreeMap<String, String>>> {
+        KubernetesManager::get_namespace_labels(self, name).await
+    }
 
-        let pods = api.list(&Default::default()).await?;
-        Ok(pods.items)
+    async fn get_deployment_readiness(
+        &self,
+        namespace: &str,
+        name: &str,
+    ) -> Result<Option<DeploymentReadiness>> {
+        KubernetesManager::get_deployment_readiness(self, namespace, name).await
     }
+}
 
-    pub async fn delete_pod(&self, pod_name: &str) -> Result<()> {
-        let api: Api<Pod> = Api::namespaced(
-            self.client.clone(),
-            &self.namespace,
-        );
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        api.delete(pod_name, &DeleteParams::default()).await?;
-        Ok(())
+    #[tokio::test]
+    async fn clients_build_once_the_crypto_provider_is_installed() {
+        install_crypto_provider();
+        let config = kube::Config::new("https://127.0.0.1:6443".parse().unwrap());
+        Client::try_from(config).unwrap();
     }
-} 
\ No newline at end of file
+}
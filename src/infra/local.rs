@@ -0,0 +1,531 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use k8s_openapi::api::core::v1::{Node, Taint};
+use kube::{
+    api::{Api, ListParams, Patch, PatchParams},
+    config::{KubeConfigOptions, Kubeconfig},
+    Client, ResourceExt,
+};
+use serde::Deserialize;
+use std::{collections::HashMap, time::Duration};
+use tokio::process::Command;
+use uuid::Uuid;
+
+use super::{
+    ClusterConfig, ClusterState, ClusterStatus, Error, InfrastructureProvider, NodePool,
+    FIELD_MANAGER,
+};
+use crate::config::CloudConfig;
+
+/// Runtime and Kubernetes label marking the nodes of a node pool.
+const NODE_POOL_LABEL: &str = "galato.io/node-pool";
+
+/// How long to wait for new pool nodes to register before tainting them.
+const NODE_REGISTRATION_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Runs clusters as k3d containers on the local Docker daemon. Each cluster
+/// has one server; the remaining nodes of its initial node group are agents,
+/// and every node pool adds agents labelled with the pool name. Pools have
+/// no autoscaler, so they run exactly `node_count` nodes.
+pub struct LocalProvider {
+    k3d: String,
+    k3s_image: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct K3dCluster {
+    name: String,
+    #[serde(default)]
+    nodes: Vec<K3dNode>,
+}
+
+#[derive(Debug, Deserialize)]
+struct K3dNode {
+    name: String,
+    role: String,
+    #[serde(default)]
+    image: String,
+    #[serde(rename = "runtimeLabels", default)]
+    runtime_labels: HashMap<String, String>,
+    #[serde(rename = "State", default)]
+    state: K3dNodeState,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct K3dNodeState {
+    #[serde(rename = "Running", default)]
+    running: bool,
+}
+
+impl K3dNode {
+    fn is_kubernetes_node(&self) -> bool {
+        self.role == "server" || self.role == "agent"
+    }
+
+    fn node_pool(&self) -> Option<&str> {
+        self.runtime_labels.get(NODE_POOL_LABEL).map(String::as_str)
+    }
+}
+
+impl K3dCluster {
+    /// Kubernetes nodes of the initial node group, the server included.
+    fn initial_nodes(&self) -> Vec<&K3dNode> {
+        self.nodes
+            .iter()
+            .filter(|n| n.is_kubernetes_node() && n.node_pool().is_none())
+            .collect()
+    }
+
+    /// Running once every node of the initial node group runs; node pools
+    /// do not count towards the state of the cluster.
+    fn state(&self) -> ClusterState {
+        let nodes = self.initial_nodes();
+        let running = nodes.iter().filter(|n| n.state.running).count();
+        if running == 0 {
+            ClusterState::Failed
+        } else if running < nodes.len() {
+            ClusterState::Scaling
+        } else {
+            ClusterState::Running
+        }
+    }
+
+    /// Kubernetes version of the server, from images like
+    /// `rancher/k3s:v1.28.5-k3s1`.
+    fn version(&self) -> String {
+        self.nodes
+            .iter()
+            .find(|n| n.role == "server")
+            .and_then(|n| n.image.rsplit(':').next())
+            .map(|tag| {
+                tag.trim_start_matches('v')
+                    .split("-k3s")
+                    .next()
+                    .unwrap_or(tag)
+            })
+            .unwrap_or_default()
+            .to_string()
+    }
+}
+
+impl LocalProvider {
+    pub fn new(cloud: &CloudConfig) -> Result<Self> {
+        Ok(Self {
+            k3d: cloud.local.k3d_binary.clone(),
+            k3s_image: cloud.local.k3s_image.clone(),
+        })
+    }
+
+    async fn k3d(&self, args: &[&str]) -> Result<String> {
+        let output = Command::new(&self.k3d)
+            .args(args)
+            .output()
+            .await
+            .map_err(|e| Error::ProviderUnavailable(format!("cannot run {}: {}", self.k3d, e)))?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "{} {} failed: {}",
+                self.k3d,
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        String::from_utf8(output.stdout).context("k3d printed invalid UTF-8")
+    }
+
+    async fn find_cluster(&self, name: &str) -> Result<Option<K3dCluster>> {
+        let clusters: Vec<K3dCluster> =
+            serde_json::from_str(&self.k3d(&["cluster", "list", "-o", "json"]).await?)
+                .context("failed to parse k3d cluster list")?;
+        Ok(clusters.into_iter().find(|c| c.name == name))
+    }
+
+    async fn require_cluster(&self, name: &str) -> Result<K3dCluster> {
+        self.find_cluster(name)
+            .await?
+            .ok_or_else(|| Error::ClusterNotFound(name.to_string()).into())
+    }
+
+    /// k3s image for a requested version such as `1.28.5`. Versions without a
+    /// patch level use the k3d default image.
+    fn image_for(&self, version: &str) -> Option<String> {
+        if let Some(image) = &self.k3s_image {
+            return Some(image.clone());
+        }
+        let version = version.trim_start_matches('v');
+        (version.split('.').count() == 3).then(|| format!("rancher/k3s:v{}-k3s1", version))
+    }
+
+    /// Adds `count` agents to the initial node group, or to `pool` when given.
+    async fn add_agents(&self, cluster: &str, pool: Option<&NodePool>, count: i32) -> Result<()> {
+        if count <= 0 {
+            return Ok(());
+        }
+        // k3d appends a replica index to the node name, so a random suffix keeps
+        // nodes added by separate calls apart
+        let suffix = &Uuid::new_v4().simple().to_string()[..8];
+        let name = match pool {
+            Some(pool) => format!("{}-{}-{}", cluster, pool.name, suffix),
+            None => format!("{}-agent-{}", cluster, suffix),
+        };
+        let replicas = count.to_string();
+
+        let mut args = vec![
+            "node".to_string(),
+            "create".to_string(),
+            name,
+            "--cluster".to_string(),
+            cluster.to_string(),
+            "--role".to_string(),
+            "agent".to_string(),
+            "--replicas".to_string(),
+            replicas,
+            "--wait".to_string(),
+        ];
+        if let Some(pool) = pool {
+            let pool_label = format!("{}={}", NODE_POOL_LABEL, pool.name);
+            args.extend(["--runtime-label".to_string(), pool_label.clone()]);
+            args.extend(["--k3s-node-label".to_string(), pool_label]);
+            for (key, value) in &pool.labels {
+                args.extend(["--k3s-node-label".to_string(), format!("{}={}", key, value)]);
+            }
+        }
+
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        self.k3d(&args).await?;
+
+        if let Some(pool) = pool {
+            self.taint_pool_nodes(cluster, pool).await?;
+        }
+        Ok(())
+    }
+
+    /// Removes agents from the initial node group, or from `pool` when given,
+    /// until `count` remain.
+    async fn remove_agents(&self, cluster: &str, pool: Option<&str>, count: i32) -> Result<()> {
+        let k3d_cluster = self.require_cluster(cluster).await?;
+        let mut agents: Vec<&K3dNode> = k3d_cluster
+            .nodes
+            .iter()
+            .filter(|n| n.role == "agent" && n.node_pool() == pool)
+            .collect();
+        agents.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let keep = usize::try_from(count.max(0)).unwrap_or_default();
+        for node in agents.iter().skip(keep) {
+            self.k3d(&["node", "delete", &node.name]).await?;
+        }
+        Ok(())
+    }
+
+    /// Agents currently in the initial node group or in `pool`.
+    async fn agent_count(&self, cluster: &str, pool: Option<&str>) -> Result<i32> {
+        let k3d_cluster = self.require_cluster(cluster).await?;
+        let count = k3d_cluster
+            .nodes
+            .iter()
+            .filter(|n| n.role == "agent" && n.node_pool() == pool)
+            .count();
+        Ok(i32::try_from(count)?)
+    }
+
+    /// k3d cannot taint nodes it creates, so pool taints are applied through
+    /// the cluster's API once the nodes have registered.
+    async fn taint_pool_nodes(&self, cluster: &str, pool: &NodePool) -> Result<()> {
+        if pool.taints.is_empty() {
+            return Ok(());
+        }
+
+        let kubeconfig = Kubeconfig::from_yaml(&self.get_kubeconfig(cluster).await?)?;
+        let config =
+            kube::Config::from_custom_kubeconfig(kubeconfig, &KubeConfigOptions::default()).await?;
+        let nodes: Api<Node> = Api::all(Client::try_from(config)?);
+        let selector = format!("{}={}", NODE_POOL_LABEL, pool.name);
+
+        let deadline = tokio::time::Instant::now() + NODE_REGISTRATION_TIMEOUT;
+        let registered = loop {
+            let registered = nodes
+                .list(&ListParams::default().labels(&selector))
+                .await?
+                .items;
+            if registered.len() >= usize::try_from(pool.node_count).unwrap_or_default() {
+                break registered;
+            }
+            if tokio::time::Instant::now() >= deadline {
+                anyhow::bail!(
+                    "only {} of {} nodes of pool {} registered with cluster {}",
+                    registered.len(),
+                    pool.node_count,
+                    pool.name,
+                    cluster
+                );
+            }
+            tokio::time::sleep(Duration::from_secs(2)).await;
+        };
+
+        for node in registered {
+            // Keep taints set by Kubernetes itself, replacing any from the pool
+            let mut taints: Vec<Taint> = node
+                .spec
+                .as_ref()
+                .and_then(|spec| spec.taints.clone())
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|t| pool.taints.iter().all(|p| p.key != t.key))
+                .collect();
+            taints.extend(pool.taints.iter().map(|taint| Taint {
+                key: taint.key.clone(),
+                value: taint.value.clone(),
+                effect: taint.effect.as_str().to_string(),
+                time_added: None,
+            }));
+
+            let patch = serde_json::json!({ "spec": { "taints": taints } });
+            let params = PatchParams {
+                field_manager: Some(FIELD_MANAGER.to_string()),
+                ..Default::default()
+            };
+            nodes
+                .patch(&node.name_any(), &params, &Patch::Merge(&patch))
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl InfrastructureProvider for LocalProvider {
+    async fn create_cluster(&self, name: &str, config: &ClusterConfig) -> Result<()> {
+        // k3d prefixes names with `k3d-` and keeps them within a DNS label
+        if name.len() > 32 {
+            return Err(Error::InvalidConfig(format!(
+                "local cluster name {} is longer than 32 characters",
+                name
+            ))
+            .into());
+        }
+        tracing::info!("Creating local k3d cluster: {}", name);
+
+        let agents = (config.node_count - 1).to_string();
+        let mut args = vec![
+            "cluster",
+            "create",
+            name,
+            "--servers",
+            "1",
+            "--agents",
+            &agents,
+            "--wait",
+            "--kubeconfig-update-default=false",
+            "--kubeconfig-switch-context=false",
+        ];
+        let image = self.image_for(&config.kubernetes_version);
+        if let Some(image) = &image {
+            args.extend(["--image", image.as_str()]);
+        }
+        self.k3d(&args).await?;
+
+        for pool in &config.node_pools {
+            self.add_agents(name, Some(pool), pool.node_count).await?;
+        }
+        Ok(())
+    }
+
+    async fn delete_cluster(&self, name: &str) -> Result<()> {
+        tracing::info!("Deleting local k3d cluster: {}", name);
+        self.k3d(&["cluster", "delete", name]).await?;
+        Ok(())
+    }
+
+    async fn get_cluster_status(&self, name: &str) -> Result<ClusterStatus> {
+        let Some(cluster) = self.find_cluster(name).await? else {
+            // The containers were removed outside galato
+            return Ok(ClusterStatus {
+                name: name.to_string(),
+                project: String::new(),
+                state: ClusterState::Failed,
+                node_count: 0,
+                version: String::new(),
+                endpoint: String::new(),
+                message: None,
+            });
+        };
+
+        let nodes = cluster.initial_nodes();
+        let state = cluster.state();
+        let endpoint = if !matches!(state, ClusterState::Failed) {
+            let kubeconfig = Kubeconfig::from_yaml(&self.get_kubeconfig(name).await?)?;
+            kubeconfig
+                .clusters
+                .into_iter()
+                .find_map(|c| c.cluster.and_then(|c| c.server))
+                .unwrap_or_default()
+        } else {
+            String::new()
+        };
+
+        Ok(ClusterStatus {
+            name: name.to_string(),
+            project: String::new(),
+            state,
+            node_count: i32::try_from(nodes.len())?,
+            version: cluster.version(),
+            endpoint,
+            message: None,
+        })
+    }
+
+    async fn scale_cluster(&self, name: &str, node_count: i32) -> Result<()> {
+        tracing::info!("Scaling local k3d cluster {} to {} nodes", name, node_count);
+        // The server counts towards the initial node group
+        let wanted = node_count - 1;
+        let current = self.agent_count(name, None).await?;
+        if wanted > current {
+            self.add_agents(name, None, wanted - current).await
+        } else {
+            self.remove_agents(name, None, wanted).await
+        }
+    }
+
+    async fn create_node_pool(&self, cluster: &str, pool: &NodePool) -> Result<()> {
+        tracing::info!(
+            "Creating local node pool {} in cluster {}",
+            pool.name,
+            cluster
+        );
+        self.require_cluster(cluster).await?;
+        self.add_agents(cluster, Some(pool), pool.node_count).await
+    }
+
+    async fn resize_node_pool(&self, cluster: &str, pool: &NodePool) -> Result<()> {
+        tracing::info!(
+            "Resizing local node pool {} in cluster {} to {} nodes",
+            pool.name,
+            cluster,
+            pool.node_count
+        );
+        let current = self.agent_count(cluster, Some(&pool.name)).await?;
+        if pool.node_count > current {
+            self.add_agents(cluster, Some(pool), pool.node_count - current)
+                .await
+        } else {
+            self.remove_agents(cluster, Some(&pool.name), pool.node_count)
+                .await
+        }
+    }
+
+    async fn delete_node_pool(&self, cluster: &str, pool: &str) -> Result<()> {
+        tracing::info!("Deleting local node pool {} from cluster {}", pool, cluster);
+        self.remove_agents(cluster, Some(pool), 0).await
+    }
+
+    async fn get_kubeconfig(&self, name: &str) -> Result<String> {
+        self.require_cluster(name).await?;
+        self.k3d(&["kubeconfig", "get", name]).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `k3d cluster list -o json` with a cluster whose pool is still starting,
+    /// one whose agent stopped and one whose containers all exited.
+    const CLUSTER_LIST: &str = include_str!("testdata/k3d/cluster_list.json");
+
+    fn cluster(name: &str) -> K3dCluster {
+        let clusters: Vec<K3dCluster> = serde_json::from_str(CLUSTER_LIST).unwrap();
+        clusters.into_iter().find(|c| c.name == name).unwrap()
+    }
+
+    fn provider(k3s_image: Option<&str>) -> LocalProvider {
+        LocalProvider {
+            k3d: "k3d".to_string(),
+            k3s_image: k3s_image.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn cluster_list_output_is_parsed() {
+        let clusters: Vec<K3dCluster> = serde_json::from_str(CLUSTER_LIST).unwrap();
+        let names: Vec<&str> = clusters.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["c1", "scaling", "stopped"]);
+
+        let c1 = cluster("c1");
+        assert_eq!(c1.nodes.len(), 6);
+        let server = &c1.nodes[0];
+        assert_eq!(server.name, "k3d-c1-server-0");
+        assert_eq!(server.image, "docker.io/rancher/k3s:v1.28.5-k3s1");
+        assert!(server.state.running);
+        assert!(server.is_kubernetes_node());
+        assert_eq!(server.node_pool(), None);
+
+        let pool_node = &c1.nodes[4];
+        assert_eq!(pool_node.role, "agent");
+        assert_eq!(pool_node.node_pool(), Some("workers"));
+        assert!(!pool_node.state.running);
+
+        let balancer = &c1.nodes[5];
+        assert_eq!(balancer.role, "loadbalancer");
+        assert!(!balancer.is_kubernetes_node());
+    }
+
+    #[test]
+    fn nodes_without_labels_or_state_are_parsed() {
+        let clusters: Vec<K3dCluster> =
+            serde_json::from_str(r#"[{"name": "c1", "nodes": [{"name": "n", "role": "agent"}]}]"#)
+                .unwrap();
+        let node = &clusters[0].nodes[0];
+        assert_eq!(node.node_pool(), None);
+        assert!(!node.state.running);
+        assert_eq!(clusters[0].version(), "");
+    }
+
+    #[test]
+    fn initial_node_group_excludes_pools_and_load_balancers() {
+        let c1 = cluster("c1");
+        let names: Vec<&str> = c1.initial_nodes().iter().map(|n| n.name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["k3d-c1-server-0", "k3d-c1-agent-0", "k3d-c1-agent-1"]
+        );
+    }
+
+    #[test]
+    fn state_follows_the_initial_node_group() {
+        // A pool node still starting does not hold up the cluster
+        assert!(matches!(cluster("c1").state(), ClusterState::Running));
+        assert!(matches!(cluster("scaling").state(), ClusterState::Scaling));
+        assert!(matches!(cluster("stopped").state(), ClusterState::Failed));
+    }
+
+    #[test]
+    fn version_is_read_from_the_server_image() {
+        assert_eq!(cluster("c1").version(), "1.28.5");
+        assert_eq!(cluster("scaling").version(), "1.27.9");
+    }
+
+    #[test]
+    fn images_are_derived_from_patch_versions() {
+        let local = provider(None);
+        assert_eq!(
+            local.image_for("1.28.5").as_deref(),
+            Some("rancher/k3s:v1.28.5-k3s1")
+        );
+        assert_eq!(
+            local.image_for("v1.27.9").as_deref(),
+            Some("rancher/k3s:v1.27.9-k3s1")
+        );
+        // k3d picks its default image for versions without a patch level
+        assert_eq!(local.image_for("1.28"), None);
+    }
+
+    #[test]
+    fn configured_image_overrides_the_version() {
+        let local = provider(Some("registry.local/k3s:custom"));
+        assert_eq!(
+            local.image_for("1.28.5").as_deref(),
+            Some("registry.local/k3s:custom")
+        );
+    }
+}
@@ -1,16 +1,40 @@
-mod cloud;
+mod aws;
+mod azure;
+mod error;
+mod gcp;
 mod kubernetes;
+mod local;
+mod node_pool;
+mod registry;
 
 use anyhow::Result;
 use async_trait::async_trait;
 use kube::Client;
-use std::sync::Arc;
-use tokio::sync::RwLock;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::{Mutex, OwnedMutexGuard, RwLock};
 
-use crate::config::Config;
+use crate::{
+    config::Config,
+    project::default_project,
+    store::{HistoryEntry, Store},
+};
 
-pub use cloud::CloudProvider;
-pub use kubernetes::KubernetesManager;
+pub use error::Error;
+pub use kubernetes::{
+    install_crypto_provider, ApplyReport, DeploymentReadiness, KubeconfigConnector, KubernetesApi,
+    KubernetesConnector, KubernetesManager, TeardownOptions, FIELD_MANAGER,
+};
+pub use node_pool::{NodePool, NodePoolSize, TaintEffect};
+pub use registry::{default_account, ProviderRegistry};
+
+/// How long a managed cluster connection is reused. Their kubeconfigs carry
+/// tokens that EKS honours for 15 minutes.
+const CLUSTER_API_TTL: Duration = Duration::from_secs(10 * 60);
 
 #[async_trait]
 pub trait InfrastructureProvider: Send + Sync {
@@ -18,28 +42,76 @@ pub trait InfrastructureProvider: Send + Sync {
     async fn delete_cluster(&self, name: &str) -> Result<()>;
     async fn get_cluster_status(&self, name: &str) -> Result<ClusterStatus>;
     async fn scale_cluster(&self, name: &str, node_count: i32) -> Result<()>;
+    async fn create_node_pool(&self, cluster: &str, pool: &NodePool) -> Result<()>;
+    async fn resize_node_pool(&self, cluster: &str, pool: &NodePool) -> Result<()>;
+    async fn delete_node_pool(&self, cluster: &str, pool: &str) -> Result<()>;
+
+    /// Kubeconfig granting admin access to the cluster.
+    async fn get_kubeconfig(&self, name: &str) -> Result<String> {
+        Err(Error::ProviderUnavailable(format!(
+            "the cloud provider cannot issue a kubeconfig for cluster {}",
+            name
+        ))
+        .into())
+    }
+
+    /// Kubeconfig galato itself connects with. Providers whose kubeconfigs
+    /// call out to a CLI plugin embed a short-lived token minted in-process
+    /// instead.
+    async fn get_service_kubeconfig(&self, name: &str) -> Result<String> {
+        self.get_kubeconfig(name).await
+    }
+
+    /// Sets the tags of `config` on an existing cluster and removes the
+    /// `removed` keys. Providers that only tag clusters when creating them
+    /// keep the tags they were created with.
+    async fn update_tags(
+        &self,
+        name: &str,
+        config: &ClusterConfig,
+        removed: &[String],
+    ) -> Result<()> {
+        let _ = (config, removed);
+        tracing::warn!("Tag changes of cluster {} only apply when it is recreated", name);
+        Ok(())
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClusterConfig {
     pub name: String,
+    /// Project owning the cluster
+    #[serde(default = "default_project")]
+    pub project: String,
+    /// Cloud account the cluster is created in; its provider must serve `region`
+    #[serde(default = "default_account")]
+    pub account: String,
     pub region: String,
+    /// Size and machine type of the node group created with the cluster
     pub node_count: i32,
     pub node_type: String,
     pub kubernetes_version: String,
     pub tags: std::collections::HashMap<String, String>,
+    /// Additional node pools, managed through the node pool routes after creation
+    #[serde(default)]
+    pub node_pools: Vec<NodePool>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ClusterStatus {
     pub name: String,
+    #[serde(default = "default_project")]
+    pub project: String,
     pub state: ClusterState,
     pub node_count: i32,
     pub version: String,
     pub endpoint: String,
+    /// Human readable reason for the current state, set when creation fails
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ClusterState {
     Creating,
     Running,
@@ -48,59 +120,877 @@ pub enum ClusterState {
     Deleting,
 }
 
+impl ClusterState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ClusterState::Creating => "Creating",
+            ClusterState::Running => "Running",
+            ClusterState::Scaling => "Scaling",
+            ClusterState::Failed => "Failed",
+            ClusterState::Deleting => "Deleting",
+        }
+    }
+}
+
+/// A cached connection to a managed cluster.
+struct ClusterApi {
+    api: Arc<dyn KubernetesApi>,
+    connected: Instant,
+}
+
 #[derive(Clone)]
 pub struct Controller {
-    config: Arc<Config>,
-    kubernetes: Arc<KubernetesManager>,
-    cloud_provider: Arc<dyn InfrastructureProvider>,
+    kubernetes: Arc<dyn KubernetesApi>,
+    /// Client for the management cluster; absent when running against a fake
+    kube_client: Option<Client>,
+    /// Opens Kubernetes APIs for managed clusters, cached in `cluster_apis`
+    connector: Arc<dyn KubernetesConnector>,
+    cluster_apis: Arc<RwLock<HashMap<String, ClusterApi>>>,
+    providers: ProviderRegistry,
+    store: Arc<dyn Store>,
+    /// Serializes changes to each cluster's stored configuration, which are
+    /// read, applied to the cloud and written back
+    config_locks: Arc<Mutex<HashMap<String, Arc<Mutex<()>>>>>,
 }
 
 impl Controller {
-    pub async fn new(config: &Config) -> Result<Self> {
+    pub async fn new(config: &Config, store: Arc<dyn Store>) -> Result<Self> {
         let kubernetes = KubernetesManager::new(config).await?;
-        let cloud_provider = match config.cloud.provider {
-            CloudProvider::Aws => Arc::new(cloud::AwsProvider::new(config)?),
-            CloudProvider::Gcp => Arc::new(cloud::GcpProvider::new(config)?),
-            CloudProvider::Azure => Arc::new(cloud::AzureProvider::new(config)?),
-        };
+        let kube_client = kubernetes.client();
+        let providers = ProviderRegistry::from_config(&config.cloud).await?;
+
+        let connector = Arc::new(KubeconfigConnector::new(config));
 
+        let mut controller =
+            Self::with_backends(Arc::new(kubernetes), connector, providers, store).await?;
+        controller.kube_client = Some(kube_client);
+        Ok(controller)
+    }
+
+    /// Builds a controller over the given Kubernetes and cloud backends.
+    pub async fn with_backends(
+        kubernetes: Arc<dyn KubernetesApi>,
+        connector: Arc<dyn KubernetesConnector>,
+        providers: ProviderRegistry,
+        store: Arc<dyn Store>,
+    ) -> Result<Self> {
         Ok(Self {
-            config: Arc::new(config.clone()),
-            kubernetes: Arc::new(kubernetes),
-            cloud_provider,
+            kubernetes,
+            kube_client: None,
+            connector,
+            cluster_apis: Arc::new(RwLock::new(HashMap::new())),
+            providers,
+            store,
+            config_locks: Arc::new(Mutex::new(HashMap::new())),
         })
     }
 
+    pub fn kube_client(&self) -> Option<Client> {
+        self.kube_client.clone()
+    }
+
     pub async fn create_cluster(&self, config: ClusterConfig) -> Result<()> {
-        self.cloud_provider.create_cluster(&config.name, &config).await?;
+        self.validate_new_cluster(&config).await?;
+        let provider = self.providers.get(&config.account)?;
+
+        // Record the cluster before creating it, so that no cloud cluster
+        // exists without a record to manage and delete it through
+        let status = ClusterStatus {
+            name: config.name.clone(),
+            project: config.project.clone(),
+            state: ClusterState::Creating,
+            node_count: config.node_count,
+            version: config.kubernetes_version.clone(),
+            endpoint: String::new(),
+            message: None,
+        };
+        self.store.insert_cluster(&config, &status).await?;
+
+        if let Err(e) = provider.create_cluster(&config.name, &config).await {
+            if let Some(Error::ProviderUnavailable(reason)) = e.downcast_ref() {
+                // The cloud may still be creating the cluster, so the record is
+                // kept for it to be inspected and deleted through
+                let failed = ClusterStatus {
+                    state: ClusterState::Failed,
+                    message: Some(format!(
+                        "creation did not complete and the cluster may still exist: {}",
+                        reason
+                    )),
+                    ..status
+                };
+                if let Err(record) = self.store.update_cluster_status(&failed).await {
+                    tracing::error!(
+                        "Failed to record that the creation of cluster {} failed: {:#}",
+                        config.name,
+                        record
+                    );
+                }
+            } else if let Err(cleanup) = self.store.delete_cluster(&config.name).await {
+                tracing::error!(
+                    "Failed to remove the record of cluster {} after its creation failed: {:#}",
+                    config.name,
+                    cleanup
+                );
+            }
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    /// Checks a configuration before the cloud provider is asked to create it.
+    pub async fn validate_new_cluster(&self, config: &ClusterConfig) -> Result<()> {
+        if self.store.get_cluster(&config.name).await?.is_some() {
+            return Err(Error::ClusterAlreadyExists(config.name.clone()).into());
+        }
+        if config.name.is_empty() || config.region.is_empty() || config.node_type.is_empty() {
+            return Err(Error::InvalidConfig("name, region and node_type are required".to_string()).into());
+        }
+        if config.node_count < 1 {
+            return Err(Error::InvalidConfig("node_count must be at least 1".to_string()).into());
+        }
+        node_pool::validate_node_pools(&config.node_pools)?;
+        self.providers.get(&config.account)?;
+
         Ok(())
     }
 
     pub async fn delete_cluster(&self, name: &str) -> Result<()> {
-        self.cloud_provider.delete_cluster(name).await?;
+        self.ensure_known_cluster(name).await?;
+        self.ensure_unused(name).await?;
+        match self.provider_of(name).await?.delete_cluster(name).await {
+            // Creation can fail before the cloud created anything
+            Err(e) if matches!(e.downcast_ref(), Some(Error::ClusterNotFound(_))) => {
+                tracing::info!("Cluster {} does not exist in the cloud, removing its record", name);
+            }
+            result => result?,
+        }
+        self.store.delete_cluster(name).await?;
+        self.cluster_apis.write().await.remove(name);
+        self.config_locks.lock().await.remove(name);
+
+        Ok(())
+    }
+
+    /// Fails while any rollup is placed on the cluster.
+    pub async fn ensure_unused(&self, name: &str) -> Result<()> {
+        let rollups: Vec<String> = self
+            .store
+            .list_rollups()
+            .await?
+            .into_iter()
+            .filter(|r| {
+                r.config.placement.cluster.as_deref() == Some(name)
+                    || r.status.cluster.as_deref() == Some(name)
+            })
+            .map(|r| r.config.name)
+            .collect();
+        if !rollups.is_empty() {
+            return Err(Error::ClusterInUse {
+                cluster: name.to_string(),
+                rollups,
+            }
+            .into());
+        }
         Ok(())
     }
 
     pub async fn get_cluster_status(&self, name: &str) -> Result<ClusterStatus> {
-        self.cloud_provider.get_cluster_status(name).await
+        let project = self.ensure_known_cluster(name).await?;
+        let mut status = self.provider_of(name).await?.get_cluster_status(name).await?;
+        // Providers do not know about projects
+        status.project = project;
+        self.record_cluster_status(&status).await?;
+        Ok(status)
+    }
+
+    pub async fn get_cluster_kubeconfig(&self, name: &str) -> Result<String> {
+        self.ensure_known_cluster(name).await?;
+        self.provider_of(name).await?.get_kubeconfig(name).await
+    }
+
+    pub async fn get_cluster_config(&self, name: &str) -> Result<Option<ClusterConfig>> {
+        Ok(self.store.get_cluster(name).await?.map(|record| record.config))
+    }
+
+    /// Replaces a cluster's configuration. Only the node count and tags can
+    /// change after creation; a new node count scales the cluster. Node pools
+    /// change through `add_node_pool`, `resize_node_pool` and `remove_node_pool`.
+    pub async fn update_cluster(&self, config: ClusterConfig) -> Result<()> {
+        let _guard = self.lock_config(&config.name).await;
+        let Some(existing) = self.get_cluster_config(&config.name).await? else {
+            return Err(Error::ClusterNotFound(config.name.clone()).into());
+        };
+
+        for (field, changed) in [
+            ("project", existing.project != config.project),
+            ("account", existing.account != config.account),
+            ("region", existing.region != config.region),
+            ("node_type", existing.node_type != config.node_type),
+            ("kubernetes_version", existing.kubernetes_version != config.kubernetes_version),
+            ("node_pools", existing.node_pools != config.node_pools),
+        ] {
+            if changed {
+                return Err(Error::ImmutableField {
+                    cluster: config.name.clone(),
+                    field,
+                }
+                .into());
+            }
+        }
+
+        if config.node_count < 1 {
+            return Err(Error::InvalidConfig("node_count must be at least 1".to_string()).into());
+        }
+        if existing.node_count != config.node_count {
+            self.scale_cluster(&config.name, config.node_count).await?;
+        }
+        if existing.tags != config.tags {
+            let removed: Vec<String> = existing
+                .tags
+                .keys()
+                .filter(|key| !config.tags.contains_key(*key))
+                .cloned()
+                .collect();
+            self.provider_of(&config.name)
+                .await?
+                .update_tags(&config.name, &config, &removed)
+                .await?;
+        }
+        self.store.update_cluster_config(&config).await?;
+
+        Ok(())
+    }
+
+    pub async fn list_clusters(&self) -> Result<Vec<ClusterStatus>> {
+        let clusters = self.store.list_clusters().await?;
+        Ok(clusters.into_iter().map(|record| record.status).collect())
+    }
+
+    pub async fn get_cluster_history(&self, name: &str) -> Result<Vec<HistoryEntry<ClusterStatus>>> {
+        self.store.cluster_history(name).await
     }
 
     pub async fn scale_cluster(&self, name: &str, node_count: i32) -> Result<()> {
-        self.cloud_provider.scale_cluster(name, node_count).await?;
+        self.provider_of(name).await?.scale_cluster(name, node_count).await?;
+
+        if let Some(record) = self.store.get_cluster(name).await? {
+            let mut status = record.status;
+            status.state = ClusterState::Scaling;
+            status.node_count = node_count;
+            self.record_cluster_status(&status).await?;
+        }
+
         Ok(())
     }
 
-    pub async fn deploy_application(&self, name: &str, manifest: &str) -> Result<()> {
-        self.kubernetes.apply_manifest(name, manifest).await?;
+    pub async fn add_node_pool(&self, cluster: &str, pool: NodePool) -> Result<()> {
+        let _guard = self.lock_config(cluster).await;
+        let mut config = self.require_cluster_config(cluster).await?;
+        if config.node_pools.iter().any(|p| p.name == pool.name) {
+            return Err(Error::NodePoolAlreadyExists {
+                cluster: cluster.to_string(),
+                pool: pool.name,
+            }
+            .into());
+        }
+        pool.validate()?;
+
+        self.providers
+            .get(&config.account)?
+            .create_node_pool(cluster, &pool)
+            .await?;
+        tracing::info!("Added node pool {} to cluster {}", pool.name, cluster);
+
+        config.node_pools.push(pool);
+        self.store.update_cluster_config(&config).await?;
         Ok(())
     }
 
-    pub async fn delete_application(&self, name: &str) -> Result<()> {
-        self.kubernetes.delete_manifest(name).await?;
+    pub async fn resize_node_pool(
+        &self,
+        cluster: &str,
+        pool: &str,
+        size: &NodePoolSize,
+    ) -> Result<NodePool> {
+        let _guard = self.lock_config(cluster).await;
+        let mut config = self.require_cluster_config(cluster).await?;
+        let existing = config
+            .node_pools
+            .iter_mut()
+            .find(|p| p.name == pool)
+            .ok_or_else(|| Error::NodePoolNotFound {
+                cluster: cluster.to_string(),
+                pool: pool.to_string(),
+            })?;
+        let resized = existing.resized(size);
+        resized.validate()?;
+
+        self.providers
+            .get(&config.account)?
+            .resize_node_pool(cluster, &resized)
+            .await?;
+        tracing::info!(
+            "Resized node pool {} of cluster {} to {} nodes ({}-{})",
+            pool,
+            cluster,
+            resized.node_count,
+            resized.min_nodes,
+            resized.max_nodes
+        );
+
+        *existing = resized.clone();
+        self.store.update_cluster_config(&config).await?;
+        Ok(resized)
+    }
+
+    pub async fn remove_node_pool(&self, cluster: &str, pool: &str) -> Result<()> {
+        let _guard = self.lock_config(cluster).await;
+        let mut config = self.require_cluster_config(cluster).await?;
+        if !config.node_pools.iter().any(|p| p.name == pool) {
+            return Err(Error::NodePoolNotFound {
+                cluster: cluster.to_string(),
+                pool: pool.to_string(),
+            }
+            .into());
+        }
+
+        self.providers
+            .get(&config.account)?
+            .delete_node_pool(cluster, pool)
+            .await?;
+        tracing::info!("Removed node pool {} from cluster {}", pool, cluster);
+
+        config.node_pools.retain(|p| p.name != pool);
+        self.store.update_cluster_config(&config).await?;
         Ok(())
     }
 
-    pub async fn get_application_status(&self, name: &str) -> Result<String> {
-        self.kubernetes.get_manifest_status(name).await
+    /// Holds off other changes to a cluster's configuration until dropped.
+    async fn lock_config(&self, name: &str) -> OwnedMutexGuard<()> {
+        let lock = self
+            .config_locks
+            .lock()
+            .await
+            .entry(name.to_string())
+            .or_default()
+            .clone();
+        lock.lock_owned().await
     }
-} 
\ No newline at end of file
+
+    async fn require_cluster_config(&self, name: &str) -> Result<ClusterConfig> {
+        self.get_cluster_config(name)
+            .await?
+            .ok_or_else(|| Error::ClusterNotFound(name.to_string()).into())
+    }
+
+    /// Provider of the account a cluster was created in.
+    async fn provider_of(&self, name: &str) -> Result<Arc<dyn InfrastructureProvider>> {
+        let config = self.require_cluster_config(name).await?;
+        Ok(self.providers.get(&config.account)?)
+    }
+
+    /// Returns the project of a known cluster.
+    async fn ensure_known_cluster(&self, name: &str) -> Result<String> {
+        Ok(self.require_cluster_config(name).await?.project)
+    }
+
+    async fn record_cluster_status(&self, status: &ClusterStatus) -> Result<()> {
+        self.store.update_cluster_status(status).await
+    }
+
+    /// Kubernetes API of a managed cluster, or of galato's own cluster for
+    /// `None`. Managed clusters are connected to with the kubeconfig issued
+    /// by their provider on first use, and again once `CLUSTER_API_TTL` has
+    /// passed.
+    async fn kubernetes_of(&self, cluster: Option<&str>) -> Result<Arc<dyn KubernetesApi>> {
+        let Some(cluster) = cluster else {
+            return Ok(self.kubernetes.clone());
+        };
+        if let Some(cached) = self.cluster_apis.read().await.get(cluster) {
+            if cached.connected.elapsed() < CLUSTER_API_TTL {
+                return Ok(cached.api.clone());
+            }
+        }
+
+        self.ensure_known_cluster(cluster).await?;
+        let kubeconfig = self
+            .provider_of(cluster)
+            .await?
+            .get_service_kubeconfig(cluster)
+            .await?;
+        let api = self.connector.connect(cluster, &kubeconfig).await?;
+        tracing::info!("Connected to cluster {}", cluster);
+
+        let cached = ClusterApi {
+            api: api.clone(),
+            connected: Instant::now(),
+        };
+        self.cluster_apis.write().await.insert(cluster.to_string(), cached);
+        Ok(api)
+    }
+
+    /// Drops a managed cluster's connection when a call through it failed
+    /// to reach the API server or was refused its credentials, so the next
+    /// call reconnects with fresh ones.
+    async fn check_connection<T>(&self, cluster: Option<&str>, result: Result<T>) -> Result<T> {
+        if let (Some(cluster), Err(err)) = (cluster, &result) {
+            if is_connection_error(err) {
+                tracing::warn!("Dropping the connection to cluster {}: {:#}", cluster, err);
+                self.cluster_apis.write().await.remove(cluster);
+            }
+        }
+        result
+    }
+
+    /// Applies a manifest to a managed cluster, or to galato's own cluster
+    /// for `None`.
+    pub async fn deploy_application(
+        &self,
+        cluster: Option<&str>,
+        name: &str,
+        manifest: &str,
+    ) -> Result<ApplyReport> {
+        let result = self.kubernetes_of(cluster).await?.apply_manifest(manifest).await;
+        let report = self.check_connection(cluster, result).await?;
+        tracing::info!(
+            "Applied {} to {}: {} created, {} updated, {} unchanged",
+            name,
+            cluster.unwrap_or("the management cluster"),
+            report.created.len(),
+            report.updated.len(),
+            report.unchanged.len()
+        );
+        Ok(report)
+    }
+
+    /// Removes every object in `namespace` labelled as belonging to an application.
+    pub async fn delete_application(
+        &self,
+        cluster: Option<&str>,
+        namespace: &str,
+        selector: &str,
+        options: &TeardownOptions,
+    ) -> Result<Vec<String>> {
+        let result = self
+            .kubernetes_of(cluster)
+            .await?
+            .delete_by_selector(namespace, selector, options)
+            .await;
+        let deleted = self.check_connection(cluster, result).await?;
+        tracing::info!(
+            "Deleted {} objects matching {} in {}",
+            deleted.len(),
+            selector,
+            namespace
+        );
+        Ok(deleted)
+    }
+
+    pub async fn delete_namespace(&self, name: &str) -> Result<()> {
+        self.kubernetes.delete_namespace(name).await?;
+        tracing::info!("Deleted namespace {}", name);
+        Ok(())
+    }
+
+    /// Labels of a namespace in galato's cluster, or `None` when it does not
+    /// exist.
+    pub async fn get_namespace_labels(
+        &self,
+        name: &str,
+    ) -> Result<Option<BTreeMap<String, String>>> {
+        self.kubernetes.get_namespace_labels(name).await
+    }
+
+    pub async fn get_deployment_readiness(
+        &self,
+        cluster: Option<&str>,
+        namespace: &str,
+        name: &str,
+    ) -> Result<Option<DeploymentReadiness>> {
+        let result = self
+            .kubernetes_of(cluster)
+            .await?
+            .get_deployment_readiness(namespace, name)
+            .await;
+        self.check_connection(cluster, result).await
+    }
+} 
+/// Whether a Kubernetes call failed because the API server could not be
+/// reached or no longer accepts the connection's credentials.
+fn is_connection_error(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| match cause.downcast_ref::<kube::Error>() {
+        Some(kube::Error::Api(response)) => response.code == 401,
+        Some(kube::Error::HyperError(_) | kube::Error::Service(_) | kube::Error::Auth(_)) => true,
+        _ => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{cluster_config, FakeProvider, Fault, Harness};
+
+    fn node_pool(name: &str) -> NodePool {
+        NodePool {
+            name: name.to_string(),
+            machine_type: "highmem-8".to_string(),
+            node_count: 2,
+            min_nodes: 1,
+            max_nodes: 4,
+            labels: Default::default(),
+            taints: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn create_cluster_records_it_with_the_provider_and_store() {
+        let harness = Harness::new().await;
+        harness.controller.create_cluster(cluster_config("c1")).await.unwrap();
+
+        assert_eq!(harness.provider.cluster("c1").unwrap().node_count, 3);
+        let status = harness.controller.get_cluster_status("c1").await.unwrap();
+        assert!(matches!(status.state, ClusterState::Running));
+        assert_eq!(status.project, "default");
+
+        let history = harness.controller.get_cluster_history("c1").await.unwrap();
+        let states: Vec<_> = history.iter().map(|e| e.state.as_str()).collect();
+        assert_eq!(states, ["Running", "Creating"]);
+    }
+
+    #[tokio::test]
+    async fn create_cluster_rejects_duplicates() {
+        let harness = Harness::new().await;
+        harness.controller.create_cluster(cluster_config("c1")).await.unwrap();
+
+        let err = harness.controller.create_cluster(cluster_config("c1")).await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(Error::ClusterAlreadyExists(_))));
+    }
+
+    #[tokio::test]
+    async fn provider_timeout_keeps_a_failed_cluster_record() {
+        let harness = Harness::new().await;
+        harness.provider.faults.inject("create_cluster", Fault::Timeout);
+
+        let err = harness.controller.create_cluster(cluster_config("c1")).await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(Error::ProviderUnavailable(_))));
+        // The cloud may still be creating the cluster, so it stays managed
+        let clusters = harness.controller.list_clusters().await.unwrap();
+        assert_eq!(clusters.len(), 1);
+        assert!(matches!(clusters[0].state, ClusterState::Failed));
+        assert!(clusters[0].message.as_ref().unwrap().contains("timed out"));
+
+        // Deleting it succeeds although the cloud never created it, and a
+        // retry then goes through
+        harness.controller.delete_cluster("c1").await.unwrap();
+        assert!(harness.controller.list_clusters().await.unwrap().is_empty());
+        harness.controller.create_cluster(cluster_config("c1")).await.unwrap();
+        assert_eq!(harness.controller.list_clusters().await.unwrap().len(), 1);
+    }
+
+
+    #[tokio::test]
+    async fn partially_applied_scale_is_not_recorded() {
+        let harness = Harness::new().await;
+        harness.controller.create_cluster(cluster_config("c1")).await.unwrap();
+        harness.provider.faults.inject("scale_cluster", Fault::Partial);
+
+        let mut config = cluster_config("c1");
+        config.node_count = 5;
+        harness.controller.update_cluster(config.clone()).await.unwrap_err();
+
+        // The cloud scaled but the stored configuration did not move, so a
+        // retry applies the change again
+        assert_eq!(harness.provider.cluster("c1").unwrap().node_count, 5);
+        let stored = harness.controller.get_cluster_config("c1").await.unwrap().unwrap();
+        assert_eq!(stored.node_count, 3);
+
+        harness.controller.update_cluster(config).await.unwrap();
+        let stored = harness.controller.get_cluster_config("c1").await.unwrap().unwrap();
+        assert_eq!(stored.node_count, 5);
+    }
+
+    #[tokio::test]
+    async fn tag_changes_reach_the_cloud() {
+        let harness = Harness::new().await;
+        let mut config = cluster_config("c1");
+        config.tags.insert("team".to_string(), "platform".to_string());
+        harness.controller.create_cluster(config.clone()).await.unwrap();
+
+        config.tags = HashMap::from([("owner".to_string(), "alice".to_string())]);
+        harness.controller.update_cluster(config.clone()).await.unwrap();
+        assert_eq!(harness.provider.cluster("c1").unwrap().config.tags, config.tags);
+        let stored = harness.controller.get_cluster_config("c1").await.unwrap().unwrap();
+        assert_eq!(stored.tags, config.tags);
+    }
+
+    #[tokio::test]
+    async fn update_cluster_rejects_immutable_fields() {
+        let harness = Harness::new().await;
+        harness.controller.create_cluster(cluster_config("c1")).await.unwrap();
+
+        let mut config = cluster_config("c1");
+        config.region = "elsewhere-2".to_string();
+        let err = harness.controller.update_cluster(config).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(Error::ImmutableField { field: "region", .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn clusters_that_cannot_be_recorded_are_not_created() {
+        let harness = Harness::new().await;
+        harness
+            .store
+            .faults
+            .inject("insert_cluster", Fault::Error("connection reset".to_string()));
+
+        assert!(harness.controller.create_cluster(cluster_config("c1")).await.is_err());
+        assert!(harness.provider.cluster("c1").is_none());
+        assert!(harness.controller.list_clusters().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn clusters_the_cloud_refuses_leave_no_record() {
+        let harness = Harness::new().await;
+        harness
+            .provider
+            .faults
+            .inject("create_cluster", Fault::Error("quota exceeded".to_string()));
+
+        assert!(harness.controller.create_cluster(cluster_config("c1")).await.is_err());
+        assert!(harness.controller.get_cluster_config("c1").await.unwrap().is_none());
+        assert!(harness.controller.list_clusters().await.unwrap().is_empty());
+
+        // The name is free to try again
+        harness.controller.create_cluster(cluster_config("c1")).await.unwrap();
+        assert!(harness.provider.cluster("c1").is_some());
+    }
+
+    #[tokio::test]
+    async fn instances_sharing_a_store_see_each_others_clusters() {
+        let harness = Harness::new().await;
+        let mut providers = ProviderRegistry::default();
+        providers.insert(registry::DEFAULT_ACCOUNT, harness.provider.clone());
+        let other = Controller::with_backends(
+            harness.kubernetes.clone(),
+            harness.connector.clone(),
+            providers,
+            harness.store.clone(),
+        )
+        .await
+        .unwrap();
+        harness.controller.create_cluster(cluster_config("c1")).await.unwrap();
+
+        assert_eq!(other.list_clusters().await.unwrap().len(), 1);
+        assert_eq!(other.get_cluster_status("c1").await.unwrap().project, "default");
+        let err = other.validate_new_cluster(&cluster_config("c1")).await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(Error::ClusterAlreadyExists(_))));
+
+        other.delete_cluster("c1").await.unwrap();
+        assert!(harness.controller.list_clusters().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn node_pools_can_be_added_resized_and_removed() {
+        let harness = Harness::new().await;
+        harness.controller.create_cluster(cluster_config("c1")).await.unwrap();
+
+        harness.controller.add_node_pool("c1", node_pool("gpu")).await.unwrap();
+        let err = harness.controller.add_node_pool("c1", node_pool("gpu")).await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(Error::NodePoolAlreadyExists { .. })));
+
+        // Without a node count the current count is clamped to the new bounds
+        let size = NodePoolSize {
+            min_nodes: 3,
+            max_nodes: 6,
+            node_count: None,
+        };
+        let resized = harness.controller.resize_node_pool("c1", "gpu", &size).await.unwrap();
+        assert_eq!(resized.node_count, 3);
+        assert_eq!(harness.provider.cluster("c1").unwrap().node_pools[0].node_count, 3);
+
+        harness.controller.remove_node_pool("c1", "gpu").await.unwrap();
+        assert!(harness.provider.cluster("c1").unwrap().node_pools.is_empty());
+        let stored = harness.controller.get_cluster_config("c1").await.unwrap().unwrap();
+        assert!(stored.node_pools.is_empty());
+
+        let err = harness.controller.remove_node_pool("c1", "gpu").await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(Error::NodePoolNotFound { .. })));
+    }
+
+    #[tokio::test]
+    async fn concurrent_node_pool_changes_are_all_kept() {
+        let harness = Harness::new().await;
+        harness.controller.create_cluster(cluster_config("c1")).await.unwrap();
+
+        let (gpu, cpu) = tokio::join!(
+            harness.controller.add_node_pool("c1", node_pool("gpu")),
+            harness.controller.add_node_pool("c1", node_pool("cpu")),
+        );
+        gpu.unwrap();
+        cpu.unwrap();
+        let stored = harness.controller.get_cluster_config("c1").await.unwrap().unwrap();
+        let mut names: Vec<&str> = stored.node_pools.iter().map(|p| p.name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, ["cpu", "gpu"]);
+
+        let size = NodePoolSize {
+            min_nodes: 1,
+            max_nodes: 8,
+            node_count: Some(6),
+        };
+        let (resized, removed) = tokio::join!(
+            harness.controller.resize_node_pool("c1", "gpu", &size),
+            harness.controller.remove_node_pool("c1", "cpu"),
+        );
+        resized.unwrap();
+        removed.unwrap();
+        let stored = harness.controller.get_cluster_config("c1").await.unwrap().unwrap();
+        assert_eq!(stored.node_pools.len(), 1);
+        assert_eq!(stored.node_pools[0].name, "gpu");
+        assert_eq!(stored.node_pools[0].node_count, 6);
+    }
+
+    #[tokio::test]
+    async fn refused_node_pool_changes_leave_the_cluster_unchanged() {
+        let harness = Harness::new().await;
+        harness.controller.create_cluster(cluster_config("c1")).await.unwrap();
+
+        let err = harness.controller.add_node_pool("c2", node_pool("gpu")).await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(Error::ClusterNotFound(name)) if name == "c2"));
+
+        let mut invalid = node_pool("gpu");
+        invalid.min_nodes = 5;
+        let err = harness.controller.add_node_pool("c1", invalid).await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(Error::InvalidConfig(_))));
+
+        harness
+            .provider
+            .faults
+            .inject("create_node_pool", Fault::Error("quota exceeded".to_string()));
+        assert!(harness.controller.add_node_pool("c1", node_pool("gpu")).await.is_err());
+        assert!(harness.provider.cluster("c1").unwrap().node_pools.is_empty());
+        let stored = harness.controller.get_cluster_config("c1").await.unwrap().unwrap();
+        assert!(stored.node_pools.is_empty());
+
+        harness.controller.add_node_pool("c1", node_pool("gpu")).await.unwrap();
+        let size = NodePoolSize {
+            min_nodes: 4,
+            max_nodes: 2,
+            node_count: None,
+        };
+        let err = harness.controller.resize_node_pool("c1", "gpu", &size).await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(Error::InvalidConfig(_))));
+        let err = harness
+            .controller
+            .resize_node_pool("c1", "cpu", &size)
+            .await
+            .unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(Error::NodePoolNotFound { .. })));
+
+        harness
+            .provider
+            .faults
+            .inject("delete_node_pool", Fault::Timeout);
+        assert!(harness.controller.remove_node_pool("c1", "gpu").await.is_err());
+        let stored = harness.controller.get_cluster_config("c1").await.unwrap().unwrap();
+        assert_eq!(stored.node_pools, vec![node_pool("gpu")]);
+    }
+
+    #[tokio::test]
+    async fn clusters_use_the_provider_of_their_account() {
+        let harness = Harness::new().await;
+        let secondary = Arc::new(FakeProvider::default());
+        let mut providers = ProviderRegistry::default();
+        providers.insert(registry::DEFAULT_ACCOUNT, harness.provider.clone());
+        providers.insert("gcp-prod", secondary.clone());
+        let controller = Controller::with_backends(
+            harness.kubernetes.clone(),
+            harness.connector.clone(),
+            providers,
+            harness.store.clone(),
+        )
+        .await
+        .unwrap();
+
+        let mut config = cluster_config("c1");
+        config.account = "gcp-prod".to_string();
+        controller.create_cluster(config).await.unwrap();
+        controller.scale_cluster("c1", 5).await.unwrap();
+        assert_eq!(secondary.cluster("c1").unwrap().node_count, 5);
+        assert!(harness.provider.cluster("c1").is_none());
+
+        let mut config = cluster_config("c2");
+        config.account = "azure-dev".to_string();
+        let err = controller.create_cluster(config).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(Error::UnknownAccount(account)) if account == "azure-dev"
+        ));
+    }
+
+    #[tokio::test]
+    async fn managed_cluster_apis_are_cached_until_the_cluster_is_deleted() {
+        let harness = Harness::new().await;
+        harness.controller.create_cluster(cluster_config("c1")).await.unwrap();
+
+        let manifest = "apiVersion: v1\nkind: ConfigMap\nmetadata:\n  name: settings\n";
+        for _ in 0..2 {
+            harness
+                .controller
+                .deploy_application(Some("c1"), "settings", manifest)
+                .await
+                .unwrap();
+        }
+        assert_eq!(harness.connector.connections(), ["c1"]);
+        let c1 = harness.connector.cluster("c1").unwrap();
+        assert!(c1.get("galato", "ConfigMap", "settings").is_some());
+        assert!(harness.kubernetes.get("galato", "ConfigMap", "settings").is_none());
+
+        harness.controller.delete_cluster("c1").await.unwrap();
+        let err = harness
+            .controller
+            .deploy_application(Some("c1"), "settings", manifest)
+            .await
+            .unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(Error::ClusterNotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn managed_cluster_apis_reconnect_after_their_credentials_are_refused() {
+        let harness = Harness::new().await;
+        harness.controller.create_cluster(cluster_config("c1")).await.unwrap();
+
+        let manifest = "apiVersion: v1\nkind: ConfigMap\nmetadata:\n  name: settings\n";
+        harness
+            .controller
+            .deploy_application(Some("c1"), "settings", manifest)
+            .await
+            .unwrap();
+        let c1 = harness.connector.cluster("c1").unwrap();
+
+        // Other failures keep the connection
+        c1.faults.inject("apply_manifest", Fault::Conflict);
+        harness
+            .controller
+            .deploy_application(Some("c1"), "settings", manifest)
+            .await
+            .unwrap_err();
+        assert_eq!(harness.connector.connections(), ["c1"]);
+
+        c1.faults.inject("apply_manifest", Fault::Unauthorized);
+        harness
+            .controller
+            .deploy_application(Some("c1"), "settings", manifest)
+            .await
+            .unwrap_err();
+        harness
+            .controller
+            .deploy_application(Some("c1"), "settings", manifest)
+            .await
+            .unwrap();
+        assert_eq!(harness.connector.connections(), ["c1", "c1"]);
+    }
+}
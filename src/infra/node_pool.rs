@@ -0,0 +1,127 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+
+use super::Error;
+
+/// A group of identically configured nodes within a cluster.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NodePool {
+    pub name: String,
+    pub machine_type: String,
+    /// Nodes requested when the pool is created or resized
+    pub node_count: i32,
+    /// Autoscaling bounds; equal bounds pin the pool at a fixed size
+    pub min_nodes: i32,
+    pub max_nodes: i32,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    #[serde(default)]
+    pub taints: Vec<Taint>,
+}
+
+/// A Kubernetes taint applied to every node in a pool.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Taint {
+    pub key: String,
+    #[serde(default)]
+    pub value: Option<String>,
+    pub effect: TaintEffect,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TaintEffect {
+    NoSchedule,
+    PreferNoSchedule,
+    NoExecute,
+}
+
+impl TaintEffect {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TaintEffect::NoSchedule => "NoSchedule",
+            TaintEffect::PreferNoSchedule => "PreferNoSchedule",
+            TaintEffect::NoExecute => "NoExecute",
+        }
+    }
+}
+
+/// New size of an existing pool. Without a node count the current count is
+/// kept, clamped to the new bounds.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct NodePoolSize {
+    pub min_nodes: i32,
+    pub max_nodes: i32,
+    #[serde(default)]
+    pub node_count: Option<i32>,
+}
+
+impl NodePool {
+    /// Applies a new size, returning the resized pool.
+    pub fn resized(&self, size: &NodePoolSize) -> NodePool {
+        NodePool {
+            node_count: size.node_count.unwrap_or_else(|| {
+                self.node_count
+                    .clamp(size.min_nodes, size.max_nodes.max(size.min_nodes))
+            }),
+            min_nodes: size.min_nodes,
+            max_nodes: size.max_nodes,
+            ..self.clone()
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), Error> {
+        let valid_name = !self.name.is_empty()
+            && self.name.len() <= 40
+            && self
+                .name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+            && !self.name.starts_with('-');
+        if !valid_name {
+            return Err(Error::InvalidConfig(format!(
+                "node pool name {:?} must be a lowercase DNS label of at most 40 characters",
+                self.name
+            )));
+        }
+        if self.machine_type.is_empty() {
+            return Err(Error::InvalidConfig(format!(
+                "node pool {} needs a machine_type",
+                self.name
+            )));
+        }
+        if self.min_nodes < 0 || self.min_nodes > self.max_nodes {
+            return Err(Error::InvalidConfig(format!(
+                "node pool {} needs 0 <= min_nodes <= max_nodes",
+                self.name
+            )));
+        }
+        if self.node_count < self.min_nodes || self.node_count > self.max_nodes {
+            return Err(Error::InvalidConfig(format!(
+                "node_count of node pool {} must be between {} and {}",
+                self.name, self.min_nodes, self.max_nodes
+            )));
+        }
+        if self.taints.iter().any(|t| t.key.is_empty()) {
+            return Err(Error::InvalidConfig(format!(
+                "node pool {} has a taint without a key",
+                self.name
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Validates every pool and checks that pool names are unique.
+pub fn validate_node_pools(pools: &[NodePool]) -> Result<(), Error> {
+    let mut names = HashSet::new();
+    for pool in pools {
+        pool.validate()?;
+        if !names.insert(pool.name.as_str()) {
+            return Err(Error::InvalidConfig(format!(
+                "node pool {} is defined more than once",
+                pool.name
+            )));
+        }
+    }
+    Ok(())
+}
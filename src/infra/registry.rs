@@ -0,0 +1,54 @@
+use anyhow::Result;
+use std::{collections::BTreeMap, sync::Arc};
+
+use super::{aws, azure, gcp, local, Error, InfrastructureProvider};
+use crate::config::{CloudConfig, CloudProvider};
+
+/// Account formed by the top-level `cloud` settings, used by clusters that do
+/// not name one.
+pub const DEFAULT_ACCOUNT: &str = "default";
+
+pub fn default_account() -> String {
+    DEFAULT_ACCOUNT.to_string()
+}
+
+/// Infrastructure providers by cloud account name, so one galato instance can
+/// manage clusters on several clouds at once.
+#[derive(Default, Clone)]
+pub struct ProviderRegistry {
+    providers: BTreeMap<String, Arc<dyn InfrastructureProvider>>,
+}
+
+impl ProviderRegistry {
+    /// Builds a provider for the default account and every account in
+    /// `cloud.accounts`.
+    pub async fn from_config(cloud: &CloudConfig) -> Result<Self> {
+        let mut registry = Self::default();
+        registry.insert(DEFAULT_ACCOUNT, build(cloud).await?);
+        for (name, account) in &cloud.accounts {
+            registry.insert(name, build(account).await?);
+            tracing::info!("Registered {:?} cloud account {}", account.provider, name);
+        }
+        Ok(registry)
+    }
+
+    pub fn insert(&mut self, name: &str, provider: Arc<dyn InfrastructureProvider>) {
+        self.providers.insert(name.to_string(), provider);
+    }
+
+    pub fn get(&self, account: &str) -> Result<Arc<dyn InfrastructureProvider>, Error> {
+        self.providers
+            .get(account)
+            .cloned()
+            .ok_or_else(|| Error::UnknownAccount(account.to_string()))
+    }
+}
+
+async fn build(cloud: &CloudConfig) -> Result<Arc<dyn InfrastructureProvider>> {
+    Ok(match cloud.provider {
+        CloudProvider::Aws => Arc::new(aws::AwsProvider::new(cloud).await?),
+        CloudProvider::Gcp => Arc::new(gcp::GcpProvider::new(cloud)?),
+        CloudProvider::Azure => Arc::new(azure::AzureProvider::new(cloud)?),
+        CloudProvider::Local => Arc::new(local::LocalProvider::new(cloud)?),
+    })
+}
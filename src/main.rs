@@ -1,19 +1,22 @@
 mod api;
+mod audit;
+mod auth;
 mod config;
-mod core;
+mod error;
 mod infra;
 mod monitoring;
+mod operations;
+mod operator;
+mod project;
 mod rollup;
-mod utils;
-
-use anyhow::Result;
-use axum::{
-    routing::{get, post},
-    Router,
-};
-use std::net::SocketAddr;
-use tokio::signal;
-use tower_http::trace::TraceLayer;
+mod store;
+#[cfg(test)]
+mod testing;
+
+use anyhow::{Context, Result};
+use std::{net::SocketAddr, sync::Arc};
+use kube::CustomResourceExt;
+use tokio::{net::TcpListener, signal};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 #[tokio::main]
@@ -26,31 +29,84 @@ async fn main() -> Result<()> {
         .with(tracing_subscriber::fmt::layer())
         .init();
 
-    // Load configuration
+    // Choose the TLS backend before any client is built
+    infra::install_crypto_provider();
+
+    // `galato crd` prints the Rollup CustomResourceDefinition for installation
+    if std::env::args().nth(1).as_deref() == Some("crd") {
+        print!("{}", serde_yaml::to_string(&operator::Rollup::crd())?);
+        return Ok(());
+    }
+
+    // Load and validate configuration
     let config = config::Config::load()?;
+    config.validate()?;
+
+    // Connect to the database and apply pending migrations
+    let store = store::PgStore::connect(&config.database).await?;
+    store.migrate().await?;
+    let store: Arc<dyn store::Store> = Arc::new(store);
+
+    // Record every mutation in the append-only audit log
+    let audit = audit::AuditLog::new(store.clone());
+
+    // Track long-running API operations, failing any left behind by stopped instances
+    let operations = Arc::new(operations::Operations::new(store.clone(), audit.clone()).await?);
 
     // Initialize infrastructure controller
-    let infra_controller = infra::Controller::new(&config).await?;
+    let infra_controller = Arc::new(infra::Controller::new(&config, store.clone()).await?);
+
+    // Load projects, creating the default project on first start
+    let projects = Arc::new(
+        project::Projects::new(&config, infra_controller.clone(), store.clone()).await?,
+    );
 
     // Initialize rollup manager
-    let rollup_manager = rollup::Manager::new(&config, infra_controller.clone()).await?;
+    let rollup_manager = Arc::new(
+        rollup::Manager::new(infra_controller.clone(), projects.clone(), store.clone()).await?,
+    );
 
     // Initialize monitoring system
-    let monitoring = monitoring::System::new(&config).await?;
+    let monitoring = Arc::new(monitoring::System::new()?);
+
+    // Start the rollup reconciliation loop
+    tokio::spawn(
+        rollup::Reconciler::new(&config, rollup_manager.clone(), monitoring.clone()).run(),
+    );
+
+    // Reconcile Rollup custom resources when running as an operator
+    if config.operator.enabled {
+        let operator = operator::Operator::new(
+            &config,
+            infra_controller
+                .kube_client()
+                .context("operator mode needs a Kubernetes client")?,
+            rollup_manager.clone(),
+            audit.clone(),
+        );
+        tokio::spawn(operator.run());
+    }
+
+    // Resolve API tokens and the JWT key set
+    let auth = Arc::new(auth::Authenticator::new(&config.auth)?);
 
-    // Build our application with a route
-    let app = Router::new()
-        .route("/health", get(api::health_check))
-        .route("/api/v1/rollups", post(api::create_rollup))
-        .route("/api/v1/rollups/:id", get(api::get_rollup))
-        .route("/metrics", get(api::metrics))
-        .layer(TraceLayer::new_for_http());
+    // Build the API router over the shared application state
+    let state = Arc::new(api::AppState {
+        infra_controller,
+        rollup_manager,
+        projects,
+        monitoring,
+        operations,
+        auth,
+        audit,
+    });
+    let app = api::create_router(state);
 
     // Run it with hyper
     let addr = SocketAddr::from(([0, 0, 0, 0], config.server.port));
     tracing::info!("listening on {}", addr);
-    axum::Server::bind(&addr)
-        .serve(app.into_make_service())
+    let listener = TcpListener::bind(addr).await?;
+    axum::serve(listener, app)
         .with_graceful_shutdown(shutdown_signal())
         .await?;
 
@@ -1,105 +1,315 @@
 use anyhow::Result;
-use metrics::{counter, gauge, histogram};
-use metrics_exporter_prometheus::PrometheusBuilder;
-use std::sync::Arc;
-use tokio::sync::RwLock;
+use axum::{
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use metrics::{
+    counter, describe_counter, describe_gauge, describe_histogram, gauge, histogram, Unit,
+};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 
-use crate::config::Config;
+use crate::{
+    infra::ClusterStatus,
+    rollup::{ChainMetrics, RollupStatus},
+};
 
-pub struct System {
-    config: Arc<Config>,
-    metrics: Arc<RwLock<Metrics>>,
+/// States reported by the inventory gauges, so states without any resource
+/// read 0 instead of keeping their last value.
+const CLUSTER_STATES: [&str; 5] = ["Creating", "Running", "Scaling", "Failed", "Deleting"];
+const ROLLUP_STATES: [&str; 4] = ["Creating", "Running", "Failed", "Deleting"];
+
+/// Chain metrics not sampled for this long are dropped, so rollups that stop
+/// running do not keep reporting their last values.
+const CHAIN_IDLE_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+
+/// Name, help text and value of a per-rollup gauge.
+type ChainGauge = (&'static str, &'static str, fn(&ChainMetrics) -> f64);
+
+/// Per-rollup gauges other than the block heights.
+const CHAIN_GAUGES: [ChainGauge; 5] = [
+    (
+        "galato_rollup_block_lag_seconds",
+        "Time since a rollup produced its latest L2 block",
+        |chain| chain.block_lag_secs as f64,
+    ),
+    (
+        "galato_rollup_l1_submission_lag_seconds",
+        "Time since a rollup last submitted a batch to L1",
+        |chain| chain.submission_lag_secs as f64,
+    ),
+    (
+        "galato_rollup_batch_submitter_balance_eth",
+        "L1 balance of a rollup's batch submitter, in ether",
+        |chain| chain.submitter_balance,
+    ),
+    (
+        "galato_rollup_gas_used_avg",
+        "Average gas used per block over a rollup's recent blocks",
+        |chain| chain.avg_gas_used,
+    ),
+    (
+        "galato_rollup_tps",
+        "Transactions per second over a rollup's recent blocks",
+        |chain| chain.tps,
+    ),
+];
+
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Latest chain metrics of a rollup.
+struct ChainSample {
+    chain_id: u64,
+    metrics: ChainMetrics,
+    sampled: Instant,
 }
 
-#[derive(Default)]
-struct Metrics {
-    cluster_count: i64,
-    pod_count: i64,
-    rollup_count: i64,
-    error_count: i64,
-    request_latency: f64,
+/// Records metrics into the process-wide Prometheus recorder and renders them
+/// in the text exposition format for `/metrics`.
+///
+/// Chain metrics are kept here rather than in the recorder, which cannot
+/// remove series, so those of deleted rollups stop being exported.
+pub struct System {
+    handle: PrometheusHandle,
+    chains: Mutex<BTreeMap<String, ChainSample>>,
 }
 
 impl System {
-    pub async fn new(config: &Config) -> Result<Self> {
-        // Initialize Prometheus metrics exporter
-        let builder = PrometheusBuilder::new();
-        builder
-            .with_namespace("galato")
-            .with_endpoint(config.monitoring.prometheus_endpoint.clone())
-            .install()?;
-
+    pub fn new() -> Result<Self> {
         Ok(Self {
-            config: Arc::new(config.clone()),
-            metrics: Arc::new(RwLock::new(Metrics::default())),
+            handle: recorder()?,
+            chains: Mutex::new(BTreeMap::new()),
         })
     }
 
-    pub async fn record_cluster_created(&self) {
-        counter!("clusters_created_total", 1);
-        let mut metrics = self.metrics.write().await;
-        metrics.cluster_count += 1;
-        gauge!("clusters_active", metrics.cluster_count as f64);
+    /// Refreshes the inventory gauges from the clusters and rollups galato
+    /// manages, so they match the store rather than drifting with missed
+    /// events. Called on every reconciliation rather than per scrape.
+    pub fn observe_inventory(&self, clusters: &[ClusterStatus], rollups: &[RollupStatus]) {
+        for state in CLUSTER_STATES {
+            let count = clusters
+                .iter()
+                .filter(|c| c.state.as_str() == state)
+                .count();
+            gauge!("galato_clusters", count as f64, "state" => state);
+        }
+        for state in ROLLUP_STATES {
+            let count = rollups.iter().filter(|r| r.state.as_str() == state).count();
+            gauge!("galato_rollups", count as f64, "state" => state);
+        }
+    }
+
+    /// Publishes the chain metrics sampled from a running rollup.
+    pub fn observe_rollup(&self, rollup: &str, chain_id: u64, chain: &ChainMetrics) {
+        let sample = ChainSample {
+            chain_id,
+            metrics: chain.clone(),
+            sampled: Instant::now(),
+        };
+        self.chains
+            .lock()
+            .unwrap()
+            .insert(rollup.to_string(), sample);
+    }
+
+    /// Stops exporting the chain metrics of a deleted rollup.
+    pub fn forget_rollup(&self, rollup: &str) {
+        self.chains.lock().unwrap().remove(rollup);
+    }
+
+    /// Stops exporting the chain metrics of every rollup not in `rollups`,
+    /// such as those deleted through another instance.
+    pub fn retain_rollups<'a>(&self, rollups: impl IntoIterator<Item = &'a str>) {
+        let rollups: HashSet<&str> = rollups.into_iter().collect();
+        self.chains
+            .lock()
+            .unwrap()
+            .retain(|rollup, _| rollups.contains(rollup.as_str()));
+    }
+
+    pub fn record_cluster_created(&self) {
+        counter!("galato_clusters_created_total", 1);
     }
 
-    pub async fn record_cluster_deleted(&self) {
-        counter!("clusters_deleted_total", 1);
-        let mut metrics = self.metrics.write().await;
-        metrics.cluster_count -= 1;
-        gauge!("clusters_active", metrics.cluster_count as f64);
+    pub fn record_cluster_deleted(&self) {
+        counter!("galato_clusters_deleted_total", 1);
     }
 
-    pub async fn record_pod_created(&self) {
-        counter!("pods_created_total", 1);
-        let mut metrics = self.metrics.write().await;
-        metrics.pod_count += 1;
-        gauge!("pods_active", metrics.pod_count as f64);
+    pub fn record_rollup_created(&self) {
+        counter!("galato_rollups_created_total", 1);
     }
 
-    pub async fn record_pod_deleted(&self) {
-        counter!("pods_deleted_total", 1);
-        let mut metrics = self.metrics.write().await;
-        metrics.pod_count -= 1;
-        gauge!("pods_active", metrics.pod_count as f64);
+    pub fn record_rollup_deleted(&self) {
+        counter!("galato_rollups_deleted_total", 1);
     }
 
-    pub async fn record_rollup_created(&self) {
-        counter!("rollups_created_total", 1);
-        let mut metrics = self.metrics.write().await;
-        metrics.rollup_count += 1;
-        gauge!("rollups_active", metrics.rollup_count as f64);
+    pub fn record_error(&self, route: &str, method: &str, status: u16) {
+        counter!(
+            "galato_errors_total",
+            1,
+            "route" => route.to_string(),
+            "method" => method.to_string(),
+            "status" => status.to_string()
+        );
     }
 
-    pub async fn record_rollup_deleted(&self) {
-        counter!("rollups_deleted_total", 1);
-        let mut metrics = self.metrics.write().await;
-        metrics.rollup_count -= 1;
-        gauge!("rollups_active", metrics.rollup_count as f64);
+    pub fn record_request_latency(&self, route: &str, method: &str, status: u16, latency: f64) {
+        histogram!(
+            "galato_request_latency_seconds",
+            latency,
+            "route" => route.to_string(),
+            "method" => method.to_string(),
+            "status" => status.to_string()
+        );
     }
 
-    pub async fn record_error(&self) {
-        counter!("errors_total", 1);
-        let mut metrics = self.metrics.write().await;
-        metrics.error_count += 1;
-        gauge!("errors_active", metrics.error_count as f64);
+    /// Every recorded metric in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut text = self.handle.render();
+        if !text.is_empty() && !text.ends_with('\n') {
+            text.push('\n');
+        }
+        text.push_str(&self.render_chains());
+        text
     }
 
-    pub async fn record_request_latency(&self, latency: f64) {
-        histogram!("request_latency_seconds", latency);
-        let mut metrics = self.metrics.write().await;
-        metrics.request_latency = latency;
-        gauge!("request_latency_current", latency);
+    /// The chain metrics of every rollup sampled within the idle timeout.
+    fn render_chains(&self) -> String {
+        let mut chains = self.chains.lock().unwrap();
+        chains.retain(|_, sample| sample.sampled.elapsed() < CHAIN_IDLE_TIMEOUT);
+        if chains.is_empty() {
+            return String::new();
+        }
+
+        let labels = |rollup: &str, sample: &ChainSample| {
+            format!("rollup=\"{}\",chain_id=\"{}\"", rollup, sample.chain_id)
+        };
+        let mut text = gauge_header(
+            "galato_rollup_block_height",
+            "L2 block height of a rollup, by head, safe and finalized block",
+        );
+        for (rollup, sample) in chains.iter() {
+            let chain = &sample.metrics;
+            for (block, height) in [
+                ("head", Some(chain.head)),
+                ("safe", chain.safe),
+                ("finalized", chain.finalized),
+            ] {
+                if let Some(height) = height {
+                    text.push_str(&format!(
+                        "galato_rollup_block_height{{{},block=\"{}\"}} {}\n",
+                        labels(rollup, sample),
+                        block,
+                        height
+                    ));
+                }
+            }
+        }
+        for (name, help, value) in CHAIN_GAUGES {
+            text.push('\n');
+            text.push_str(&gauge_header(name, help));
+            for (rollup, sample) in chains.iter() {
+                text.push_str(&format!(
+                    "{}{{{}}} {}\n",
+                    name,
+                    labels(rollup, sample),
+                    value(&sample.metrics)
+                ));
+            }
+        }
+        text
     }
+}
+
+/// The `# HELP` and `# TYPE` lines of a gauge.
+fn gauge_header(name: &str, help: &str) -> String {
+    format!("# HELP {} {}\n# TYPE {} gauge\n", name, help, name)
+}
+
+/// Route label for requests that matched no route, so unknown paths do not
+/// each get their own series.
+const UNMATCHED_ROUTE: &str = "unmatched";
+
+/// Records the latency of every API request, and counts those answered with
+/// an error status, labelled by route template, method and status.
+pub async fn track_requests(
+    State(monitoring): State<Arc<System>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| UNMATCHED_ROUTE.to_string());
+    let method = request.method().clone();
+    let started = Instant::now();
+
+    let response = next.run(request).await;
 
-    pub async fn get_metrics(&self) -> String {
-        let metrics = self.metrics.read().await;
-        format!(
-            "clusters_active: {}\npods_active: {}\nrollups_active: {}\nerrors_active: {}\nrequest_latency: {}",
-            metrics.cluster_count,
-            metrics.pod_count,
-            metrics.rollup_count,
-            metrics.error_count,
-            metrics.request_latency
-        )
+    let status = response.status();
+    monitoring.record_request_latency(
+        &route,
+        method.as_str(),
+        status.as_u16(),
+        started.elapsed().as_secs_f64(),
+    );
+    if status.is_client_error() || status.is_server_error() {
+        monitoring.record_error(&route, method.as_str(), status.as_u16());
     }
-} 
\ No newline at end of file
+    response
+}
+
+/// Installs the Prometheus recorder on first use. `metrics` allows a single
+/// recorder per process, so every `System` shares it.
+fn recorder() -> Result<PrometheusHandle> {
+    static HANDLE: Mutex<Option<PrometheusHandle>> = Mutex::new(None);
+
+    let mut handle = HANDLE.lock().unwrap();
+    if let Some(handle) = handle.as_ref() {
+        return Ok(handle.clone());
+    }
+    let installed = PrometheusBuilder::new()
+        .set_buckets_for_metric(Matcher::Suffix("_seconds".to_string()), &LATENCY_BUCKETS)?
+        .install_recorder()?;
+    describe();
+    Ok(handle.insert(installed).clone())
+}
+
+/// HELP lines for every metric galato records.
+fn describe() {
+    describe_gauge!("galato_clusters", "Clusters managed by galato, by state");
+    describe_gauge!("galato_rollups", "Rollups managed by galato, by state");
+    describe_counter!(
+        "galato_clusters_created_total",
+        "Clusters created through the API"
+    );
+    describe_counter!(
+        "galato_clusters_deleted_total",
+        "Clusters deleted through the API"
+    );
+    describe_counter!(
+        "galato_rollups_created_total",
+        "Rollups created through the API"
+    );
+    describe_counter!(
+        "galato_rollups_deleted_total",
+        "Rollups deleted through the API"
+    );
+    describe_counter!(
+        "galato_errors_total",
+        "API requests answered with an error status"
+    );
+    describe_histogram!(
+        "galato_request_latency_seconds",
+        Unit::Seconds,
+        "Time taken to answer API requests"
+    );
+}
@@ -0,0 +1,448 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{future::Future, sync::Arc, time::Duration};
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use crate::{
+    audit::{AuditEvent, AuditLog, AuditOutcome},
+    store::Store,
+};
+
+/// Reason recorded on operations that were still in flight when galato stopped.
+const INTERRUPTED: &str = "operation was interrupted by a restart of galato";
+
+/// How often each instance reports itself alive and looks for operations
+/// left behind by instances that stopped.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Instances silent for this long are considered stopped.
+const INSTANCE_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OperationState {
+    Pending,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+impl OperationState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OperationState::Pending => "Pending",
+            OperationState::Running => "Running",
+            OperationState::Succeeded => "Succeeded",
+            OperationState::Failed => "Failed",
+        }
+    }
+
+    pub fn parse(state: &str) -> Result<Self> {
+        match state {
+            "Pending" => Ok(OperationState::Pending),
+            "Running" => Ok(OperationState::Running),
+            "Succeeded" => Ok(OperationState::Succeeded),
+            "Failed" => Ok(OperationState::Failed),
+            other => anyhow::bail!("unknown operation state {}", other),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OperationStep {
+    pub timestamp: DateTime<Utc>,
+    pub message: String,
+}
+
+/// A unit of long-running work started through the API.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Operation {
+    pub id: Uuid,
+    /// What the operation does, e.g. `create_rollup`
+    pub kind: String,
+    /// Name of the rollup or cluster being acted on
+    pub target: String,
+    /// Project the target belongs to
+    pub project: Option<String>,
+    /// Principal that started the operation
+    pub requested_by: Option<String>,
+    /// galato instance running the operation
+    pub instance: Option<Uuid>,
+    pub state: OperationState,
+    /// Completion estimate in percent
+    pub progress: u8,
+    pub steps: Vec<OperationStep>,
+    pub result: Option<serde_json::Value>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Operation {
+    fn new(kind: &str, target: &str, project: &str, requested_by: &str, instance: Uuid) -> Self {
+        let now = Utc::now();
+        Self {
+            id: Uuid::new_v4(),
+            kind: kind.to_string(),
+            target: target.to_string(),
+            project: Some(project.to_string()),
+            requested_by: Some(requested_by.to_string()),
+            instance: Some(instance),
+            state: OperationState::Pending,
+            progress: 0,
+            steps: Vec::new(),
+            result: None,
+            error: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    fn log(&mut self, message: impl Into<String>) {
+        let now = Utc::now();
+        self.steps.push(OperationStep {
+            timestamp: now,
+            message: message.into(),
+        });
+        self.updated_at = now;
+    }
+}
+
+/// Runs long-running work in the background and persists its progress so
+/// callers can poll it, including across restarts. Several instances can
+/// share a store: each sends heartbeats, and work is only failed as
+/// interrupted once the instance running it stops sending them.
+pub struct Operations {
+    store: Arc<dyn Store>,
+    audit: AuditLog,
+    instance: Uuid,
+}
+
+impl Operations {
+    pub async fn new(store: Arc<dyn Store>, audit: AuditLog) -> Result<Self> {
+        let instance = Uuid::new_v4();
+        store.heartbeat(instance).await?;
+        fail_orphaned(store.as_ref(), &audit).await?;
+
+        let heartbeats = store.clone();
+        let heartbeat_audit = audit.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(HEARTBEAT_INTERVAL);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                let result = async {
+                    heartbeats.heartbeat(instance).await?;
+                    fail_orphaned(heartbeats.as_ref(), &heartbeat_audit).await
+                };
+                if let Err(e) = result.await {
+                    tracing::warn!("Failed to send operations heartbeat: {:#}", e);
+                }
+            }
+        });
+
+        Ok(Self {
+            store,
+            audit,
+            instance,
+        })
+    }
+
+    /// Records a new operation on `target` in `project` on behalf of
+    /// `requested_by` and runs `work` in the background. The value returned by
+    /// `work` becomes the operation result.
+    pub async fn start<F, Fut>(
+        &self,
+        kind: &str,
+        target: &str,
+        project: &str,
+        requested_by: &str,
+        work: F,
+    ) -> Result<Operation>
+    where
+        F: FnOnce(OperationHandle) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<serde_json::Value>> + Send + 'static,
+    {
+        let operation = Operation::new(kind, target, project, requested_by, self.instance);
+        self.store.insert_operation(&operation).await?;
+        tracing::info!("{} started {} of {} ({})", requested_by, kind, target, operation.id);
+
+        let handle = OperationHandle {
+            store: self.store.clone(),
+            audit: self.audit.clone(),
+            operation: Arc::new(Mutex::new(operation.clone())),
+            change: Arc::new(Mutex::new(Change::default())),
+        };
+
+        tokio::spawn(async move {
+            if let Err(e) = handle.begin().await {
+                tracing::error!("Failed to start operation: {:#}", e);
+                return;
+            }
+
+            let outcome = work(handle.clone()).await;
+            if let Err(e) = handle.finish(outcome).await {
+                tracing::error!("Failed to record operation outcome: {:#}", e);
+            }
+        });
+
+        Ok(operation)
+    }
+
+    pub async fn get(&self, id: Uuid) -> Result<Option<Operation>> {
+        self.store.get_operation(id).await
+    }
+
+    /// The latest operations, limited to `projects` when set.
+    pub async fn list(&self, projects: Option<&[String]>, limit: i64) -> Result<Vec<Operation>> {
+        self.store.list_operations(projects, limit).await
+    }
+}
+
+/// Fails and audits the operations of instances that stopped sending
+/// heartbeats. What they changed before stopping is unknown.
+async fn fail_orphaned(store: &dyn Store, audit: &AuditLog) -> Result<()> {
+    let interrupted = store
+        .fail_orphaned_operations(INTERRUPTED, INSTANCE_TIMEOUT)
+        .await?;
+    if !interrupted.is_empty() {
+        tracing::warn!("Marked {} interrupted operations as failed", interrupted.len());
+    }
+    for operation in &interrupted {
+        audit.record(audit_event(operation, Change::default())).await?;
+    }
+    Ok(())
+}
+
+/// Configuration before and after the change an operation makes.
+#[derive(Debug, Default)]
+struct Change {
+    before: Option<serde_json::Value>,
+    after: Option<serde_json::Value>,
+}
+
+/// Passed to running work so it can report progress.
+#[derive(Clone)]
+pub struct OperationHandle {
+    store: Arc<dyn Store>,
+    audit: AuditLog,
+    operation: Arc<Mutex<Operation>>,
+    change: Arc<Mutex<Change>>,
+}
+
+impl OperationHandle {
+    /// Appends a step to the operation log and advances its progress.
+    pub async fn step(&self, progress: u8, message: impl Into<String>) -> Result<()> {
+        let mut operation = self.operation.lock().await;
+        operation.progress = progress.min(100);
+        operation.log(message);
+        self.store.update_operation(&operation).await
+    }
+
+    /// Describes the change being made, for the audit entry written when the
+    /// operation finishes.
+    pub async fn record_change(
+        &self,
+        before: Option<serde_json::Value>,
+        after: Option<serde_json::Value>,
+    ) {
+        *self.change.lock().await = Change { before, after };
+    }
+
+    async fn begin(&self) -> Result<()> {
+        let mut operation = self.operation.lock().await;
+        operation.state = OperationState::Running;
+        operation.log("Operation started");
+        self.store.update_operation(&operation).await
+    }
+
+    async fn finish(&self, outcome: Result<serde_json::Value>) -> Result<()> {
+        let mut operation = self.operation.lock().await;
+        match outcome {
+            Ok(result) => {
+                operation.state = OperationState::Succeeded;
+                operation.progress = 100;
+                operation.result = Some(result);
+                operation.log("Operation succeeded");
+            }
+            Err(e) => {
+                tracing::warn!(
+                    "Operation {} ({} {}) failed: {:#}",
+                    operation.id,
+                    operation.kind,
+                    operation.target,
+                    e
+                );
+                operation.state = OperationState::Failed;
+                operation.error = Some(format!("{:#}", e));
+                operation.log("Operation failed");
+            }
+        }
+
+        // An operation whose change cannot be audited fails, so the gap in
+        // the audit log is visible to the caller
+        let change = std::mem::take(&mut *self.change.lock().await);
+        if let Err(e) = self.audit.record(audit_event(&operation, change)).await {
+            let error = format!("recording the audit entry failed: {:#}", e);
+            operation.state = OperationState::Failed;
+            operation.error = Some(match operation.error.take() {
+                Some(cause) => format!("{}; {}", cause, error),
+                None => error,
+            });
+            operation.log("Audit entry could not be recorded");
+        }
+
+        self.store.update_operation(&operation).await
+    }
+}
+
+/// The audit entry for a finished operation.
+fn audit_event(operation: &Operation, change: Change) -> AuditEvent {
+    AuditEvent {
+        actor: operation.requested_by.clone().unwrap_or_default(),
+        action: operation.kind.clone(),
+        target: operation.target.clone(),
+        project: operation.project.clone(),
+        before: change.before,
+        after: change.after,
+        outcome: match operation.state {
+            OperationState::Failed => AuditOutcome::Failed,
+            _ => AuditOutcome::Succeeded,
+        },
+        error: operation.error.clone(),
+        operation_id: Some(operation.id),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        audit::AuditFilter,
+        testing::{Fault, MemoryStore},
+    };
+
+    async fn running(store: &dyn Store, instance: Option<Uuid>) -> Uuid {
+        let mut operation = Operation::new("create_rollup", "r1", "default", "alice", Uuid::nil());
+        operation.instance = instance;
+        operation.state = OperationState::Running;
+        store.insert_operation(&operation).await.unwrap();
+        operation.id
+    }
+
+    async fn state(store: &dyn Store, id: Uuid) -> OperationState {
+        store.get_operation(id).await.unwrap().unwrap().state
+    }
+
+    #[tokio::test]
+    async fn only_operations_of_stopped_instances_are_interrupted() {
+        let store: Arc<dyn Store> = Arc::new(MemoryStore::default());
+        let alive = Uuid::new_v4();
+        store.heartbeat(alive).await.unwrap();
+        let other_replica = running(store.as_ref(), Some(alive)).await;
+        let stopped = running(store.as_ref(), Some(Uuid::new_v4())).await;
+        let unowned = running(store.as_ref(), None).await;
+
+        let operations = Operations::new(store.clone(), AuditLog::new(store.clone()))
+            .await
+            .unwrap();
+        assert_eq!(
+            state(store.as_ref(), other_replica).await,
+            OperationState::Running
+        );
+        assert_eq!(state(store.as_ref(), stopped).await, OperationState::Failed);
+        assert_eq!(state(store.as_ref(), unowned).await, OperationState::Failed);
+        let failed = store.get_operation(stopped).await.unwrap().unwrap();
+        assert_eq!(failed.error.as_deref(), Some(INTERRUPTED));
+
+        // Each interrupted operation is audited as failed
+        let entries = store.query_audit(&AuditFilter::default()).await.unwrap();
+        let mut audited: Vec<_> = entries
+            .iter()
+            .map(|entry| entry.event.operation_id.unwrap())
+            .collect();
+        audited.sort();
+        let mut expected = vec![stopped, unowned];
+        expected.sort();
+        assert_eq!(audited, expected);
+        assert!(entries.iter().all(|entry| {
+            entry.event.outcome == AuditOutcome::Failed
+                && entry.event.error.as_deref() == Some(INTERRUPTED)
+                && entry.event.actor == "alice"
+        }));
+
+        // Operations of a live instance are failed once its heartbeats stop
+        let own = running(store.as_ref(), Some(operations.instance)).await;
+        store
+            .fail_orphaned_operations(INTERRUPTED, Duration::ZERO)
+            .await
+            .unwrap();
+        assert_eq!(state(store.as_ref(), own).await, OperationState::Failed);
+        assert_eq!(
+            state(store.as_ref(), other_replica).await,
+            OperationState::Failed
+        );
+    }
+
+    #[tokio::test]
+    async fn operations_are_limited_after_filtering_by_project() {
+        let store: Arc<dyn Store> = Arc::new(MemoryStore::default());
+        let mut legacy = Operation::new("create_rollup", "r0", "default", "alice", Uuid::nil());
+        legacy.project = None;
+        let payments = Operation::new("create_rollup", "r1", "payments", "alice", Uuid::nil());
+        let games = Operation::new("create_rollup", "r2", "games", "bob", Uuid::nil());
+        for operation in [&legacy, &payments, &games] {
+            store.insert_operation(operation).await.unwrap();
+        }
+        let operations = Operations::new(store.clone(), AuditLog::new(store.clone()))
+            .await
+            .unwrap();
+
+        // Newer operations of other projects do not use up the limit
+        let listed = operations
+            .list(Some(&["payments".to_string()]), 1)
+            .await
+            .unwrap();
+        assert_eq!(listed[0].id, payments.id);
+        let listed = operations
+            .list(Some(&["default".to_string()]), 1)
+            .await
+            .unwrap();
+        assert_eq!(listed[0].id, legacy.id);
+        assert_eq!(operations.list(None, 10).await.unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn operations_whose_change_cannot_be_audited_fail() {
+        let store = Arc::new(MemoryStore::default());
+        let operations = Operations::new(store.clone(), AuditLog::new(store.clone()))
+            .await
+            .unwrap();
+        store
+            .faults
+            .inject("append_audit", Fault::Error("disk full".to_string()));
+
+        let operation = operations
+            .start("create_rollup", "r1", "default", "alice", |_| async {
+                Ok(serde_json::json!({ "name": "r1" }))
+            })
+            .await
+            .unwrap();
+        let operation = loop {
+            let current = operations.get(operation.id).await.unwrap().unwrap();
+            if current.state == OperationState::Failed {
+                break current;
+            }
+            assert_ne!(current.state, OperationState::Succeeded);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+        let error = operation.error.unwrap();
+        assert!(
+            error.contains("recording the audit entry failed"),
+            "{}",
+            error
+        );
+        assert!(error.contains("disk full"), "{}", error);
+    }
+}
@@ -0,0 +1,414 @@
+use ethers::types::Address;
+use futures::StreamExt;
+use kube::{
+    api::{Api, Patch, PatchParams},
+    runtime::{
+        controller::{Action, Controller},
+        finalizer::{self, finalizer, Event},
+        watcher,
+    },
+    Client, CustomResource, ResourceExt,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{sync::Arc, time::Duration};
+
+use crate::{
+    audit::{AuditEvent, AuditLog, AuditOutcome},
+    config::Config,
+    infra::{TeardownOptions, FIELD_MANAGER},
+    project::default_project,
+    rollup::{DeploymentType, Manager, Placement, RollupConfig, RollupStatus, TopologyConfig},
+};
+
+pub const FINALIZER: &str = "galato.io/rollup-cleanup";
+
+/// Actor recorded in the audit log for changes made from `Rollup` resources.
+const AUDIT_ACTOR: &str = "galato-operator";
+
+const ERROR_REQUEUE: Duration = Duration::from_secs(30);
+
+/// Desired state of a rollup, mirroring `rollup::RollupConfig`. The rollup
+/// name is taken from the resource name; a rollup managed through the API or
+/// by a resource in another namespace is left alone.
+#[derive(CustomResource, Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[kube(
+    group = "galato.io",
+    version = "v1alpha1",
+    kind = "Rollup",
+    namespaced,
+    status = "RollupStatus",
+    shortname = "rlp",
+    printcolumn = r#"{"name":"Chain ID","type":"integer","jsonPath":".spec.chainId"}"#,
+    printcolumn = r#"{"name":"State","type":"string","jsonPath":".status.state"}"#
+)]
+#[serde(rename_all = "camelCase")]
+pub struct RollupSpec {
+    /// Project owning the rollup
+    #[serde(default = "default_project")]
+    pub project: String,
+    pub chain_id: u64,
+    #[schemars(with = "String")]
+    pub sequencer_address: Address,
+    #[schemars(with = "String")]
+    pub validator_address: Address,
+    #[schemars(with = "String")]
+    pub batch_submitter_address: Address,
+    pub l1_chain_id: u64,
+    pub l1_rpc_url: String,
+    pub l2_rpc_url: String,
+    pub deployment_type: DeploymentType,
+    #[serde(default)]
+    pub topology: TopologyConfig,
+    #[serde(default)]
+    pub placement: Placement,
+}
+
+impl Rollup {
+    /// Identifies the resource as `<namespace>/<name>`.
+    pub fn resource_id(&self) -> String {
+        format!("{}/{}", self.namespace().unwrap_or_default(), self.name_any())
+    }
+
+    pub fn to_config(&self) -> RollupConfig {
+        RollupConfig {
+            name: self.name_any(),
+            project: self.spec.project.clone(),
+            chain_id: self.spec.chain_id,
+            sequencer_address: self.spec.sequencer_address,
+            validator_address: self.spec.validator_address,
+            batch_submitter_address: self.spec.batch_submitter_address,
+            l1_chain_id: self.spec.l1_chain_id,
+            l1_rpc_url: self.spec.l1_rpc_url.clone(),
+            l2_rpc_url: self.spec.l2_rpc_url.clone(),
+            deployment_type: self.spec.deployment_type.clone(),
+            topology: self.spec.topology.clone(),
+            placement: self.spec.placement.clone(),
+            resource: Some(self.resource_id()),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("{0:#}")]
+    Manager(anyhow::Error),
+    #[error("kubernetes error: {0}")]
+    Kube(#[from] kube::Error),
+}
+
+impl From<anyhow::Error> for Error {
+    fn from(e: anyhow::Error) -> Self {
+        Error::Manager(e)
+    }
+}
+
+struct Context {
+    client: Client,
+    manager: Arc<Manager>,
+    audit: AuditLog,
+    requeue: Duration,
+}
+
+/// Watches `Rollup` resources and drives them through the rollup manager.
+pub struct Operator {
+    rollups: Api<Rollup>,
+    context: Arc<Context>,
+}
+
+impl Operator {
+    pub fn new(
+        config: &Config,
+        client: Client,
+        manager: Arc<Manager>,
+        audit: AuditLog,
+    ) -> Self {
+        let rollups = match &config.operator.watch_namespace {
+            Some(namespace) => Api::namespaced(client.clone(), namespace),
+            None => Api::all(client.clone()),
+        };
+
+        Self {
+            rollups,
+            context: Arc::new(Context {
+                client,
+                manager,
+                audit,
+                requeue: Duration::from_secs(config.rollup.reconcile_interval_secs),
+            }),
+        }
+    }
+
+    pub async fn run(self) {
+        tracing::info!("Starting Rollup operator");
+        Controller::new(self.rollups, watcher::Config::default())
+            .shutdown_on_signal()
+            .run(reconcile, error_policy, self.context)
+            .for_each(|result| async move {
+                match result {
+                    Ok((object, _)) => tracing::debug!("Reconciled Rollup {}", object.name),
+                    Err(e) => tracing::warn!("Rollup reconciliation failed: {}", e),
+                }
+            })
+            .await;
+    }
+}
+
+async fn reconcile(rollup: Arc<Rollup>, ctx: Arc<Context>) -> Result<Action, finalizer::Error<Error>> {
+    let namespace = rollup.namespace().unwrap_or_default();
+    let api: Api<Rollup> = Api::namespaced(ctx.client.clone(), &namespace);
+    let status_api = api.clone();
+
+    finalizer(&api, FINALIZER, rollup, |event| async move {
+        match event {
+            Event::Apply(rollup) => apply(&status_api, rollup, &ctx).await,
+            Event::Cleanup(rollup) => cleanup(rollup, &ctx).await,
+        }
+    })
+    .await
+}
+
+async fn apply(api: &Api<Rollup>, rollup: Arc<Rollup>, ctx: &Context) -> Result<Action, Error> {
+    let name = rollup.name_any();
+    apply_spec(&rollup, ctx).await?;
+
+    // Mirror the manager's view of the rollup into the resource status
+    if let Some(status) = ctx.manager.get_rollup_status(&name).await? {
+        let patch = serde_json::json!({ "status": status });
+        let params = PatchParams {
+            field_manager: Some(FIELD_MANAGER.to_string()),
+            ..Default::default()
+        };
+        api.patch_status(&name, &params, &Patch::Merge(&patch)).await?;
+    }
+
+    Ok(Action::requeue(ctx.requeue))
+}
+
+/// Applies the resource's spec through the manager, auditing it when the
+/// stored configuration changes. Rollups managed elsewhere are refused.
+async fn apply_spec(rollup: &Rollup, ctx: &Context) -> Result<(), Error> {
+    let name = rollup.name_any();
+    let config = rollup.to_config();
+
+    let current = ctx.manager.get_rollup_config(&name).await?;
+    // Refused on every requeue, so not audited
+    if let Some(current) = &current {
+        current.check_manager(&config).map_err(anyhow::Error::from)?;
+    }
+    let before = current.and_then(|config| serde_json::to_value(config).ok());
+    let action = if before.is_some() {
+        "update_rollup"
+    } else {
+        "create_rollup"
+    };
+
+    let result = ctx.manager.apply_rollup(config.clone()).await;
+
+    // Every requeue re-applies the spec; only actual changes are audited.
+    // Compare what was stored, as the manager resolves the spec's placement
+    let after = match &result {
+        Ok(()) => ctx
+            .manager
+            .get_rollup_config(&name)
+            .await?
+            .and_then(|config| serde_json::to_value(config).ok()),
+        Err(_) => serde_json::to_value(&config).ok(),
+    };
+    if before != after {
+        audit(ctx, action, rollup, before, after, &result).await?;
+    }
+    result?;
+
+    Ok(())
+}
+
+async fn cleanup(rollup: Arc<Rollup>, ctx: &Context) -> Result<Action, Error> {
+    let name = rollup.name_any();
+    let owned = ctx
+        .manager
+        .get_rollup_config(&name)
+        .await?
+        .filter(|config| config.resource == Some(rollup.resource_id()));
+    if let Some(before) = owned {
+        let before = serde_json::to_value(before).ok();
+
+        let result = ctx.manager.delete_rollup(&name, &TeardownOptions::default()).await;
+        audit(ctx, "delete_rollup", &rollup, before, None, &result).await?;
+        result?;
+    }
+
+    Ok(Action::await_change())
+}
+
+async fn audit(
+    ctx: &Context,
+    action: &str,
+    rollup: &Rollup,
+    before: Option<serde_json::Value>,
+    after: Option<serde_json::Value>,
+    result: &anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    ctx.audit
+        .record(AuditEvent {
+            actor: AUDIT_ACTOR.to_string(),
+            action: action.to_string(),
+            target: rollup.name_any(),
+            project: Some(rollup.spec.project.clone()),
+            before,
+            after,
+            outcome: match result {
+                Ok(()) => AuditOutcome::Succeeded,
+                Err(_) => AuditOutcome::Failed,
+            },
+            error: result.as_ref().err().map(|e| format!("{:#}", e)),
+            operation_id: None,
+        })
+        .await
+}
+
+fn error_policy(rollup: Arc<Rollup>, error: &finalizer::Error<Error>, _ctx: Arc<Context>) -> Action {
+    tracing::warn!("Failed to reconcile Rollup {}: {}", rollup.name_any(), error);
+    Action::requeue(ERROR_REQUEUE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        audit::AuditFilter,
+        infra::install_crypto_provider,
+        testing::{cluster_config, rollup_config, Harness},
+    };
+
+    /// Context whose client is never used: `apply_spec` only talks to the manager.
+    fn context(harness: &Harness) -> Context {
+        install_crypto_provider();
+        let config = kube::Config::new("https://127.0.0.1:6443".parse().unwrap());
+        Context {
+            client: Client::try_from(config).unwrap(),
+            manager: harness.manager.clone(),
+            audit: harness.state.audit.clone(),
+            requeue: ERROR_REQUEUE,
+        }
+    }
+
+    fn resource(config: &RollupConfig) -> Rollup {
+        let mut rollup = Rollup::new(
+            &config.name,
+            RollupSpec {
+                project: config.project.clone(),
+                chain_id: config.chain_id,
+                sequencer_address: config.sequencer_address,
+                validator_address: config.validator_address,
+                batch_submitter_address: config.batch_submitter_address,
+                l1_chain_id: config.l1_chain_id,
+                l1_rpc_url: config.l1_rpc_url.clone(),
+                l2_rpc_url: config.l2_rpc_url.clone(),
+                deployment_type: config.deployment_type.clone(),
+                topology: config.topology.clone(),
+                placement: config.placement.clone(),
+            },
+        );
+        rollup.metadata.namespace = Some("default".to_string());
+        rollup
+    }
+
+    async fn audited_actions(harness: &Harness) -> Vec<String> {
+        let filter = AuditFilter {
+            actor: Some(AUDIT_ACTOR.to_string()),
+            ..Default::default()
+        };
+        let entries = harness.state.audit.query(&filter).await.unwrap();
+        entries.into_iter().rev().map(|e| e.event.action).collect()
+    }
+
+    #[tokio::test]
+    async fn requeues_of_an_unchanged_spec_are_not_audited() {
+        let harness = Harness::new().await;
+        harness
+            .controller
+            .create_cluster(cluster_config("c1"))
+            .await
+            .unwrap();
+        let ctx = context(&harness);
+
+        // The spec only asks for a region; the stored placement names the cluster
+        let mut config = rollup_config("r1");
+        config.placement.region = Some("test-1".to_string());
+        let rollup = resource(&config);
+        for _ in 0..3 {
+            apply_spec(&rollup, &ctx).await.unwrap();
+        }
+        assert_eq!(audited_actions(&harness).await, ["create_rollup"]);
+
+        config.l2_rpc_url = "http://127.0.0.1:2".to_string();
+        let rollup = resource(&config);
+        for _ in 0..2 {
+            apply_spec(&rollup, &ctx).await.unwrap();
+        }
+        assert_eq!(
+            audited_actions(&harness).await,
+            ["create_rollup", "update_rollup"]
+        );
+    }
+
+    #[tokio::test]
+    async fn rollups_managed_elsewhere_are_neither_adopted_nor_torn_down() {
+        let harness = Harness::new().await;
+        let ctx = context(&harness);
+        harness
+            .manager
+            .create_rollup(rollup_config("api"))
+            .await
+            .unwrap();
+        apply_spec(&resource(&rollup_config("r1")), &ctx)
+            .await
+            .unwrap();
+
+        // A resource named after an API-created rollup does not adopt it
+        let adopter = Arc::new(resource(&rollup_config("api")));
+        let error = apply_spec(&adopter, &ctx).await.unwrap_err();
+        assert!(
+            error.to_string().contains("managed by the API"),
+            "{}",
+            error
+        );
+        cleanup(adopter, &ctx).await.unwrap();
+        assert!(harness
+            .manager
+            .get_rollup_config("api")
+            .await
+            .unwrap()
+            .is_some());
+
+        // Nor does a same-named resource in another namespace
+        let mut other = resource(&rollup_config("r1"));
+        other.metadata.namespace = Some("team-b".to_string());
+        let other = Arc::new(other);
+        let error = apply_spec(&other, &ctx).await.unwrap_err();
+        assert!(
+            error.to_string().contains("Rollup resource default/r1"),
+            "{}",
+            error
+        );
+        cleanup(other, &ctx).await.unwrap();
+        let stored = harness.manager.get_rollup_config("r1").await.unwrap();
+        assert_eq!(stored.unwrap().resource.as_deref(), Some("default/r1"));
+
+        // The owning resource still tears its rollup down
+        cleanup(Arc::new(resource(&rollup_config("r1"))), &ctx)
+            .await
+            .unwrap();
+        assert!(harness
+            .manager
+            .get_rollup_config("r1")
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            audited_actions(&harness).await,
+            ["create_rollup", "delete_rollup"]
+        );
+    }
+}
@@ -0,0 +1,21 @@
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("project {0} not found")]
+    NotFound(String),
+    #[error("project {0} already exists")]
+    AlreadyExists(String),
+    #[error("project {project} still owns {rollups} rollups and {clusters} clusters")]
+    NotEmpty {
+        project: String,
+        rollups: i64,
+        clusters: i64,
+    },
+    #[error("project {project} has reached its quota of {limit} {resource}")]
+    QuotaExceeded {
+        project: String,
+        resource: &'static str,
+        limit: u32,
+    },
+    #[error("invalid project configuration: {0}")]
+    InvalidConfig(String),
+}
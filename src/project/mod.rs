@@ -0,0 +1,445 @@
+mod error;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use k8s_openapi::{
+    api::core::v1::{Namespace, ResourceQuota, ResourceQuotaSpec},
+    apimachinery::pkg::api::resource::Quantity,
+};
+use kube::api::ObjectMeta;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, sync::Arc};
+use tokio::sync::RwLock;
+
+use crate::{
+    config::Config,
+    infra::Controller,
+    rollup::manifest::{MANAGED_BY_LABEL, MANAGER_NAME},
+    store::Store,
+};
+
+pub use error::Error;
+
+/// Project that owns everything created before projects existed.
+pub const DEFAULT_PROJECT: &str = "default";
+
+pub const PROJECT_LABEL: &str = "galato.io/project";
+
+/// Name of the ResourceQuota galato maintains in each project namespace.
+const QUOTA_NAME: &str = "galato-project-quota";
+
+/// Namespaces of the Kubernetes system that no project may take.
+const RESERVED_NAMESPACES: [&str; 4] = ["default", "kube-system", "kube-public", "kube-node-lease"];
+
+pub fn default_project() -> String {
+    DEFAULT_PROJECT.to_string()
+}
+
+/// A tenant owning rollups and clusters, isolated in its own namespace.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Project {
+    pub name: String,
+    pub namespace: String,
+    #[serde(default)]
+    pub quotas: ProjectQuotas,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Limits on a project. Counts are enforced by galato; compute and storage
+/// limits become a ResourceQuota in the project namespace.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProjectQuotas {
+    pub max_rollups: Option<u32>,
+    pub max_clusters: Option<u32>,
+    /// Total CPU requests, e.g. `16`
+    pub cpu: Option<String>,
+    /// Total memory requests, e.g. `64Gi`
+    pub memory: Option<String>,
+    /// Total storage requests, e.g. `1Ti`
+    pub storage: Option<String>,
+}
+
+/// Rollups and clusters currently owned by a project.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct ProjectUsage {
+    pub rollups: i64,
+    pub clusters: i64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum QuotaResource {
+    Rollups,
+    Clusters,
+}
+
+pub struct Projects {
+    /// galato's own namespace, reserved for the default project
+    namespace: String,
+    infra_controller: Arc<Controller>,
+    store: Arc<dyn Store>,
+    projects: Arc<RwLock<Vec<Project>>>,
+}
+
+impl Projects {
+    pub async fn new(
+        config: &Config,
+        infra_controller: Arc<Controller>,
+        store: Arc<dyn Store>,
+    ) -> Result<Self> {
+        let mut projects = store.list_projects().await?;
+
+        // Existing rollups and clusters belong to the default project, which
+        // uses the namespace galato deployed into before projects existed
+        if !projects.iter().any(|p| p.name == DEFAULT_PROJECT) {
+            let project = Project {
+                name: DEFAULT_PROJECT.to_string(),
+                namespace: config.kubernetes.namespace.clone(),
+                quotas: ProjectQuotas::default(),
+                created_at: Utc::now(),
+            };
+            store.insert_project(&project).await?;
+            projects.push(project);
+        }
+        tracing::info!("Loaded {} projects from the database", projects.len());
+
+        Ok(Self {
+            namespace: config.kubernetes.namespace.clone(),
+            infra_controller,
+            store,
+            projects: Arc::new(RwLock::new(projects)),
+        })
+    }
+
+    /// Checks a project before its namespace is created.
+    pub async fn validate_new_project(&self, project: &Project) -> Result<()> {
+        if self.get(&project.name).await.is_some() {
+            return Err(Error::AlreadyExists(project.name.clone()).into());
+        }
+        validate_project(project)?;
+
+        if RESERVED_NAMESPACES.contains(&project.namespace.as_str())
+            || project.namespace == self.namespace
+        {
+            return Err(Error::InvalidConfig(format!(
+                "namespace {} is reserved",
+                project.namespace
+            ))
+            .into());
+        }
+
+        if let Some(owner) = self
+            .projects
+            .read()
+            .await
+            .iter()
+            .find(|p| p.namespace == project.namespace)
+        {
+            return Err(Error::InvalidConfig(format!(
+                "namespace {} is already used by project {}",
+                project.namespace, owner.name
+            ))
+            .into());
+        }
+
+        // Applying the project would adopt an existing namespace and deleting
+        // it would delete the namespace, so only galato's own are accepted
+        let labels = self
+            .infra_controller
+            .get_namespace_labels(&project.namespace)
+            .await?;
+        if labels.is_some_and(|labels| !owned_by(&labels, &project.name)) {
+            return Err(Error::InvalidConfig(format!(
+                "namespace {} already exists and is not managed by galato",
+                project.namespace
+            ))
+            .into());
+        }
+
+        Ok(())
+    }
+
+    pub async fn create_project(&self, project: Project) -> Result<()> {
+        self.validate_new_project(&project).await?;
+
+        self.infra_controller
+            .deploy_application(None, &project.name, &project_manifest(&project)?)
+            .await?;
+        self.store.insert_project(&project).await?;
+
+        let mut projects = self.projects.write().await;
+        projects.push(project);
+
+        Ok(())
+    }
+
+    pub async fn update_quotas(&self, name: &str, quotas: ProjectQuotas) -> Result<Project> {
+        let mut project = self.require(name).await?;
+        project.quotas = quotas;
+        validate_project(&project)?;
+
+        self.infra_controller
+            .deploy_application(None, &project.name, &project_manifest(&project)?)
+            .await?;
+        self.store.update_project(&project).await?;
+
+        let mut projects = self.projects.write().await;
+        if let Some(existing) = projects.iter_mut().find(|p| p.name == name) {
+            *existing = project.clone();
+        }
+
+        Ok(project)
+    }
+
+    /// Creates the project's namespace and quota on a managed cluster that
+    /// runs its rollups.
+    pub async fn deploy_namespace(&self, name: &str, cluster: &str) -> Result<()> {
+        let project = self.require(name).await?;
+        self.infra_controller
+            .deploy_application(Some(cluster), &project.name, &project_manifest(&project)?)
+            .await?;
+        Ok(())
+    }
+
+    /// Deletes an empty project together with its namespace.
+    pub async fn delete_project(&self, name: &str) -> Result<()> {
+        let project = self.require(name).await?;
+        if project.name == DEFAULT_PROJECT {
+            return Err(
+                Error::InvalidConfig("the default project cannot be deleted".to_string()).into(),
+            );
+        }
+        self.ensure_empty(name).await?;
+
+        // Namespaces galato did not create outlive the project
+        let labels = self
+            .infra_controller
+            .get_namespace_labels(&project.namespace)
+            .await?;
+        match labels {
+            Some(labels) if owned_by(&labels, &project.name) => {
+                self.infra_controller
+                    .delete_namespace(&project.namespace)
+                    .await?
+            }
+            Some(_) => tracing::warn!(
+                "Keeping namespace {} of project {}: it is not managed by galato",
+                project.namespace,
+                project.name
+            ),
+            None => {}
+        }
+        self.store.delete_project(name).await?;
+
+        let mut projects = self.projects.write().await;
+        projects.retain(|p| p.name != name);
+
+        Ok(())
+    }
+
+    pub async fn ensure_empty(&self, name: &str) -> Result<()> {
+        let usage = self.store.project_usage(name).await?;
+        if usage.rollups > 0 || usage.clusters > 0 {
+            return Err(Error::NotEmpty {
+                project: name.to_string(),
+                rollups: usage.rollups,
+                clusters: usage.clusters,
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    pub async fn get(&self, name: &str) -> Option<Project> {
+        let projects = self.projects.read().await;
+        projects.iter().find(|p| p.name == name).cloned()
+    }
+
+    pub async fn require(&self, name: &str) -> Result<Project, Error> {
+        self.get(name)
+            .await
+            .ok_or_else(|| Error::NotFound(name.to_string()))
+    }
+
+    pub async fn list(&self) -> Vec<Project> {
+        self.projects.read().await.clone()
+    }
+
+    pub async fn usage(&self, name: &str) -> Result<ProjectUsage> {
+        self.store.project_usage(name).await
+    }
+
+    pub async fn namespace_of(&self, name: &str) -> Result<String, Error> {
+        Ok(self.require(name).await?.namespace)
+    }
+
+    /// Fails if the project cannot own another rollup or cluster.
+    pub async fn check_quota(&self, name: &str, resource: QuotaResource) -> Result<()> {
+        let project = self.require(name).await?;
+        let usage = self.store.project_usage(name).await?;
+
+        let (limit, used, resource) = match resource {
+            QuotaResource::Rollups => (project.quotas.max_rollups, usage.rollups, "rollups"),
+            QuotaResource::Clusters => (project.quotas.max_clusters, usage.clusters, "clusters"),
+        };
+        match limit {
+            Some(limit) if used >= i64::from(limit) => Err(Error::QuotaExceeded {
+                project: project.name,
+                resource,
+                limit,
+            }
+            .into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn validate_project(project: &Project) -> Result<(), Error> {
+    for (field, value) in [("name", &project.name), ("namespace", &project.namespace)] {
+        let valid = !value.is_empty()
+            && value.len() <= 63
+            && value
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+            && !value.starts_with('-')
+            && !value.ends_with('-');
+        if !valid {
+            return Err(Error::InvalidConfig(format!(
+                "{} {:?} must be a lowercase DNS label",
+                field, value
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Whether galato created the namespace with these labels for `project`.
+fn owned_by(labels: &BTreeMap<String, String>, project: &str) -> bool {
+    labels.get(MANAGED_BY_LABEL).map(String::as_str) == Some(MANAGER_NAME)
+        && labels.get(PROJECT_LABEL).map(String::as_str) == Some(project)
+}
+
+/// The ResourceQuota limits of a project. Rollup containers request every
+/// compute resource listed here, or their pods would be rejected.
+pub fn quota_resources(project: &Project) -> BTreeMap<String, Quantity> {
+    let mut hard = BTreeMap::new();
+    for (resource, limit) in [
+        ("requests.cpu", &project.quotas.cpu),
+        ("requests.memory", &project.quotas.memory),
+        ("requests.storage", &project.quotas.storage),
+    ] {
+        if let Some(limit) = limit {
+            hard.insert(resource.to_string(), Quantity(limit.clone()));
+        }
+    }
+    hard
+}
+
+/// Renders the project namespace and its ResourceQuota.
+fn project_manifest(project: &Project) -> Result<String> {
+    let labels = BTreeMap::from([
+        (MANAGED_BY_LABEL.to_string(), MANAGER_NAME.to_string()),
+        (PROJECT_LABEL.to_string(), project.name.clone()),
+    ]);
+
+    let namespace = Namespace {
+        metadata: ObjectMeta {
+            name: Some(project.namespace.clone()),
+            labels: Some(labels.clone()),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    let quota = ResourceQuota {
+        metadata: ObjectMeta {
+            name: Some(QUOTA_NAME.to_string()),
+            namespace: Some(project.namespace.clone()),
+            labels: Some(labels),
+            ..Default::default()
+        },
+        spec: Some(ResourceQuotaSpec {
+            hard: Some(quota_resources(project)),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+
+    Ok([
+        serde_yaml::to_string(&namespace)?,
+        serde_yaml::to_string(&quota)?,
+    ]
+    .join("---\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{infra::KubernetesApi, testing::Harness};
+
+    fn project(name: &str, namespace: &str) -> Project {
+        Project {
+            name: name.to_string(),
+            namespace: namespace.to_string(),
+            quotas: ProjectQuotas::default(),
+            created_at: Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn projects_cannot_take_reserved_or_foreign_namespaces() {
+        let harness = Harness::new().await;
+        harness
+            .kubernetes
+            .apply_manifest("apiVersion: v1\nkind: Namespace\nmetadata:\n  name: monitoring\n")
+            .await
+            .unwrap();
+
+        for namespace in ["kube-system", "default", "galato", "monitoring"] {
+            let err = harness
+                .projects
+                .create_project(project("team-a", namespace))
+                .await
+                .unwrap_err();
+            assert!(
+                matches!(err.downcast_ref(), Some(Error::InvalidConfig(_))),
+                "{}: {}",
+                namespace,
+                err
+            );
+        }
+        assert!(harness
+            .kubernetes
+            .get("", "Namespace", "monitoring")
+            .unwrap()["metadata"]
+            .get("labels")
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn deleting_a_project_only_deletes_namespaces_galato_created() {
+        let harness = Harness::new().await;
+        harness
+            .projects
+            .create_project(project("team-a", "team-a"))
+            .await
+            .unwrap();
+        harness.projects.delete_project("team-a").await.unwrap();
+        assert!(harness.kubernetes.get("", "Namespace", "team-a").is_none());
+
+        // A namespace relabelled behind galato's back is left in place
+        harness
+            .projects
+            .create_project(project("team-b", "team-b"))
+            .await
+            .unwrap();
+        harness
+            .kubernetes
+            .apply_manifest("apiVersion: v1\nkind: Namespace\nmetadata:\n  name: team-b\n  labels:\n    owner: someone-else\n")
+            .await
+            .unwrap();
+        harness.projects.delete_project("team-b").await.unwrap();
+        assert!(harness.kubernetes.get("", "Namespace", "team-b").is_some());
+        assert!(harness.projects.get("team-b").await.is_none());
+    }
+}
@@ -0,0 +1,11 @@
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("rollup {0} not found")]
+    NotFound(String),
+    #[error("rollup {0} already exists")]
+    AlreadyExists(String),
+    #[error("invalid rollup configuration: {0}")]
+    InvalidConfig(String),
+    #[error("rollup {rollup} is managed by {manager}")]
+    ManagedElsewhere { rollup: String, manager: String },
+}
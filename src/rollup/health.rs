@@ -0,0 +1,593 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use chrono::Utc;
+use ethers::{
+    providers::{Http, JsonRpcClient, Middleware, Provider, ProviderError, Ws},
+    types::{Address, BlockNumber, U256, U64},
+    utils::format_ether,
+};
+use futures::future::try_join_all;
+use serde::{
+    de::{DeserializeOwned, IgnoredAny},
+    Deserialize, Serialize,
+};
+use std::{fmt::Debug, future::Future, time::Duration};
+
+use crate::config::Config;
+use super::{RollupConfig, RollupStatus};
+
+/// Recent L2 blocks averaged into the gas and throughput metrics.
+const SAMPLE_BLOCKS: u64 = 20;
+
+/// Chain metrics of a running rollup, sampled from its RPC endpoints.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChainMetrics {
+    pub head: u64,
+    /// Absent when the node does not support the `safe` tag
+    pub safe: Option<u64>,
+    /// Absent when the node does not support the `finalized` tag
+    pub finalized: Option<u64>,
+    /// Seconds since the latest L2 block was produced
+    pub block_lag_secs: u64,
+    /// Seconds since the batch submitter last posted a batch to L1
+    pub submission_lag_secs: u64,
+    /// L1 balance of the batch submitter, in ether
+    pub submitter_balance: f64,
+    /// Average gas used per block over the sampled blocks
+    pub avg_gas_used: f64,
+    pub tps: f64,
+}
+
+/// JSON-RPC transport picked by the scheme of an endpoint's URL.
+#[derive(Debug)]
+enum Transport {
+    Http(Http),
+    Ws(Ws),
+}
+
+#[async_trait]
+impl JsonRpcClient for Transport {
+    type Error = ProviderError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, ProviderError>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        match self {
+            Transport::Http(http) => http.request(method, params).await.map_err(Into::into),
+            Transport::Ws(ws) => ws.request(method, params).await.map_err(Into::into),
+        }
+    }
+}
+
+/// The fields of a block the prober reads. Blocks are requested without
+/// their transactions, whose hashes are only counted.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Header {
+    number: U64,
+    timestamp: U256,
+    gas_used: U256,
+    transactions: Vec<IgnoredAny>,
+}
+
+/// Providers of a rollup's L2 and L1 endpoints, opened once per
+/// reconciliation and shared by `probe` and `sample`. An endpoint that cannot
+/// be opened only fails the components and metrics it serves.
+pub struct Endpoints {
+    l2: Result<Provider<Transport>>,
+    l1: Result<Provider<Transport>>,
+}
+
+/// Fills component status from JSON-RPC calls against a rollup's L1 and L2
+/// endpoints and marks components unhealthy when their data goes stale.
+pub struct HealthProber {
+    probe_timeout: Duration,
+    max_block_age: u64,
+    max_validation_age: u64,
+    max_submission_age: u64,
+}
+
+impl HealthProber {
+    pub fn new(config: &Config) -> Self {
+        let health = &config.rollup.health;
+        Self {
+            probe_timeout: Duration::from_secs(health.probe_timeout_secs),
+            max_block_age: health.max_block_age_secs,
+            max_validation_age: health.max_validation_age_secs,
+            max_submission_age: health.max_submission_age_secs,
+        }
+    }
+
+    /// Opens the L2 and L1 endpoints of a rollup.
+    pub async fn connect(&self, config: &RollupConfig) -> Endpoints {
+        Endpoints {
+            l2: self.provider("L2", &config.l2_rpc_url).await,
+            l1: self.provider("L1", &config.l1_rpc_url).await,
+        }
+    }
+
+    /// Updates `status` in place. Health flags are only ever lowered here, so
+    /// a component already marked unhealthy by the caller stays unhealthy.
+    pub async fn probe(
+        &self,
+        config: &RollupConfig,
+        endpoints: &Endpoints,
+        status: &mut RollupStatus,
+    ) -> Result<()> {
+        let now = Utc::now().timestamp() as u64;
+
+        // The sequencer is healthy while it keeps producing L2 blocks
+        match async { self.block(connected(&endpoints.l2)?, BlockNumber::Latest).await }.await {
+            Ok((number, timestamp)) => {
+                let sequencer = &mut status.sequencer_status;
+                sequencer.last_block = number;
+                sequencer.last_timestamp = timestamp;
+                sequencer.is_healthy &= now.saturating_sub(timestamp) <= self.max_block_age;
+            }
+            Err(e) => {
+                tracing::warn!("Sequencer probe for rollup {} failed: {:#}", config.name, e);
+                status.sequencer_status.is_healthy = false;
+            }
+        }
+
+        // The validator is healthy while the finalized head keeps advancing
+        match async { self.block(connected(&endpoints.l2)?, BlockNumber::Finalized).await }.await {
+            Ok((number, timestamp)) => {
+                let validator = &mut status.validator_status;
+                validator.last_validated_block = number;
+                validator.last_validation_timestamp = timestamp;
+                validator.is_healthy &= now.saturating_sub(timestamp) <= self.max_validation_age;
+            }
+            Err(e) => {
+                tracing::warn!("Validator probe for rollup {} failed: {:#}", config.name, e);
+                status.validator_status.is_healthy = false;
+            }
+        }
+
+        // Every batch is one L1 transaction from the submitter account, so its
+        // nonce counts submitted batches
+        let known = status.batch_submitter_status.last_submitted_batch;
+        match async { self.latest_submission(connected(&endpoints.l1)?, config, known).await }.await {
+            Ok(submission) => {
+                let submitter = &mut status.batch_submitter_status;
+                if let Some((batches, timestamp)) = submission {
+                    submitter.last_submitted_batch = batches;
+                    submitter.last_submission_timestamp = timestamp;
+                }
+                submitter.is_healthy &= submitter.last_submission_timestamp != 0
+                    && now.saturating_sub(submitter.last_submission_timestamp)
+                        <= self.max_submission_age;
+            }
+            Err(e) => {
+                tracing::warn!("Batch submitter probe for rollup {} failed: {:#}", config.name, e);
+                status.batch_submitter_status.is_healthy = false;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Samples the chain metrics of a rollup. `status` must have been probed
+    /// first, as the L1 submission lag comes from the batch submitter status.
+    pub async fn sample(
+        &self,
+        config: &RollupConfig,
+        endpoints: &Endpoints,
+        status: &RollupStatus,
+    ) -> Result<ChainMetrics> {
+        let l2 = connected(&endpoints.l2)?;
+        let l1 = connected(&endpoints.l1)?;
+        let now = Utc::now().timestamp() as u64;
+
+        let latest = self.header(l2, BlockNumber::Latest).await?;
+        let head = latest.number.as_u64();
+        let previous = (head.saturating_sub(SAMPLE_BLOCKS - 1)..head)
+            .rev()
+            .map(|number| self.header(l2, BlockNumber::Number(number.into())));
+        let (previous, safe, finalized, balance) = tokio::join!(
+            try_join_all(previous),
+            self.height(l2, BlockNumber::Safe),
+            self.height(l2, BlockNumber::Finalized),
+            self.call(l1.get_balance(config.batch_submitter_address, Some(BlockNumber::Latest.into()))),
+        );
+        let mut blocks = vec![latest];
+        blocks.extend(previous?);
+        let (avg_gas_used, tps) = throughput(&blocks);
+        let balance = balance?;
+
+        let submitted = status.batch_submitter_status.last_submission_timestamp;
+        Ok(ChainMetrics {
+            head,
+            safe,
+            finalized,
+            block_lag_secs: now.saturating_sub(blocks[0].timestamp.as_u64()),
+            submission_lag_secs: if submitted == 0 { 0 } else { now.saturating_sub(submitted) },
+            submitter_balance: format_ether(balance).parse()?,
+            avg_gas_used,
+            tps,
+        })
+    }
+
+    /// Opens a provider for an `http(s)` or `ws(s)` endpoint.
+    async fn provider(&self, layer: &str, url: &str) -> Result<Provider<Transport>> {
+        let transport = if url.starts_with("ws://") || url.starts_with("wss://") {
+            let ws = self
+                .call(Ws::connect(url))
+                .await
+                .with_context(|| format!("cannot connect to {} RPC endpoint {}", layer, url))?;
+            Transport::Ws(ws)
+        } else {
+            let http = url
+                .parse()
+                .with_context(|| format!("invalid {} RPC URL {}", layer, url))?;
+            Transport::Http(http)
+        };
+        Ok(Provider::new(transport))
+    }
+
+    async fn header(&self, provider: &Provider<Transport>, tag: BlockNumber) -> Result<Header> {
+        let request = provider
+            .as_ref()
+            .request::<_, Option<Header>>("eth_getBlockByNumber", (tag, false));
+        self.call(request)
+            .await?
+            .with_context(|| format!("node returned no {:?} block", tag))
+    }
+
+    /// Height of a tagged block, or `None` when the node cannot resolve the
+    /// tag, as older nodes do for `safe` and `finalized`.
+    async fn height(&self, provider: &Provider<Transport>, tag: BlockNumber) -> Option<u64> {
+        match self.block(provider, tag).await {
+            Ok((number, _)) => Some(number),
+            Err(e) => {
+                tracing::debug!("No {:?} block: {:#}", tag, e);
+                None
+            }
+        }
+    }
+
+    async fn block(&self, provider: &Provider<Transport>, tag: BlockNumber) -> Result<(u64, u64)> {
+        let header = self.header(provider, tag).await?;
+
+        Ok((header.number.as_u64(), header.timestamp.as_u64()))
+    }
+
+    /// The batches the submitter has posted and the timestamp of the L1 block
+    /// holding the latest one, or `None` when none was posted since the
+    /// `known` count.
+    async fn latest_submission(
+        &self,
+        l1: &Provider<Transport>,
+        config: &RollupConfig,
+        known: u64,
+    ) -> Result<Option<(u64, u64)>> {
+        let address = config.batch_submitter_address;
+        let (head, _) = self.block(l1, BlockNumber::Latest).await?;
+        let batches = self.nonce(l1, address, head).await?;
+        if batches <= known {
+            return Ok(None);
+        }
+        let included = self.inclusion_block(l1, address, batches, head).await?;
+        let (_, timestamp) = self.block(l1, BlockNumber::Number(included.into())).await?;
+
+        Ok(Some((batches, timestamp)))
+    }
+
+    /// The first block up to `head` after which the nonce of `address` reached
+    /// `nonce`, which is the block holding its latest transaction. Steps back
+    /// in doubling strides and then bisects, so a recent transaction is found
+    /// in a few requests against the node's recent state.
+    async fn inclusion_block(
+        &self,
+        l1: &Provider<Transport>,
+        address: Address,
+        nonce: u64,
+        head: u64,
+    ) -> Result<u64> {
+        // The nonce has reached `nonce` after `found` but not after `before`
+        let mut found = head;
+        let mut stride = 1;
+        let mut before = loop {
+            let candidate = found.saturating_sub(stride);
+            if candidate == found {
+                return Ok(found);
+            }
+            if self.nonce(l1, address, candidate).await? < nonce {
+                break candidate;
+            }
+            found = candidate;
+            stride *= 2;
+        };
+        while found - before > 1 {
+            let middle = before + (found - before) / 2;
+            if self.nonce(l1, address, middle).await? < nonce {
+                before = middle;
+            } else {
+                found = middle;
+            }
+        }
+
+        Ok(found)
+    }
+
+    /// Transactions sent from `address` up to and including block `number`.
+    async fn nonce(&self, l1: &Provider<Transport>, address: Address, number: u64) -> Result<u64> {
+        let block = BlockNumber::Number(number.into());
+        let nonce = self.call(l1.get_transaction_count(address, Some(block.into()))).await?;
+
+        Ok(nonce.as_u64())
+    }
+
+    async fn call<T, E>(&self, request: impl Future<Output = Result<T, E>>) -> Result<T>
+    where
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        tokio::time::timeout(self.probe_timeout, request)
+            .await
+            .context("JSON-RPC request timed out")?
+            .map_err(Into::into)
+    }
+}
+
+/// The provider of an endpoint, or why it could not be opened.
+fn connected(endpoint: &Result<Provider<Transport>>) -> Result<&Provider<Transport>> {
+    endpoint.as_ref().map_err(|e| anyhow::anyhow!("{:#}", e))
+}
+
+/// Average gas used per block and transactions per second over `blocks`,
+/// newest first. The oldest block only marks the start of the time span, so
+/// its transactions are not counted.
+fn throughput(blocks: &[Header]) -> (f64, f64) {
+    if blocks.is_empty() {
+        return (0.0, 0.0);
+    }
+    let gas: f64 = blocks.iter().map(|b| b.gas_used.as_u128() as f64).sum();
+    let avg_gas_used = gas / blocks.len() as f64;
+
+    let (newest, oldest) = (&blocks[0], &blocks[blocks.len() - 1]);
+    let span = newest.timestamp.as_u64().saturating_sub(oldest.timestamp.as_u64());
+    if span == 0 {
+        return (avg_gas_used, 0.0);
+    }
+    let transactions: usize = blocks[..blocks.len() - 1].iter().map(|b| b.transactions.len()).sum();
+
+    (avg_gas_used, transactions as f64 / span as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    use crate::{
+        rollup::RollupStatus,
+        testing::{rollup_config, test_config, FakeNode},
+    };
+
+    const UNREACHABLE: &str = "http://127.0.0.1:1";
+
+    fn healthy_status() -> RollupStatus {
+        serde_json::from_value(json!({
+            "name": "r1",
+            "state": "Running",
+            "chain_id": 4242,
+            "sequencer_status": { "is_healthy": true, "last_block": 0, "last_timestamp": 0 },
+            "validator_status": {
+                "is_healthy": true,
+                "last_validated_block": 0,
+                "last_validation_timestamp": 0,
+            },
+            "batch_submitter_status": {
+                "is_healthy": true,
+                "last_submitted_batch": 0,
+                "last_submission_timestamp": 0,
+            },
+        }))
+        .unwrap()
+    }
+
+    /// Probes a rollup whose L2 and L1 nodes are at the given URLs.
+    async fn probe(l2: &str, l1: &str, status: &mut RollupStatus) {
+        let mut config = rollup_config("r1");
+        config.l2_rpc_url = l2.to_string();
+        config.l1_rpc_url = l1.to_string();
+        let prober = HealthProber::new(&test_config());
+        let endpoints = prober.connect(&config).await;
+        prober.probe(&config, &endpoints, status).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn components_with_fresh_data_are_healthy() {
+        let (l2, l2_node) = FakeNode::new(1000, 4).finalized(990).start().await;
+        let (l1, l1_node) = FakeNode::new(500, 12)
+            .transaction_count(7)
+            .last_transaction(497)
+            .start()
+            .await;
+
+        let mut status = healthy_status();
+        probe(&l2, &l1, &mut status).await;
+
+        let sequencer = &status.sequencer_status;
+        assert!(sequencer.is_healthy);
+        assert_eq!(sequencer.last_block, 1000);
+        assert_eq!(sequencer.last_timestamp, l2_node.timestamp(1000));
+        let validator = &status.validator_status;
+        assert!(validator.is_healthy);
+        assert_eq!(validator.last_validated_block, 990);
+        assert_eq!(validator.last_validation_timestamp, l2_node.timestamp(990));
+        let submitter = &status.batch_submitter_status;
+        assert!(submitter.is_healthy);
+        assert_eq!(submitter.last_submitted_batch, 7);
+        assert_eq!(submitter.last_submission_timestamp, l1_node.timestamp(497));
+    }
+
+    #[tokio::test]
+    async fn submissions_are_timed_by_the_block_that_included_them() {
+        // A new batch was found, but it landed on L1 over an hour ago
+        let (l2, _) = FakeNode::new(1000, 4).finalized(990).start().await;
+        let (l1, l1_node) = FakeNode::new(2000, 12)
+            .transaction_count(7)
+            .last_transaction(200)
+            .start()
+            .await;
+
+        let mut status = healthy_status();
+        probe(&l2, &l1, &mut status).await;
+        let submitter = &status.batch_submitter_status;
+        assert_eq!(submitter.last_submitted_batch, 7);
+        assert_eq!(submitter.last_submission_timestamp, l1_node.timestamp(200));
+        assert!(!submitter.is_healthy);
+
+        // Transactions since genesis date from the first block
+        let (l1, l1_node) = FakeNode::new(30, 12).transaction_count(7).start().await;
+        let mut status = healthy_status();
+        probe(&l2, &l1, &mut status).await;
+        assert_eq!(
+            status.batch_submitter_status.last_submission_timestamp,
+            l1_node.timestamp(0)
+        );
+    }
+
+    #[tokio::test]
+    async fn components_with_stale_data_are_unhealthy() {
+        // The head is older than the 60 second limit and the finalized head
+        // older than the hour allowed for validation
+        let (l2, _) = FakeNode::new(5000, 120).finalized(100).start().await;
+        // No batch since the last probe, half an hour ago
+        let (l1, _) = FakeNode::new(500, 12).transaction_count(7).start().await;
+
+        let mut status = healthy_status();
+        status.batch_submitter_status.last_submitted_batch = 7;
+        status.batch_submitter_status.last_submission_timestamp =
+            Utc::now().timestamp() as u64 - 1900;
+        probe(&l2, &l1, &mut status).await;
+
+        assert!(!status.sequencer_status.is_healthy);
+        assert_eq!(status.sequencer_status.last_block, 5000);
+        assert!(!status.validator_status.is_healthy);
+        assert!(!status.batch_submitter_status.is_healthy);
+    }
+
+    #[tokio::test]
+    async fn components_behind_unreachable_nodes_are_unhealthy() {
+        let (node, _) = FakeNode::new(1000, 4)
+            .finalized(990)
+            .transaction_count(7)
+            .last_transaction(999)
+            .start()
+            .await;
+
+        let mut status = healthy_status();
+        probe(UNREACHABLE, &node, &mut status).await;
+        assert!(!status.sequencer_status.is_healthy);
+        assert!(!status.validator_status.is_healthy);
+        assert!(status.batch_submitter_status.is_healthy);
+
+        let mut status = healthy_status();
+        probe(&node, UNREACHABLE, &mut status).await;
+        assert!(status.sequencer_status.is_healthy);
+        assert!(status.validator_status.is_healthy);
+        assert!(!status.batch_submitter_status.is_healthy);
+
+        // A node without finalized blocks cannot vouch for the validator
+        let (l2, _) = FakeNode::new(1000, 4).start().await;
+        let mut status = healthy_status();
+        probe(&l2, &node, &mut status).await;
+        assert!(status.sequencer_status.is_healthy);
+        assert!(!status.validator_status.is_healthy);
+    }
+
+    #[tokio::test]
+    async fn websocket_endpoints_are_probed() {
+        let (l2, _) = FakeNode::new(1000, 4).finalized(990).start().await;
+        let (l1, _) = FakeNode::new(500, 12).transaction_count(7).start().await;
+
+        let mut status = healthy_status();
+        probe(&l2.replace("http://", "ws://"), &l1, &mut status).await;
+        assert!(status.sequencer_status.is_healthy);
+        assert_eq!(status.sequencer_status.last_block, 1000);
+        assert!(status.validator_status.is_healthy);
+
+        let mut status = healthy_status();
+        probe("ws://127.0.0.1:1", &l1, &mut status).await;
+        assert!(!status.sequencer_status.is_healthy);
+        assert!(status.batch_submitter_status.is_healthy);
+    }
+
+    /// Samples a rollup whose L2 and L1 nodes are at the given URLs.
+    async fn sample(l2: &str, l1: &str, status: &RollupStatus) -> Result<ChainMetrics> {
+        let mut config = rollup_config("r1");
+        config.l2_rpc_url = l2.to_string();
+        config.l1_rpc_url = l1.to_string();
+        let prober = HealthProber::new(&test_config());
+        let endpoints = prober.connect(&config).await;
+        prober.sample(&config, &endpoints, status).await
+    }
+
+    #[tokio::test]
+    async fn chain_metrics_are_sampled_from_both_layers() {
+        let (l2, _) = FakeNode::new(1000, 4)
+            .safe(995)
+            .finalized(990)
+            .start()
+            .await;
+        let (l1, _) = FakeNode::new(500, 12)
+            .balance(U256::exp10(18) * 3 / 2)
+            .start()
+            .await;
+
+        let mut status = healthy_status();
+        status.batch_submitter_status.last_submission_timestamp =
+            Utc::now().timestamp() as u64 - 30;
+        let chain = sample(&l2, &l1, &status).await.unwrap();
+
+        assert_eq!(chain.head, 1000);
+        assert_eq!(chain.safe, Some(995));
+        assert_eq!(chain.finalized, Some(990));
+        assert!((4..=6).contains(&chain.block_lag_secs));
+        assert!((30..=32).contains(&chain.submission_lag_secs));
+        assert_eq!(chain.submitter_balance, 1.5);
+        // Twenty blocks two seconds apart with ten transactions each
+        assert_eq!(chain.avg_gas_used, 210_000.0);
+        assert_eq!(chain.tps, 190.0 / 38.0);
+    }
+
+    #[tokio::test]
+    async fn nodes_without_safe_or_finalized_blocks_are_still_sampled() {
+        let (l2, _) = FakeNode::new(1000, 4).start().await;
+        let (l1, _) = FakeNode::new(500, 12).start().await;
+
+        let chain = sample(&l2, &l1, &healthy_status()).await.unwrap();
+        assert_eq!(chain.head, 1000);
+        assert_eq!(chain.safe, None);
+        assert_eq!(chain.finalized, None);
+        // No batch was ever submitted
+        assert_eq!(chain.submission_lag_secs, 0);
+
+        assert!(sample(UNREACHABLE, &l1, &healthy_status()).await.is_err());
+    }
+
+    fn block(gas_used: u64, transactions: usize, timestamp: u64) -> Header {
+        Header {
+            number: U64::zero(),
+            gas_used: gas_used.into(),
+            transactions: vec![IgnoredAny; transactions],
+            timestamp: timestamp.into(),
+        }
+    }
+
+    #[test]
+    fn throughput_averages_gas_and_counts_transactions_over_the_span() {
+        let blocks = [block(300, 10, 24), block(200, 20, 12), block(100, 99, 0)];
+        assert_eq!(throughput(&blocks), (200.0, 30.0 / 24.0));
+    }
+
+    #[test]
+    fn throughput_of_a_single_block_has_no_rate() {
+        assert_eq!(throughput(&[block(100, 5, 12)]), (100.0, 0.0));
+        assert_eq!(throughput(&[]), (0.0, 0.0));
+    }
+}
@@ -0,0 +1,777 @@
+use anyhow::Result;
+use ethers::types::Address;
+use k8s_openapi::{
+    api::{
+        apps::v1::{Deployment, DeploymentSpec, DeploymentStrategy},
+        core::v1::{
+            ConfigMap, ConfigMapEnvSource, Container, ContainerPort, EnvFromSource, EnvVar,
+            PersistentVolumeClaim, PersistentVolumeClaimSpec, PersistentVolumeClaimVolumeSource,
+            PodSpec, PodTemplateSpec, ResourceRequirements, Service, ServicePort, ServiceSpec,
+            Volume, VolumeMount,
+        },
+    },
+    apimachinery::pkg::{
+        api::resource::Quantity, apis::meta::v1::LabelSelector, util::intstr::IntOrString,
+    },
+};
+use kube::api::ObjectMeta;
+use std::collections::BTreeMap;
+
+use crate::project::PROJECT_LABEL;
+use super::{
+    topology::{DataAvailabilityMode, TopologyConfig},
+    DeploymentType, RollupConfig,
+};
+
+pub const ROLLUP_ID_LABEL: &str = "galato.io/rollup-id";
+pub const MANAGED_BY_LABEL: &str = "app.kubernetes.io/managed-by";
+pub const MANAGER_NAME: &str = "galato";
+
+/// Owner references cannot point across clusters, so every object names the
+/// rollup that owns it here instead.
+const OWNER_ANNOTATION: &str = "galato.io/owner";
+
+const CHAIN_ID_ANNOTATION: &str = "galato.io/chain-id";
+const DEPLOYMENT_TYPE_ANNOTATION: &str = "galato.io/deployment-type";
+const DATA_MOUNT_PATH: &str = "/data";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ComponentKind {
+    Sequencer,
+    Validator,
+    BatchSubmitter,
+    Proposer,
+    Challenger,
+    Prover,
+    DaCommittee,
+    DaNode,
+}
+
+impl ComponentKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ComponentKind::Sequencer => "sequencer",
+            ComponentKind::Validator => "validator",
+            ComponentKind::BatchSubmitter => "batch-submitter",
+            ComponentKind::Proposer => "proposer",
+            ComponentKind::Challenger => "challenger",
+            ComponentKind::Prover => "prover",
+            ComponentKind::DaCommittee => "da-committee",
+            ComponentKind::DaNode => "da-node",
+        }
+    }
+
+    /// CPU and memory requested by each replica. Every container requests
+    /// both so that pods can be scheduled in namespaces with a quota.
+    pub fn resources(&self) -> (&'static str, &'static str) {
+        match self {
+            ComponentKind::Sequencer => ("2", "8Gi"),
+            ComponentKind::Validator => ("1", "4Gi"),
+            ComponentKind::BatchSubmitter => ("500m", "1Gi"),
+            ComponentKind::Proposer | ComponentKind::Challenger => ("250m", "512Mi"),
+            ComponentKind::Prover => ("4", "16Gi"),
+            ComponentKind::DaCommittee => ("500m", "1Gi"),
+            ComponentKind::DaNode => ("1", "4Gi"),
+        }
+    }
+
+    /// Components every rollup runs regardless of its deployment type.
+    pub fn is_core(&self) -> bool {
+        matches!(
+            self,
+            ComponentKind::Sequencer | ComponentKind::Validator | ComponentKind::BatchSubmitter
+        )
+    }
+}
+
+/// Everything needed to render one rollup component into Kubernetes objects.
+#[derive(Debug, Clone)]
+pub struct Component {
+    pub kind: ComponentKind,
+    pub image: String,
+    pub replicas: i32,
+    /// Environment variable and value of the component's signing address
+    pub address: Option<(&'static str, Address)>,
+    /// Named container port exposed through a Service
+    pub port: Option<(&'static str, i32)>,
+    /// Size of the persistent data volume, if the component keeps state
+    pub storage: Option<&'static str>,
+}
+
+/// Returns the components deployed for a rollup: the core sequencer,
+/// validator and batch submitter plus the topology of its deployment type.
+pub fn components(config: &RollupConfig) -> Vec<Component> {
+    let mut components = vec![
+        Component {
+            kind: ComponentKind::Sequencer,
+            image: "galato/sequencer:latest".to_string(),
+            replicas: 1,
+            address: Some(("SEQUENCER_ADDRESS", config.sequencer_address)),
+            port: Some(("rpc", 8545)),
+            storage: Some("50Gi"),
+        },
+        Component {
+            kind: ComponentKind::Validator,
+            image: "galato/validator:latest".to_string(),
+            replicas: 1,
+            address: Some(("VALIDATOR_ADDRESS", config.validator_address)),
+            port: Some(("rpc", 8546)),
+            storage: None,
+        },
+        Component {
+            kind: ComponentKind::BatchSubmitter,
+            image: "galato/batch-submitter:latest".to_string(),
+            replicas: 1,
+            address: Some(("BATCH_SUBMITTER_ADDRESS", config.batch_submitter_address)),
+            port: Some(("metrics", 8547)),
+            storage: None,
+        },
+    ];
+
+    let topology = &config.topology;
+    match config.deployment_type {
+        DeploymentType::Optimistic => {
+            let optimistic = &topology.optimistic;
+            components.push(Component {
+                kind: ComponentKind::Proposer,
+                image: "galato/proposer:latest".to_string(),
+                replicas: 1,
+                address: Some((
+                    "PROPOSER_ADDRESS",
+                    optimistic.proposer_address.unwrap_or(config.validator_address),
+                )),
+                port: Some(("metrics", 7300)),
+                storage: None,
+            });
+            components.push(Component {
+                kind: ComponentKind::Challenger,
+                image: "galato/challenger:latest".to_string(),
+                replicas: 1,
+                address: Some((
+                    "CHALLENGER_ADDRESS",
+                    optimistic.challenger_address.unwrap_or(config.validator_address),
+                )),
+                port: Some(("metrics", 7301)),
+                storage: None,
+            });
+        }
+        DeploymentType::ZkRollup => components.push(Component {
+            kind: ComponentKind::Prover,
+            image: "galato/prover:latest".to_string(),
+            replicas: topology.zk.prover_replicas,
+            address: None,
+            port: Some(("rpc", 9050)),
+            storage: None,
+        }),
+        DeploymentType::Validium => match topology.validium.mode {
+            DataAvailabilityMode::Committee => components.push(Component {
+                kind: ComponentKind::DaCommittee,
+                image: "galato/da-committee:latest".to_string(),
+                replicas: topology.validium.committee_size,
+                address: None,
+                port: Some(("rpc", 8444)),
+                storage: None,
+            }),
+            DataAvailabilityMode::Node => components.push(Component {
+                kind: ComponentKind::DaNode,
+                image: "galato/da-node:latest".to_string(),
+                replicas: 1,
+                address: None,
+                port: Some(("rpc", 7980)),
+                storage: Some("100Gi"),
+            }),
+        },
+    }
+
+    components
+}
+
+/// Name shared by the Deployment, Service and volume claim of a component.
+pub fn resource_name(rollup: &str, kind: ComponentKind) -> String {
+    format!("{}-{}", rollup, kind.as_str())
+}
+
+/// Label selector matching every object that belongs to a rollup.
+pub fn rollup_selector(rollup: &str) -> String {
+    format!("{}={},{}={}", ROLLUP_ID_LABEL, rollup, MANAGED_BY_LABEL, MANAGER_NAME)
+}
+
+/// The full set of Kubernetes objects backing a rollup.
+#[derive(Debug, Clone)]
+pub struct RollupManifest {
+    pub config_map: ConfigMap,
+    pub deployments: Vec<Deployment>,
+    pub services: Vec<Service>,
+    pub volume_claims: Vec<PersistentVolumeClaim>,
+}
+
+impl RollupManifest {
+    pub fn build(namespace: &str, config: &RollupConfig) -> Self {
+        let config_map = build_config_map(namespace, config);
+        let mut deployments = Vec::new();
+        let mut services = Vec::new();
+        let mut volume_claims = Vec::new();
+
+        for component in components(config) {
+            deployments.push(build_deployment(namespace, config, &component));
+            if let Some(service) = build_service(namespace, config, &component) {
+                services.push(service);
+            }
+            if let Some(claim) = build_volume_claim(namespace, config, &component) {
+                volume_claims.push(claim);
+            }
+        }
+
+        Self {
+            config_map,
+            deployments,
+            services,
+            volume_claims,
+        }
+    }
+
+    /// Objects in apply order: configuration and storage before workloads.
+    pub fn to_values(&self) -> Result<Vec<serde_json::Value>> {
+        let mut values = vec![serde_json::to_value(&self.config_map)?];
+        for claim in &self.volume_claims {
+            values.push(serde_json::to_value(claim)?);
+        }
+        for deployment in &self.deployments {
+            values.push(serde_json::to_value(deployment)?);
+        }
+        for service in &self.services {
+            values.push(serde_json::to_value(service)?);
+        }
+        Ok(values)
+    }
+
+    /// Renders a multi-document YAML stream.
+    pub fn to_yaml(&self) -> Result<String> {
+        let documents = self
+            .to_values()?
+            .iter()
+            .map(serde_yaml::to_string)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(documents.join("---\n"))
+    }
+}
+
+fn metadata(name: String, namespace: &str, config: &RollupConfig, component: Option<ComponentKind>) -> ObjectMeta {
+    let mut labels = BTreeMap::from([
+        ("app.kubernetes.io/instance".to_string(), config.name.clone()),
+        ("app.kubernetes.io/part-of".to_string(), "galato".to_string()),
+        (MANAGED_BY_LABEL.to_string(), MANAGER_NAME.to_string()),
+        (ROLLUP_ID_LABEL.to_string(), config.name.clone()),
+        (PROJECT_LABEL.to_string(), config.project.clone()),
+    ]);
+    if let Some(kind) = component {
+        labels.insert("app.kubernetes.io/component".to_string(), kind.as_str().to_string());
+        labels.extend(selector_labels(&config.name, kind));
+    }
+
+    let annotations = BTreeMap::from([
+        (OWNER_ANNOTATION.to_string(), owner(config)),
+        (CHAIN_ID_ANNOTATION.to_string(), config.chain_id.to_string()),
+        (DEPLOYMENT_TYPE_ANNOTATION.to_string(), format!("{:?}", config.deployment_type)),
+    ]);
+
+    ObjectMeta {
+        name: Some(name),
+        namespace: Some(namespace.to_string()),
+        labels: Some(labels),
+        annotations: Some(annotations),
+        ..Default::default()
+    }
+}
+
+/// Identifies the owning rollup as `<project>/rollups/<name>`.
+fn owner(config: &RollupConfig) -> String {
+    format!("{}/rollups/{}", config.project, config.name)
+}
+
+fn selector_labels(rollup: &str, kind: ComponentKind) -> BTreeMap<String, String> {
+    BTreeMap::from([("app".to_string(), resource_name(rollup, kind))])
+}
+
+fn config_map_name(rollup: &str) -> String {
+    format!("{}-config", rollup)
+}
+
+fn build_config_map(namespace: &str, config: &RollupConfig) -> ConfigMap {
+    let mut data = BTreeMap::from([
+        ("CHAIN_ID".to_string(), config.chain_id.to_string()),
+        ("L1_CHAIN_ID".to_string(), config.l1_chain_id.to_string()),
+        ("L1_RPC_URL".to_string(), config.l1_rpc_url.clone()),
+        ("L2_RPC_URL".to_string(), config.l2_rpc_url.clone()),
+    ]);
+    data.extend(topology_settings(&config.deployment_type, &config.topology));
+
+    ConfigMap {
+        metadata: metadata(config_map_name(&config.name), namespace, config, None),
+        data: Some(data),
+        ..Default::default()
+    }
+}
+
+fn topology_settings(deployment_type: &DeploymentType, topology: &TopologyConfig) -> BTreeMap<String, String> {
+    let mut settings = BTreeMap::new();
+    match deployment_type {
+        DeploymentType::Optimistic => {
+            settings.insert(
+                "CHALLENGE_PERIOD_SECS".to_string(),
+                topology.optimistic.challenge_period_secs.to_string(),
+            );
+        }
+        DeploymentType::ZkRollup => {
+            settings.insert("PROOF_SYSTEM".to_string(), topology.zk.proof_system.clone());
+            if let Some(verifier) = topology.zk.verifier_address {
+                settings.insert("VERIFIER_ADDRESS".to_string(), format!("{:?}", verifier));
+            }
+        }
+        DeploymentType::Validium => {
+            let validium = &topology.validium;
+            let mode = match validium.mode {
+                DataAvailabilityMode::Committee => "committee",
+                DataAvailabilityMode::Node => "node",
+            };
+            settings.insert("DA_MODE".to_string(), mode.to_string());
+            settings.insert("DA_COMMITTEE_SIZE".to_string(), validium.committee_size.to_string());
+            settings.insert(
+                "DA_COMMITTEE_THRESHOLD".to_string(),
+                validium.committee_threshold.to_string(),
+            );
+        }
+    }
+    settings
+}
+
+fn build_deployment(namespace: &str, config: &RollupConfig, component: &Component) -> Deployment {
+    let name = resource_name(&config.name, component.kind);
+    let selector = selector_labels(&config.name, component.kind);
+
+    let mut container = Container {
+        name: component.kind.as_str().to_string(),
+        image: Some(component.image.clone()),
+        env: component.address.map(|(env, address)| {
+            vec![EnvVar {
+                name: env.to_string(),
+                value: Some(format!("{:?}", address)),
+                ..Default::default()
+            }]
+        }),
+        env_from: Some(vec![EnvFromSource {
+            config_map_ref: Some(ConfigMapEnvSource {
+                name: Some(config_map_name(&config.name)),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }]),
+        ports: component.port.map(|(port_name, port)| {
+            vec![ContainerPort {
+                name: Some(port_name.to_string()),
+                container_port: port,
+                ..Default::default()
+            }]
+        }),
+        resources: Some(container_resources(component.kind)),
+        ..Default::default()
+    };
+
+    let mut volumes = None;
+    let mut strategy = None;
+    if component.storage.is_some() {
+        container.volume_mounts = Some(vec![VolumeMount {
+            name: "data".to_string(),
+            mount_path: DATA_MOUNT_PATH.to_string(),
+            ..Default::default()
+        }]);
+        volumes = Some(vec![Volume {
+            name: "data".to_string(),
+            persistent_volume_claim: Some(PersistentVolumeClaimVolumeSource {
+                claim_name: name.clone(),
+                read_only: None,
+            }),
+            ..Default::default()
+        }]);
+        // The claim is ReadWriteOnce, so the old pod has to release it before
+        // its replacement can start.
+        strategy = Some(DeploymentStrategy {
+            type_: Some("Recreate".to_string()),
+            ..Default::default()
+        });
+    }
+
+    Deployment {
+        metadata: metadata(name, namespace, config, Some(component.kind)),
+        spec: Some(DeploymentSpec {
+            replicas: Some(component.replicas),
+            selector: LabelSelector {
+                match_labels: Some(selector.clone()),
+                ..Default::default()
+            },
+            template: PodTemplateSpec {
+                metadata: Some(ObjectMeta {
+                    labels: Some(selector),
+                    ..Default::default()
+                }),
+                spec: Some(PodSpec {
+                    containers: vec![container],
+                    volumes,
+                    ..Default::default()
+                }),
+            },
+            strategy,
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+/// Requests the component's CPU and memory, and caps memory at the request
+/// so a leaking node is restarted rather than starving its neighbours.
+fn container_resources(kind: ComponentKind) -> ResourceRequirements {
+    let (cpu, memory) = kind.resources();
+    ResourceRequirements {
+        requests: Some(BTreeMap::from([
+            ("cpu".to_string(), Quantity(cpu.to_string())),
+            ("memory".to_string(), Quantity(memory.to_string())),
+        ])),
+        limits: Some(BTreeMap::from([("memory".to_string(), Quantity(memory.to_string()))])),
+        ..Default::default()
+    }
+}
+
+fn build_service(namespace: &str, config: &RollupConfig, component: &Component) -> Option<Service> {
+    let (port_name, port) = component.port?;
+
+    Some(Service {
+        metadata: metadata(resource_name(&config.name, component.kind), namespace, config, Some(component.kind)),
+        spec: Some(ServiceSpec {
+            selector: Some(selector_labels(&config.name, component.kind)),
+            ports: Some(vec![ServicePort {
+                name: Some(port_name.to_string()),
+                port,
+                target_port: Some(IntOrString::String(port_name.to_string())),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        }),
+        ..Default::default()
+    })
+}
+
+fn build_volume_claim(
+    namespace: &str,
+    config: &RollupConfig,
+    component: &Component,
+) -> Option<PersistentVolumeClaim> {
+    let size = component.storage?;
+
+    Some(PersistentVolumeClaim {
+        metadata: metadata(resource_name(&config.name, component.kind), namespace, config, Some(component.kind)),
+        spec: Some(PersistentVolumeClaimSpec {
+            access_modes: Some(vec!["ReadWriteOnce".to_string()]),
+            resources: Some(ResourceRequirements {
+                requests: Some(BTreeMap::from([("storage".to_string(), Quantity(size.to_string()))])),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        project::{Project, ProjectQuotas},
+        rollup::topology::ValidiumConfig,
+        testing::rollup_config,
+    };
+
+    /// A rollup of every deployment type and data availability mode.
+    fn every_type() -> Vec<RollupConfig> {
+        let mut zk = rollup_config("zk");
+        zk.deployment_type = DeploymentType::ZkRollup;
+        let mut committee = rollup_config("committee");
+        committee.deployment_type = DeploymentType::Validium;
+        let mut da_node = rollup_config("da-node");
+        da_node.deployment_type = DeploymentType::Validium;
+        da_node.topology.validium = ValidiumConfig {
+            mode: DataAvailabilityMode::Node,
+            ..Default::default()
+        };
+        vec![rollup_config("optimistic"), zk, committee, da_node]
+    }
+
+    fn containers(manifest: &RollupManifest) -> impl Iterator<Item = &Container> {
+        manifest.deployments.iter().flat_map(|deployment| {
+            let pod = deployment.spec.as_ref().unwrap().template.spec.as_ref();
+            pod.unwrap().containers.iter()
+        })
+    }
+
+    #[test]
+    fn pods_request_every_resource_a_project_quota_limits() {
+        let project = Project {
+            name: "team-a".to_string(),
+            namespace: "team-a".to_string(),
+            quotas: ProjectQuotas {
+                cpu: Some("32".to_string()),
+                memory: Some("128Gi".to_string()),
+                ..Default::default()
+            },
+            created_at: chrono::Utc::now(),
+        };
+        let quota = crate::project::quota_resources(&project);
+        assert!(!quota.is_empty());
+
+        for config in every_type() {
+            let manifest = RollupManifest::build("team-a", &config);
+            for container in containers(&manifest) {
+                let resources = container.resources.as_ref().unwrap();
+                let requests = resources.requests.as_ref().unwrap();
+                for resource in quota.keys() {
+                    let resource = resource.trim_start_matches("requests.");
+                    assert!(
+                        resource == "storage" || requests.contains_key(resource),
+                        "{} does not request {}",
+                        container.name,
+                        resource
+                    );
+                }
+            }
+        }
+    }
+
+    /// Expected components of each rollup returned by `every_type`.
+    fn expected_components(config: &RollupConfig) -> Vec<ComponentKind> {
+        let mut kinds = vec![
+            ComponentKind::Sequencer,
+            ComponentKind::Validator,
+            ComponentKind::BatchSubmitter,
+        ];
+        match config.name.as_str() {
+            "optimistic" => kinds.extend([ComponentKind::Proposer, ComponentKind::Challenger]),
+            "zk" => kinds.push(ComponentKind::Prover),
+            "committee" => kinds.push(ComponentKind::DaCommittee),
+            "da-node" => kinds.push(ComponentKind::DaNode),
+            name => panic!("unexpected rollup {}", name),
+        }
+        kinds
+    }
+
+    fn names<'a>(objects: impl Iterator<Item = &'a ObjectMeta>) -> Vec<String> {
+        objects.map(|meta| meta.name.clone().unwrap()).collect()
+    }
+
+    /// Whether `labels` satisfy a selector of comma separated `key=value` pairs.
+    fn selected(selector: &str, labels: &BTreeMap<String, String>) -> bool {
+        selector.split(',').all(|term| {
+            let (key, value) = term.split_once('=').unwrap();
+            labels.get(key).map(String::as_str) == Some(value)
+        })
+    }
+
+    #[test]
+    fn every_component_gets_a_deployment_and_its_service_and_storage() {
+        for config in every_type() {
+            let manifest = RollupManifest::build("rollups", &config);
+            let kinds = expected_components(&config);
+
+            let expected: Vec<_> = kinds
+                .iter()
+                .map(|kind| resource_name(&config.name, *kind))
+                .collect();
+            let deployments = names(manifest.deployments.iter().map(|d| &d.metadata));
+            assert_eq!(deployments, expected, "{}", config.name);
+            let services = names(manifest.services.iter().map(|s| &s.metadata));
+            assert_eq!(services, expected, "{}", config.name);
+
+            let mut stateful = vec![resource_name(&config.name, ComponentKind::Sequencer)];
+            if kinds.contains(&ComponentKind::DaNode) {
+                stateful.push(resource_name(&config.name, ComponentKind::DaNode));
+            }
+            let claims = names(manifest.volume_claims.iter().map(|c| &c.metadata));
+            assert_eq!(claims, stateful, "{}", config.name);
+
+            assert_eq!(
+                manifest.config_map.metadata.name.as_deref(),
+                Some(format!("{}-config", config.name).as_str())
+            );
+            let data = manifest.config_map.data.as_ref().unwrap();
+            assert_eq!(data["CHAIN_ID"], "4242");
+        }
+    }
+
+    #[test]
+    fn every_object_is_labelled_with_its_rollup_and_owner() {
+        for config in every_type() {
+            let manifest = RollupManifest::build("rollups", &config);
+            let values = manifest.to_values().unwrap();
+            assert_eq!(
+                values.len(),
+                1 + manifest.deployments.len()
+                    + manifest.services.len()
+                    + manifest.volume_claims.len()
+            );
+            assert_eq!(values[0]["kind"], "ConfigMap");
+
+            for value in values {
+                let meta: ObjectMeta = serde_json::from_value(value["metadata"].clone()).unwrap();
+                let labels = meta.labels.unwrap();
+                assert!(
+                    selected(&rollup_selector(&config.name), &labels),
+                    "{:?}",
+                    meta.name
+                );
+                assert!(!selected(&rollup_selector("other"), &labels));
+                assert_eq!(labels[PROJECT_LABEL], config.project);
+                assert_eq!(meta.namespace.as_deref(), Some("rollups"));
+
+                let annotations = meta.annotations.unwrap();
+                assert_eq!(
+                    annotations[OWNER_ANNOTATION],
+                    format!("{}/rollups/{}", config.project, config.name)
+                );
+                assert_eq!(annotations[CHAIN_ID_ANNOTATION], "4242");
+            }
+        }
+    }
+
+    #[test]
+    fn selectors_match_only_their_own_component() {
+        for config in every_type() {
+            let manifest = RollupManifest::build("rollups", &config);
+            let pods: Vec<_> = manifest
+                .deployments
+                .iter()
+                .map(|deployment| {
+                    let spec = deployment.spec.as_ref().unwrap();
+                    let labels = spec
+                        .template
+                        .metadata
+                        .as_ref()
+                        .unwrap()
+                        .labels
+                        .clone()
+                        .unwrap();
+                    assert_eq!(spec.selector.match_labels.as_ref(), Some(&labels));
+                    (deployment.metadata.name.clone().unwrap(), labels)
+                })
+                .collect();
+
+            for service in &manifest.services {
+                let selector = service.spec.as_ref().unwrap().selector.as_ref().unwrap();
+                let matched: Vec<_> = pods
+                    .iter()
+                    .filter(|(_, labels)| {
+                        selector
+                            .iter()
+                            .all(|(key, value)| labels.get(key) == Some(value))
+                    })
+                    .map(|(name, _)| name)
+                    .collect();
+                assert_eq!(matched, vec![service.metadata.name.as_ref().unwrap()]);
+            }
+        }
+    }
+
+    #[test]
+    fn stateful_components_mount_their_claim() {
+        for config in every_type() {
+            let manifest = RollupManifest::build("rollups", &config);
+            for claim in &manifest.volume_claims {
+                let name = claim.metadata.name.as_ref().unwrap();
+                let deployment = manifest
+                    .deployments
+                    .iter()
+                    .find(|deployment| deployment.metadata.name.as_ref() == Some(name))
+                    .unwrap();
+                let pod = deployment
+                    .spec
+                    .as_ref()
+                    .unwrap()
+                    .template
+                    .spec
+                    .as_ref()
+                    .unwrap();
+                let volume = &pod.volumes.as_ref().unwrap()[0];
+                assert_eq!(
+                    &volume.persistent_volume_claim.as_ref().unwrap().claim_name,
+                    name
+                );
+                let mounts = pod.containers[0].volume_mounts.as_ref().unwrap();
+                assert_eq!(mounts[0].mount_path, DATA_MOUNT_PATH);
+            }
+        }
+    }
+
+    #[test]
+    fn only_stateful_components_are_recreated() {
+        for config in every_type() {
+            let manifest = RollupManifest::build("rollups", &config);
+            for deployment in &manifest.deployments {
+                let name = deployment.metadata.name.as_ref().unwrap();
+                let stateful = manifest
+                    .volume_claims
+                    .iter()
+                    .any(|claim| claim.metadata.name.as_ref() == Some(name));
+                let strategy = deployment.spec.as_ref().unwrap().strategy.as_ref();
+                let recreated = strategy.and_then(|strategy| strategy.type_.as_deref());
+                assert_eq!(recreated == Some("Recreate"), stateful, "{}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn topology_replicas_and_settings_are_rendered() {
+        for config in every_type() {
+            let manifest = RollupManifest::build("rollups", &config);
+            let replicas = |kind| {
+                let name = resource_name(&config.name, kind);
+                let deployment = manifest
+                    .deployments
+                    .iter()
+                    .find(|d| d.metadata.name == Some(name.clone()));
+                deployment.and_then(|d| d.spec.as_ref().unwrap().replicas)
+            };
+            let data = manifest.config_map.data.as_ref().unwrap();
+            let topology = &config.topology;
+            match config.name.as_str() {
+                "optimistic" => assert_eq!(
+                    data["CHALLENGE_PERIOD_SECS"],
+                    topology.optimistic.challenge_period_secs.to_string()
+                ),
+                "zk" => {
+                    assert_eq!(
+                        replicas(ComponentKind::Prover),
+                        Some(topology.zk.prover_replicas)
+                    );
+                    assert_eq!(data["PROOF_SYSTEM"], topology.zk.proof_system);
+                }
+                "committee" => {
+                    assert_eq!(
+                        replicas(ComponentKind::DaCommittee),
+                        Some(topology.validium.committee_size)
+                    );
+                    assert_eq!(data["DA_MODE"], "committee");
+                }
+                _ => assert_eq!(data["DA_MODE"], "node"),
+            }
+        }
+    }
+
+    #[test]
+    fn yaml_renders_one_document_per_object() {
+        let manifest = RollupManifest::build("rollups", &rollup_config("optimistic"));
+        let yaml = manifest.to_yaml().unwrap();
+        let documents: Vec<serde_yaml::Value> = yaml
+            .split("---\n")
+            .map(|document| serde_yaml::from_str(document).unwrap())
+            .collect();
+        assert_eq!(documents.len(), manifest.to_values().unwrap().len());
+        assert_eq!(documents[0]["kind"], serde_yaml::Value::from("ConfigMap"));
+    }
+}
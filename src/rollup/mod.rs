@@ -1,21 +1,37 @@
+mod error;
+mod health;
+pub mod manifest;
+mod placement;
+mod reconciler;
+pub mod topology;
+
 use anyhow::Result;
-use async_trait::async_trait;
-use ethers::{
-    providers::{Http, Provider, Ws},
-    types::{Address, U256},
-};
+use chrono::{DateTime, Utc};
+use ethers::types::Address;
+use futures::future::join_all;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
-use tokio::sync::RwLock;
 
 use crate::{
-    config::Config,
-    infra::Controller,
+    infra::{Controller, TeardownOptions},
+    project::{default_project, Projects, QuotaResource},
+    store::{HistoryEntry, Store},
 };
 
+pub use error::Error;
+pub use health::{ChainMetrics, HealthProber};
+pub use manifest::RollupManifest;
+pub use placement::Placement;
+pub use reconciler::Reconciler;
+pub use topology::{TopologyConfig, TopologyStatus};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RollupConfig {
     pub name: String,
+    /// Project owning the rollup; its namespace receives the rollup resources
+    #[serde(default = "default_project")]
+    pub project: String,
     pub chain_id: u64,
     pub sequencer_address: Address,
     pub validator_address: Address,
@@ -24,26 +40,77 @@ pub struct RollupConfig {
     pub l1_rpc_url: String,
     pub l2_rpc_url: String,
     pub deployment_type: DeploymentType,
+    #[serde(default)]
+    pub topology: TopologyConfig,
+    #[serde(default)]
+    pub placement: Placement,
+    /// `Rollup` resource managing the rollup, as `<namespace>/<name>`; unset
+    /// for rollups managed through the API
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resource: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl RollupConfig {
+    /// Checks that `requested` comes from whatever manages this rollup, so
+    /// neither the API nor another namespace's resource takes it over.
+    pub fn check_manager(&self, requested: &RollupConfig) -> Result<(), Error> {
+        if self.resource == requested.resource {
+            return Ok(());
+        }
+        Err(Error::ManagedElsewhere {
+            rollup: self.name.clone(),
+            manager: match &self.resource {
+                Some(resource) => format!("Rollup resource {}", resource),
+                None => "the API".to_string(),
+            },
+        })
+    }
+
+    /// Refuses a change of deployment type. Manifests are applied without
+    /// pruning, so the components only the old type runs would be left
+    /// behind unmanaged.
+    pub fn check_deployment_type(&self, requested: &RollupConfig) -> Result<(), Error> {
+        if self.deployment_type == requested.deployment_type {
+            return Ok(());
+        }
+        Err(Error::InvalidConfig(format!(
+            "rollup {} is a {:?} rollup and cannot change its deployment_type",
+            self.name, self.deployment_type
+        )))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 pub enum DeploymentType {
     Optimistic,
     ZkRollup,
     Validium,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct RollupStatus {
     pub name: String,
+    #[serde(default = "default_project")]
+    pub project: String,
     pub state: RollupState,
     pub chain_id: u64,
+    /// Managed cluster the rollup runs on; unset for galato's own cluster
+    #[serde(default)]
+    pub cluster: Option<String>,
     pub sequencer_status: SequencerStatus,
     pub validator_status: ValidatorStatus,
     pub batch_submitter_status: BatchSubmitterStatus,
+    /// Health of the components specific to the rollup's deployment type
+    #[serde(default)]
+    pub topology: Option<TopologyStatus>,
+    /// Human readable reason for the current state, set when a rollup fails
+    #[serde(default)]
+    pub message: Option<String>,
+    #[serde(default = "Utc::now")]
+    pub last_transition_time: DateTime<Utc>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 pub enum RollupState {
     Creating,
     Running,
@@ -51,21 +118,32 @@ pub enum RollupState {
     Deleting,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl RollupState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RollupState::Creating => "Creating",
+            RollupState::Running => "Running",
+            RollupState::Failed => "Failed",
+            RollupState::Deleting => "Deleting",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct SequencerStatus {
     pub is_healthy: bool,
     pub last_block: u64,
     pub last_timestamp: u64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct ValidatorStatus {
     pub is_healthy: bool,
     pub last_validated_block: u64,
     pub last_validation_timestamp: u64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct BatchSubmitterStatus {
     pub is_healthy: bool,
     pub last_submitted_batch: u64,
@@ -73,30 +151,37 @@ pub struct BatchSubmitterStatus {
 }
 
 pub struct Manager {
-    config: Arc<Config>,
     infra_controller: Arc<Controller>,
-    rollups: Arc<RwLock<Vec<RollupStatus>>>,
+    projects: Arc<Projects>,
+    store: Arc<dyn Store>,
 }
 
 impl Manager {
-    pub async fn new(config: &Config, infra_controller: Arc<Controller>) -> Result<Self> {
+    pub async fn new(
+        infra_controller: Arc<Controller>,
+        projects: Arc<Projects>,
+        store: Arc<dyn Store>,
+    ) -> Result<Self> {
         Ok(Self {
-            config: Arc::new(config.clone()),
             infra_controller,
-            rollups: Arc::new(RwLock::new(Vec::new())),
+            projects,
+            store,
         })
     }
 
-    pub async fn create_rollup(&self, config: RollupConfig) -> Result<()> {
-        // Deploy Kubernetes resources for the rollup
-        let manifest = self.generate_rollup_manifest(&config)?;
-        self.infra_controller.deploy_application(&config.name, &manifest).await?;
+    pub async fn create_rollup(&self, mut config: RollupConfig) -> Result<()> {
+        self.validate_new_rollup(&config).await?;
+        config.placement = self.place(&config).await?;
 
-        // Initialize rollup status
+        // Record the rollup before deploying it: the primary key turns away a
+        // concurrent create of the same name before it can overwrite the
+        // objects of this one
         let status = RollupStatus {
             name: config.name.clone(),
+            project: config.project.clone(),
             state: RollupState::Creating,
             chain_id: config.chain_id,
+            cluster: config.placement.cluster.clone(),
             sequencer_status: SequencerStatus {
                 is_healthy: false,
                 last_block: 0,
@@ -112,148 +197,543 @@ impl Manager {
                 last_submitted_batch: 0,
                 last_submission_timestamp: 0,
             },
+            topology: None,
+            message: None,
+            last_transition_time: Utc::now(),
         };
 
-        let mut rollups = self.rollups.write().await;
-        rollups.push(status);
+        self.store.insert_rollup(&config, &status).await?;
+
+        if let Err(e) = self.deploy(&config).await {
+            if let Err(cleanup) = self.store.delete_rollup(&config.name).await {
+                tracing::error!(
+                    "Failed to remove the record of rollup {} after its deployment failed: {:#}",
+                    config.name,
+                    cleanup
+                );
+            }
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    /// Checks a configuration before any resources are created for it.
+    pub async fn validate_new_rollup(&self, config: &RollupConfig) -> Result<()> {
+        if self.get_rollup_status(&config.name).await?.is_some() {
+            return Err(Error::AlreadyExists(config.name.clone()).into());
+        }
+        validate_rollup_config(config)?;
+        self.projects.check_quota(&config.project, QuotaResource::Rollups).await?;
+        self.place(config).await?;
 
         Ok(())
     }
 
-    pub async fn delete_rollup(&self, name: &str) -> Result<()> {
-        // Delete Kubernetes resources
-        self.infra_controller.delete_application(name).await?;
+    /// Resolves the placement of a new rollup against the managed clusters.
+    async fn place(&self, config: &RollupConfig) -> Result<Placement> {
+        if config.placement.is_empty() {
+            return Ok(Placement::default());
+        }
+        // Stored cluster states are only as fresh as their last status check,
+        // so the project's clusters are refreshed from their providers first
+        let clusters = self.store.list_clusters().await?;
+        let names: Vec<&str> = clusters
+            .iter()
+            .filter(|c| c.config.project == config.project)
+            .map(|c| c.config.name.as_str())
+            .collect();
+        let refreshed = join_all(
+            names
+                .iter()
+                .map(|name| self.infra_controller.get_cluster_status(name)),
+        )
+        .await;
+        for (name, refreshed) in names.iter().zip(refreshed) {
+            if let Err(e) = refreshed {
+                tracing::warn!("Failed to refresh the status of cluster {}: {:#}", name, e);
+            }
+        }
+
+        let clusters = self.store.list_clusters().await?;
+        let rollups = self.store.list_rollups().await?;
+        Ok(config.placement.resolve(&config.project, &clusters, &rollups)?)
+    }
+
+    /// Creates the rollup if it is unknown, otherwise re-applies its manifest
+    /// and stores the new configuration.
+    pub async fn apply_rollup(&self, mut config: RollupConfig) -> Result<()> {
+        let Some(current) = self.get_rollup_config(&config.name).await? else {
+            return self.create_rollup(config).await;
+        };
+        validate_rollup_config(&config)?;
+        current.check_manager(&config)?;
+        if current.project != config.project {
+            return Err(Error::InvalidConfig(format!(
+                "rollup {} belongs to project {} and cannot move to {}",
+                config.name, current.project, config.project
+            ))
+            .into());
+        }
+        current.check_deployment_type(&config)?;
+        config.placement = current.placement.keep(&config.placement, &config.name)?;
+
+        self.deploy(&config).await?;
+        self.store.update_rollup_config(&config).await?;
 
-        // Remove from rollups list
-        let mut rollups = self.rollups.write().await;
-        rollups.retain(|r| r.name != name);
+        Ok(())
+    }
+
+    /// Applies the rollup's manifest on the cluster it is placed on, creating
+    /// the project namespace there first.
+    async fn deploy(&self, config: &RollupConfig) -> Result<()> {
+        let cluster = config.placement.cluster.as_deref();
+        if let Some(cluster) = cluster {
+            self.projects.deploy_namespace(&config.project, cluster).await?;
+        }
+        let manifest = self.generate_rollup_manifest(config).await?.to_yaml()?;
+        self.infra_controller
+            .deploy_application(cluster, &config.name, &manifest)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn delete_rollup(&self, name: &str, options: &TeardownOptions) -> Result<()> {
+        // Mark the rollup so reconciliation leaves it alone during teardown
+        let mut status = self
+            .get_rollup_status(name)
+            .await?
+            .ok_or_else(|| Error::NotFound(name.to_string()))?;
+        let namespace = self.projects.namespace_of(&status.project).await?;
+        let cluster = status.cluster.clone();
+        status.state = RollupState::Deleting;
+        status.message = None;
+        status.last_transition_time = Utc::now();
+        self.update_rollup_status(status).await?;
+
+        // Delete every Kubernetes resource labelled with the rollup id
+        let selector = manifest::rollup_selector(name);
+        self.infra_controller
+            .delete_application(cluster.as_deref(), &namespace, &selector, options)
+            .await?;
+
+        self.store.delete_rollup(name).await?;
 
         Ok(())
     }
 
     pub async fn get_rollup_status(&self, name: &str) -> Result<Option<RollupStatus>> {
-        let rollups = self.rollups.read().await;
-        Ok(rollups.iter().find(|r| r.name == name).cloned())
+        Ok(self.store.get_rollup(name).await?.map(|record| record.status))
+    }
+
+    pub async fn get_rollup_config(&self, name: &str) -> Result<Option<RollupConfig>> {
+        Ok(self.store.get_rollup(name).await?.map(|record| record.config))
     }
 
     pub async fn list_rollups(&self) -> Result<Vec<RollupStatus>> {
-        let rollups = self.rollups.read().await;
-        Ok(rollups.clone())
-    }
-
-    fn generate_rollup_manifest(&self, config: &RollupConfig) -> Result<String> {
-        // Generate Kubernetes manifest for the rollup deployment
-        let manifest = format!(
-            r#"apiVersion: apps/v1
-kind: Deployment
-metadata:
-  name: {}-sequencer
-  namespace: {}
-spec:
-  replicas: 1
-  selector:
-    matchLabels:
-      app: {}-sequencer
-  template:
-    metadata:
-      labels:
-        app: {}-sequencer
-    spec:
-      containers:
-      - name: sequencer
-        image: galato/sequencer:latest
-        env:
-        - name: CHAIN_ID
-          value: "{}"
-        - name: SEQUENCER_ADDRESS
-          value: "{}"
-        - name: L1_RPC_URL
-          value: "{}"
-        - name: L2_RPC_URL
-          value: "{}"
----
-apiVersion: apps/v1
-kind: Deployment
-metadata:
-  name: {}-validator
-  namespace: {}
-spec:
-  replicas: 1
-  selector:
-    matchLabels:
-      app: {}-validator
-  template:
-    metadata:
-      labels:
-        app: {}-validator
-    spec:
-      containers:
-      - name: validator
-        image: galato/validator:latest
-        env:
-        - name: CHAIN_ID
-          value: "{}"
-        - name: VALIDATOR_ADDRESS
-          value: "{}"
-        - name: L1_RPC_URL
-          value: "{}"
-        - name: L2_RPC_URL
-          value: "{}"
----
-apiVersion: apps/v1
-kind: Deployment
-metadata:
-  name: {}-batch-submitter
-  namespace: {}
-spec:
-  replicas: 1
-  selector:
-    matchLabels:
-      app: {}-batch-submitter
-  template:
-    metadata:
-      labels:
-        app: {}-batch-submitter
-    spec:
-      containers:
-      - name: batch-submitter
-        image: galato/batch-submitter:latest
-        env:
-        - name: CHAIN_ID
-          value: "{}"
-        - name: BATCH_SUBMITTER_ADDRESS
-          value: "{}"
-        - name: L1_RPC_URL
-          value: "{}"
-        - name: L2_RPC_URL
-          value: "{}"
-"#,
-            config.name,
-            self.config.kubernetes.namespace,
-            config.name,
-            config.name,
-            config.chain_id,
-            config.sequencer_address,
-            config.l1_rpc_url,
-            config.l2_rpc_url,
-            config.name,
-            self.config.kubernetes.namespace,
-            config.name,
-            config.name,
-            config.chain_id,
-            config.validator_address,
-            config.l1_rpc_url,
-            config.l2_rpc_url,
-            config.name,
-            self.config.kubernetes.namespace,
-            config.name,
-            config.name,
-            config.chain_id,
-            config.batch_submitter_address,
-            config.l1_rpc_url,
-            config.l2_rpc_url,
+        let rollups = self.store.list_rollups().await?;
+        Ok(rollups.into_iter().map(|record| record.status).collect())
+    }
+
+    pub async fn update_rollup_status(&self, status: RollupStatus) -> Result<()> {
+        self.store.update_rollup_status(&status).await
+    }
+
+    /// Writes `status` only while the rollup is still in the `expected`
+    /// state, so a change made meanwhile, such as a deletion, is not undone.
+    /// Returns whether it was written.
+    pub async fn update_rollup_status_if(
+        &self,
+        expected: &RollupState,
+        status: RollupStatus,
+    ) -> Result<bool> {
+        self.store.update_rollup_status_if(expected, &status).await
+    }
+
+    pub async fn get_rollup_history(&self, name: &str) -> Result<Vec<HistoryEntry<RollupStatus>>> {
+        self.store.rollup_history(name).await
+    }
+
+    /// Namespace of the project owning the rollup.
+    pub async fn namespace_of(&self, config: &RollupConfig) -> Result<String> {
+        Ok(self.projects.namespace_of(&config.project).await?)
+    }
+
+    pub async fn generate_rollup_manifest(&self, config: &RollupConfig) -> Result<RollupManifest> {
+        let namespace = self.namespace_of(config).await?;
+        Ok(RollupManifest::build(&namespace, config))
+    }
+}
+
+/// Rejects configurations that could never produce a working rollup.
+fn validate_rollup_config(config: &RollupConfig) -> Result<(), Error> {
+    // The name becomes part of every Kubernetes resource name and label
+    let valid_name = !config.name.is_empty()
+        && config.name.len() <= 40
+        && config
+            .name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        && !config.name.starts_with('-')
+        && !config.name.ends_with('-');
+    if !valid_name {
+        return Err(Error::InvalidConfig(format!(
+            "name {:?} must be a lowercase DNS label of at most 40 characters",
+            config.name
+        )));
+    }
+
+    if config.chain_id == 0 {
+        return Err(Error::InvalidConfig("chain_id cannot be 0".to_string()));
+    }
+    // The store keeps chain ids in a signed 64-bit column
+    if i64::try_from(config.chain_id).is_err() {
+        return Err(Error::InvalidConfig(format!(
+            "chain_id must be at most {}",
+            i64::MAX
+        )));
+    }
+    if config.chain_id == config.l1_chain_id {
+        return Err(Error::InvalidConfig(
+            "chain_id must differ from l1_chain_id".to_string(),
+        ));
+    }
+
+    for (field, url) in [("l1_rpc_url", &config.l1_rpc_url), ("l2_rpc_url", &config.l2_rpc_url)] {
+        let scheme_ok = ["http://", "https://", "ws://", "wss://"]
+            .iter()
+            .any(|scheme| url.starts_with(scheme));
+        if !scheme_ok {
+            return Err(Error::InvalidConfig(format!(
+                "{} must be an http(s) or ws(s) URL",
+                field
+            )));
+        }
+    }
+
+    config.topology.validate(&config.deployment_type)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        infra::{ClusterState, DeploymentReadiness},
+        project::{Project, ProjectQuotas},
+        testing::{cluster_config, rollup_config, test_config, Fault, Harness},
+    };
+
+    #[tokio::test]
+    async fn create_rollup_deploys_into_the_project_namespace() {
+        let harness = Harness::new().await;
+        harness.manager.create_rollup(rollup_config("r1")).await.unwrap();
+
+        // Optimistic rollups run the core components plus a proposer and challenger
+        let deployments = harness.kubernetes.objects("Deployment");
+        assert_eq!(deployments.len(), 5);
+        assert!(deployments.iter().all(|(namespace, _, _)| namespace == "galato"));
+
+        let status = harness.manager.get_rollup_status("r1").await.unwrap().unwrap();
+        assert_eq!(status.state, RollupState::Creating);
+    }
+
+    #[tokio::test]
+    async fn kubernetes_conflict_fails_creation_without_recording_it() {
+        let harness = Harness::new().await;
+        harness.kubernetes.faults.inject("apply_manifest", Fault::Conflict);
+
+        let err = harness.manager.create_rollup(rollup_config("r1")).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(kube::Error::Api(response)) if response.code == 409
+        ));
+        assert!(harness.manager.get_rollup_status("r1").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn chain_ids_beyond_the_stored_range_are_rejected() {
+        let harness = Harness::new().await;
+        let mut config = rollup_config("r1");
+        config.chain_id = i64::MAX as u64 + 1;
+
+        let err = harness.manager.create_rollup(config).await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(Error::InvalidConfig(_))));
+        assert!(harness.manager.get_rollup_status("r1").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn instances_sharing_a_store_see_each_others_rollups() {
+        let harness = Harness::new().await;
+        let other = Manager::new(
+            harness.controller.clone(),
+            harness.projects.clone(),
+            harness.store.clone(),
+        )
+        .await
+        .unwrap();
+        harness.manager.create_rollup(rollup_config("r1")).await.unwrap();
+
+        assert!(other.get_rollup_status("r1").await.unwrap().is_some());
+        assert_eq!(other.list_rollups().await.unwrap().len(), 1);
+        let err = other.validate_new_rollup(&rollup_config("r1")).await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(Error::AlreadyExists(_))));
+
+        other.delete_rollup("r1", &TeardownOptions::default()).await.unwrap();
+        assert!(harness.manager.get_rollup_status("r1").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn rollups_that_cannot_be_recorded_are_not_deployed() {
+        let harness = Harness::new().await;
+        harness
+            .store
+            .faults
+            .inject("insert_rollup", Fault::Error("connection reset".to_string()));
+
+        assert!(harness.manager.create_rollup(rollup_config("r1")).await.is_err());
+        assert!(harness.kubernetes.objects("Deployment").is_empty());
+        assert!(harness.kubernetes.objects("ConfigMap").is_empty());
+    }
+
+    #[tokio::test]
+    async fn deployment_types_cannot_change() {
+        let harness = Harness::new().await;
+        harness.manager.create_rollup(rollup_config("r1")).await.unwrap();
+
+        let mut config = rollup_config("r1");
+        config.deployment_type = DeploymentType::ZkRollup;
+        let err = harness.manager.apply_rollup(config).await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(Error::InvalidConfig(_))));
+
+        // The optimistic components are still the only ones deployed
+        assert_eq!(harness.kubernetes.objects("Deployment").len(), 5);
+        let stored = harness.manager.get_rollup_config("r1").await.unwrap().unwrap();
+        assert_eq!(stored.deployment_type, DeploymentType::Optimistic);
+    }
+
+    #[tokio::test]
+    async fn delete_rollup_removes_its_objects() {
+        let harness = Harness::new().await;
+        harness.manager.create_rollup(rollup_config("r1")).await.unwrap();
+        harness.manager.create_rollup(rollup_config("r2")).await.unwrap();
+
+        harness
+            .manager
+            .delete_rollup("r1", &TeardownOptions::default())
+            .await
+            .unwrap();
+
+        let remaining = harness.kubernetes.objects("Deployment");
+        assert_eq!(remaining.len(), 5);
+        assert!(remaining.iter().all(|(_, _, name)| name.starts_with("r2-")));
+        let history = harness.manager.get_rollup_history("r1").await.unwrap();
+        assert_eq!(history[0].state, "Deleted");
+
+        let err = harness
+            .manager
+            .delete_rollup("r1", &TeardownOptions::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(Error::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn project_quota_limits_rollups() {
+        let harness = Harness::new().await;
+        let project = Project {
+            name: "team-a".to_string(),
+            namespace: "team-a".to_string(),
+            quotas: ProjectQuotas {
+                max_rollups: Some(1),
+                ..Default::default()
+            },
+            created_at: Utc::now(),
+        };
+        harness.projects.create_project(project).await.unwrap();
+
+        let mut config = rollup_config("r1");
+        config.project = "team-a".to_string();
+        harness.manager.create_rollup(config).await.unwrap();
+        assert!(harness
+            .kubernetes
+            .get("team-a", "Deployment", "r1-sequencer")
+            .is_some());
+
+        let mut config = rollup_config("r2");
+        config.project = "team-a".to_string();
+        let err = harness.manager.create_rollup(config).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(crate::project::Error::QuotaExceeded { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn reconciler_marks_ready_rollups_running() {
+        let harness = Harness::new().await;
+        harness.manager.create_rollup(rollup_config("r1")).await.unwrap();
+
+        harness.reconciler().reconcile_all().await.unwrap();
+
+        let status = harness.manager.get_rollup_status("r1").await.unwrap().unwrap();
+        assert_eq!(status.state, RollupState::Running);
+        // Nothing answers on the test RPC URLs
+        assert!(!status.sequencer_status.is_healthy);
+    }
+
+    #[tokio::test]
+    async fn reconciled_status_does_not_undo_a_deletion() {
+        let harness = Harness::new().await;
+        harness.manager.create_rollup(rollup_config("r1")).await.unwrap();
+        // Read by the reconciler before the rollup was marked for deletion
+        let probed = harness.manager.get_rollup_status("r1").await.unwrap().unwrap();
+        let mut deleting = probed.clone();
+        deleting.state = RollupState::Deleting;
+        harness.manager.update_rollup_status(deleting).await.unwrap();
+
+        let mut running = probed.clone();
+        running.state = RollupState::Running;
+        let written = harness
+            .manager
+            .update_rollup_status_if(&probed.state, running)
+            .await
+            .unwrap();
+        assert!(!written);
+        let status = harness.manager.get_rollup_status("r1").await.unwrap().unwrap();
+        assert_eq!(status.state, RollupState::Deleting);
+
+        // Deleting rollups are left alone by the reconciler
+        harness.reconciler().reconcile_all().await.unwrap();
+        let status = harness.manager.get_rollup_status("r1").await.unwrap().unwrap();
+        assert_eq!(status.state, RollupState::Deleting);
+    }
+
+    #[tokio::test]
+    async fn reconciler_fails_rollups_whose_deployments_stop_progressing() {
+        let harness = Harness::new().await;
+        harness.manager.create_rollup(rollup_config("r1")).await.unwrap();
+        harness.kubernetes.set_readiness(
+            "galato",
+            "r1-validator",
+            DeploymentReadiness {
+                replicas: 1,
+                ready_replicas: 0,
+                available_replicas: 0,
+                failure: Some("ImagePullBackOff".to_string()),
+            },
+        );
+
+        harness.reconciler().reconcile_all().await.unwrap();
+
+        let status = harness.manager.get_rollup_status("r1").await.unwrap().unwrap();
+        assert_eq!(status.state, RollupState::Failed);
+        assert!(status.message.unwrap().contains("ImagePullBackOff"));
+    }
+
+    #[tokio::test]
+    async fn reconciler_fails_running_rollups_whose_deployments_disappear() {
+        let harness = Harness::new().await;
+        harness.manager.create_rollup(rollup_config("r1")).await.unwrap();
+        harness.reconciler().reconcile_all().await.unwrap();
+
+        harness.kubernetes.remove("galato", "Deployment", "r1-sequencer");
+        harness.reconciler().reconcile_all().await.unwrap();
+
+        let status = harness.manager.get_rollup_status("r1").await.unwrap().unwrap();
+        assert_eq!(status.state, RollupState::Failed);
+        assert!(status.message.unwrap().contains("r1-sequencer"));
+    }
+
+    #[tokio::test]
+    async fn reconciler_times_out_rollups_that_never_become_ready() {
+        let mut config = test_config();
+        config.rollup.creation_timeout_secs = 0;
+        let harness = Harness::with_config(config).await;
+        harness.kubernetes.set_auto_ready(false);
+        harness.manager.create_rollup(rollup_config("r1")).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+
+        harness.reconciler().reconcile_all().await.unwrap();
+
+        let status = harness.manager.get_rollup_status("r1").await.unwrap().unwrap();
+        assert_eq!(status.state, RollupState::Failed);
+        assert!(status.message.unwrap().starts_with("timed out"));
+    }
+
+    #[tokio::test]
+    async fn placement_spreads_rollups_over_matching_clusters() {
+        let harness = Harness::new().await;
+        let mut other_region = cluster_config("c0");
+        other_region.region = "test-2".to_string();
+        // Clusters are stored as Creating; placement finds them running
+        for config in [cluster_config("c1"), cluster_config("c2"), other_region] {
+            harness.controller.create_cluster(config).await.unwrap();
+        }
+
+        let placed = |name: &str| {
+            let mut config = rollup_config(name);
+            config.placement.region = Some("test-1".to_string());
+            config
+        };
+        harness.manager.create_rollup(placed("r1")).await.unwrap();
+        harness.manager.create_rollup(placed("r2")).await.unwrap();
+
+        let status = harness.manager.get_rollup_status("r2").await.unwrap().unwrap();
+        assert_eq!(status.cluster.as_deref(), Some("c2"));
+        let config = harness.manager.get_rollup_config("r1").await.unwrap().unwrap();
+        assert_eq!(
+            config.placement,
+            Placement {
+                cluster: Some("c1".to_string()),
+                account: Some("default".to_string()),
+                region: Some("test-1".to_string()),
+            }
         );
 
-        Ok(manifest)
+        // Re-applying keeps the rollup where it is, and it cannot move
+        harness.manager.apply_rollup(placed("r1")).await.unwrap();
+        let mut moved = placed("r1");
+        moved.placement.cluster = Some("c2".to_string());
+        let err = harness.manager.apply_rollup(moved).await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(Error::InvalidConfig(_))));
+
+        let mut unknown = rollup_config("r3");
+        unknown.placement.cluster = Some("c9".to_string());
+        let err = harness.manager.validate_new_rollup(&unknown).await.unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(Error::InvalidConfig(_))));
+
+        // Only running clusters take new rollups
+        harness.provider.set_state("c2", ClusterState::Failed);
+        let mut failed = rollup_config("r4");
+        failed.placement.cluster = Some("c2".to_string());
+        let err = harness.manager.validate_new_rollup(&failed).await.unwrap_err();
+        assert!(err.to_string().contains("not Running"));
+    }
+
+    #[tokio::test]
+    async fn placed_rollups_run_on_their_cluster() {
+        let harness = Harness::new().await;
+        harness.controller.create_cluster(cluster_config("c1")).await.unwrap();
+
+        let mut config = rollup_config("r1");
+        config.placement.cluster = Some("c1".to_string());
+        harness.manager.create_rollup(config).await.unwrap();
+
+        // The project namespace is created on the cluster next to the rollup
+        let c1 = harness.connector.cluster("c1").unwrap();
+        assert!(c1.get("", "Namespace", "galato").is_some());
+        assert_eq!(c1.objects("Deployment").len(), 5);
+        assert!(harness.kubernetes.objects("Deployment").is_empty());
+
+        harness.reconciler().reconcile_all().await.unwrap();
+        let status = harness.manager.get_rollup_status("r1").await.unwrap().unwrap();
+        assert_eq!(status.state, RollupState::Running);
+
+        harness
+            .manager
+            .delete_rollup("r1", &TeardownOptions::default())
+            .await
+            .unwrap();
+        assert!(c1.objects("Deployment").is_empty());
     }
-} 
\ No newline at end of file
+}
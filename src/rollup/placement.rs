@@ -0,0 +1,126 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use super::Error;
+use crate::{
+    infra::{ClusterConfig, ClusterState},
+    store::{ClusterRecord, RollupRecord},
+};
+
+/// Where a rollup runs. Without any field set the rollup runs in galato's own
+/// cluster; otherwise it is placed on a running managed cluster of its
+/// project when created and stays there.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub struct Placement {
+    /// Managed cluster to run on; chosen among the clusters matching
+    /// `account` and `region` when unset
+    #[serde(default)]
+    pub cluster: Option<String>,
+    /// Only consider clusters of this cloud account
+    #[serde(default)]
+    pub account: Option<String>,
+    /// Only consider clusters in this region
+    #[serde(default)]
+    pub region: Option<String>,
+}
+
+impl Placement {
+    pub fn is_empty(&self) -> bool {
+        *self == Placement::default()
+    }
+
+    fn accepts(&self, cluster: &ClusterConfig) -> bool {
+        self.account
+            .as_ref()
+            .is_none_or(|a| *a == cluster.account)
+            && self.region.as_ref().is_none_or(|r| *r == cluster.region)
+    }
+
+    /// Chooses the cluster for a new rollup of `project`: the named cluster,
+    /// or else the matching running cluster hosting the fewest rollups. The
+    /// result records the chosen cluster with its account and region.
+    pub fn resolve(
+        &self,
+        project: &str,
+        clusters: &[ClusterRecord],
+        rollups: &[RollupRecord],
+    ) -> Result<Placement, Error> {
+        if self.is_empty() {
+            return Ok(Placement::default());
+        }
+        let running = |c: &&ClusterRecord| matches!(c.status.state, ClusterState::Running);
+
+        let chosen = match &self.cluster {
+            Some(name) => {
+                let cluster = clusters
+                    .iter()
+                    .find(|c| c.config.name == *name && c.config.project == project)
+                    .ok_or_else(|| {
+                        Error::InvalidConfig(format!(
+                            "cluster {} does not exist in project {}",
+                            name, project
+                        ))
+                    })?;
+                if !self.accepts(&cluster.config) {
+                    return Err(Error::InvalidConfig(format!(
+                        "cluster {} is not in the requested account and region",
+                        name
+                    )));
+                }
+                if !running(&cluster) {
+                    return Err(Error::InvalidConfig(format!(
+                        "cluster {} is {}, not Running",
+                        name,
+                        cluster.status.state.as_str()
+                    )));
+                }
+                cluster
+            }
+            None => {
+                let hosted = |cluster: &str| {
+                    rollups
+                        .iter()
+                        .filter(|r| r.config.placement.cluster.as_deref() == Some(cluster))
+                        .count()
+                };
+                clusters
+                    .iter()
+                    .filter(|c| c.config.project == project && self.accepts(&c.config))
+                    .filter(running)
+                    .min_by_key(|c| (hosted(&c.config.name), c.config.name.clone()))
+                    .ok_or_else(|| {
+                        Error::InvalidConfig(format!(
+                            "no running cluster of project {} matches the placement",
+                            project
+                        ))
+                    })?
+            }
+        };
+
+        Ok(Placement {
+            cluster: Some(chosen.config.name.clone()),
+            account: Some(chosen.config.account.clone()),
+            region: Some(chosen.config.region.clone()),
+        })
+    }
+
+    /// Placement of an existing rollup re-applied with `requested`. Rollups
+    /// do not move between clusters, so any requested field must match.
+    pub fn keep(&self, requested: &Placement, rollup: &str) -> Result<Placement, Error> {
+        for (field, current, wanted) in [
+            ("cluster", &self.cluster, &requested.cluster),
+            ("account", &self.account, &requested.account),
+            ("region", &self.region, &requested.region),
+        ] {
+            if wanted.is_some() && wanted != current {
+                return Err(Error::InvalidConfig(format!(
+                    "rollup {} cannot move: placement.{} is {}",
+                    rollup,
+                    field,
+                    current.as_deref().unwrap_or("unset")
+                )));
+            }
+        }
+        Ok(self.clone())
+    }
+}
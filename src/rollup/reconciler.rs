@@ -0,0 +1,207 @@
+use anyhow::Result;
+use chrono::Utc;
+use futures::{stream, StreamExt};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use crate::{config::Config, monitoring::System};
+use super::{
+    manifest::{self, ComponentKind},
+    HealthProber, Manager, RollupState, RollupStatus, TopologyStatus,
+};
+
+/// Rollups reconciled at the same time.
+const CONCURRENT_RECONCILES: usize = 8;
+
+/// Periodically compares each rollup with its deployed Kubernetes objects
+/// and moves it through `Creating -> Running/Failed`, publishing the chain
+/// metrics of running rollups and the inventory gauges.
+pub struct Reconciler {
+    manager: Arc<Manager>,
+    prober: HealthProber,
+    monitoring: Arc<System>,
+    interval: Duration,
+    creation_timeout: Duration,
+}
+
+impl Reconciler {
+    pub fn new(config: &Config, manager: Arc<Manager>, monitoring: Arc<System>) -> Self {
+        Self {
+            manager,
+            prober: HealthProber::new(config),
+            monitoring,
+            interval: Duration::from_secs(config.rollup.reconcile_interval_secs),
+            creation_timeout: Duration::from_secs(config.rollup.creation_timeout_secs),
+        }
+    }
+
+    pub async fn run(self) {
+        let mut ticker = tokio::time::interval(self.interval);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = self.reconcile_all().await {
+                tracing::error!("Rollup reconciliation failed: {:#}", e);
+            }
+        }
+    }
+
+    pub async fn reconcile_all(&self) -> Result<()> {
+        // Probing waits on RPC endpoints, so one slow rollup must not hold up
+        // the others
+        stream::iter(self.manager.list_rollups().await?)
+            .for_each_concurrent(CONCURRENT_RECONCILES, |status| async move {
+                let name = status.name.clone();
+                if let Err(e) = self.reconcile(status).await {
+                    tracing::warn!("Failed to reconcile rollup {}: {:#}", name, e);
+                }
+            })
+            .await;
+
+        // Counted after reconciling, so the gauges include this pass's transitions
+        let rollups = self.manager.list_rollups().await?;
+        self.monitoring.observe_inventory(
+            &self.manager.infra_controller.list_clusters().await?,
+            &rollups,
+        );
+        // Rollups may also be deleted by the operator or another instance
+        self.monitoring
+            .retain_rollups(rollups.iter().map(|rollup| rollup.name.as_str()));
+        Ok(())
+    }
+
+    async fn reconcile(&self, current: RollupStatus) -> Result<()> {
+        if current.state == RollupState::Deleting {
+            return Ok(());
+        }
+
+        let Some(config) = self.manager.get_rollup_config(&current.name).await? else {
+            return Ok(());
+        };
+
+        let mut status = current.clone();
+        let mut observed = HashMap::new();
+        let mut missing = Vec::new();
+        let mut not_ready = Vec::new();
+        let mut failure = None;
+
+        let namespace = self.manager.namespace_of(&config).await?;
+        for component in manifest::components(&config) {
+            let deployment = manifest::resource_name(&config.name, component.kind);
+            let readiness = self
+                .manager
+                .infra_controller
+                .get_deployment_readiness(
+                    config.placement.cluster.as_deref(),
+                    &namespace,
+                    &deployment,
+                )
+                .await?;
+
+            match &readiness {
+                Some(readiness) => {
+                    if let Some(reason) = &readiness.failure {
+                        failure.get_or_insert(format!("{}: {}", deployment, reason));
+                    }
+                    if !readiness.is_ready() {
+                        not_ready.push(deployment);
+                    }
+                }
+                None => missing.push(deployment),
+            }
+
+            let healthy = readiness.as_ref().is_some_and(|r| r.is_ready());
+            match component.kind {
+                ComponentKind::Sequencer => status.sequencer_status.is_healthy = healthy,
+                ComponentKind::Validator => status.validator_status.is_healthy = healthy,
+                ComponentKind::BatchSubmitter => status.batch_submitter_status.is_healthy = healthy,
+                _ => {}
+            }
+            observed.insert(component.kind, readiness);
+        }
+
+        // Pools and committees tolerate some unavailable replicas, so readiness
+        // of type-specific components is judged by their own health model
+        let topology = TopologyStatus::observe(&config, &observed);
+        let core_ready = observed
+            .iter()
+            .filter(|(kind, _)| kind.is_core())
+            .all(|(_, readiness)| readiness.as_ref().is_some_and(|r| r.is_ready()));
+        status.topology = Some(topology.clone());
+
+        let all_ready = missing.is_empty() && core_ready && topology.is_healthy();
+        match current.state {
+            RollupState::Creating => {
+                let elapsed = (Utc::now() - current.last_transition_time)
+                    .to_std()
+                    .unwrap_or_default();
+
+                if all_ready {
+                    transition(&mut status, RollupState::Running, None);
+                } else if let Some(reason) = failure {
+                    transition(&mut status, RollupState::Failed, Some(reason));
+                } else if elapsed > self.creation_timeout {
+                    let pending: Vec<String> = missing.into_iter().chain(not_ready).collect();
+                    let reason = format!(
+                        "timed out after {}s waiting for {} to become ready",
+                        self.creation_timeout.as_secs(),
+                        pending.join(", ")
+                    );
+                    transition(&mut status, RollupState::Failed, Some(reason));
+                }
+            }
+            RollupState::Running => {
+                if !missing.is_empty() {
+                    let reason = format!("deployments no longer exist: {}", missing.join(", "));
+                    transition(&mut status, RollupState::Failed, Some(reason));
+                } else if let Some(reason) = failure {
+                    transition(&mut status, RollupState::Failed, Some(reason));
+                }
+            }
+            RollupState::Failed => {
+                // A failed rollup recovers once every component is available again
+                if all_ready {
+                    transition(&mut status, RollupState::Running, None);
+                }
+            }
+            RollupState::Deleting => {}
+        }
+
+        // Chain data is only meaningful once every component is up
+        if status.state == RollupState::Running {
+            let endpoints = self.prober.connect(&config).await;
+            self.prober.probe(&config, &endpoints, &mut status).await?;
+            match self.prober.sample(&config, &endpoints, &status).await {
+                Ok(chain) => {
+                    self.monitoring
+                        .observe_rollup(&config.name, config.chain_id, &chain)
+                }
+                Err(e) => tracing::warn!(
+                    "Failed to sample chain metrics of rollup {}: {:#}",
+                    config.name,
+                    e
+                ),
+            }
+        }
+
+        // The rollup may have been marked for deletion while it was probed
+        let (name, state) = (status.name.clone(), status.state.clone());
+        if !self.manager.update_rollup_status_if(&current.state, status).await? {
+            tracing::debug!("Rollup {} changed state while it was reconciled", name);
+            return Ok(());
+        }
+        if state != current.state {
+            tracing::info!(
+                "Rollup {} transitioned from {} to {}",
+                name,
+                current.state.as_str(),
+                state.as_str()
+            );
+        }
+        Ok(())
+    }
+}
+
+fn transition(status: &mut RollupStatus, state: RollupState, message: Option<String>) {
+    status.state = state;
+    status.message = message;
+    status.last_transition_time = Utc::now();
+}
@@ -0,0 +1,378 @@
+use ethers::types::Address;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::infra::DeploymentReadiness;
+use super::{manifest::ComponentKind, DeploymentType, Error, RollupConfig};
+
+/// Settings for the components specific to each `DeploymentType`. Only the
+/// section matching the rollup's type is used; missing sections fall back to
+/// their defaults.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(default, rename_all = "camelCase")]
+pub struct TopologyConfig {
+    pub optimistic: OptimisticConfig,
+    pub zk: ZkConfig,
+    pub validium: ValidiumConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(default, rename_all = "camelCase")]
+pub struct OptimisticConfig {
+    /// Account proposing output roots; defaults to the validator address
+    #[schemars(with = "Option<String>")]
+    pub proposer_address: Option<Address>,
+    /// Account disputing invalid output roots; defaults to the validator address
+    #[schemars(with = "Option<String>")]
+    pub challenger_address: Option<Address>,
+    pub challenge_period_secs: u64,
+}
+
+impl Default for OptimisticConfig {
+    fn default() -> Self {
+        Self {
+            proposer_address: None,
+            challenger_address: None,
+            challenge_period_secs: 7 * 24 * 60 * 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ZkConfig {
+    pub prover_replicas: i32,
+    pub proof_system: String,
+    /// L1 verifier contract the provers submit proofs to
+    #[schemars(with = "Option<String>")]
+    pub verifier_address: Option<Address>,
+}
+
+impl Default for ZkConfig {
+    fn default() -> Self {
+        Self {
+            prover_replicas: 2,
+            proof_system: "plonk".to_string(),
+            verifier_address: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+pub enum DataAvailabilityMode {
+    /// A committee of signers attests to data availability
+    Committee,
+    /// A single DA node stores and serves batch data
+    Node,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ValidiumConfig {
+    pub mode: DataAvailabilityMode,
+    pub committee_size: i32,
+    /// Signatures required for a batch to be considered available
+    pub committee_threshold: i32,
+}
+
+impl Default for ValidiumConfig {
+    fn default() -> Self {
+        Self {
+            mode: DataAvailabilityMode::Committee,
+            committee_size: 3,
+            committee_threshold: 2,
+        }
+    }
+}
+
+impl TopologyConfig {
+    /// Rejects replica counts and thresholds of the section used by
+    /// `deployment_type` that could never be deployed or become healthy.
+    pub fn validate(&self, deployment_type: &DeploymentType) -> Result<(), Error> {
+        match deployment_type {
+            DeploymentType::Optimistic => {}
+            DeploymentType::ZkRollup => {
+                if self.zk.prover_replicas < 1 {
+                    return Err(Error::InvalidConfig(
+                        "topology.zk.proverReplicas must be at least 1".to_string(),
+                    ));
+                }
+            }
+            DeploymentType::Validium => {
+                let validium = &self.validium;
+                if validium.mode != DataAvailabilityMode::Committee {
+                    return Ok(());
+                }
+                if validium.committee_size < 1 {
+                    return Err(Error::InvalidConfig(
+                        "topology.validium.committeeSize must be at least 1".to_string(),
+                    ));
+                }
+                let threshold = validium.committee_threshold;
+                if threshold < 1 || threshold > validium.committee_size {
+                    return Err(Error::InvalidConfig(format!(
+                        "topology.validium.committeeThreshold must be between 1 and {}",
+                        validium.committee_size
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Health of the type-specific components of a rollup.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "type")]
+pub enum TopologyStatus {
+    Optimistic {
+        proposer: ProposerStatus,
+        challenger: ChallengerStatus,
+    },
+    ZkRollup {
+        prover_pool: ProverPoolStatus,
+    },
+    Validium {
+        data_availability: DataAvailabilityStatus,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ProposerStatus {
+    pub is_healthy: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ChallengerStatus {
+    pub is_healthy: bool,
+}
+
+/// The pool keeps proving while at least one prover is available.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ProverPoolStatus {
+    pub is_healthy: bool,
+    pub ready_provers: i32,
+    pub desired_provers: i32,
+}
+
+/// A committee is healthy while enough members are up to reach the threshold.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct DataAvailabilityStatus {
+    pub is_healthy: bool,
+    pub mode: DataAvailabilityMode,
+    pub ready_members: i32,
+    pub required_members: i32,
+}
+
+impl TopologyStatus {
+    /// Builds the status from the readiness of each observed component.
+    pub fn observe(
+        config: &RollupConfig,
+        readiness: &HashMap<ComponentKind, Option<DeploymentReadiness>>,
+    ) -> Self {
+        let ready = |kind: ComponentKind| {
+            readiness
+                .get(&kind)
+                .and_then(Option::as_ref)
+                .is_some_and(DeploymentReadiness::is_ready)
+        };
+        let ready_replicas = |kind: ComponentKind| {
+            readiness
+                .get(&kind)
+                .and_then(Option::as_ref)
+                .map_or(0, |r| r.ready_replicas)
+        };
+
+        match config.deployment_type {
+            DeploymentType::Optimistic => TopologyStatus::Optimistic {
+                proposer: ProposerStatus {
+                    is_healthy: ready(ComponentKind::Proposer),
+                },
+                challenger: ChallengerStatus {
+                    is_healthy: ready(ComponentKind::Challenger),
+                },
+            },
+            DeploymentType::ZkRollup => {
+                let ready_provers = ready_replicas(ComponentKind::Prover);
+                TopologyStatus::ZkRollup {
+                    prover_pool: ProverPoolStatus {
+                        is_healthy: ready_provers > 0,
+                        ready_provers,
+                        desired_provers: config.topology.zk.prover_replicas,
+                    },
+                }
+            }
+            DeploymentType::Validium => {
+                let validium = &config.topology.validium;
+                let data_availability = match validium.mode {
+                    DataAvailabilityMode::Committee => {
+                        let ready_members = ready_replicas(ComponentKind::DaCommittee);
+                        DataAvailabilityStatus {
+                            is_healthy: ready_members >= validium.committee_threshold,
+                            mode: validium.mode,
+                            ready_members,
+                            required_members: validium.committee_threshold,
+                        }
+                    }
+                    DataAvailabilityMode::Node => {
+                        let is_healthy = ready(ComponentKind::DaNode);
+                        DataAvailabilityStatus {
+                            is_healthy,
+                            mode: validium.mode,
+                            ready_members: i32::from(is_healthy),
+                            required_members: 1,
+                        }
+                    }
+                };
+                TopologyStatus::Validium { data_availability }
+            }
+        }
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        match self {
+            TopologyStatus::Optimistic { proposer, challenger } => {
+                proposer.is_healthy && challenger.is_healthy
+            }
+            TopologyStatus::ZkRollup { prover_pool } => prover_pool.is_healthy,
+            TopologyStatus::Validium { data_availability } => data_availability.is_healthy,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::rollup_config;
+
+    fn readiness(replicas: i32, ready: i32) -> Option<DeploymentReadiness> {
+        Some(DeploymentReadiness {
+            replicas,
+            ready_replicas: ready,
+            available_replicas: ready,
+            failure: None,
+        })
+    }
+
+    fn config(deployment_type: DeploymentType) -> RollupConfig {
+        let mut config = rollup_config("r1");
+        config.deployment_type = deployment_type;
+        config
+    }
+
+    #[test]
+    fn optimistic_rollups_need_a_ready_proposer_and_challenger() {
+        let config = config(DeploymentType::Optimistic);
+        let mut observed = HashMap::from([
+            (ComponentKind::Proposer, readiness(1, 1)),
+            (ComponentKind::Challenger, readiness(1, 1)),
+        ]);
+        assert!(TopologyStatus::observe(&config, &observed).is_healthy());
+
+        observed.insert(ComponentKind::Challenger, readiness(1, 0));
+        assert!(!TopologyStatus::observe(&config, &observed).is_healthy());
+        observed.insert(ComponentKind::Challenger, None);
+        assert!(!TopologyStatus::observe(&config, &observed).is_healthy());
+    }
+
+    #[test]
+    fn prover_pools_are_healthy_while_any_prover_is_ready() {
+        let config = config(DeploymentType::ZkRollup);
+        let observed = HashMap::from([(ComponentKind::Prover, readiness(2, 1))]);
+        let status = TopologyStatus::observe(&config, &observed);
+        assert!(status.is_healthy());
+        let TopologyStatus::ZkRollup { prover_pool } = status else {
+            panic!("unexpected status {:?}", status);
+        };
+        assert_eq!(
+            (prover_pool.ready_provers, prover_pool.desired_provers),
+            (1, 2)
+        );
+
+        let observed = HashMap::from([(ComponentKind::Prover, readiness(2, 0))]);
+        assert!(!TopologyStatus::observe(&config, &observed).is_healthy());
+        assert!(!TopologyStatus::observe(&config, &HashMap::new()).is_healthy());
+    }
+
+    #[test]
+    fn committees_are_healthy_once_the_threshold_is_ready() {
+        let config = config(DeploymentType::Validium);
+        for (ready, healthy) in [(0, false), (1, false), (2, true), (3, true)] {
+            let observed = HashMap::from([(ComponentKind::DaCommittee, readiness(3, ready))]);
+            let status = TopologyStatus::observe(&config, &observed);
+            assert_eq!(status.is_healthy(), healthy, "{} ready", ready);
+            let TopologyStatus::Validium { data_availability } = status else {
+                panic!("unexpected status {:?}", status);
+            };
+            assert_eq!(data_availability.ready_members, ready);
+            assert_eq!(data_availability.required_members, 2);
+        }
+    }
+
+    #[test]
+    fn da_nodes_are_healthy_when_ready() {
+        let mut config = config(DeploymentType::Validium);
+        config.topology.validium.mode = DataAvailabilityMode::Node;
+        let observed = HashMap::from([(ComponentKind::DaNode, readiness(1, 1))]);
+        assert!(TopologyStatus::observe(&config, &observed).is_healthy());
+        let observed = HashMap::from([(ComponentKind::DaNode, readiness(1, 0))]);
+        assert!(!TopologyStatus::observe(&config, &observed).is_healthy());
+    }
+
+    #[test]
+    fn unusable_replica_counts_and_thresholds_are_rejected() {
+        let topology = |f: fn(&mut TopologyConfig)| {
+            let mut topology = TopologyConfig::default();
+            f(&mut topology);
+            topology
+        };
+        let invalid = [
+            (
+                DeploymentType::ZkRollup,
+                topology(|t| t.zk.prover_replicas = 0),
+            ),
+            (
+                DeploymentType::ZkRollup,
+                topology(|t| t.zk.prover_replicas = -1),
+            ),
+            (
+                DeploymentType::Validium,
+                topology(|t| t.validium.committee_size = 0),
+            ),
+            (
+                DeploymentType::Validium,
+                topology(|t| t.validium.committee_threshold = 0),
+            ),
+            (
+                DeploymentType::Validium,
+                topology(|t| t.validium.committee_threshold = 4),
+            ),
+        ];
+        for (deployment_type, topology) in invalid {
+            let result = topology.validate(&deployment_type);
+            assert!(
+                matches!(result, Err(Error::InvalidConfig(_))),
+                "{:?} {:?}",
+                deployment_type,
+                topology
+            );
+        }
+
+        // Only the section of the rollup's type is used
+        let unused = topology(|t| t.zk.prover_replicas = 0);
+        unused.validate(&DeploymentType::Optimistic).unwrap();
+        let mut node = topology(|t| t.validium.committee_threshold = 0);
+        node.validium.mode = DataAvailabilityMode::Node;
+        node.validate(&DeploymentType::Validium).unwrap();
+        for deployment_type in [
+            DeploymentType::Optimistic,
+            DeploymentType::ZkRollup,
+            DeploymentType::Validium,
+        ] {
+            TopologyConfig::default()
+                .validate(&deployment_type)
+                .unwrap();
+        }
+    }
+}
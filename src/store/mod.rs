@@ -0,0 +1,96 @@
+mod postgres;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::{
+    audit::{AuditEntry, AuditEvent, AuditFilter},
+    infra::{ClusterConfig, ClusterStatus},
+    operations::Operation,
+    project::{Project, ProjectUsage},
+    rollup::{RollupConfig, RollupState, RollupStatus},
+};
+
+pub use postgres::PgStore;
+
+#[async_trait]
+pub trait Store: Send + Sync {
+    async fn insert_rollup(&self, config: &RollupConfig, status: &RollupStatus) -> Result<()>;
+    async fn update_rollup_config(&self, config: &RollupConfig) -> Result<()>;
+    async fn update_rollup_status(&self, status: &RollupStatus) -> Result<()>;
+    /// Writes `status` only while the stored state is still `expected`, and
+    /// returns whether it was written.
+    async fn update_rollup_status_if(
+        &self,
+        expected: &RollupState,
+        status: &RollupStatus,
+    ) -> Result<bool>;
+    async fn delete_rollup(&self, name: &str) -> Result<()>;
+    async fn get_rollup(&self, name: &str) -> Result<Option<RollupRecord>>;
+    async fn list_rollups(&self) -> Result<Vec<RollupRecord>>;
+    async fn rollup_history(&self, name: &str) -> Result<Vec<HistoryEntry<RollupStatus>>>;
+
+    async fn insert_cluster(&self, config: &ClusterConfig, status: &ClusterStatus) -> Result<()>;
+    async fn update_cluster_config(&self, config: &ClusterConfig) -> Result<()>;
+    async fn update_cluster_status(&self, status: &ClusterStatus) -> Result<()>;
+    async fn delete_cluster(&self, name: &str) -> Result<()>;
+    async fn get_cluster(&self, name: &str) -> Result<Option<ClusterRecord>>;
+    async fn list_clusters(&self) -> Result<Vec<ClusterRecord>>;
+    async fn cluster_history(&self, name: &str) -> Result<Vec<HistoryEntry<ClusterStatus>>>;
+
+    async fn insert_project(&self, project: &Project) -> Result<()>;
+    async fn update_project(&self, project: &Project) -> Result<()>;
+    async fn delete_project(&self, name: &str) -> Result<()>;
+    async fn list_projects(&self) -> Result<Vec<Project>>;
+    async fn project_usage(&self, name: &str) -> Result<ProjectUsage>;
+
+    async fn append_audit(&self, event: &AuditEvent) -> Result<()>;
+    async fn query_audit(&self, filter: &AuditFilter) -> Result<Vec<AuditEntry>>;
+
+    async fn insert_operation(&self, operation: &Operation) -> Result<()>;
+    async fn update_operation(&self, operation: &Operation) -> Result<()>;
+    async fn get_operation(&self, id: Uuid) -> Result<Option<Operation>>;
+    /// The latest operations, limited to `projects` when set. Operations
+    /// recorded before projects existed belong to the default project.
+    async fn list_operations(
+        &self,
+        projects: Option<&[String]>,
+        limit: i64,
+    ) -> Result<Vec<Operation>>;
+    /// Records that the galato instance `id` is alive.
+    async fn heartbeat(&self, id: Uuid) -> Result<()>;
+    /// Fails every pending or running operation whose instance has not sent
+    /// a heartbeat within `timeout`, returning the operations it failed.
+    async fn fail_orphaned_operations(
+        &self,
+        reason: &str,
+        timeout: Duration,
+    ) -> Result<Vec<Operation>>;
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RollupRecord {
+    pub config: RollupConfig,
+    pub status: RollupStatus,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClusterRecord {
+    pub config: ClusterConfig,
+    pub status: ClusterStatus,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry<T> {
+    pub state: String,
+    pub status: T,
+    pub recorded_at: DateTime<Utc>,
+}
@@ -0,0 +1,900 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use sqlx::{
+    postgres::{PgPool, PgPoolOptions, PgRow},
+    types::Json,
+    Postgres, QueryBuilder, Row,
+};
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::{
+    audit::{AuditEntry, AuditEvent, AuditFilter, AuditOutcome},
+    config::DatabaseConfig,
+    infra::{ClusterConfig, ClusterStatus},
+    operations::{Operation, OperationState, OperationStep},
+    project::{Project, ProjectQuotas, ProjectUsage, DEFAULT_PROJECT},
+    rollup::{RollupConfig, RollupState, RollupStatus},
+};
+use super::{ClusterRecord, HistoryEntry, RollupRecord, Store};
+
+pub struct PgStore {
+    pool: PgPool,
+}
+
+impl PgStore {
+    pub async fn connect(config: &DatabaseConfig) -> Result<Self> {
+        let pool = PgPoolOptions::new()
+            .max_connections(config.max_connections)
+            .min_connections(config.min_connections)
+            .connect(&config.url)
+            .await?;
+
+        Ok(Self { pool })
+    }
+
+    pub async fn migrate(&self) -> Result<()> {
+        sqlx::migrate!("./migrations").run(&self.pool).await?;
+        Ok(())
+    }
+
+    /// Writes a rollup's status, only while its state is `expected` when set,
+    /// and returns whether it was written. Only state transitions are written
+    /// to the history table.
+    async fn write_rollup_status(
+        &self,
+        expected: Option<&RollupState>,
+        status: &RollupStatus,
+    ) -> Result<bool> {
+        let updated: i64 = sqlx::query_scalar(
+            r#"WITH previous AS (
+                SELECT state FROM rollups WHERE name = $1
+            ), updated AS (
+                UPDATE rollups SET state = $2, status = $3, updated_at = now()
+                WHERE name = $1 AND ($4::text IS NULL OR state = $4)
+                RETURNING name, state, config, status
+            ), history AS (
+                INSERT INTO rollup_history (rollup_name, state, config, status)
+                SELECT updated.name, updated.state, updated.config, updated.status
+                FROM updated, previous
+                WHERE previous.state <> updated.state
+            )
+            SELECT count(*) FROM updated"#,
+        )
+        .bind(&status.name)
+        .bind(status.state.as_str())
+        .bind(Json(status))
+        .bind(expected.map(RollupState::as_str))
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(updated > 0)
+    }
+}
+
+/// Chain ids are stored in a BIGINT column, which holds only half the range
+/// of a `u64`.
+fn chain_id(config: &RollupConfig) -> Result<i64> {
+    i64::try_from(config.chain_id).with_context(|| {
+        format!(
+            "chain id {} of rollup {} cannot be stored",
+            config.chain_id, config.name
+        )
+    })
+}
+
+fn rollup_record(row: PgRow) -> Result<RollupRecord> {
+    Ok(RollupRecord {
+        config: row.try_get::<Json<RollupConfig>, _>("config")?.0,
+        status: row.try_get::<Json<RollupStatus>, _>("status")?.0,
+        created_at: row.try_get("created_at")?,
+        updated_at: row.try_get("updated_at")?,
+    })
+}
+
+fn cluster_record(row: PgRow) -> Result<ClusterRecord> {
+    Ok(ClusterRecord {
+        config: row.try_get::<Json<ClusterConfig>, _>("config")?.0,
+        status: row.try_get::<Json<ClusterStatus>, _>("status")?.0,
+        created_at: row.try_get("created_at")?,
+        updated_at: row.try_get("updated_at")?,
+    })
+}
+
+fn operation(row: PgRow) -> Result<Operation> {
+    let state: String = row.try_get("state")?;
+    let progress: i16 = row.try_get("progress")?;
+
+    Ok(Operation {
+        id: row.try_get("id")?,
+        kind: row.try_get("kind")?,
+        target: row.try_get("target")?,
+        project: row.try_get("project")?,
+        requested_by: row.try_get("requested_by")?,
+        instance: row.try_get("instance_id")?,
+        state: OperationState::parse(&state)?,
+        progress: progress.clamp(0, 100) as u8,
+        steps: row.try_get::<Json<Vec<OperationStep>>, _>("steps")?.0,
+        result: row
+            .try_get::<Option<Json<serde_json::Value>>, _>("result")?
+            .map(|result| result.0),
+        error: row.try_get("error")?,
+        created_at: row.try_get("created_at")?,
+        updated_at: row.try_get("updated_at")?,
+    })
+}
+
+fn project(row: PgRow) -> Result<Project> {
+    Ok(Project {
+        name: row.try_get("name")?,
+        namespace: row.try_get("namespace")?,
+        quotas: row.try_get::<Json<ProjectQuotas>, _>("quotas")?.0,
+        created_at: row.try_get("created_at")?,
+    })
+}
+
+fn audit_entry(row: PgRow) -> Result<AuditEntry> {
+    let outcome: String = row.try_get("outcome")?;
+
+    Ok(AuditEntry {
+        id: row.try_get("id")?,
+        recorded_at: row.try_get("recorded_at")?,
+        event: AuditEvent {
+            actor: row.try_get("actor")?,
+            action: row.try_get("action")?,
+            target: row.try_get("target")?,
+            project: row.try_get("project")?,
+            before: row
+                .try_get::<Option<Json<serde_json::Value>>, _>("before")?
+                .map(|before| before.0),
+            after: row
+                .try_get::<Option<Json<serde_json::Value>>, _>("after")?
+                .map(|after| after.0),
+            outcome: AuditOutcome::parse(&outcome)?,
+            error: row.try_get("error")?,
+            operation_id: row.try_get("operation_id")?,
+        },
+    })
+}
+
+fn history_entry<T>(row: PgRow) -> Result<HistoryEntry<T>>
+where
+    T: serde::de::DeserializeOwned + Send + Unpin + 'static,
+{
+    Ok(HistoryEntry {
+        state: row.try_get("state")?,
+        status: row.try_get::<Json<T>, _>("status")?.0,
+        recorded_at: row.try_get("recorded_at")?,
+    })
+}
+
+#[async_trait]
+impl Store for PgStore {
+    async fn insert_rollup(&self, config: &RollupConfig, status: &RollupStatus) -> Result<()> {
+        sqlx::query(
+            r#"WITH inserted AS (
+                INSERT INTO rollups (name, project, chain_id, state, config, status)
+                VALUES ($1, $2, $3, $4, $5, $6)
+                RETURNING name, state, config, status
+            )
+            INSERT INTO rollup_history (rollup_name, state, config, status)
+            SELECT name, state, config, status FROM inserted"#,
+        )
+        .bind(&config.name)
+        .bind(&config.project)
+        .bind(chain_id(config)?)
+        .bind(status.state.as_str())
+        .bind(Json(config))
+        .bind(Json(status))
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn update_rollup_config(&self, config: &RollupConfig) -> Result<()> {
+        // Re-applying an identical configuration does not add history
+        sqlx::query(
+            r#"WITH previous AS (
+                SELECT config FROM rollups WHERE name = $1
+            ), updated AS (
+                UPDATE rollups SET chain_id = $2, config = $3, updated_at = now()
+                WHERE name = $1
+                RETURNING name, state, config, status
+            )
+            INSERT INTO rollup_history (rollup_name, state, config, status)
+            SELECT updated.name, updated.state, updated.config, updated.status
+            FROM updated, previous
+            WHERE previous.config <> updated.config"#,
+        )
+        .bind(&config.name)
+        .bind(chain_id(config)?)
+        .bind(Json(config))
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn update_rollup_status(&self, status: &RollupStatus) -> Result<()> {
+        self.write_rollup_status(None, status).await?;
+        Ok(())
+    }
+
+    async fn update_rollup_status_if(
+        &self,
+        expected: &RollupState,
+        status: &RollupStatus,
+    ) -> Result<bool> {
+        self.write_rollup_status(Some(expected), status).await
+    }
+
+    async fn delete_rollup(&self, name: &str) -> Result<()> {
+        sqlx::query(
+            r#"WITH deleted AS (
+                DELETE FROM rollups WHERE name = $1
+                RETURNING name, config, status
+            )
+            INSERT INTO rollup_history (rollup_name, state, config, status)
+            SELECT name, 'Deleted', config, status FROM deleted"#,
+        )
+        .bind(name)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_rollup(&self, name: &str) -> Result<Option<RollupRecord>> {
+        let row = sqlx::query(
+            "SELECT config, status, created_at, updated_at FROM rollups WHERE name = $1",
+        )
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(rollup_record).transpose()
+    }
+
+    async fn list_rollups(&self) -> Result<Vec<RollupRecord>> {
+        let rows = sqlx::query(
+            "SELECT config, status, created_at, updated_at FROM rollups ORDER BY created_at",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(rollup_record).collect()
+    }
+
+    async fn rollup_history(&self, name: &str) -> Result<Vec<HistoryEntry<RollupStatus>>> {
+        let rows = sqlx::query(
+            r#"SELECT state, status, recorded_at FROM rollup_history
+            WHERE rollup_name = $1 ORDER BY recorded_at DESC, id DESC"#,
+        )
+        .bind(name)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(history_entry).collect()
+    }
+
+    async fn insert_cluster(&self, config: &ClusterConfig, status: &ClusterStatus) -> Result<()> {
+        sqlx::query(
+            r#"WITH inserted AS (
+                INSERT INTO clusters (name, project, state, config, status)
+                VALUES ($1, $2, $3, $4, $5)
+                RETURNING name, state, config, status
+            )
+            INSERT INTO cluster_history (cluster_name, state, config, status)
+            SELECT name, state, config, status FROM inserted"#,
+        )
+        .bind(&config.name)
+        .bind(&config.project)
+        .bind(status.state.as_str())
+        .bind(Json(config))
+        .bind(Json(status))
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn update_cluster_config(&self, config: &ClusterConfig) -> Result<()> {
+        sqlx::query(
+            r#"WITH previous AS (
+                SELECT config FROM clusters WHERE name = $1
+            ), updated AS (
+                UPDATE clusters SET config = $2, updated_at = now()
+                WHERE name = $1
+                RETURNING name, state, config, status
+            )
+            INSERT INTO cluster_history (cluster_name, state, config, status)
+            SELECT updated.name, updated.state, updated.config, updated.status
+            FROM updated, previous
+            WHERE previous.config <> updated.config"#,
+        )
+        .bind(&config.name)
+        .bind(Json(config))
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn update_cluster_status(&self, status: &ClusterStatus) -> Result<()> {
+        sqlx::query(
+            r#"WITH previous AS (
+                SELECT state FROM clusters WHERE name = $1
+            ), updated AS (
+                UPDATE clusters SET state = $2, status = $3, updated_at = now()
+                WHERE name = $1
+                RETURNING name, state, config, status
+            )
+            INSERT INTO cluster_history (cluster_name, state, config, status)
+            SELECT updated.name, updated.state, updated.config, updated.status
+            FROM updated, previous
+            WHERE previous.state <> updated.state"#,
+        )
+        .bind(&status.name)
+        .bind(status.state.as_str())
+        .bind(Json(status))
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete_cluster(&self, name: &str) -> Result<()> {
+        sqlx::query(
+            r#"WITH deleted AS (
+                DELETE FROM clusters WHERE name = $1
+                RETURNING name, config, status
+            )
+            INSERT INTO cluster_history (cluster_name, state, config, status)
+            SELECT name, 'Deleted', config, status FROM deleted"#,
+        )
+        .bind(name)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_cluster(&self, name: &str) -> Result<Option<ClusterRecord>> {
+        let row = sqlx::query(
+            "SELECT config, status, created_at, updated_at FROM clusters WHERE name = $1",
+        )
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(cluster_record).transpose()
+    }
+
+    async fn list_clusters(&self) -> Result<Vec<ClusterRecord>> {
+        let rows = sqlx::query(
+            "SELECT config, status, created_at, updated_at FROM clusters ORDER BY created_at",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(cluster_record).collect()
+    }
+
+    async fn cluster_history(&self, name: &str) -> Result<Vec<HistoryEntry<ClusterStatus>>> {
+        let rows = sqlx::query(
+            r#"SELECT state, status, recorded_at FROM cluster_history
+            WHERE cluster_name = $1 ORDER BY recorded_at DESC, id DESC"#,
+        )
+        .bind(name)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter().map(history_entry).collect()
+    }
+
+    async fn insert_project(&self, project: &Project) -> Result<()> {
+        sqlx::query(
+            r#"INSERT INTO projects (name, namespace, quotas, created_at)
+            VALUES ($1, $2, $3, $4)"#,
+        )
+        .bind(&project.name)
+        .bind(&project.namespace)
+        .bind(Json(&project.quotas))
+        .bind(project.created_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn update_project(&self, project: &Project) -> Result<()> {
+        sqlx::query("UPDATE projects SET quotas = $2, updated_at = now() WHERE name = $1")
+            .bind(&project.name)
+            .bind(Json(&project.quotas))
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn delete_project(&self, name: &str) -> Result<()> {
+        sqlx::query("DELETE FROM projects WHERE name = $1")
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn list_projects(&self) -> Result<Vec<Project>> {
+        let rows = sqlx::query("SELECT * FROM projects ORDER BY name")
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.into_iter().map(project).collect()
+    }
+
+    async fn project_usage(&self, name: &str) -> Result<ProjectUsage> {
+        let row = sqlx::query(
+            r#"SELECT
+                (SELECT count(*) FROM rollups WHERE project = $1) AS rollups,
+                (SELECT count(*) FROM clusters WHERE project = $1) AS clusters"#,
+        )
+        .bind(name)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(ProjectUsage {
+            rollups: row.try_get("rollups")?,
+            clusters: row.try_get("clusters")?,
+        })
+    }
+
+    async fn append_audit(&self, event: &AuditEvent) -> Result<()> {
+        sqlx::query(
+            r#"INSERT INTO audit_log
+                (actor, action, target, project, before, after, outcome, error, operation_id)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)"#,
+        )
+        .bind(&event.actor)
+        .bind(&event.action)
+        .bind(&event.target)
+        .bind(&event.project)
+        .bind(event.before.as_ref().map(Json))
+        .bind(event.after.as_ref().map(Json))
+        .bind(event.outcome.as_str())
+        .bind(&event.error)
+        .bind(event.operation_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn query_audit(&self, filter: &AuditFilter) -> Result<Vec<AuditEntry>> {
+        let mut query: QueryBuilder<Postgres> =
+            QueryBuilder::new("SELECT * FROM audit_log WHERE true");
+        for (column, value) in [
+            ("actor", &filter.actor),
+            ("action", &filter.action),
+            ("target", &filter.target),
+            ("project", &filter.project),
+        ] {
+            if let Some(value) = value {
+                query.push(format!(" AND {} = ", column)).push_bind(value.clone());
+            }
+        }
+        if let Some(outcome) = filter.outcome {
+            query.push(" AND outcome = ").push_bind(outcome.as_str());
+        }
+        if let Some(since) = filter.since {
+            query.push(" AND recorded_at >= ").push_bind(since);
+        }
+        if let Some(until) = filter.until {
+            query.push(" AND recorded_at < ").push_bind(until);
+        }
+        if let Some(projects) = &filter.projects {
+            query.push(" AND project = ANY(").push_bind(projects.clone()).push(")");
+        }
+        query
+            .push(" ORDER BY recorded_at DESC, id DESC LIMIT ")
+            .push_bind(filter.limit());
+
+        let rows = query.build().fetch_all(&self.pool).await?;
+        rows.into_iter().map(audit_entry).collect()
+    }
+
+    async fn insert_operation(&self, operation: &Operation) -> Result<()> {
+        sqlx::query(
+            r#"INSERT INTO operations
+                (id, kind, target, project, requested_by, instance_id, state, progress, steps,
+                 result, error, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)"#,
+        )
+        .bind(operation.id)
+        .bind(&operation.kind)
+        .bind(&operation.target)
+        .bind(&operation.project)
+        .bind(&operation.requested_by)
+        .bind(operation.instance)
+        .bind(operation.state.as_str())
+        .bind(operation.progress as i16)
+        .bind(Json(&operation.steps))
+        .bind(operation.result.as_ref().map(Json))
+        .bind(&operation.error)
+        .bind(operation.created_at)
+        .bind(operation.updated_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn update_operation(&self, operation: &Operation) -> Result<()> {
+        sqlx::query(
+            r#"UPDATE operations
+            SET state = $2, progress = $3, steps = $4, result = $5, error = $6, updated_at = $7
+            WHERE id = $1"#,
+        )
+        .bind(operation.id)
+        .bind(operation.state.as_str())
+        .bind(operation.progress as i16)
+        .bind(Json(&operation.steps))
+        .bind(operation.result.as_ref().map(Json))
+        .bind(&operation.error)
+        .bind(operation.updated_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn get_operation(&self, id: Uuid) -> Result<Option<Operation>> {
+        let row = sqlx::query("SELECT * FROM operations WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        row.map(operation).transpose()
+    }
+
+    async fn list_operations(
+        &self,
+        projects: Option<&[String]>,
+        limit: i64,
+    ) -> Result<Vec<Operation>> {
+        let mut query: QueryBuilder<Postgres> =
+            QueryBuilder::new("SELECT * FROM operations WHERE true");
+        if let Some(projects) = projects {
+            query
+                .push(" AND COALESCE(project, ")
+                .push_bind(DEFAULT_PROJECT)
+                .push(") = ANY(")
+                .push_bind(projects.to_vec())
+                .push(")");
+        }
+        query.push(" ORDER BY created_at DESC LIMIT ").push_bind(limit);
+
+        let rows = query.build().fetch_all(&self.pool).await?;
+
+        rows.into_iter().map(operation).collect()
+    }
+
+    async fn heartbeat(&self, id: Uuid) -> Result<()> {
+        sqlx::query(
+            r#"INSERT INTO instances (id) VALUES ($1)
+            ON CONFLICT (id) DO UPDATE SET heartbeat_at = now()"#,
+        )
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn fail_orphaned_operations(
+        &self,
+        reason: &str,
+        timeout: Duration,
+    ) -> Result<Vec<Operation>> {
+        let timeout = timeout.as_secs() as i64;
+        let rows = sqlx::query(
+            r#"UPDATE operations
+            SET state = 'Failed', error = $1, updated_at = now()
+            WHERE state IN ('Pending', 'Running')
+              AND NOT EXISTS (
+                SELECT 1 FROM instances
+                WHERE instances.id = operations.instance_id
+                  AND instances.heartbeat_at > now() - $2 * interval '1 second')
+            RETURNING *"#,
+        )
+        .bind(reason)
+        .bind(timeout)
+        .fetch_all(&self.pool)
+        .await?;
+
+        // Instances that stopped no longer run anything
+        sqlx::query("DELETE FROM instances WHERE heartbeat_at <= now() - $1 * interval '1 second'")
+            .bind(timeout)
+            .execute(&self.pool)
+            .await?;
+
+        rows.into_iter().map(operation).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use serde_json::json;
+
+    use crate::{
+        infra::ClusterState,
+        rollup::RollupState,
+        testing::{cluster_config, rollup_config},
+    };
+
+    /// Database the ignored store tests run against. Records are named
+    /// uniquely, so the database can be shared and reused.
+    const DATABASE_URL: &str = "GALATO_TEST_DATABASE_URL";
+
+    async fn store() -> PgStore {
+        let url = std::env::var(DATABASE_URL)
+            .unwrap_or_else(|_| panic!("{} must name a PostgreSQL database", DATABASE_URL));
+        let config = DatabaseConfig {
+            url,
+            max_connections: 2,
+            min_connections: 0,
+        };
+        let store = PgStore::connect(&config).await.unwrap();
+        store.migrate().await.unwrap();
+        store
+    }
+
+    fn unique(prefix: &str) -> String {
+        format!("{}-{}", prefix, &Uuid::new_v4().simple().to_string()[..8])
+    }
+
+    fn rollup_status(config: &RollupConfig, state: RollupState) -> RollupStatus {
+        serde_json::from_value(json!({
+            "name": config.name,
+            "project": config.project,
+            "state": state,
+            "chain_id": config.chain_id,
+            "sequencer_status": { "is_healthy": false, "last_block": 0, "last_timestamp": 0 },
+            "validator_status": {
+                "is_healthy": false,
+                "last_validated_block": 0,
+                "last_validation_timestamp": 0,
+            },
+            "batch_submitter_status": {
+                "is_healthy": false,
+                "last_submitted_batch": 0,
+                "last_submission_timestamp": 0,
+            },
+        }))
+        .unwrap()
+    }
+
+    fn cluster_status(config: &ClusterConfig, state: ClusterState) -> ClusterStatus {
+        ClusterStatus {
+            name: config.name.clone(),
+            project: config.project.clone(),
+            state,
+            node_count: config.node_count,
+            version: config.kubernetes_version.clone(),
+            endpoint: String::new(),
+            message: None,
+        }
+    }
+
+    fn states<T>(history: &[HistoryEntry<T>]) -> Vec<&str> {
+        history.iter().map(|entry| entry.state.as_str()).collect()
+    }
+
+    #[test]
+    fn chain_ids_must_fit_a_bigint() {
+        let mut config = rollup_config("r1");
+        config.chain_id = i64::MAX as u64;
+        assert_eq!(chain_id(&config).unwrap(), i64::MAX);
+        config.chain_id = u64::MAX;
+        assert!(chain_id(&config).is_err());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a PostgreSQL database in GALATO_TEST_DATABASE_URL"]
+    async fn rollup_history_records_changes_and_state_transitions() {
+        let store = store().await;
+        let mut config = rollup_config(&unique("r"));
+        store
+            .insert_rollup(&config, &rollup_status(&config, RollupState::Creating))
+            .await
+            .unwrap();
+
+        // Identical configurations and statuses without a new state add nothing
+        store.update_rollup_config(&config).await.unwrap();
+        store
+            .update_rollup_status(&rollup_status(&config, RollupState::Creating))
+            .await
+            .unwrap();
+        assert_eq!(
+            states(&store.rollup_history(&config.name).await.unwrap()),
+            ["Creating"]
+        );
+
+        config.l2_rpc_url = "http://l2.example:8545".to_string();
+        store.update_rollup_config(&config).await.unwrap();
+        store
+            .update_rollup_status(&rollup_status(&config, RollupState::Running))
+            .await
+            .unwrap();
+        let record = store.get_rollup(&config.name).await.unwrap().unwrap();
+        assert_eq!(record.config.l2_rpc_url, config.l2_rpc_url);
+        assert_eq!(record.status.state, RollupState::Running);
+
+        store.delete_rollup(&config.name).await.unwrap();
+        assert!(store.get_rollup(&config.name).await.unwrap().is_none());
+        assert_eq!(
+            states(&store.rollup_history(&config.name).await.unwrap()),
+            ["Deleted", "Running", "Creating", "Creating"]
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a PostgreSQL database in GALATO_TEST_DATABASE_URL"]
+    async fn conditional_status_updates_need_the_expected_state() {
+        let store = store().await;
+        let config = rollup_config(&unique("r"));
+        store
+            .insert_rollup(&config, &rollup_status(&config, RollupState::Creating))
+            .await
+            .unwrap();
+        let running = rollup_status(&config, RollupState::Running);
+
+        assert!(!store
+            .update_rollup_status_if(&RollupState::Failed, &running)
+            .await
+            .unwrap());
+        assert!(store
+            .update_rollup_status_if(&RollupState::Creating, &running)
+            .await
+            .unwrap());
+        let record = store.get_rollup(&config.name).await.unwrap().unwrap();
+        assert_eq!(record.status.state, RollupState::Running);
+        assert_eq!(
+            states(&store.rollup_history(&config.name).await.unwrap()),
+            ["Running", "Creating"]
+        );
+        store.delete_rollup(&config.name).await.unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a PostgreSQL database in GALATO_TEST_DATABASE_URL"]
+    async fn rollups_with_chain_ids_beyond_a_bigint_are_refused() {
+        let store = store().await;
+        let mut config = rollup_config(&unique("r"));
+        config.chain_id = u64::MAX;
+        let status = rollup_status(&config, RollupState::Creating);
+        assert!(store.insert_rollup(&config, &status).await.is_err());
+        assert!(store.get_rollup(&config.name).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a PostgreSQL database in GALATO_TEST_DATABASE_URL"]
+    async fn cluster_history_records_state_transitions() {
+        let store = store().await;
+        let config = cluster_config(&unique("c"));
+        let creating = cluster_status(&config, ClusterState::Creating);
+        store.insert_cluster(&config, &creating).await.unwrap();
+        assert!(store.insert_cluster(&config, &creating).await.is_err());
+
+        store.update_cluster_status(&creating).await.unwrap();
+        store
+            .update_cluster_status(&cluster_status(&config, ClusterState::Running))
+            .await
+            .unwrap();
+        let record = store.get_cluster(&config.name).await.unwrap().unwrap();
+        assert!(matches!(record.status.state, ClusterState::Running));
+
+        store.delete_cluster(&config.name).await.unwrap();
+        assert!(store.get_cluster(&config.name).await.unwrap().is_none());
+        assert_eq!(
+            states(&store.cluster_history(&config.name).await.unwrap()),
+            ["Deleted", "Running", "Creating"]
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a PostgreSQL database in GALATO_TEST_DATABASE_URL"]
+    async fn operations_of_stopped_instances_are_failed() {
+        let store = store().await;
+        let alive = Uuid::new_v4();
+        store.heartbeat(alive).await.unwrap();
+        let running = |instance: Option<Uuid>| {
+            let now = Utc::now();
+            Operation {
+                id: Uuid::new_v4(),
+                kind: "create_rollup".to_string(),
+                target: unique("r"),
+                project: None,
+                requested_by: None,
+                instance,
+                state: OperationState::Running,
+                progress: 50,
+                steps: Vec::new(),
+                result: None,
+                error: None,
+                created_at: now,
+                updated_at: now,
+            }
+        };
+        let live = running(Some(alive));
+        let stopped = running(Some(Uuid::new_v4()));
+        let unowned = running(None);
+        for operation in [&live, &stopped, &unowned] {
+            store.insert_operation(operation).await.unwrap();
+        }
+
+        let failed = store
+            .fail_orphaned_operations("interrupted", Duration::from_secs(60))
+            .await
+            .unwrap();
+        let failed: Vec<Uuid> = failed.iter().map(|operation| operation.id).collect();
+        assert!(failed.contains(&stopped.id));
+        assert!(failed.contains(&unowned.id));
+        assert!(!failed.contains(&live.id));
+
+        let live = store.get_operation(live.id).await.unwrap().unwrap();
+        assert_eq!(live.state, OperationState::Running);
+        let stopped = store.get_operation(stopped.id).await.unwrap().unwrap();
+        assert_eq!(stopped.state, OperationState::Failed);
+        assert_eq!(stopped.error.as_deref(), Some("interrupted"));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a PostgreSQL database in GALATO_TEST_DATABASE_URL"]
+    async fn operations_are_filtered_by_project_before_the_limit() {
+        let store = store().await;
+        let pending = |project: Option<String>| {
+            let now = Utc::now();
+            Operation {
+                id: Uuid::new_v4(),
+                kind: "create_rollup".to_string(),
+                target: unique("r"),
+                project,
+                requested_by: None,
+                instance: None,
+                state: OperationState::Pending,
+                progress: 0,
+                steps: Vec::new(),
+                result: None,
+                error: None,
+                created_at: now,
+                updated_at: now,
+            }
+        };
+        let (payments, games) = (unique("payments"), unique("games"));
+        let older = pending(Some(payments.clone()));
+        let newer = pending(Some(games.clone()));
+        let legacy = pending(None);
+        for operation in [&older, &newer, &legacy] {
+            store.insert_operation(operation).await.unwrap();
+        }
+        let ids = |operations: Vec<Operation>| -> Vec<Uuid> {
+            operations.iter().map(|operation| operation.id).collect()
+        };
+
+        let listed = store.list_operations(Some(std::slice::from_ref(&payments)), 1).await.unwrap();
+        assert_eq!(ids(listed), [older.id]);
+        let listed = store.list_operations(Some(&[payments, games]), 10).await.unwrap();
+        assert_eq!(ids(listed), [newer.id, older.id]);
+        // Operations recorded before projects existed belong to the default one
+        let listed = store
+            .list_operations(Some(&[DEFAULT_PROJECT.to_string()]), 1)
+            .await
+            .unwrap();
+        assert_eq!(ids(listed), [legacy.id]);
+    }
+}
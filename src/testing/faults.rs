@@ -0,0 +1,53 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+};
+
+/// A failure to inject into a fake backend.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fault {
+    /// The call gives up waiting for the backend
+    Timeout,
+    /// The backend rejects the call because of a concurrent change
+    Conflict,
+    /// The backend no longer accepts the caller's credentials
+    Unauthorized,
+    /// The call takes effect but still reports an error
+    Partial,
+    /// The call fails outright with this message
+    Error(String),
+}
+
+/// Faults queued per method name. Each queued fault fails exactly one call.
+#[derive(Default)]
+pub struct Faults {
+    queued: Mutex<HashMap<String, VecDeque<Fault>>>,
+}
+
+impl Faults {
+    pub fn inject(&self, method: &str, fault: Fault) {
+        self.queued
+            .lock()
+            .unwrap()
+            .entry(method.to_string())
+            .or_default()
+            .push_back(fault);
+    }
+
+    /// Takes the next fault for `method`. Faults that stop the call are
+    /// returned as errors; `Ok(true)` means the call should apply its change
+    /// and then fail with `Fault::Partial`.
+    pub fn check(&self, method: &str) -> Result<bool, Fault> {
+        let fault = self
+            .queued
+            .lock()
+            .unwrap()
+            .get_mut(method)
+            .and_then(VecDeque::pop_front);
+        match fault {
+            None => Ok(false),
+            Some(Fault::Partial) => Ok(true),
+            Some(fault) => Err(fault),
+        }
+    }
+}
@@ -0,0 +1,300 @@
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use kube::core::ErrorResponse;
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+};
+
+use super::{Fault, Faults};
+use crate::infra::{
+    ApplyReport, DeploymentReadiness, KubernetesApi, KubernetesConnector, TeardownOptions,
+};
+
+/// Namespace, kind and name of a stored object. Cluster-scoped objects have
+/// an empty namespace.
+pub type ObjectKey = (String, String, String);
+
+/// Kinds removed by `delete_by_selector`, mirroring `KubernetesManager`.
+const TEARDOWN_KINDS: [&str; 3] = ["Deployment", "Service", "ConfigMap"];
+
+const CLUSTER_SCOPED_KINDS: [&str; 2] = ["Namespace", "Node"];
+
+/// In-memory Kubernetes API server. Applied objects are stored as JSON, and
+/// Deployments report every replica available unless readiness is overridden
+/// with `set_readiness` or `set_auto_ready(false)`.
+pub struct FakeKubernetes {
+    default_namespace: String,
+    objects: Mutex<BTreeMap<ObjectKey, serde_json::Value>>,
+    readiness: Mutex<HashMap<(String, String), DeploymentReadiness>>,
+    auto_ready: Mutex<bool>,
+    pub faults: Faults,
+}
+
+impl FakeKubernetes {
+    pub fn new(default_namespace: &str) -> Self {
+        Self {
+            default_namespace: default_namespace.to_string(),
+            objects: Mutex::new(BTreeMap::new()),
+            readiness: Mutex::new(HashMap::new()),
+            auto_ready: Mutex::new(true),
+            faults: Faults::default(),
+        }
+    }
+
+    pub fn get(&self, namespace: &str, kind: &str, name: &str) -> Option<serde_json::Value> {
+        let key = (namespace.to_string(), kind.to_string(), name.to_string());
+        self.objects.lock().unwrap().get(&key).cloned()
+    }
+
+    /// Keys of every stored object of `kind`.
+    pub fn objects(&self, kind: &str) -> Vec<ObjectKey> {
+        self.objects
+            .lock()
+            .unwrap()
+            .keys()
+            .filter(|(_, k, _)| k == kind)
+            .cloned()
+            .collect()
+    }
+
+    /// Removes an object as if someone deleted it behind galato's back.
+    pub fn remove(&self, namespace: &str, kind: &str, name: &str) {
+        let key = (namespace.to_string(), kind.to_string(), name.to_string());
+        self.objects.lock().unwrap().remove(&key);
+    }
+
+    pub fn set_auto_ready(&self, ready: bool) {
+        *self.auto_ready.lock().unwrap() = ready;
+    }
+
+    pub fn set_readiness(&self, namespace: &str, name: &str, readiness: DeploymentReadiness) {
+        self.readiness
+            .lock()
+            .unwrap()
+            .insert((namespace.to_string(), name.to_string()), readiness);
+    }
+
+    fn check(&self, method: &str) -> Result<bool> {
+        self.faults
+            .check(method)
+            .map_err(|fault| fault_error(method, fault))
+    }
+
+    fn key_of(&self, object: &serde_json::Value) -> Result<ObjectKey> {
+        let kind = object["kind"]
+            .as_str()
+            .context("manifest object is missing apiVersion or kind")?;
+        let name = object["metadata"]["name"]
+            .as_str()
+            .context("manifest object has no name")?;
+        let namespace = if CLUSTER_SCOPED_KINDS.contains(&kind) {
+            String::new()
+        } else {
+            object["metadata"]["namespace"]
+                .as_str()
+                .unwrap_or(&self.default_namespace)
+                .to_string()
+        };
+        Ok((namespace, kind.to_string(), name.to_string()))
+    }
+}
+
+/// Errors as the API server would return them, so callers see the same
+/// `kube::Error` variants they handle in production.
+fn fault_error(method: &str, fault: Fault) -> anyhow::Error {
+    let (code, reason, message) = match fault {
+        Fault::Timeout => (504, "Timeout", format!("{} timed out", method)),
+        Fault::Conflict => (
+            409,
+            "Conflict",
+            format!("{}: the object has been modified", method),
+        ),
+        Fault::Unauthorized => (401, "Unauthorized", "Unauthorized".to_string()),
+        Fault::Partial => (
+            500,
+            "InternalError",
+            format!("{} only partially succeeded", method),
+        ),
+        Fault::Error(message) => (500, "InternalError", message),
+    };
+    kube::Error::Api(ErrorResponse {
+        status: "Failure".to_string(),
+        message,
+        reason: reason.to_string(),
+        code,
+    })
+    .into()
+}
+
+fn finish(method: &str, partial: bool) -> Result<()> {
+    if partial {
+        return Err(fault_error(method, Fault::Partial));
+    }
+    Ok(())
+}
+
+/// Matches equality-based selectors such as `a=b,c=d`.
+fn matches_selector(object: &serde_json::Value, selector: &str) -> bool {
+    let labels = &object["metadata"]["labels"];
+    selector
+        .split(',')
+        .filter_map(|term| term.split_once('='))
+        .all(|(key, value)| labels[key].as_str() == Some(value))
+}
+
+#[async_trait]
+impl KubernetesApi for FakeKubernetes {
+    async fn apply_manifest(&self, manifest: &str) -> Result<ApplyReport> {
+        let partial = self.check("apply_manifest")?;
+        let mut report = ApplyReport::default();
+
+        for document in serde_yaml::Deserializer::from_str(manifest) {
+            let value = serde_yaml::Value::deserialize(document)?;
+            if value.is_null() {
+                continue;
+            }
+            let object: serde_json::Value = serde_yaml::from_value(value)?;
+            let key = self.key_of(&object)?;
+            let entry = format!("{}/{}", key.1, key.2);
+
+            let previous = self.objects.lock().unwrap().insert(key, object.clone());
+            match previous {
+                None => report.created.push(entry),
+                Some(previous) if previous == object => report.unchanged.push(entry),
+                Some(_) => report.updated.push(entry),
+            }
+        }
+
+        finish("apply_manifest", partial)?;
+        Ok(report)
+    }
+
+    async fn delete_by_selector(
+        &self,
+        namespace: &str,
+        selector: &str,
+        options: &TeardownOptions,
+    ) -> Result<Vec<String>> {
+        let partial = self.check("delete_by_selector")?;
+        let mut objects = self.objects.lock().unwrap();
+
+        let doomed: Vec<ObjectKey> = objects
+            .iter()
+            .filter(|((ns, kind, _), object)| {
+                ns == namespace
+                    && (TEARDOWN_KINDS.contains(&kind.as_str())
+                        || (options.include_volumes && kind == "PersistentVolumeClaim"))
+                    && matches_selector(object, selector)
+            })
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &doomed {
+            objects.remove(key);
+        }
+        drop(objects);
+
+        finish("delete_by_selector", partial)?;
+        Ok(doomed
+            .into_iter()
+            .map(|(_, kind, name)| format!("{}/{}", kind, name))
+            .collect())
+    }
+
+    async fn delete_namespace(&self, name: &str) -> Result<()> {
+        let partial = self.check("delete_namespace")?;
+        self.objects
+            .lock()
+            .unwrap()
+            .retain(|(ns, kind, object), _| ns != name && !(kind == "Namespace" && object == name));
+        finish("delete_namespace", partial)
+    }
+
+    async fn get_namespace_labels(&self, name: &str) -> Result<Option<BTreeMap<String, String>>> {
+        self.check("get_namespace_labels")?;
+        Ok(self.get("", "Namespace", name).map(|namespace| {
+            serde_json::from_value(namespace["metadata"]["labels"].clone()).unwrap_or_default()
+        }))
+    }
+
+    async fn get_deployment_readiness(
+        &self,
+        namespace: &str,
+        name: &str,
+    ) -> Result<Option<DeploymentReadiness>> {
+        self.check("get_deployment_readiness")?;
+        let Some(deployment) = self.get(namespace, "Deployment", name) else {
+            return Ok(None);
+        };
+
+        let key = (namespace.to_string(), name.to_string());
+        if let Some(readiness) = self.readiness.lock().unwrap().get(&key) {
+            return Ok(Some(readiness.clone()));
+        }
+
+        let replicas = deployment["spec"]["replicas"]
+            .as_i64()
+            .and_then(|r| i32::try_from(r).ok())
+            .unwrap_or(1);
+        let available = if *self.auto_ready.lock().unwrap() {
+            replicas
+        } else {
+            0
+        };
+        Ok(Some(DeploymentReadiness {
+            replicas,
+            ready_replicas: available,
+            available_replicas: available,
+            failure: None,
+        }))
+    }
+}
+
+/// Hands out one `FakeKubernetes` per managed cluster, recording each
+/// connection.
+pub struct FakeConnector {
+    default_namespace: String,
+    clusters: Mutex<HashMap<String, Arc<FakeKubernetes>>>,
+    connections: Mutex<Vec<String>>,
+}
+
+impl FakeConnector {
+    pub fn new(default_namespace: &str) -> Self {
+        Self {
+            default_namespace: default_namespace.to_string(),
+            clusters: Mutex::new(HashMap::new()),
+            connections: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// The fake API server of a managed cluster, once connected to.
+    pub fn cluster(&self, name: &str) -> Option<Arc<FakeKubernetes>> {
+        self.clusters.lock().unwrap().get(name).cloned()
+    }
+
+    /// Clusters connected to, in order.
+    pub fn connections(&self) -> Vec<String> {
+        self.connections.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl KubernetesConnector for FakeConnector {
+    async fn connect(&self, cluster: &str, kubeconfig: &str) -> Result<Arc<dyn KubernetesApi>> {
+        anyhow::ensure!(
+            kubeconfig.contains(cluster),
+            "kubeconfig does not point at cluster {}",
+            cluster
+        );
+        self.connections.lock().unwrap().push(cluster.to_string());
+        let api = self
+            .clusters
+            .lock()
+            .unwrap()
+            .entry(cluster.to_string())
+            .or_insert_with(|| Arc::new(FakeKubernetes::new(&self.default_namespace)))
+            .clone();
+        Ok(api)
+    }
+}
@@ -0,0 +1,240 @@
+//! Fakes for exercising the controller, rollup manager and API without a
+//! cloud account, a Kubernetes cluster or Postgres.
+
+mod faults;
+mod kubernetes;
+mod provider;
+mod replay;
+mod rpc;
+mod store;
+
+use axum::{
+    body::{self, Body},
+    http::{header, Method, Request, StatusCode},
+    Router,
+};
+use ethers::types::Address;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tower::ServiceExt;
+use uuid::Uuid;
+
+use crate::{
+    api::{self, AppState},
+    audit::AuditLog,
+    auth::Authenticator,
+    config::Config,
+    infra::{default_account, ClusterConfig, Controller, ProviderRegistry},
+    monitoring::System,
+    operations::{Operation, OperationState, Operations},
+    project::{Projects, DEFAULT_PROJECT},
+    rollup::{DeploymentType, Manager, Reconciler, RollupConfig},
+};
+
+pub use faults::{Fault, Faults};
+pub use kubernetes::{FakeConnector, FakeKubernetes};
+pub use provider::FakeProvider;
+pub use replay::Replay;
+pub use rpc::FakeNode;
+pub use store::MemoryStore;
+
+/// How long `wait_for_operation` polls before giving up.
+const OPERATION_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Configuration for tests: authentication off and health probes that give up
+/// quickly on the unreachable RPC URLs of `rollup_config`.
+pub fn test_config() -> Config {
+    let mut config = Config::default();
+    config.auth.enabled = false;
+    config.rollup.health.probe_timeout_secs = 1;
+    config
+}
+
+pub fn rollup_config(name: &str) -> RollupConfig {
+    RollupConfig {
+        name: name.to_string(),
+        project: DEFAULT_PROJECT.to_string(),
+        chain_id: 4242,
+        sequencer_address: Address::repeat_byte(1),
+        validator_address: Address::repeat_byte(2),
+        batch_submitter_address: Address::repeat_byte(3),
+        l1_chain_id: 1,
+        l1_rpc_url: "http://127.0.0.1:1".to_string(),
+        l2_rpc_url: "http://127.0.0.1:1".to_string(),
+        deployment_type: DeploymentType::Optimistic,
+        topology: Default::default(),
+        placement: Default::default(),
+        resource: None,
+    }
+}
+
+pub fn cluster_config(name: &str) -> ClusterConfig {
+    ClusterConfig {
+        name: name.to_string(),
+        project: DEFAULT_PROJECT.to_string(),
+        account: default_account(),
+        region: "test-1".to_string(),
+        node_count: 3,
+        node_type: "standard-4".to_string(),
+        kubernetes_version: "1.28".to_string(),
+        tags: HashMap::new(),
+        node_pools: Vec::new(),
+    }
+}
+
+/// The whole application wired to fakes.
+pub struct Harness {
+    pub config: Arc<Config>,
+    pub store: Arc<MemoryStore>,
+    pub provider: Arc<FakeProvider>,
+    pub kubernetes: Arc<FakeKubernetes>,
+    /// Kubernetes API servers of managed clusters
+    pub connector: Arc<FakeConnector>,
+    pub controller: Arc<Controller>,
+    pub projects: Arc<Projects>,
+    pub manager: Arc<Manager>,
+    pub state: Arc<AppState>,
+}
+
+impl Harness {
+    pub async fn new() -> Self {
+        Self::with_config(test_config()).await
+    }
+
+    pub async fn with_config(config: Config) -> Self {
+        let config = Arc::new(config);
+        let store = Arc::new(MemoryStore::default());
+        let provider = Arc::new(FakeProvider::default());
+        let kubernetes = Arc::new(FakeKubernetes::new(&config.kubernetes.namespace));
+        let connector = Arc::new(FakeConnector::new(&config.kubernetes.namespace));
+        let mut providers = ProviderRegistry::default();
+        providers.insert(&default_account(), provider.clone());
+
+        let audit = AuditLog::new(store.clone());
+        let operations = Arc::new(Operations::new(store.clone(), audit.clone()).await.unwrap());
+        let controller = Arc::new(
+            Controller::with_backends(
+                kubernetes.clone(),
+                connector.clone(),
+                providers,
+                store.clone(),
+            )
+            .await
+            .unwrap(),
+        );
+        let projects = Arc::new(
+            Projects::new(&config, controller.clone(), store.clone())
+                .await
+                .unwrap(),
+        );
+        let manager = Arc::new(
+            Manager::new(controller.clone(), projects.clone(), store.clone())
+                .await
+                .unwrap(),
+        );
+
+        let state = Arc::new(AppState {
+            infra_controller: controller.clone(),
+            rollup_manager: manager.clone(),
+            projects: projects.clone(),
+            monitoring: Arc::new(System::new().unwrap()),
+            operations,
+            auth: Arc::new(Authenticator::new(&config.auth).unwrap()),
+            audit,
+        });
+
+        Self {
+            config,
+            store,
+            provider,
+            kubernetes,
+            connector,
+            controller,
+            projects,
+            manager,
+            state,
+        }
+    }
+
+    pub fn router(&self) -> Router {
+        api::create_router(self.state.clone())
+    }
+
+    pub fn reconciler(&self) -> Reconciler {
+        Reconciler::new(
+            &self.config,
+            self.manager.clone(),
+            self.state.monitoring.clone(),
+        )
+    }
+
+    /// Sends a request through the router and returns the status and JSON body
+    /// (`Null` for empty or non-JSON bodies).
+    pub async fn request(
+        &self,
+        method: Method,
+        uri: &str,
+        body: Option<serde_json::Value>,
+        token: Option<&str>,
+    ) -> (StatusCode, serde_json::Value) {
+        let (status, text) = self.request_text(method, uri, body, token).await;
+        let json = serde_json::from_str(&text).unwrap_or(serde_json::Value::Null);
+        (status, json)
+    }
+
+    /// Sends a request through the router and returns the status and body.
+    pub async fn request_text(
+        &self,
+        method: Method,
+        uri: &str,
+        body: Option<serde_json::Value>,
+        token: Option<&str>,
+    ) -> (StatusCode, String) {
+        let mut request = Request::builder().method(method).uri(uri);
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let request = match body {
+            Some(body) => request
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string())),
+            None => request.body(Body::empty()),
+        }
+        .unwrap();
+
+        let response = self.router().oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    /// Polls an operation until it succeeds or fails.
+    pub async fn wait_for_operation(&self, id: Uuid) -> Operation {
+        let deadline = tokio::time::Instant::now() + OPERATION_TIMEOUT;
+        loop {
+            let operation = self.state.operations.get(id).await.unwrap().unwrap();
+            if matches!(
+                operation.state,
+                OperationState::Succeeded | OperationState::Failed
+            ) {
+                return operation;
+            }
+            assert!(
+                tokio::time::Instant::now() < deadline,
+                "operation {} did not finish",
+                id
+            );
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    /// Waits for the operation returned by a `202 Accepted` response.
+    pub async fn finish(&self, accepted: &serde_json::Value) -> Operation {
+        let id = accepted["id"]
+            .as_str()
+            .and_then(|id| Uuid::parse_str(id).ok())
+            .expect("response is not an operation");
+        self.wait_for_operation(id).await
+    }
+}
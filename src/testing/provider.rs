@@ -0,0 +1,173 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use std::{collections::HashMap, sync::Mutex};
+
+use super::{Fault, Faults};
+use crate::infra::{
+    ClusterConfig, ClusterState, ClusterStatus, Error, InfrastructureProvider, NodePool,
+};
+
+/// A cluster as the fake cloud sees it.
+#[derive(Debug, Clone)]
+pub struct FakeCluster {
+    pub config: ClusterConfig,
+    pub state: ClusterState,
+    pub node_count: i32,
+    pub node_pools: Vec<NodePool>,
+}
+
+/// Stateful in-memory cloud. Clusters become `Running` as soon as they are
+/// created; use `set_state` to simulate anything else. Node pool calls yield
+/// once, as a cloud call would, so concurrent callers interleave.
+#[derive(Default)]
+pub struct FakeProvider {
+    clusters: Mutex<HashMap<String, FakeCluster>>,
+    pub faults: Faults,
+}
+
+impl FakeProvider {
+    pub fn cluster(&self, name: &str) -> Option<FakeCluster> {
+        self.clusters.lock().unwrap().get(name).cloned()
+    }
+
+    pub fn set_state(&self, name: &str, state: ClusterState) {
+        if let Some(cluster) = self.clusters.lock().unwrap().get_mut(name) {
+            cluster.state = state;
+        }
+    }
+
+    fn check(&self, method: &str) -> Result<bool> {
+        self.faults
+            .check(method)
+            .map_err(|fault| fault_error(method, fault))
+    }
+
+    fn with_cluster<T>(&self, name: &str, f: impl FnOnce(&mut FakeCluster) -> T) -> Result<T> {
+        let mut clusters = self.clusters.lock().unwrap();
+        let cluster = clusters
+            .get_mut(name)
+            .ok_or_else(|| Error::ClusterNotFound(name.to_string()))?;
+        Ok(f(cluster))
+    }
+}
+
+fn fault_error(method: &str, fault: Fault) -> anyhow::Error {
+    match fault {
+        Fault::Timeout => Error::ProviderUnavailable(format!("{} timed out", method)).into(),
+        Fault::Conflict => anyhow::anyhow!("{} conflicts with an operation in progress", method),
+        Fault::Unauthorized => anyhow::anyhow!("{} was refused the caller's credentials", method),
+        Fault::Partial => anyhow::anyhow!("{} only partially succeeded", method),
+        Fault::Error(message) => anyhow::anyhow!("{} failed: {}", method, message),
+    }
+}
+
+/// Fails with `Fault::Partial` once the change has been applied.
+fn finish(method: &str, partial: bool) -> Result<()> {
+    if partial {
+        return Err(fault_error(method, Fault::Partial));
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl InfrastructureProvider for FakeProvider {
+    async fn create_cluster(&self, name: &str, config: &ClusterConfig) -> Result<()> {
+        let partial = self.check("create_cluster")?;
+        {
+            let mut clusters = self.clusters.lock().unwrap();
+            if clusters.contains_key(name) {
+                return Err(Error::ClusterAlreadyExists(name.to_string()).into());
+            }
+            clusters.insert(
+                name.to_string(),
+                FakeCluster {
+                    config: config.clone(),
+                    state: ClusterState::Running,
+                    node_count: config.node_count,
+                    node_pools: config.node_pools.clone(),
+                },
+            );
+        }
+        finish("create_cluster", partial)
+    }
+
+    async fn delete_cluster(&self, name: &str) -> Result<()> {
+        let partial = self.check("delete_cluster")?;
+        self.clusters
+            .lock()
+            .unwrap()
+            .remove(name)
+            .ok_or_else(|| Error::ClusterNotFound(name.to_string()))?;
+        finish("delete_cluster", partial)
+    }
+
+    async fn get_cluster_status(&self, name: &str) -> Result<ClusterStatus> {
+        let partial = self.check("get_cluster_status")?;
+        let status = self.with_cluster(name, |cluster| ClusterStatus {
+            name: name.to_string(),
+            project: String::new(),
+            state: cluster.state.clone(),
+            node_count: cluster.node_count,
+            version: cluster.config.kubernetes_version.clone(),
+            endpoint: format!("https://{}.fake.galato.test", name),
+            message: None,
+        })?;
+        finish("get_cluster_status", partial)?;
+        Ok(status)
+    }
+
+    async fn scale_cluster(&self, name: &str, node_count: i32) -> Result<()> {
+        let partial = self.check("scale_cluster")?;
+        self.with_cluster(name, |cluster| cluster.node_count = node_count)?;
+        finish("scale_cluster", partial)
+    }
+
+    async fn create_node_pool(&self, cluster: &str, pool: &NodePool) -> Result<()> {
+        let partial = self.check("create_node_pool")?;
+        tokio::task::yield_now().await;
+        self.with_cluster(cluster, |c| c.node_pools.push(pool.clone()))?;
+        finish("create_node_pool", partial)
+    }
+
+    async fn resize_node_pool(&self, cluster: &str, pool: &NodePool) -> Result<()> {
+        let partial = self.check("resize_node_pool")?;
+        tokio::task::yield_now().await;
+        self.with_cluster(cluster, |c| {
+            if let Some(existing) = c.node_pools.iter_mut().find(|p| p.name == pool.name) {
+                *existing = pool.clone();
+            }
+        })?;
+        finish("resize_node_pool", partial)
+    }
+
+    async fn delete_node_pool(&self, cluster: &str, pool: &str) -> Result<()> {
+        let partial = self.check("delete_node_pool")?;
+        tokio::task::yield_now().await;
+        self.with_cluster(cluster, |c| c.node_pools.retain(|p| p.name != pool))?;
+        finish("delete_node_pool", partial)
+    }
+
+    async fn update_tags(
+        &self,
+        name: &str,
+        config: &ClusterConfig,
+        removed: &[String],
+    ) -> Result<()> {
+        let partial = self.check("update_tags")?;
+        self.with_cluster(name, |cluster| {
+            cluster.config.tags.extend(config.tags.clone());
+            cluster.config.tags.retain(|key, _| !removed.contains(key));
+        })?;
+        finish("update_tags", partial)
+    }
+
+    async fn get_kubeconfig(&self, name: &str) -> Result<String> {
+        self.check("get_kubeconfig")?;
+        self.with_cluster(name, |_| {
+            format!(
+                "apiVersion: v1\nkind: Config\nclusters:\n- name: {0}\n  cluster:\n    server: https://{0}.fake.galato.test\n",
+                name
+            )
+        })
+    }
+}
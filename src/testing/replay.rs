@@ -0,0 +1,152 @@
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{header, HeaderMap, HeaderName, Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    Router,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+/// Placeholder in recorded bodies and headers replaced by the server's own
+/// URL, so recorded links point back at the replay server.
+const BASE_URL: &str = "{base}";
+
+/// A request received by the replay server.
+#[derive(Debug, Clone)]
+pub struct Received {
+    pub method: Method,
+    pub path: String,
+    pub query: Option<String>,
+    pub authorization: Option<String>,
+    pub body: String,
+}
+
+impl Received {
+    pub fn json(&self) -> serde_json::Value {
+        serde_json::from_str(&self.body).unwrap()
+    }
+}
+
+struct Recorded {
+    status: u16,
+    body: &'static str,
+    headers: Vec<(&'static str, &'static str)>,
+}
+
+/// HTTP server replaying recorded cloud API responses, keyed by method and
+/// path. Responses recorded several times for one key are served in order,
+/// the last one repeating; unknown requests get `501 Not Implemented`.
+#[derive(Default)]
+pub struct Replay {
+    responses: Mutex<HashMap<String, VecDeque<Recorded>>>,
+    received: Mutex<Vec<Received>>,
+}
+
+impl Replay {
+    pub fn respond(self, method: &str, path: &str, status: u16, body: &'static str) -> Self {
+        self.respond_with_headers(method, path, status, body, &[])
+    }
+
+    pub fn respond_with_headers(
+        self,
+        method: &str,
+        path: &str,
+        status: u16,
+        body: &'static str,
+        headers: &[(&'static str, &'static str)],
+    ) -> Self {
+        self.responses
+            .lock()
+            .unwrap()
+            .entry(format!("{} {}", method, path))
+            .or_default()
+            .push_back(Recorded {
+                status,
+                body,
+                headers: headers.to_vec(),
+            });
+        self
+    }
+
+    /// Serves the recorded responses on an ephemeral local port and returns
+    /// the server's URL.
+    pub async fn start(self) -> (String, Arc<Replay>) {
+        let replay = Arc::new(self);
+        let app = Router::new()
+            .fallback(serve_recorded)
+            .with_state(replay.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        (url, replay)
+    }
+
+    /// Requests received for a method and path, oldest first.
+    pub fn received(&self, method: &str, path: &str) -> Vec<Received> {
+        self.received
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|r| r.method.as_str() == method && r.path == path)
+            .cloned()
+            .collect()
+    }
+}
+
+async fn serve_recorded(
+    State(replay): State<Arc<Replay>>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let key = format!("{} {}", method, uri.path());
+    replay.received.lock().unwrap().push(Received {
+        method,
+        path: uri.path().to_string(),
+        query: uri.query().map(str::to_string),
+        authorization: headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string),
+        body: String::from_utf8_lossy(&body).into_owned(),
+    });
+
+    let base = headers
+        .get(header::HOST)
+        .and_then(|v| v.to_str().ok())
+        .map(|host| format!("http://{}", host))
+        .unwrap_or_default();
+
+    let mut responses = replay.responses.lock().unwrap();
+    let Some(queue) = responses.get_mut(&key) else {
+        return (StatusCode::NOT_IMPLEMENTED, key).into_response();
+    };
+    let recorded = if queue.len() > 1 {
+        queue.pop_front().unwrap()
+    } else {
+        let last = &queue[0];
+        Recorded {
+            status: last.status,
+            body: last.body,
+            headers: last.headers.clone(),
+        }
+    };
+
+    let mut response = (
+        StatusCode::from_u16(recorded.status).unwrap(),
+        [(header::CONTENT_TYPE, "application/json")],
+        recorded.body.replace(BASE_URL, &base),
+    )
+        .into_response();
+    for (name, value) in recorded.headers {
+        response.headers_mut().insert(
+            HeaderName::from_static(name),
+            value.replace(BASE_URL, &base).parse().unwrap(),
+        );
+    }
+    response
+}
@@ -0,0 +1,185 @@
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    response::Response,
+    routing::post,
+    Json, Router,
+};
+use chrono::Utc;
+use ethers::types::{Block, TxHash, U256, U64};
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+
+/// Seconds between two blocks of a fake chain.
+const BLOCK_TIME: u64 = 2;
+
+/// JSON-RPC node serving a fake chain over HTTP and WebSocket on the same
+/// URL. Every block up to the head exists, `BLOCK_TIME` apart; the `safe` and
+/// `finalized` tags resolve only once set.
+pub struct FakeNode {
+    chain: Mutex<Chain>,
+}
+
+struct Chain {
+    head: u64,
+    head_timestamp: u64,
+    safe: Option<u64>,
+    finalized: Option<u64>,
+    transaction_count: u64,
+    last_transaction: Option<u64>,
+    balance: U256,
+    transactions_per_block: usize,
+    gas_per_block: u64,
+}
+
+impl FakeNode {
+    /// A chain whose block `head` was produced `age` seconds ago.
+    pub fn new(head: u64, age: u64) -> Self {
+        Self {
+            chain: Mutex::new(Chain {
+                head,
+                head_timestamp: Utc::now().timestamp() as u64 - age,
+                safe: None,
+                finalized: None,
+                transaction_count: 0,
+                last_transaction: None,
+                balance: U256::zero(),
+                transactions_per_block: 10,
+                gas_per_block: 210_000,
+            }),
+        }
+    }
+
+    pub fn safe(self, number: u64) -> Self {
+        self.chain.lock().unwrap().safe = Some(number);
+        self
+    }
+
+    pub fn finalized(self, number: u64) -> Self {
+        self.chain.lock().unwrap().finalized = Some(number);
+        self
+    }
+
+    /// Nonce reported for every account.
+    pub fn transaction_count(self, count: u64) -> Self {
+        self.chain.lock().unwrap().transaction_count = count;
+        self
+    }
+
+    /// Block holding the latest transaction of every account; nonces read at
+    /// earlier blocks are one lower. Without it, nonces never changed.
+    pub fn last_transaction(self, number: u64) -> Self {
+        self.chain.lock().unwrap().last_transaction = Some(number);
+        self
+    }
+
+    /// Balance reported for every account, in wei.
+    pub fn balance(self, wei: U256) -> Self {
+        self.chain.lock().unwrap().balance = wei;
+        self
+    }
+
+    /// Timestamp of a block of the chain.
+    pub fn timestamp(&self, number: u64) -> u64 {
+        let chain = self.chain.lock().unwrap();
+        chain.head_timestamp - (chain.head - number) * BLOCK_TIME
+    }
+
+    /// Serves the chain on an ephemeral local port and returns its `http://`
+    /// URL; the same URL with `ws://` reaches the WebSocket endpoint.
+    pub async fn start(self) -> (String, Arc<FakeNode>) {
+        let node = Arc::new(self);
+        let app = Router::new()
+            .route("/", post(serve_http).get(serve_ws))
+            .with_state(node.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        (url, node)
+    }
+
+    fn answer(&self, request: &Value) -> Value {
+        let chain = self.chain.lock().unwrap();
+        let params = &request["params"];
+        let result = match request["method"].as_str() {
+            Some("eth_getBlockByNumber") => chain.block(params[0].as_str().unwrap_or_default()),
+            Some("eth_getTransactionCount") => {
+                chain.transaction_count(params[1].as_str().unwrap_or_default())
+            }
+            Some("eth_getBalance") => json!(chain.balance),
+            method => {
+                return json!({
+                    "jsonrpc": "2.0",
+                    "id": request["id"],
+                    "error": { "code": -32601, "message": format!("{:?} is not supported", method) },
+                })
+            }
+        };
+        json!({ "jsonrpc": "2.0", "id": request["id"], "result": result })
+    }
+}
+
+impl Chain {
+    /// The number of the block for a tag or hex number, if there is one.
+    fn resolve(&self, tag: &str) -> Option<u64> {
+        let number = match tag {
+            "latest" => Some(self.head),
+            "safe" => self.safe,
+            "finalized" => self.finalized,
+            number => u64::from_str_radix(number.trim_start_matches("0x"), 16).ok(),
+        };
+        number.filter(|n| *n <= self.head)
+    }
+
+    /// The block for a tag or hex number, or `null` when there is none.
+    fn block(&self, tag: &str) -> Value {
+        let Some(number) = self.resolve(tag) else {
+            return Value::Null;
+        };
+        let block = Block::<TxHash> {
+            number: Some(number.into()),
+            timestamp: (self.head_timestamp - (self.head - number) * BLOCK_TIME).into(),
+            gas_used: self.gas_per_block.into(),
+            transactions: vec![TxHash::zero(); self.transactions_per_block],
+            ..Default::default()
+        };
+        serde_json::to_value(block).unwrap()
+    }
+
+    /// The nonce of every account after a block.
+    fn transaction_count(&self, tag: &str) -> Value {
+        let Some(number) = self.resolve(tag) else {
+            return Value::Null;
+        };
+        let count = match self.last_transaction {
+            Some(block) if number < block => self.transaction_count.saturating_sub(1),
+            _ => self.transaction_count,
+        };
+        json!(U64::from(count))
+    }
+}
+
+async fn serve_http(State(node): State<Arc<FakeNode>>, Json(request): Json<Value>) -> Json<Value> {
+    Json(node.answer(&request))
+}
+
+async fn serve_ws(State(node): State<Arc<FakeNode>>, ws: WebSocketUpgrade) -> Response {
+    ws.on_upgrade(move |socket| serve_socket(node, socket))
+}
+
+async fn serve_socket(node: Arc<FakeNode>, mut socket: WebSocket) {
+    while let Some(Ok(message)) = socket.recv().await {
+        let Message::Text(text) = message else {
+            continue;
+        };
+        let Ok(request) = serde_json::from_str::<Value>(&text) else {
+            continue;
+        };
+        let response = node.answer(&request).to_string();
+        if socket.send(Message::Text(response)).await.is_err() {
+            break;
+        }
+    }
+}
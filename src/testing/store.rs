@@ -0,0 +1,428 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+use uuid::Uuid;
+
+use crate::{
+    audit::{AuditEntry, AuditEvent, AuditFilter},
+    infra::{ClusterConfig, ClusterStatus},
+    operations::{Operation, OperationState},
+    project::{Project, ProjectUsage, DEFAULT_PROJECT},
+    rollup::{RollupConfig, RollupState, RollupStatus},
+    store::{ClusterRecord, HistoryEntry, RollupRecord, Store},
+};
+use super::Faults;
+
+/// `Store` kept in memory, with the same history and ordering rules as
+/// `PgStore`.
+#[derive(Default)]
+pub struct MemoryStore {
+    pub faults: Faults,
+    inner: Mutex<Inner>,
+}
+
+impl MemoryStore {
+    fn check(&self, method: &str) -> Result<()> {
+        match self.faults.check(method) {
+            Ok(_) => Ok(()),
+            Err(fault) => anyhow::bail!("{} failed: {:?}", method, fault),
+        }
+    }
+
+    fn write_rollup_status(&self, expected: Option<&RollupState>, status: &RollupStatus) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        let Some(record) = inner
+            .rollups
+            .iter_mut()
+            .find(|r| r.config.name == status.name)
+        else {
+            return false;
+        };
+        if expected.is_some_and(|expected| *expected != record.status.state) {
+            return false;
+        }
+        let transitioned = record.status.state != status.state;
+        record.status = status.clone();
+        record.updated_at = Utc::now();
+        if transitioned {
+            inner
+                .rollup_history
+                .push((status.name.clone(), entry(status.state.as_str(), status)));
+        }
+        true
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    rollups: Vec<RollupRecord>,
+    rollup_history: Vec<(String, HistoryEntry<RollupStatus>)>,
+    clusters: Vec<ClusterRecord>,
+    cluster_history: Vec<(String, HistoryEntry<ClusterStatus>)>,
+    projects: Vec<Project>,
+    audit: Vec<AuditEntry>,
+    operations: Vec<Operation>,
+    instances: HashMap<Uuid, DateTime<Utc>>,
+}
+
+fn entry<T>(state: &str, status: &T) -> HistoryEntry<T>
+where
+    T: Clone,
+{
+    HistoryEntry {
+        state: state.to_string(),
+        status: status.clone(),
+        recorded_at: Utc::now(),
+    }
+}
+
+/// Newest first, like the history queries.
+fn history<T: Clone>(entries: &[(String, HistoryEntry<T>)], name: &str) -> Vec<HistoryEntry<T>> {
+    entries
+        .iter()
+        .rev()
+        .filter(|(n, _)| n == name)
+        .map(|(_, e)| e.clone())
+        .collect()
+}
+
+fn same<T: serde::Serialize>(a: &T, b: &T) -> bool {
+    serde_json::to_value(a).ok() == serde_json::to_value(b).ok()
+}
+
+#[async_trait]
+impl Store for MemoryStore {
+    async fn insert_rollup(&self, config: &RollupConfig, status: &RollupStatus) -> Result<()> {
+        self.check("insert_rollup")?;
+        let mut inner = self.inner.lock().unwrap();
+        if inner.rollups.iter().any(|r| r.config.name == config.name) {
+            anyhow::bail!("duplicate rollup {}", config.name);
+        }
+        let now = Utc::now();
+        inner.rollups.push(RollupRecord {
+            config: config.clone(),
+            status: status.clone(),
+            created_at: now,
+            updated_at: now,
+        });
+        inner
+            .rollup_history
+            .push((config.name.clone(), entry(status.state.as_str(), status)));
+        Ok(())
+    }
+
+    async fn update_rollup_config(&self, config: &RollupConfig) -> Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        let Some(record) = inner
+            .rollups
+            .iter_mut()
+            .find(|r| r.config.name == config.name)
+        else {
+            return Ok(());
+        };
+        let changed = !same(&record.config, config);
+        record.config = config.clone();
+        record.updated_at = Utc::now();
+        if changed {
+            let history = entry(record.status.state.as_str(), &record.status);
+            inner.rollup_history.push((config.name.clone(), history));
+        }
+        Ok(())
+    }
+
+    async fn update_rollup_status(&self, status: &RollupStatus) -> Result<()> {
+        self.write_rollup_status(None, status);
+        Ok(())
+    }
+
+    async fn update_rollup_status_if(
+        &self,
+        expected: &RollupState,
+        status: &RollupStatus,
+    ) -> Result<bool> {
+        Ok(self.write_rollup_status(Some(expected), status))
+    }
+
+    async fn delete_rollup(&self, name: &str) -> Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(index) = inner.rollups.iter().position(|r| r.config.name == name) {
+            let record = inner.rollups.remove(index);
+            inner
+                .rollup_history
+                .push((name.to_string(), entry("Deleted", &record.status)));
+        }
+        Ok(())
+    }
+
+    async fn get_rollup(&self, name: &str) -> Result<Option<RollupRecord>> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner
+            .rollups
+            .iter()
+            .find(|r| r.config.name == name)
+            .cloned())
+    }
+
+    async fn list_rollups(&self) -> Result<Vec<RollupRecord>> {
+        self.check("list_rollups")?;
+        Ok(self.inner.lock().unwrap().rollups.clone())
+    }
+
+    async fn rollup_history(&self, name: &str) -> Result<Vec<HistoryEntry<RollupStatus>>> {
+        Ok(history(&self.inner.lock().unwrap().rollup_history, name))
+    }
+
+    async fn insert_cluster(&self, config: &ClusterConfig, status: &ClusterStatus) -> Result<()> {
+        self.check("insert_cluster")?;
+        let mut inner = self.inner.lock().unwrap();
+        if inner.clusters.iter().any(|c| c.config.name == config.name) {
+            anyhow::bail!("duplicate cluster {}", config.name);
+        }
+        let now = Utc::now();
+        inner.clusters.push(ClusterRecord {
+            config: config.clone(),
+            status: status.clone(),
+            created_at: now,
+            updated_at: now,
+        });
+        inner
+            .cluster_history
+            .push((config.name.clone(), entry(status.state.as_str(), status)));
+        Ok(())
+    }
+
+    async fn update_cluster_config(&self, config: &ClusterConfig) -> Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        let Some(record) = inner
+            .clusters
+            .iter_mut()
+            .find(|c| c.config.name == config.name)
+        else {
+            return Ok(());
+        };
+        let changed = !same(&record.config, config);
+        record.config = config.clone();
+        record.updated_at = Utc::now();
+        if changed {
+            let history = entry(record.status.state.as_str(), &record.status);
+            inner.cluster_history.push((config.name.clone(), history));
+        }
+        Ok(())
+    }
+
+    async fn update_cluster_status(&self, status: &ClusterStatus) -> Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        let Some(record) = inner
+            .clusters
+            .iter_mut()
+            .find(|c| c.config.name == status.name)
+        else {
+            return Ok(());
+        };
+        let transitioned = record.status.state.as_str() != status.state.as_str();
+        record.status = status.clone();
+        record.updated_at = Utc::now();
+        if transitioned {
+            inner
+                .cluster_history
+                .push((status.name.clone(), entry(status.state.as_str(), status)));
+        }
+        Ok(())
+    }
+
+    async fn delete_cluster(&self, name: &str) -> Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(index) = inner.clusters.iter().position(|c| c.config.name == name) {
+            let record = inner.clusters.remove(index);
+            inner
+                .cluster_history
+                .push((name.to_string(), entry("Deleted", &record.status)));
+        }
+        Ok(())
+    }
+
+    async fn get_cluster(&self, name: &str) -> Result<Option<ClusterRecord>> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner
+            .clusters
+            .iter()
+            .find(|c| c.config.name == name)
+            .cloned())
+    }
+
+    async fn list_clusters(&self) -> Result<Vec<ClusterRecord>> {
+        Ok(self.inner.lock().unwrap().clusters.clone())
+    }
+
+    async fn cluster_history(&self, name: &str) -> Result<Vec<HistoryEntry<ClusterStatus>>> {
+        Ok(history(&self.inner.lock().unwrap().cluster_history, name))
+    }
+
+    async fn insert_project(&self, project: &Project) -> Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.projects.iter().any(|p| p.name == project.name) {
+            anyhow::bail!("duplicate project {}", project.name);
+        }
+        inner.projects.push(project.clone());
+        Ok(())
+    }
+
+    async fn update_project(&self, project: &Project) -> Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(existing) = inner.projects.iter_mut().find(|p| p.name == project.name) {
+            *existing = project.clone();
+        }
+        Ok(())
+    }
+
+    async fn delete_project(&self, name: &str) -> Result<()> {
+        self.inner
+            .lock()
+            .unwrap()
+            .projects
+            .retain(|p| p.name != name);
+        Ok(())
+    }
+
+    async fn list_projects(&self) -> Result<Vec<Project>> {
+        let mut projects = self.inner.lock().unwrap().projects.clone();
+        projects.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(projects)
+    }
+
+    async fn project_usage(&self, name: &str) -> Result<ProjectUsage> {
+        let inner = self.inner.lock().unwrap();
+        Ok(ProjectUsage {
+            rollups: inner
+                .rollups
+                .iter()
+                .filter(|r| r.config.project == name)
+                .count() as i64,
+            clusters: inner
+                .clusters
+                .iter()
+                .filter(|c| c.config.project == name)
+                .count() as i64,
+        })
+    }
+
+    async fn append_audit(&self, event: &AuditEvent) -> Result<()> {
+        self.check("append_audit")?;
+        let mut inner = self.inner.lock().unwrap();
+        let id = inner.audit.len() as i64 + 1;
+        inner.audit.push(AuditEntry {
+            id,
+            recorded_at: Utc::now(),
+            event: event.clone(),
+        });
+        Ok(())
+    }
+
+    async fn query_audit(&self, filter: &AuditFilter) -> Result<Vec<AuditEntry>> {
+        let inner = self.inner.lock().unwrap();
+        let matches = |wanted: &Option<String>, actual: &str| {
+            wanted.as_deref().is_none_or(|wanted| wanted == actual)
+        };
+        Ok(inner
+            .audit
+            .iter()
+            .rev()
+            .filter(|e| {
+                let project = e.event.project.as_deref().unwrap_or_default();
+                matches(&filter.actor, &e.event.actor)
+                    && matches(&filter.action, &e.event.action)
+                    && matches(&filter.target, &e.event.target)
+                    && matches(&filter.project, project)
+                    && filter.outcome.is_none_or(|o| o == e.event.outcome)
+                    && filter.since.is_none_or(|since| e.recorded_at >= since)
+                    && filter.until.is_none_or(|until| e.recorded_at < until)
+                    && filter
+                        .projects
+                        .as_ref()
+                        .is_none_or(|projects| projects.iter().any(|p| p == project))
+            })
+            .take(filter.limit() as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn insert_operation(&self, operation: &Operation) -> Result<()> {
+        self.inner
+            .lock()
+            .unwrap()
+            .operations
+            .push(operation.clone());
+        Ok(())
+    }
+
+    async fn update_operation(&self, operation: &Operation) -> Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(existing) = inner.operations.iter_mut().find(|o| o.id == operation.id) {
+            *existing = operation.clone();
+        }
+        Ok(())
+    }
+
+    async fn get_operation(&self, id: Uuid) -> Result<Option<Operation>> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner.operations.iter().find(|o| o.id == id).cloned())
+    }
+
+    async fn list_operations(
+        &self,
+        projects: Option<&[String]>,
+        limit: i64,
+    ) -> Result<Vec<Operation>> {
+        let inner = self.inner.lock().unwrap();
+        Ok(inner
+            .operations
+            .iter()
+            .rev()
+            .filter(|o| {
+                let project = o.project.as_deref().unwrap_or(DEFAULT_PROJECT);
+                projects.is_none_or(|projects| projects.iter().any(|p| p == project))
+            })
+            .take(limit.max(0) as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn heartbeat(&self, id: Uuid) -> Result<()> {
+        self.inner.lock().unwrap().instances.insert(id, Utc::now());
+        Ok(())
+    }
+
+    async fn fail_orphaned_operations(
+        &self,
+        reason: &str,
+        timeout: Duration,
+    ) -> Result<Vec<Operation>> {
+        let mut inner = self.inner.lock().unwrap();
+        let cutoff = Utc::now() - chrono::Duration::from_std(timeout)?;
+        inner.instances.retain(|_, heartbeat| *heartbeat > cutoff);
+        let Inner {
+            operations,
+            instances,
+            ..
+        } = &mut *inner;
+        let mut failed = Vec::new();
+        for operation in operations.iter_mut() {
+            let alive = operation
+                .instance
+                .is_some_and(|instance| instances.contains_key(&instance));
+            if !alive
+                && matches!(
+                    operation.state,
+                    OperationState::Pending | OperationState::Running
+                )
+            {
+                operation.state = OperationState::Failed;
+                operation.error = Some(reason.to_string());
+                operation.updated_at = Utc::now();
+                failed.push(operation.clone());
+            }
+        }
+        Ok(failed)
+    }
+}